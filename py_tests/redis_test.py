@@ -4,6 +4,8 @@ Requires a running server on localhost:6379.
 Run with: pytest redis_test.py -v
 """
 
+import time
+
 import pytest
 import redis
 
@@ -12,7 +14,7 @@ import redis
 def r():
     client = redis.Redis(host="localhost", port=6379, db=0, decode_responses=True)
     client.ping()
-    client.flushdb()
+    client.flushall()
     return client
 
 
@@ -37,15 +39,159 @@ class TestStrings:
         r.incrby("counter", 8)
         assert r.get("counter") == "10"
 
+    def test_strlen(self, r):
+        r.set("key", "Alice")
+        assert r.strlen("key") == 5
+        assert r.strlen("ghost") == 0
+
+    def test_getrange(self, r):
+        r.set("key", "This is a string")
+        assert r.getrange("key", 0, 3) == "This"
+        assert r.getrange("key", -3, -1) == "ing"
+        assert r.getrange("key", 0, -1) == "This is a string"
+        assert r.getrange("key", 10, 100) == "string"
+        assert r.getrange("key", 5, 2) == ""
+
+    def test_setrange(self, r):
+        r.set("key", "Hello World")
+        assert r.setrange("key", 6, "Redis") == 11
+        assert r.get("key") == "Hello Redis"
+        assert r.setrange("padded", 3, "x") == 4
+        assert r.get("padded") == "\x00\x00\x00x"
+
+    def test_setrange_keeps_ttl(self, r):
+        r.set("key", "Hello", ex=100)
+        r.setrange("key", 0, "J")
+        assert r.ttl("key") > 0
+
+    def test_getset(self, r):
+        assert r.getset("key", "first") is None
+        assert r.getset("key", "second") == "first"
+        assert r.get("key") == "second"
+
+    def test_getdel(self, r):
+        r.set("key", "value")
+        assert r.getdel("key") == "value"
+        assert r.exists("key") == 0
+        assert r.getdel("key") is None
+
+    def test_getdel_wrong_type(self, r):
+        r.rpush("list", "a")
+        with pytest.raises(redis.ResponseError):
+            r.getdel("list")
+        assert r.exists("list") == 1
+
+    def test_incr_missing_key(self, r):
+        assert r.incr("visits") == 1
+        assert r.incrby("other", 5) == 5
+        assert r.get("other") == "5"
+
+    def test_incr_keeps_ttl(self, r):
+        r.set("counter", 1, ex=100)
+        assert r.incr("counter") == 2
+        assert r.ttl("counter") > 0
+
+    def test_decr(self, r):
+        r.set("counter", 10)
+        assert r.decr("counter") == 9
+        assert r.decrby("counter", 4) == 5
+        assert r.decr("fresh") == -1
+
+    def test_incr_not_an_integer(self, r):
+        r.set("key", "abc")
+        with pytest.raises(redis.ResponseError, match="value is not an integer or out of range"):
+            r.incr("key")
+        with pytest.raises(redis.ResponseError, match="value is not an integer or out of range"):
+            r.decrby("key", 2)
+
+    def test_incrby_overflow(self, r):
+        assert r.incrby("counter", 9223372036854775807) == 9223372036854775807
+        with pytest.raises(redis.ResponseError, match="increment or decrement would overflow"):
+            r.incrby("counter", 9223372036854775807)
+        assert r.get("counter") == "9223372036854775807"
+        r.set("low", -9223372036854775808)
+        with pytest.raises(redis.ResponseError, match="increment or decrement would overflow"):
+            r.decr("low")
+
+    def test_incrbyfloat(self, r):
+        r.set("price", "10.50")
+        assert r.incrbyfloat("price", 0.1) == 10.6
+        assert r.incrbyfloat("price", -5) == 5.6
+        assert r.incrbyfloat("fresh", 3.0) == 3
+        assert r.get("fresh") == "3"
+
+    def test_incrbyfloat_not_a_float(self, r):
+        r.set("key", "abc")
+        with pytest.raises(redis.ResponseError):
+            r.incrbyfloat("key", 1.5)
+
+    def test_incrbyfloat_wrong_type(self, r):
+        r.rpush("key", "a")
+        with pytest.raises(redis.ResponseError, match="WRONGTYPE"):
+            r.incrbyfloat("key", 1.5)
+
     def test_set_with_ex(self, r):
         r.set("temp", "bye", ex=5)
         assert r.ttl("temp") > 0
 
+    def test_set_with_px_expires(self, r):
+        r.set("temp", "bye", px=100)
+        assert r.get("temp") == "bye"
+        time.sleep(0.2)
+        assert r.get("temp") is None
+
+    def test_ttl_missing_and_persistent(self, r):
+        r.set("key", "value")
+        assert r.ttl("key") == -1
+        assert r.ttl("ghost") == -2
+
+    def test_pttl(self, r):
+        r.set("temp", "bye", px=1500)
+        assert 1000 < r.pttl("temp") <= 1500
+        r.set("key", "value")
+        assert r.pttl("key") == -1
+        assert r.pttl("ghost") == -2
+
+    def test_expire(self, r):
+        r.set("key", "value")
+        assert r.expire("key", 100) == 1
+        assert 0 < r.ttl("key") <= 100
+        assert r.expire("ghost", 100) == 0
+
+    def test_pexpire(self, r):
+        r.set("key", "value")
+        assert r.pexpire("key", 50) == 1
+        time.sleep(0.1)
+        assert r.get("key") is None
+
+    def test_expire_negative_deletes(self, r):
+        r.set("key", "value")
+        assert r.expire("key", -1) == 1
+        assert r.exists("key") == 0
+
+    def test_ttl_after_expiry(self, r):
+        r.set("temp", "bye", px=50)
+        time.sleep(0.1)
+        assert r.ttl("temp") == -2
+
+    def test_exists_after_expiry(self, r):
+        r.set("temp", "bye", px=50)
+        time.sleep(0.1)
+        assert r.exists("temp") == 0
+
     def test_set_nx(self, r):
         r.set("nx", "first", nx=True)
         r.set("nx", "second", nx=True)
         assert r.get("nx") == "first"
 
+    def test_set_nx_xx_reply_nil_when_skipped(self, r):
+        r.delete("nxxx")
+        assert r.set("nxxx", "v", xx=True) is None
+        assert r.set("nxxx", "v", nx=True) is True
+        assert r.set("nxxx", "w", nx=True) is None
+        assert r.set("nxxx", "w", xx=True) is True
+        assert r.get("nxxx") == "w"
+
 
 # ── Lists ─────────────────────────────────────────────────────────────────────
 
@@ -55,6 +201,64 @@ class TestLists:
         assert r.llen("q:test_rpush_and_lrange") == 3
         assert r.lrange("q:test_rpush_and_lrange", 0, -1) == ["a", "b", "c"]
 
+    def test_lrange_negative_indices(self, r):
+        r.rpush("q:test_lrange_negative_indices", "a", "b", "c", "d")
+        assert r.lrange("q:test_lrange_negative_indices", -3, -2) == ["b", "c"]
+        assert r.lrange("q:test_lrange_negative_indices", 1, 100) == ["b", "c", "d"]
+        assert r.lrange("q:test_lrange_negative_indices", 3, 1) == []
+
+    def test_lrange_missing_key(self, r):
+        assert r.lrange("q:test_lrange_missing_key", 0, -1) == []
+
+    def test_lindex(self, r):
+        r.rpush("q:test_lindex", "a", "b", "c")
+        assert r.lindex("q:test_lindex", 0) == "a"
+        assert r.lindex("q:test_lindex", -1) == "c"
+        assert r.lindex("q:test_lindex", 3) is None
+
+    def test_lset(self, r):
+        r.rpush("q:test_lset", "a", "b", "c")
+        assert r.lset("q:test_lset", 0, "x") is True
+        assert r.lset("q:test_lset", -1, "z") is True
+        assert r.lrange("q:test_lset", 0, -1) == ["x", "b", "z"]
+        with pytest.raises(redis.ResponseError, match="index out of range"):
+            r.lset("q:test_lset", 3, "y")
+        with pytest.raises(redis.ResponseError, match="no such key"):
+            r.lset("q:ghost", 0, "y")
+
+    def test_lrem(self, r):
+        r.rpush("q:test_lrem", "a", "x", "b", "x", "c", "x")
+        assert r.lrem("q:test_lrem", -1, "x") == 1
+        assert r.lrem("q:test_lrem", 1, "x") == 1
+        assert r.lrange("q:test_lrem", 0, -1) == ["a", "b", "x", "c"]
+        assert r.lrem("q:test_lrem", 0, "x") == 1
+        assert r.lrange("q:test_lrem", 0, -1) == ["a", "b", "c"]
+
+    def test_linsert(self, r):
+        r.rpush("q:test_linsert", "a", "c")
+        assert r.linsert("q:test_linsert", "BEFORE", "c", "b") == 3
+        assert r.linsert("q:test_linsert", "AFTER", "c", "d") == 4
+        assert r.lrange("q:test_linsert", 0, -1) == ["a", "b", "c", "d"]
+        assert r.linsert("q:test_linsert", "AFTER", "z", "y") == -1
+        assert r.linsert("q:ghost", "AFTER", "a", "y") == 0
+
+    def test_lpos(self, r):
+        r.rpush("q:test_lpos", "a", "x", "b", "x", "x")
+        assert r.lpos("q:test_lpos", "x") == 1
+        assert r.lpos("q:test_lpos", "x", rank=-1) == 4
+        assert r.lpos("q:test_lpos", "x", count=0) == [1, 3, 4]
+        assert r.lpos("q:test_lpos", "x", rank=2, count=1) == [3]
+        assert r.lpos("q:test_lpos", "z") is None
+        assert r.lpos("q:test_lpos", "z", count=2) == []
+
+    def test_pushx(self, r):
+        assert r.lpushx("q:test_pushx", "a") == 0
+        assert r.exists("q:test_pushx") == 0
+        r.rpush("q:test_pushx", "b")
+        assert r.lpushx("q:test_pushx", "a") == 2
+        assert r.rpushx("q:test_pushx", "c") == 3
+        assert r.lrange("q:test_pushx", 0, -1) == ["a", "b", "c"]
+
     def test_lpush(self, r):
         r.rpush("q:test_lpush", "a", "b")
         r.lpush("q:test_lpush", "z")
@@ -70,6 +274,12 @@ class TestLists:
         assert r.rpop("q:test_rpop") == "c"
         assert r.lrange("q:test_rpop", 0, -1) == ["a", "b"]
 
+    def test_lpop_invalid_count(self, r):
+        r.rpush("q:test_lpop_invalid_count", "a")
+        with pytest.raises(redis.ResponseError):
+            r.execute_command("LPOP", "q:test_lpop_invalid_count", "notanumber")
+        assert r.ping()
+
     def test_llen(self, r):
         r.rpush("q:test_llen", "a", "b", "c")
         assert r.llen("q:test_llen") == 3
@@ -86,20 +296,47 @@ class TestHashes:
         r.hset("h:test_hset_mapping_and_hgetall", mapping={"name": "Alice", "email": "a@b.com", "age": "30"})
         assert r.hgetall("h:test_hset_mapping_and_hgetall") == {"name": "Alice", "email": "a@b.com", "age": "30"}
 
+    def test_hset_counts_new_fields(self, r):
+        assert r.hset("h:test_hset_counts_new_fields", mapping={"f1": "v1", "f2": "v2"}) == 2
+        assert r.hset("h:test_hset_counts_new_fields", mapping={"f1": "v1", "f2": "v2"}) == 0
+        assert r.hset("h:test_hset_counts_new_fields", mapping={"f2": "x", "f3": "v3"}) == 1
+        assert r.hmset("h:test_hset_counts_new_fields", {"f4": "v4"}) is True
+
     def test_hmget(self, r):
         r.hset("h:test_hmget", mapping={"a": "1", "b": "2", "c": "3"})
         assert r.hmget("h:test_hmget", "a", "c") == ["1", "3"]
 
+    def test_hmget_missing_fields(self, r):
+        r.hset("h:test_hmget_missing_fields", mapping={"a": "1", "c": "3"})
+        assert r.hmget("h:test_hmget_missing_fields", "a", "b", "c") == ["1", None, "3"]
+        assert r.hmget("h:ghost", "a", "b") == [None, None]
+
     def test_hincrby(self, r):
         r.hset("h:test_hincrby", "score", "100")
         r.hincrby("h:test_hincrby", "score", 50)
         assert r.hget("h:test_hincrby", "score") == "150"
 
+    def test_hincrby_overflow(self, r):
+        r.hset("h:test_hincrby_overflow", "score", "9223372036854775807")
+        with pytest.raises(redis.ResponseError, match="increment or decrement would overflow"):
+            r.hincrby("h:test_hincrby_overflow", "score", 1)
+        assert r.hget("h:test_hincrby_overflow", "score") == "9223372036854775807"
+
     def test_hincrby_missing_field(self, r):
         r.hset("h:test_hincrby_missing_field", "other", "x")
         r.hincrby("h:test_hincrby_missing_field", "score", 10)
         assert r.hget("h:test_hincrby_missing_field", "score") == "10"
 
+    def test_hincrbyfloat(self, r):
+        r.hset("h:test_hincrbyfloat", "price", "10.5")
+        assert r.hincrbyfloat("h:test_hincrbyfloat", "price", 0.1) == 10.6
+        assert r.hincrbyfloat("h:test_hincrbyfloat", "fresh", 2.5) == 2.5
+
+    def test_hincrbyfloat_wrong_type(self, r):
+        r.set("h:test_hincrbyfloat_wrong_type", "plain")
+        with pytest.raises(redis.ResponseError, match="WRONGTYPE"):
+            r.hincrbyfloat("h:test_hincrbyfloat_wrong_type", "price", 0.1)
+
     def test_hexists(self, r):
         r.hset("h:test_hexists", "name", "Alice")
         assert r.hexists("h:test_hexists", "name") is True
@@ -109,6 +346,27 @@ class TestHashes:
         r.hset("h:test_hkeys", mapping={"a": "1", "b": "2", "c": "3"})
         assert set(r.hkeys("h:test_hkeys")) == {"a", "b", "c"}
 
+    def test_hscan(self, r):
+        mapping = {f"f{i}": str(i) for i in range(25)}
+        r.hset("h:test_hscan", mapping=mapping)
+        assert dict(r.hscan_iter("h:test_hscan", count=7)) == mapping
+        assert dict(r.hscan_iter("h:test_hscan", match="f1*")) == {
+            k: v for k, v in mapping.items() if k.startswith("f1")
+        }
+        assert r.hscan("h:ghost") == (0, {})
+
+    def test_hrandfield(self, r):
+        mapping = {"a": "1", "b": "2", "c": "3"}
+        r.hset("h:test_hrandfield", mapping=mapping)
+        assert r.hrandfield("h:test_hrandfield") in mapping
+        picked = r.hrandfield("h:test_hrandfield", 2)
+        assert len(set(picked)) == 2 and set(picked) <= set(mapping)
+        assert len(r.hrandfield("h:test_hrandfield", -5)) == 5
+        pairs = r.hrandfield("h:test_hrandfield", 10, withvalues=True)
+        assert dict(zip(pairs[::2], pairs[1::2])) == mapping
+        assert r.hrandfield("h:ghost") is None
+        assert r.hrandfield("h:ghost", 2) == []
+
 
 # ── Sets ──────────────────────────────────────────────────────────────────────
 
@@ -117,11 +375,68 @@ class TestSets:
         r.sadd("s:test_sadd_and_smembers", "Alice", "Bob", "Carol")
         assert r.smembers("s:test_sadd_and_smembers") == {"Alice", "Bob", "Carol"}
 
+    def test_srem(self, r):
+        r.sadd("s:test_srem", "Alice", "Bob")
+        assert r.srem("s:test_srem", "Alice", "Dave") == 1
+        assert r.smembers("s:test_srem") == {"Bob"}
+        assert r.srem("s:test_srem", "Bob") == 1
+        assert r.exists("s:test_srem") == 0
+        assert r.srem("s:ghost", "Bob") == 0
+
+    def test_smove(self, r):
+        r.sadd("a:test_smove", "Alice", "Bob")
+        assert r.smove("a:test_smove", "b:test_smove", "Alice") == 1
+        assert r.smove("a:test_smove", "b:test_smove", "Dave") == 0
+        assert r.smembers("b:test_smove") == {"Alice"}
+        assert r.smove("a:test_smove", "b:test_smove", "Bob") == 1
+        assert r.exists("a:test_smove") == 0
+        assert r.smembers("b:test_smove") == {"Alice", "Bob"}
+
+    def test_smove_wrong_type(self, r):
+        r.sadd("a:test_smove_wrong_type", "Alice")
+        r.set("b:test_smove_wrong_type", "plain")
+        with pytest.raises(redis.ResponseError):
+            r.smove("a:test_smove_wrong_type", "b:test_smove_wrong_type", "Alice")
+        assert r.smembers("a:test_smove_wrong_type") == {"Alice"}
+
+    def test_spop(self, r):
+        r.sadd("s:test_spop", "Alice", "Bob", "Carol")
+        popped = r.spop("s:test_spop")
+        assert popped in {"Alice", "Bob", "Carol"}
+        assert r.sismember("s:test_spop", popped) == 0
+        assert len(r.spop("s:test_spop", 5)) == 2
+        assert r.exists("s:test_spop") == 0
+        assert r.spop("s:test_spop") is None
+
+    def test_srandmember(self, r):
+        members = {"Alice", "Bob", "Carol"}
+        r.sadd("s:test_srandmember", *members)
+        assert r.srandmember("s:test_srandmember") in members
+        picked = r.srandmember("s:test_srandmember", 2)
+        assert len(set(picked)) == 2 and set(picked) <= members
+        assert set(r.srandmember("s:test_srandmember", 10)) == members
+        repeated = r.srandmember("s:test_srandmember", -10)
+        assert len(repeated) == 10 and set(repeated) <= members
+        assert r.scard("s:test_srandmember") == 3
+        assert r.srandmember("s:ghost") is None
+        assert r.srandmember("s:ghost", 3) == []
+
+    def test_sscan(self, r):
+        members = {f"m{i}" for i in range(25)}
+        r.sadd("s:test_sscan", *members)
+        assert set(r.sscan_iter("s:test_sscan", count=4)) == members
+        assert r.sscan("s:ghost") == (0, [])
+
     def test_sismember(self, r):
         r.sadd("s:test_sadd_and_smembers", "Alice", "Bob")
         assert r.sismember("s:test_sadd_and_smembers", "Alice") is 1
         assert r.sismember("s:test_sadd_and_smembers", "Dave") is 0
 
+    def test_smismember(self, r):
+        r.sadd("s:test_smismember", "Alice", "Bob")
+        assert r.smismember("s:test_smismember", ["Bob", "Dave", "Alice"]) == [1, 0, 1]
+        assert r.smismember("s:ghost", ["Alice", "Bob"]) == [0, 0]
+
     def test_sinter(self, r):
         r.sadd("a:test_sinter", "Alice", "Bob", "Carol")
         r.sadd("b:test_sinter", "Bob", "Dave")
@@ -137,6 +452,18 @@ class TestSets:
         r.sadd("b:test_sdiff", "Bob", "Dave")
         assert r.sdiff("a:test_sdiff", "b:test_sdiff") == {"Alice", "Carol"}
 
+    def test_sstore(self, r):
+        r.sadd("a:test_sstore", "Alice", "Bob", "Carol")
+        r.sadd("b:test_sstore", "Bob", "Dave")
+        assert r.sinterstore("d:test_sstore", ["a:test_sstore", "b:test_sstore"]) == 1
+        assert r.smembers("d:test_sstore") == {"Bob"}
+        assert r.sunionstore("d:test_sstore", ["a:test_sstore", "b:test_sstore"]) == 4
+        assert r.smembers("d:test_sstore") == {"Alice", "Bob", "Carol", "Dave"}
+        assert r.sdiffstore("d:test_sstore", ["a:test_sstore", "b:test_sstore"]) == 2
+        assert r.smembers("d:test_sstore") == {"Alice", "Carol"}
+        assert r.sinterstore("d:test_sstore", ["a:test_sstore", "s:ghost"]) == 0
+        assert r.exists("d:test_sstore") == 0
+
     def test_scard(self, r):
         r.sadd("s:test_scard", "Alice", "Bob", "Carol")
         assert r.scard("s:test_scard") == 3
@@ -176,12 +503,86 @@ class TestSortedSets:
         assert r.zscore("z:test_zscore", "Carol") == 870.0
         assert r.zscore("z:test_zscore", "missing") is None
 
+    def test_zmscore(self, r):
+        r.zadd("z:test_zmscore", {"Alice": 900, "Carol": 870})
+        assert r.zmscore("z:test_zmscore", ["Carol", "missing", "Alice"]) == [870.0, None, 900.0]
+        assert r.zmscore("z:ghost", ["Alice", "Bob"]) == [None, None]
+
     def test_zrangebyscore(self, r):
         r.zadd("z:test_zrangebyscore", {"Alice": 900, "Bob": 750, "Carol": 870, "Dave": 600})
         assert r.zrangebyscore("z:test_zrangebyscore", 700, 900, withscores=True) == [
             ("Bob", 750.0), ("Carol", 870.0), ("Alice", 900.0),
         ]
 
+    def test_zrangebyscore_exclusive_and_infinite(self, r):
+        r.zadd("z:test_zrangebyscore_bounds", {"Alice": 900, "Bob": 750, "Carol": 870, "Dave": -600})
+        assert r.zrangebyscore("z:test_zrangebyscore_bounds", "(750", "+inf") == ["Carol", "Alice"]
+        assert r.zrangebyscore("z:test_zrangebyscore_bounds", "-inf", "(870") == ["Dave", "Bob"]
+        assert r.zrangebyscore("z:test_zrangebyscore_bounds", -700, 0) == ["Dave"]
+
+    def test_zrangebylex(self, r):
+        r.zadd("z:test_zrangebylex", {"a": 0, "b": 0, "c": 0, "d": 0})
+        assert r.zrangebylex("z:test_zrangebylex", "-", "+") == ["a", "b", "c", "d"]
+        assert r.zrangebylex("z:test_zrangebylex", "[b", "(d") == ["b", "c"]
+        assert r.zrangebylex("z:test_zrangebylex", "(b", "[d") == ["c", "d"]
+        assert r.zrangebylex("z:test_zrangebylex", "(d", "+") == []
+        assert r.zrangebylex("z:ghost", "-", "+") == []
+
+    def test_zscan(self, r):
+        r.zadd("z:test_zscan", {f"m{i}": i for i in range(25)})
+        scanned = dict(r.zscan_iter("z:test_zscan", count=6))
+        assert scanned == {f"m{i}": float(i) for i in range(25)}
+        assert r.zscan("z:ghost") == (0, [])
+
+    def test_zremrangebyrank(self, r):
+        r.zadd("z:test_zremrangebyrank", {"Alice": 900, "Bob": 750, "Carol": 870, "Dave": 600})
+        assert r.zremrangebyrank("z:test_zremrangebyrank", 0, 1) == 2
+        assert r.zrange("z:test_zremrangebyrank", 0, -1) == ["Carol", "Alice"]
+        assert r.zremrangebyrank("z:test_zremrangebyrank", 5, 10) == 0
+        assert r.zremrangebyrank("z:test_zremrangebyrank", 0, -1) == 2
+        assert r.exists("z:test_zremrangebyrank") == 0
+
+    def test_zremrangebyscore(self, r):
+        r.zadd("z:test_zremrangebyscore", {"Alice": 900, "Bob": 750, "Carol": 870, "Dave": 600})
+        assert r.zremrangebyscore("z:test_zremrangebyscore", "(750", 870) == 1
+        assert r.zremrangebyscore("z:test_zremrangebyscore", "-inf", 750) == 2
+        assert r.zrange("z:test_zremrangebyscore", 0, -1) == ["Alice"]
+        assert r.zremrangebyscore("z:ghost", "-inf", "+inf") == 0
+
+    def test_zunionstore(self, r):
+        r.zadd("a:test_zunionstore", {"Alice": 10, "Bob": 20})
+        r.zadd("b:test_zunionstore", {"Bob": 5, "Carol": 7})
+        assert r.zunionstore("d:test_zunionstore", ["a:test_zunionstore", "b:test_zunionstore"]) == 3
+        assert r.zrange("d:test_zunionstore", 0, -1, withscores=True) == [
+            ("Carol", 7.0), ("Alice", 10.0), ("Bob", 25.0),
+        ]
+        assert r.zunionstore(
+            "d:test_zunionstore", {"a:test_zunionstore": 2, "b:test_zunionstore": 3}, aggregate="MAX"
+        ) == 3
+        assert r.zrange("d:test_zunionstore", 0, -1, withscores=True) == [
+            ("Alice", 20.0), ("Carol", 21.0), ("Bob", 40.0),
+        ]
+
+    def test_zinterstore(self, r):
+        r.zadd("a:test_zinterstore", {"Alice": 10, "Bob": 20})
+        r.zadd("b:test_zinterstore", {"Bob": 5, "Carol": 7})
+        r.sadd("s:test_zinterstore", "Bob")
+        keys = ["a:test_zinterstore", "b:test_zinterstore", "s:test_zinterstore"]
+        assert r.zinterstore("d:test_zinterstore", keys) == 1
+        assert r.zrange("d:test_zinterstore", 0, -1, withscores=True) == [("Bob", 26.0)]
+        assert r.zinterstore("d:test_zinterstore", keys, aggregate="MIN") == 1
+        assert r.zscore("d:test_zinterstore", "Bob") == 1.0
+        assert r.zinterstore("d:test_zinterstore", ["a:test_zinterstore", "z:ghost"]) == 0
+        assert r.exists("d:test_zinterstore") == 0
+
+    def test_zunionstore_errors(self, r):
+        r.zadd("a:test_zunionstore_errors", {"Alice": 1})
+        r.set("s:test_zunionstore_errors", "plain")
+        with pytest.raises(redis.ResponseError, match="WRONGTYPE"):
+            r.zunionstore("d:test_zunionstore_errors", ["a:test_zunionstore_errors", "s:test_zunionstore_errors"])
+        with pytest.raises(redis.ResponseError, match="resulting score is not an integer"):
+            r.zunionstore("d:test_zunionstore_errors", {"a:test_zunionstore_errors": 0.5})
+
     def test_zincrby(self, r):
         r.zadd("z:test_zincrby", {"Dave": 600})
         r.zincrby("z:test_zincrby", 200, "Dave")
@@ -193,18 +594,374 @@ class TestSortedSets:
         assert r.zscore("z:test_zadd_updates_existing", "Alice") == 200.0
         assert r.zcard("z:test_zadd_updates_existing") == 1
 
+    def test_zrem(self, r):
+        r.zadd("z:test_zrem", {"Alice": 900, "Bob": 750, "Carol": 870})
+        assert r.zrem("z:test_zrem", "Bob", "Dave") == 1
+        assert r.zrange("z:test_zrem", 0, -1) == ["Carol", "Alice"]
+        assert r.zrank("z:test_zrem", "Alice") == 1
+        assert r.zrem("z:test_zrem", "Alice", "Carol") == 2
+        assert r.exists("z:test_zrem") == 0
+        assert r.zrem("z:ghost", "Alice") == 0
+
 
 # ── Key Utilities ─────────────────────────────────────────────────────────────
 
 class TestKeyUtilities:
+    def test_keys(self, r):
+        for key in ["hello", "hallo", "hllo", "heeeello", "other"]:
+            r.set(key, "1")
+        assert set(r.keys("*")) == {"hello", "hallo", "hllo", "heeeello", "other"}
+        assert set(r.keys("h?llo")) == {"hello", "hallo"}
+        assert set(r.keys("h*llo")) == {"hello", "hallo", "hllo", "heeeello"}
+        assert set(r.keys("h[ae]llo")) == {"hello", "hallo"}
+        assert r.keys("nothing*") == []
+
     def test_exists(self, r):
         r.set("key", "value")
         assert r.exists("key") == 1
         assert r.exists("ghost") == 0
 
+    def test_flushdb(self, r):
+        r.set("a", "1")
+        r.rpush("b", "x")
+        r.set("c", "2", px=100000)
+        assert r.dbsize() == 3
+        assert r.flushdb() is True
+        assert r.dbsize() == 0
+        assert r.get("a") is None
+        r.set("a", "1")
+        assert r.flushdb(asynchronous=True) is True
+        assert r.dbsize() == 0
+
+    def test_flushall(self, r):
+        r.set("a", "1")
+        r.sadd("s", "x")
+        assert r.flushall() is True
+        assert r.dbsize() == 0
+        assert r.flushall(asynchronous=True) is True
+
+    def test_select_isolates_databases(self, r):
+        r.set("key", "zero")
+        assert r.execute_command("SELECT", 1) is True
+        assert r.get("key") is None
+        r.set("key", "one")
+        assert r.dbsize() == 1
+        r.flushdb()
+        assert r.execute_command("SELECT", 0) is True
+        assert r.get("key") == "zero"
+        with pytest.raises(redis.ResponseError):
+            r.execute_command("SELECT", 16)
+
+    def test_move(self, r):
+        r.set("key", "value", ex=100)
+        r.set("taken", "here")
+        assert r.move("key", 1) is True
+        assert r.get("key") is None
+        assert r.move("missing", 1) is False
+        r.execute_command("SELECT", 1)
+        r.set("taken", "there")
+        assert r.get("key") == "value"
+        assert r.ttl("key") > 0
+        r.execute_command("SELECT", 0)
+        assert r.move("taken", 1) is False
+        assert r.get("taken") == "here"
+        with pytest.raises(redis.ResponseError):
+            r.move("taken", 0)
+
+    def test_flushall_clears_every_database(self, r):
+        r.set("a", "1")
+        r.execute_command("SELECT", 2)
+        r.set("b", "2")
+        r.flushall()
+        assert r.dbsize() == 0
+        r.execute_command("SELECT", 0)
+        assert r.dbsize() == 0
+
+    def test_object_refcount_and_idletime(self, r):
+        r.set("key", "value")
+        assert r.object("refcount", "key") == 1
+        time.sleep(1.1)
+        assert r.object("idletime", "key") >= 1
+        r.get("key")
+        assert r.object("idletime", "key") == 0
+        with pytest.raises(redis.ResponseError):
+            r.object("idletime", "missing")
+
+    def test_exists_multiple_keys(self, r):
+        r.set("a", "1")
+        r.rpush("b", "x")
+        assert r.exists("a", "b", "ghost") == 2
+        assert r.exists("a", "a", "a") == 3
+
+    def test_unlink(self, r):
+        r.set("a", "1")
+        r.rpush("b", "x")
+        assert r.unlink("a", "b", "ghost") == 2
+        assert r.exists("a") == 0
+        assert r.exists("b") == 0
+
+    def test_touch(self, r):
+        r.set("a", "1")
+        r.sadd("b", "x")
+        assert r.touch("a", "b", "ghost") == 2
+        assert r.get("a") == "1"
+
+    def test_copy(self, r):
+        r.set("src", "value", ex=100)
+        r.rpush("list", "a", "b")
+        r.hset("hash", mapping={"f": "v"})
+        r.sadd("set", "a")
+        r.zadd("zset", {"a": 1})
+        assert r.copy("src", "dst") == 1
+        assert r.get("dst") == "value"
+        assert r.ttl("dst") > 0
+        assert r.copy("list", "list:copy") == 1
+        assert r.lrange("list:copy", 0, -1) == ["a", "b"]
+        assert r.copy("hash", "hash:copy") == 1
+        assert r.hgetall("hash:copy") == {"f": "v"}
+        assert r.copy("set", "set:copy") == 1
+        assert r.smembers("set:copy") == {"a"}
+        assert r.copy("zset", "zset:copy") == 1
+        assert r.zrange("zset:copy", 0, -1, withscores=True) == [("a", 1.0)]
+
+    def test_copy_replace(self, r):
+        r.set("a", "1")
+        r.set("b", "2")
+        assert r.copy("a", "b") == 0
+        assert r.get("b") == "2"
+        assert r.copy("a", "b", replace=True) == 1
+        assert r.get("b") == "1"
+        assert r.copy("ghost", "c") == 0
+
     def test_del(self, r):
         r.set("a", "1")
         r.set("b", "2")
         r.delete("a", "b")
         assert r.get("a") is None
         assert r.get("b") is None
+
+
+# ── Server ────────────────────────────────────────────────────────────────────
+
+class TestServer:
+    def test_maxmemory_allkeys_lru_evicts(self, r):
+        try:
+            assert r.config_set("maxmemory-policy", "allkeys-lru") is True
+            # any dataset exceeds a single byte, so every write evicts
+            assert r.config_set("maxmemory", "1") is True
+            r.set("a", "1")
+            r.set("b", "2")
+            assert r.dbsize() == 0
+            info = r.info()
+            assert info["maxmemory_policy"] == "allkeys-lru"
+            assert info["evicted_keys"] >= 2
+        finally:
+            r.config_set("maxmemory", "0")
+            r.config_set("maxmemory-policy", "noeviction")
+
+    def test_hello(self, r):
+        # a flat list of pairs in RESP2
+        hello = r.execute_command("HELLO")
+        assert hello[hello.index("proto") + 1] == 2
+        assert hello[hello.index("server") + 1] == "redis"
+        with pytest.raises(redis.ResponseError):
+            r.execute_command("HELLO", 4)
+
+    def test_resp3_maps(self, r):
+        r3 = redis.Redis(host="localhost", port=6379, protocol=3, decode_responses=True)
+        r3.hset("hash", mapping={"f1": "v1", "f2": "v2"})
+        assert r3.hgetall("hash") == {"f1": "v1", "f2": "v2"}
+        assert r3.config_get("timeout") == {"timeout": "0"}
+        r3.zadd("zset", {"a": 5})
+        assert r3.zscore("zset", "a") == 5.0
+        assert r3.zmscore("zset", ["a", "b"]) == [5.0, None]
+        assert r3.get("missing") is None
+
+    def test_command_count_and_docs(self, r):
+        count = r.command_count()
+        assert count > 50
+        docs = r.execute_command("COMMAND", "DOCS", "get", "set", "nope")
+        assert docs == ["get", [], "set", []]
+        assert len(r.execute_command("COMMAND", "DOCS")) == count * 2
+
+    def test_config_get_and_set(self, r):
+        assert r.config_get("timeout") == {"timeout": "0"}
+        assert r.config_get("max*") == {
+            "maxmemory": "0",
+            "maxmemory-policy": "noeviction",
+        }
+        assert r.config_get("nope") == {}
+        try:
+            assert r.config_set("timeout", "30") is True
+            assert r.config_get("timeout") == {"timeout": "30"}
+        finally:
+            r.config_set("timeout", "0")
+
+    def test_config_resetstat(self, r):
+        r.set("a", "1")
+        r.get("a")
+        assert any(key.startswith("cmdstat_") for key in r.info())
+        assert r.config_resetstat() is True
+        assert not any(key.startswith("cmdstat_") for key in r.info())
+
+    def test_save_and_bgsave(self, r):
+        r.set("a", "1")
+        assert r.save() is True
+        assert r.bgsave() is True
+
+    def test_appendonly_and_bgrewriteaof(self, r):
+        with pytest.raises(redis.ResponseError):
+            r.bgrewriteaof()
+        r.config_set("appendonly", "yes")
+        try:
+            r.set("a", "1")
+            r.pexpireat("a", 2**42)
+            assert r.bgrewriteaof() is True
+        finally:
+            r.config_set("appendonly", "no")
+
+    def test_config_set_rejects_bad_values(self, r):
+        with pytest.raises(redis.ResponseError):
+            r.config_set("maxmemory", "lots")
+        with pytest.raises(redis.ResponseError):
+            r.config_set("maxmemory-policy", "volatile-lfu")
+        with pytest.raises(redis.ResponseError):
+            r.config_set("no-such-param", "1")
+
+
+# ── Transactions ──────────────────────────────────────────────────────────────
+
+class TestTransactions:
+    def test_multi_exec(self, r):
+        pipe = r.pipeline(transaction=True)
+        pipe.set("a", "1").incr("a").get("a")
+        assert pipe.execute() == [True, 2, "2"]
+
+    def test_discard(self, r):
+        assert r.execute_command("MULTI") == "OK"
+        assert r.execute_command("SET", "a", "1") == "QUEUED"
+        assert r.execute_command("DISCARD") == "OK"
+        assert r.get("a") is None
+        with pytest.raises(redis.ResponseError):
+            r.execute_command("EXEC")
+
+    def test_watch_aborts_on_write(self, r):
+        r.set("a", "1")
+        other = redis.Redis(host="localhost", port=6379, decode_responses=True)
+        with r.pipeline() as pipe:
+            pipe.watch("a")
+            other.set("a", "2")
+            pipe.multi()
+            pipe.set("a", "3")
+            with pytest.raises(redis.WatchError):
+                pipe.execute()
+        assert r.get("a") == "2"
+
+    def test_watch_deleted_key_counts_as_change(self, r):
+        r.set("a", "1")
+        other = redis.Redis(host="localhost", port=6379, decode_responses=True)
+        with r.pipeline() as pipe:
+            pipe.watch("a")
+            other.delete("a")
+            pipe.multi()
+            pipe.set("b", "1")
+            with pytest.raises(redis.WatchError):
+                pipe.execute()
+        assert r.get("b") is None
+
+    def test_unwatch(self, r):
+        r.set("a", "1")
+        other = redis.Redis(host="localhost", port=6379, decode_responses=True)
+        with r.pipeline() as pipe:
+            pipe.watch("a")
+            other.set("a", "2")
+            pipe.unwatch()
+            pipe.multi()
+            pipe.set("a", "3")
+            assert pipe.execute() == [True]
+        assert r.get("a") == "3"
+
+
+# ── Pub/Sub ───────────────────────────────────────────────────────────────────
+
+class TestPubSub:
+    def test_publish_reaches_subscribers(self, r):
+        p = r.pubsub()
+        p.subscribe("news", "sport")
+        assert p.get_message(timeout=1) == {
+            "type": "subscribe", "pattern": None, "channel": "news", "data": 1,
+        }
+        assert p.get_message(timeout=1)["data"] == 2
+        assert r.publish("news", "hello") == 1
+        assert r.publish("weather", "hello") == 0
+        message = p.get_message(timeout=1)
+        assert message["type"] == "message"
+        assert message["channel"] == "news"
+        assert message["data"] == "hello"
+        p.close()
+
+    def test_unsubscribe(self, r):
+        p = r.pubsub()
+        p.subscribe("news")
+        p.get_message(timeout=1)
+        p.unsubscribe("news")
+        assert p.get_message(timeout=1) == {
+            "type": "unsubscribe", "pattern": None, "channel": "news", "data": 0,
+        }
+        assert r.publish("news", "hello") == 0
+        p.close()
+
+    def test_psubscribe(self, r):
+        p = r.pubsub()
+        p.psubscribe("news.*")
+        p.subscribe("news.tech")
+        assert p.get_message(timeout=1)["type"] == "psubscribe"
+        assert p.get_message(timeout=1)["data"] == 2
+        # delivered once per matching subscription
+        assert r.publish("news.tech", "hello") == 2
+        assert r.publish("sport.tech", "hello") == 0
+        messages = [p.get_message(timeout=1) for _ in range(2)]
+        assert sorted(m["type"] for m in messages) == ["message", "pmessage"]
+        pmessage = next(m for m in messages if m["type"] == "pmessage")
+        assert pmessage["pattern"] == "news.*"
+        assert pmessage["channel"] == "news.tech"
+        p.punsubscribe("news.*")
+        assert p.get_message(timeout=1)["data"] == 1
+        p.close()
+
+    def test_keyspace_notifications(self, r):
+        try:
+            assert r.config_set("notify-keyspace-events", "KEA") is True
+            assert r.config_get("notify-keyspace-events") == {
+                "notify-keyspace-events": "AKE",
+            }
+            p = r.pubsub()
+            p.psubscribe("__key*__:*")
+            p.get_message(timeout=1)
+            r.set("a", "1")
+            r.delete("missing")
+            r.delete("a")
+            messages = [p.get_message(timeout=1) for _ in range(4)]
+            assert [(m["channel"], m["data"]) for m in messages] == [
+                ("__keyspace@0__:a", "set"),
+                ("__keyevent@0__:set", "a"),
+                ("__keyspace@0__:a", "del"),
+                ("__keyevent@0__:del", "a"),
+            ]
+            p.close()
+        finally:
+            r.config_set("notify-keyspace-events", "")
+
+    def test_subscribed_client_is_restricted(self, r):
+        conn = r.connection_pool.get_connection("SUBSCRIBE")
+        try:
+            conn.send_command("SUBSCRIBE", "news")
+            assert conn.read_response() == ["subscribe", "news", 1]
+            conn.send_command("GET", "a")
+            with pytest.raises(redis.ResponseError):
+                conn.read_response()
+            conn.send_command("PING")
+            assert conn.read_response() == ["pong", ""]
+        finally:
+            conn.disconnect()
+            r.connection_pool.release(conn)
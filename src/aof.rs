@@ -0,0 +1,501 @@
+use crate::cmd::owned::OwnedCommand;
+use crate::cmd::parser;
+use crate::cmd::{Aggregate, ExpiryOption, ScoreBound};
+use crate::err::RedisError;
+use crate::persistence::unix_now;
+use crate::stored_value::StoredValue;
+use anyhow::{Context, bail};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::Instant;
+
+/// Name of the log, in the working directory like the snapshot.
+pub const FILENAME: &str = "appendonly.aof";
+
+/// Append-only log of the write commands, in RESP, so replaying it rebuilds
+/// the dataset. Commands are logged so they replay the same way later:
+/// relative expirations become `PEXPIREAT` deadlines, and the server logs
+/// `SPOP` as the `SREM` of what it popped.
+#[derive(Debug, Default)]
+pub struct Aof {
+    /// The log being appended to, `None` while `appendonly` is off.
+    file: Option<File>,
+    /// Database the log's commands currently run in.
+    db_index: usize,
+}
+
+impl Aof {
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Replaces the log with the minimal commands recreating `dbs`, and keeps
+    /// appending to it. Also how logging starts, so the log never depends on
+    /// a snapshot.
+    pub fn rewrite(&mut self, dbs: &[HashMap<Bytes, StoredValue>]) -> anyhow::Result<()> {
+        let path = Path::new(FILENAME);
+        let tmp_path = path.with_extension("tmp");
+        let (log, db_index) = encode_dataset(dbs);
+        std::fs::write(&tmp_path, log)
+            .with_context(|| format!("can't write {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path).with_context(|| {
+            format!("can't rename {} to {}", tmp_path.display(), path.display())
+        })?;
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .with_context(|| format!("can't open {}", path.display()))?;
+        self.file = Some(file);
+        self.db_index = db_index;
+        Ok(())
+    }
+
+    /// Stops logging. The log is removed, since it'd miss every later write
+    /// and replaying it would bring back stale data.
+    pub fn disable(&mut self) -> std::io::Result<()> {
+        if self.file.take().is_some() {
+            std::fs::remove_file(FILENAME)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the appended commands to disk.
+    pub fn sync(&self) -> std::io::Result<()> {
+        match &self.file {
+            None => Ok(()),
+            Some(file) => file.sync_data(),
+        }
+    }
+
+    /// Appends a write run in database `db_index`, switching the log to that
+    /// database first if needed.
+    pub fn append(&mut self, db_index: usize, cmd: &OwnedCommand) -> std::io::Result<()> {
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+        let mut buf = Vec::new();
+        encode(&mut buf, &mut self.db_index, db_index, cmd);
+        file.write_all(&buf)
+    }
+}
+
+/// Encodes a write run in database `db_index` as the commands replaying it,
+/// selecting that database first if the reader runs its commands in another,
+/// `selected`.
+pub fn encode(buf: &mut Vec<u8>, selected: &mut usize, db_index: usize, cmd: &OwnedCommand) {
+    if !matches!(cmd, OwnedCommand::FlushAll) && db_index != *selected {
+        put_command(buf, &select(db_index));
+        *selected = db_index;
+    }
+    for args in command_args(cmd, now_millis()) {
+        put_command(buf, &args);
+    }
+}
+
+/// Splits the first command off `log`, returning it as the parser expects
+/// it, and the rest of the log.
+pub fn next_entry(log: &[u8]) -> anyhow::Result<(&[u8], &[u8])> {
+    // written by the server, so within any limit on what clients send
+    match parser::frame_len(log, usize::MAX) {
+        Ok(len) => Ok(log.split_at(len)),
+        Err(RedisError::IncompleteInput) => bail!("truncated entry"),
+        Err(e) => bail!("malformed entry: {e}"),
+    }
+}
+
+fn put_command(buf: &mut Vec<u8>, args: &[Bytes]) {
+    buf.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for arg in args {
+        buf.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        buf.extend_from_slice(arg);
+        buf.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Milliseconds since the Unix epoch, the unit of `PEXPIREAT` deadlines.
+fn now_millis() -> i64 {
+    unix_now().as_millis() as i64
+}
+
+fn number(n: impl ToString) -> Bytes {
+    Bytes::from(n.to_string())
+}
+
+fn command(name: &'static str, args: impl IntoIterator<Item = Bytes>) -> Vec<Bytes> {
+    std::iter::once(Bytes::from_static(name.as_bytes()))
+        .chain(args)
+        .collect()
+}
+
+fn with_key(key: &Bytes, values: &[Bytes]) -> impl Iterator<Item = Bytes> {
+    std::iter::once(key.clone()).chain(values.iter().cloned())
+}
+
+fn select(db_index: usize) -> Vec<Bytes> {
+    command("SELECT", [number(db_index)])
+}
+
+fn pexpire_at(key: &Bytes, unix_millis: i64) -> Vec<Bytes> {
+    command("PEXPIREAT", [key.clone(), number(unix_millis)])
+}
+
+fn score_bound(bound: &ScoreBound) -> Bytes {
+    match bound {
+        ScoreBound::Inclusive(score) => number(score),
+        ScoreBound::Exclusive(score) => Bytes::from(format!("({score}")),
+        ScoreBound::PosInf => Bytes::from_static(b"+inf"),
+        ScoreBound::NegInf => Bytes::from_static(b"-inf"),
+    }
+}
+
+fn zstore(
+    name: &'static str,
+    dst: &Bytes,
+    keys: &[Bytes],
+    weights: &Option<Vec<f64>>,
+    aggregate: &Aggregate,
+) -> Vec<Bytes> {
+    let mut args = command(name, [dst.clone(), number(keys.len())]);
+    args.extend(keys.iter().cloned());
+    if let Some(weights) = weights {
+        args.push(Bytes::from_static(b"WEIGHTS"));
+        args.extend(weights.iter().map(number));
+    }
+    let aggregate: &'static [u8] = match aggregate {
+        Aggregate::Sum => b"SUM",
+        Aggregate::Min => b"MIN",
+        Aggregate::Max => b"MAX",
+    };
+    args.extend([
+        Bytes::from_static(b"AGGREGATE"),
+        Bytes::from_static(aggregate),
+    ]);
+    args
+}
+
+/// The commands to log for `cmd`, with relative expirations turned into
+/// deadlines counted from `now_millis`. Reads leave nothing to log.
+fn command_args(cmd: &OwnedCommand, now_millis: i64) -> Vec<Vec<Bytes>> {
+    let deadline = |left: i64| now_millis.saturating_add(left);
+    let entry = match cmd {
+        OwnedCommand::Set(key, value, Some(ttl)) => {
+            return vec![
+                command("SET", [key.clone(), value.clone()]),
+                pexpire_at(key, deadline(ttl.as_millis() as i64)),
+            ];
+        }
+        OwnedCommand::Set(key, value, None) => command("SET", [key.clone(), value.clone()]),
+        OwnedCommand::Setnx(key, value) => command("SETNX", [key.clone(), value.clone()]),
+        OwnedCommand::SetNx(key, value) => command(
+            "SET",
+            [key.clone(), value.clone(), Bytes::from_static(b"NX")],
+        ),
+        OwnedCommand::SetXx(key, value) => command(
+            "SET",
+            [key.clone(), value.clone(), Bytes::from_static(b"XX")],
+        ),
+        OwnedCommand::SetAndGet(key, value) => command(
+            "SET",
+            [key.clone(), value.clone(), Bytes::from_static(b"GET")],
+        ),
+        OwnedCommand::SetKeepTtl(key, value) => command(
+            "SET",
+            [key.clone(), value.clone(), Bytes::from_static(b"KEEPTTL")],
+        ),
+        OwnedCommand::SetRange(key, offset, value) => {
+            command("SETRANGE", [key.clone(), number(offset), value.clone()])
+        }
+        OwnedCommand::GetDel(key) => command("DEL", [key.clone()]),
+        OwnedCommand::GetEx(key, expiry) => match expiry {
+            ExpiryOption::Keep => return vec![],
+            ExpiryOption::In(ttl) => pexpire_at(key, deadline(ttl.as_millis() as i64)),
+            ExpiryOption::At(unix_millis) => pexpire_at(key, *unix_millis),
+            // there's no command to only drop an expiry
+            ExpiryOption::Persist => {
+                unreachable!("GETEX PERSIST is logged as the SET of the value it read")
+            }
+        },
+        OwnedCommand::Lpush(key, values) => command("LPUSH", with_key(key, values)),
+        OwnedCommand::Rpush(key, values) => command("RPUSH", with_key(key, values)),
+        OwnedCommand::LpushX(key, values) => command("LPUSHX", with_key(key, values)),
+        OwnedCommand::RpushX(key, values) => command("RPUSHX", with_key(key, values)),
+        OwnedCommand::Lpop(key, count) => command(
+            "LPOP",
+            std::iter::once(key.clone()).chain(count.map(number)),
+        ),
+        OwnedCommand::Rpop(key, count) => command(
+            "RPOP",
+            std::iter::once(key.clone()).chain(count.map(number)),
+        ),
+        OwnedCommand::Del(keys) | OwnedCommand::Unlink(keys) => {
+            command("DEL", keys.iter().cloned())
+        }
+        OwnedCommand::Copy(src, dst, db, replace) => {
+            let mut args = command("COPY", [src.clone(), dst.clone()]);
+            if let Some(db) = db {
+                args.extend([Bytes::from_static(b"DB"), number(db)]);
+            }
+            if *replace {
+                args.push(Bytes::from_static(b"REPLACE"));
+            }
+            args
+        }
+        OwnedCommand::Incr(key) => command("INCR", [key.clone()]),
+        OwnedCommand::IncrBy(key, by) => command("INCRBY", [key.clone(), number(by)]),
+        OwnedCommand::IncrByFloat(key, by) => command("INCRBYFLOAT", [key.clone(), number(by)]),
+        OwnedCommand::Decr(key) => command("DECR", [key.clone()]),
+        OwnedCommand::DecrBy(key, by) => command("DECRBY", [key.clone(), number(by)]),
+        OwnedCommand::FlushDb => command("FLUSHDB", []),
+        OwnedCommand::FlushAll => command("FLUSHALL", []),
+        OwnedCommand::Move(key, db_index) => command("MOVE", [key.clone(), number(db_index)]),
+        OwnedCommand::Expire(key, seconds) => {
+            pexpire_at(key, deadline(seconds.saturating_mul(1000)))
+        }
+        OwnedCommand::Pexpire(key, millis) => pexpire_at(key, deadline(*millis)),
+        OwnedCommand::PexpireAt(key, unix_millis) => pexpire_at(key, *unix_millis),
+        OwnedCommand::Lset(key, index, value) => {
+            command("LSET", [key.clone(), number(index), value.clone()])
+        }
+        OwnedCommand::Lrem(key, count, value) => {
+            command("LREM", [key.clone(), number(count), value.clone()])
+        }
+        OwnedCommand::Linsert(key, before, pivot, value) => {
+            let position: &'static [u8] = if *before { b"BEFORE" } else { b"AFTER" };
+            command(
+                "LINSERT",
+                [
+                    key.clone(),
+                    Bytes::from_static(position),
+                    pivot.clone(),
+                    value.clone(),
+                ],
+            )
+        }
+        OwnedCommand::Hset(key, fields_and_values)
+        | OwnedCommand::HMset(key, fields_and_values) => {
+            command("HSET", with_key(key, fields_and_values))
+        }
+        OwnedCommand::HincrBy(key, field, by) => {
+            command("HINCRBY", [key.clone(), field.clone(), number(by)])
+        }
+        OwnedCommand::HincrByFloat(key, field, by) => {
+            command("HINCRBYFLOAT", [key.clone(), field.clone(), number(by)])
+        }
+        OwnedCommand::Sadd(key, members) => command("SADD", with_key(key, members)),
+        OwnedCommand::Srem(key, members) => command("SREM", with_key(key, members)),
+        OwnedCommand::Smove(src, dst, member) => {
+            command("SMOVE", [src.clone(), dst.clone(), member.clone()])
+        }
+        // random, so replaying it wouldn't pop the same members
+        OwnedCommand::Spop(..) => {
+            unreachable!("SPOP is logged as the SREM of the popped members")
+        }
+        OwnedCommand::Sinterstore(dst, keys) => command("SINTERSTORE", with_key(dst, keys)),
+        OwnedCommand::Sunionstore(dst, keys) => command("SUNIONSTORE", with_key(dst, keys)),
+        OwnedCommand::Sdiffstore(dst, keys) => command("SDIFFSTORE", with_key(dst, keys)),
+        OwnedCommand::Zadd(key, members) => command(
+            "ZADD",
+            std::iter::once(key.clone()).chain(
+                members
+                    .iter()
+                    .flat_map(|(score, member)| [number(score), member.clone()]),
+            ),
+        ),
+        OwnedCommand::Zrem(key, members) => command("ZREM", with_key(key, members)),
+        OwnedCommand::Zremrangebyrank(key, start, stop) => command(
+            "ZREMRANGEBYRANK",
+            [key.clone(), number(start), number(stop)],
+        ),
+        OwnedCommand::Zremrangebyscore(key, min, max) => command(
+            "ZREMRANGEBYSCORE",
+            [key.clone(), score_bound(min), score_bound(max)],
+        ),
+        OwnedCommand::Zincrby(key, by, member) => {
+            command("ZINCRBY", [key.clone(), number(by), member.clone()])
+        }
+        OwnedCommand::Zunionstore(dst, keys, weights, aggregate) => {
+            zstore("ZUNIONSTORE", dst, keys, weights, aggregate)
+        }
+        OwnedCommand::Zinterstore(dst, keys, weights, aggregate) => {
+            zstore("ZINTERSTORE", dst, keys, weights, aggregate)
+        }
+        _ => return vec![],
+    };
+    vec![entry]
+}
+
+/// The commands recreating `key` holding `value`.
+fn value_args(key: &Bytes, value: &StoredValue, now: Instant, now_millis: i64) -> Vec<Vec<Bytes>> {
+    match value {
+        StoredValue::Plain(bytes) => vec![command("SET", [key.clone(), bytes.clone()])],
+        StoredValue::TtlPlain(bytes, deadline) => {
+            let left = deadline.saturating_duration_since(now).as_millis() as i64;
+            vec![
+                command("SET", [key.clone(), bytes.clone()]),
+                pexpire_at(key, now_millis + left),
+            ]
+        }
+        StoredValue::List(items) => {
+            vec![command(
+                "RPUSH",
+                std::iter::once(key.clone()).chain(items.iter().cloned()),
+            )]
+        }
+        StoredValue::Dict(fields) => vec![command(
+            "HSET",
+            std::iter::once(key.clone()).chain(
+                fields
+                    .iter()
+                    .flat_map(|(field, value)| [field.clone(), value.clone()]),
+            ),
+        )],
+        StoredValue::Set(members) => {
+            vec![command(
+                "SADD",
+                std::iter::once(key.clone()).chain(members.iter().cloned()),
+            )]
+        }
+        StoredValue::SortedSet(tree, _) => vec![command(
+            "ZADD",
+            std::iter::once(key.clone()).chain(
+                tree.iter()
+                    .flat_map(|(score, member)| [number(score), member.clone()]),
+            ),
+        )],
+    }
+}
+
+/// Encodes the commands recreating `dbs`, returning them with the database
+/// they leave selected.
+fn encode_dataset(dbs: &[HashMap<Bytes, StoredValue>]) -> (Vec<u8>, usize) {
+    let (now, now_millis) = (Instant::now(), now_millis());
+    let mut buf = Vec::new();
+    let mut selected = 0;
+    for (db_index, hmap) in dbs.iter().enumerate() {
+        if hmap.is_empty() {
+            continue;
+        }
+        if db_index != selected {
+            put_command(&mut buf, &select(db_index));
+            selected = db_index;
+        }
+        for (key, value) in hmap {
+            for args in value_args(key, value, now, now_millis) {
+                put_command(&mut buf, &args);
+            }
+        }
+    }
+    (buf, selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::parser::parse;
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    fn bytes(s: &'static str) -> Bytes {
+        Bytes::from_static(s.as_bytes())
+    }
+
+    fn encoded(cmd: &OwnedCommand, now_millis: i64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for args in command_args(cmd, now_millis) {
+            put_command(&mut buf, &args);
+        }
+        buf
+    }
+
+    #[test]
+    fn test_logged_commands_parse_back() {
+        let cmds = [
+            OwnedCommand::Set(bytes("k"), bytes("v"), None),
+            OwnedCommand::Lpop(bytes("k"), Some(2)),
+            OwnedCommand::Linsert(bytes("k"), false, bytes("p"), bytes("v")),
+            OwnedCommand::IncrByFloat(bytes("k"), 0.1),
+            OwnedCommand::Zremrangebyscore(
+                bytes("k"),
+                ScoreBound::Exclusive(1.5),
+                ScoreBound::PosInf,
+            ),
+            OwnedCommand::Zunionstore(
+                bytes("d"),
+                vec![bytes("a"), bytes("b")],
+                Some(vec![2.0, 0.5]),
+                Aggregate::Max,
+            ),
+            OwnedCommand::Copy(bytes("a"), bytes("b"), None, true),
+            OwnedCommand::Copy(bytes("a"), bytes("b"), Some(3), false),
+        ];
+        for cmd in cmds {
+            let log = encoded(&cmd, 0);
+            assert_eq!(parse(&log, usize::MAX).unwrap().0.to_owned(), cmd);
+        }
+        assert!(command_args(&OwnedCommand::Get(bytes("k")), 0).is_empty());
+    }
+
+    #[test]
+    fn test_expirations_logged_as_deadlines() {
+        let log = encoded(&OwnedCommand::Expire(bytes("k"), 10), 1_000);
+        assert_eq!(
+            parse(&log, usize::MAX).unwrap().0.to_owned(),
+            OwnedCommand::PexpireAt(bytes("k"), 11_000)
+        );
+
+        let set = OwnedCommand::Set(bytes("k"), bytes("v"), Some(Duration::from_millis(5)));
+        let log = encoded(&set, 1_000);
+        let (first, rest) = next_entry(&log).unwrap();
+        assert_eq!(
+            parse(first, usize::MAX).unwrap().0.to_owned(),
+            OwnedCommand::Set(bytes("k"), bytes("v"), None)
+        );
+        assert_eq!(
+            parse(rest, usize::MAX).unwrap().0.to_owned(),
+            OwnedCommand::PexpireAt(bytes("k"), 1_005)
+        );
+    }
+
+    #[test]
+    fn test_next_entry() {
+        let mut log = encoded(&OwnedCommand::Del(vec![bytes("a"), bytes("b")]), 0);
+        log.extend(encoded(&OwnedCommand::FlushAll, 0));
+        let (first, rest) = next_entry(&log).unwrap();
+        assert_eq!(
+            parse(first, usize::MAX).unwrap().0.to_owned(),
+            OwnedCommand::Del(vec![bytes("a"), bytes("b")])
+        );
+        assert_eq!(next_entry(rest).unwrap(), (rest, [].as_slice()));
+
+        // a torn write at the end
+        assert!(next_entry(&rest[..rest.len() - 3]).is_err());
+        assert!(next_entry(b"*1\r\n$3\r\nDE").is_err());
+        assert!(next_entry(b"*x\r\n").is_err());
+    }
+
+    #[test]
+    fn test_encode_dataset() {
+        let mut dbs = vec![HashMap::new(); 3];
+        dbs[2].insert(
+            bytes("s"),
+            StoredValue::Set(HashSet::from([bytes("a"), bytes("b")])),
+        );
+        let (log, selected) = encode_dataset(&dbs);
+        assert_eq!(selected, 2);
+
+        let (first, rest) = next_entry(&log).unwrap();
+        assert_eq!(
+            parse(first, usize::MAX).unwrap().0.to_owned(),
+            OwnedCommand::Select(2)
+        );
+        let OwnedCommand::Sadd(key, mut members) = parse(rest, usize::MAX).unwrap().0.to_owned()
+        else {
+            panic!("expected SADD");
+        };
+        members.sort();
+        assert_eq!((key, members), (bytes("s"), vec![bytes("a"), bytes("b")]));
+    }
+}
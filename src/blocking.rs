@@ -0,0 +1,243 @@
+use crate::list_ops::{HMapListOps, Popped};
+use crate::stored_value::StoredValue;
+use bytes::Bytes;
+use mio::Token;
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+/// The end of a list `BLPOP` and `BRPOP` pop from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum End {
+    Front,
+    Back,
+}
+
+impl End {
+    /// The keyspace event of a pop from this end, as `LPOP`/`RPOP` raise it.
+    pub fn event(self) -> &'static str {
+        match self {
+            End::Front => "lpop",
+            End::Back => "rpop",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Blocked {
+    db_index: usize,
+    keys: Vec<Bytes>,
+    end: End,
+    /// `None` to wait for as long as it takes.
+    deadline: Option<Instant>,
+}
+
+/// Clients blocked by `BLPOP`/`BRPOP` until one of their lists has an element
+/// or their timeout fires. Those waiting on the same list are served in the
+/// order they blocked.
+#[derive(Debug, Default)]
+pub struct Blocking {
+    blocked: HashMap<Token, Blocked>,
+    waiting: HashMap<(usize, Bytes), VecDeque<Token>>,
+    /// Lists written to while clients wait on them, to serve once the
+    /// command that wrote them is done.
+    ready: Vec<(usize, Bytes)>,
+    /// Clients let go, whose buffered commands can run again.
+    resumed: Vec<Token>,
+}
+
+impl Blocking {
+    pub fn block(
+        &mut self,
+        token: Token,
+        db_index: usize,
+        keys: &[&[u8]],
+        end: End,
+        deadline: Option<Instant>,
+    ) {
+        let keys: Vec<Bytes> = keys.iter().map(|key| Bytes::copy_from_slice(key)).collect();
+        for key in &keys {
+            let queue = self.waiting.entry((db_index, key.clone())).or_default();
+            // a key given twice still makes one place in line
+            if !queue.contains(&token) {
+                queue.push_back(token);
+            }
+        }
+        self.blocked.insert(
+            token,
+            Blocked {
+                db_index,
+                keys,
+                end,
+                deadline,
+            },
+        );
+    }
+
+    pub fn is_blocked(&self, token: Token) -> bool {
+        self.blocked.contains_key(&token)
+    }
+
+    /// How many clients are blocked.
+    pub fn count(&self) -> usize {
+        self.blocked.len()
+    }
+
+    /// Forgets a blocked client, as when it disconnects. Returns whether it
+    /// was blocked.
+    pub fn remove(&mut self, token: Token) -> bool {
+        let Some(blocked) = self.blocked.remove(&token) else {
+            return false;
+        };
+        for key in blocked.keys {
+            let entry = (blocked.db_index, key);
+            if let Some(queue) = self.waiting.get_mut(&entry) {
+                queue.retain(|&waiter| waiter != token);
+                if queue.is_empty() {
+                    self.waiting.remove(&entry);
+                }
+            }
+        }
+        true
+    }
+
+    /// Lets a client go once it got its reply, so its buffered commands run.
+    pub fn unblock(&mut self, token: Token) {
+        if self.remove(token) {
+            self.resumed.push(token);
+        }
+    }
+
+    /// Notes that a command wrote `key`, which may give its waiters an
+    /// element to pop.
+    pub fn key_written(&mut self, db_index: usize, key: &[u8]) {
+        if self.blocked.is_empty() {
+            return;
+        }
+        let entry = (db_index, Bytes::copy_from_slice(key));
+        if self.waiting.contains_key(&entry) && !self.ready.contains(&entry) {
+            self.ready.push(entry);
+        }
+    }
+
+    pub fn take_ready(&mut self) -> Vec<(usize, Bytes)> {
+        std::mem::take(&mut self.ready)
+    }
+
+    pub fn take_resumed(&mut self) -> Vec<Token> {
+        std::mem::take(&mut self.resumed)
+    }
+
+    /// The client first in line for `key`, and the end it pops from.
+    pub fn waiter(&self, db_index: usize, key: &Bytes) -> Option<(Token, End)> {
+        let token = *self.waiting.get(&(db_index, key.clone()))?.front()?;
+        Some((token, self.blocked[&token].end))
+    }
+
+    /// The nearest timeout, for the event loop to wake up in time.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.blocked
+            .values()
+            .filter_map(|blocked| blocked.deadline)
+            .min()
+    }
+
+    /// The clients whose timeout fired by `now`.
+    pub fn timed_out(&self, now: Instant) -> Vec<Token> {
+        self.blocked
+            .iter()
+            .filter(|(_, blocked)| blocked.deadline.is_some_and(|deadline| deadline <= now))
+            .map(|(token, _)| *token)
+            .collect()
+    }
+}
+
+/// Pops from the first of `keys` holding a non-empty list, as `BLPOP` and
+/// `BRPOP` do before blocking. Fails on a key holding another type.
+pub fn pop_first<'k>(
+    hmap: &mut HashMap<Bytes, StoredValue>,
+    keys: &[&'k [u8]],
+    end: End,
+) -> anyhow::Result<Option<(&'k [u8], Bytes)>> {
+    for key in keys {
+        let popped = match end {
+            End::Front => hmap.pop_front(key, None)?,
+            End::Back => hmap.pop_back(key, None)?,
+        };
+        if let Popped::Single(value) = popped {
+            return Ok(Some((key, value)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_waiters_in_order() {
+        let mut blocking = Blocking::default();
+        let list = Bytes::from_static(b"l");
+        blocking.block(Token(1), 0, &[b"l", b"l"], End::Front, None);
+        blocking.block(Token(2), 0, &[b"other", b"l"], End::Back, None);
+        assert_eq!(blocking.count(), 2);
+        assert_eq!(blocking.waiter(0, &list), Some((Token(1), End::Front)));
+        assert_eq!(blocking.waiter(1, &list), None);
+
+        blocking.key_written(0, b"l");
+        blocking.key_written(0, b"l");
+        blocking.key_written(0, b"nobody");
+        assert_eq!(blocking.take_ready(), [(0, list.clone())]);
+
+        blocking.unblock(Token(1));
+        assert_eq!(blocking.waiter(0, &list), Some((Token(2), End::Back)));
+        assert_eq!(blocking.take_resumed(), [Token(1)]);
+
+        // a disconnected client isn't resumed
+        assert!(blocking.remove(Token(2)));
+        assert_eq!(blocking.waiter(0, &list), None);
+        assert!(blocking.take_resumed().is_empty());
+        assert!(blocking.waiting.is_empty());
+    }
+
+    #[test]
+    fn test_timed_out() {
+        let mut blocking = Blocking::default();
+        let now = Instant::now();
+        blocking.block(Token(1), 0, &[b"a"], End::Front, None);
+        blocking.block(Token(2), 0, &[b"a"], End::Front, Some(now));
+        blocking.block(
+            Token(3),
+            0,
+            &[b"a"],
+            End::Front,
+            Some(now + Duration::from_secs(1)),
+        );
+        assert_eq!(blocking.next_deadline(), Some(now));
+        assert_eq!(blocking.timed_out(now), [Token(2)]);
+    }
+
+    #[test]
+    fn test_pop_first() {
+        let mut hmap = HashMap::new();
+        hmap.insert(
+            Bytes::from_static(b"l"),
+            StoredValue::List(VecDeque::from([
+                Bytes::from_static(b"x"),
+                Bytes::from_static(b"y"),
+            ])),
+        );
+        hmap.insert(
+            Bytes::from_static(b"s"),
+            StoredValue::Plain(Bytes::from_static(b"v")),
+        );
+        let popped = pop_first(&mut hmap, &[b"missing", b"l"], End::Back).unwrap();
+        assert_eq!(popped, Some((b"l".as_slice(), Bytes::from_static(b"y"))));
+        assert_eq!(
+            pop_first(&mut hmap, &[b"missing"], End::Front).unwrap(),
+            None
+        );
+        assert!(pop_first(&mut hmap, &[b"s", b"l"], End::Front).is_err());
+    }
+}
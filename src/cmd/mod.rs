@@ -1,68 +1,293 @@
 use std::fmt::Debug;
 use std::time::Duration;
 
+pub mod owned;
 pub mod parser;
 
 use compact_str::CompactString;
 
 pub const GET: CompactString = CompactString::const_new("get");
 pub const SET: CompactString = CompactString::const_new("set");
+pub const STRLEN: CompactString = CompactString::const_new("strlen");
+pub const GETRANGE: CompactString = CompactString::const_new("getrange");
+pub const SETRANGE: CompactString = CompactString::const_new("setrange");
+pub const GETDEL: CompactString = CompactString::const_new("getdel");
+pub const GETEX: CompactString = CompactString::const_new("getex");
+pub const SETNX: CompactString = CompactString::const_new("setnx");
+pub const WAIT: CompactString = CompactString::const_new("wait");
+pub const REPLICAOF: CompactString = CompactString::const_new("replicaof");
+pub const PSYNC: CompactString = CompactString::const_new("psync");
 pub const PING: CompactString = CompactString::const_new("ping");
 pub const FLUSHDB: CompactString = CompactString::const_new("flushdb");
+pub const FLUSHALL: CompactString = CompactString::const_new("flushall");
+pub const SELECT: CompactString = CompactString::const_new("select");
+pub const MOVE: CompactString = CompactString::const_new("move");
+pub const OBJECT: CompactString = CompactString::const_new("object");
 pub const DOCS: CompactString = CompactString::const_new("docs");
+pub const COMMAND: CompactString = CompactString::const_new("command");
+pub const HELLO: CompactString = CompactString::const_new("hello");
+pub const AUTH: CompactString = CompactString::const_new("auth");
+pub const ACL: CompactString = CompactString::const_new("acl");
+pub const RESET: CompactString = CompactString::const_new("reset");
+pub const DEBUG: CompactString = CompactString::const_new("debug");
 pub const DBSIZE: CompactString = CompactString::const_new("dbsize");
 pub const CONFIG: CompactString = CompactString::const_new("config");
 pub const LPUSH: CompactString = CompactString::const_new("lpush");
 pub const RPUSH: CompactString = CompactString::const_new("rpush");
+pub const LPUSHX: CompactString = CompactString::const_new("lpushx");
+pub const RPUSHX: CompactString = CompactString::const_new("rpushx");
 pub const LPOP: CompactString = CompactString::const_new("lpop");
 pub const RPOP: CompactString = CompactString::const_new("rpop");
+pub const BLPOP: CompactString = CompactString::const_new("blpop");
+pub const BRPOP: CompactString = CompactString::const_new("brpop");
 pub const DEL: CompactString = CompactString::const_new("del");
+pub const COPY: CompactString = CompactString::const_new("copy");
+pub const UNLINK: CompactString = CompactString::const_new("unlink");
+pub const TOUCH: CompactString = CompactString::const_new("touch");
 pub const INCR: CompactString = CompactString::const_new("incr");
+pub const INCRBYFLOAT: CompactString = CompactString::const_new("incrbyfloat");
+pub const DECR: CompactString = CompactString::const_new("decr");
+pub const DECRBY: CompactString = CompactString::const_new("decrby");
+pub const KEYS: CompactString = CompactString::const_new("keys");
+pub const HSCAN: CompactString = CompactString::const_new("hscan");
+pub const SSCAN: CompactString = CompactString::const_new("sscan");
+pub const ZSCAN: CompactString = CompactString::const_new("zscan");
 pub const CLIENT: CompactString = CompactString::const_new("client");
 pub const TTL: CompactString = CompactString::const_new("ttl");
+pub const PTTL: CompactString = CompactString::const_new("pttl");
+pub const EXPIRE: CompactString = CompactString::const_new("expire");
+pub const PEXPIRE: CompactString = CompactString::const_new("pexpire");
+pub const PEXPIREAT: CompactString = CompactString::const_new("pexpireat");
 pub const LRANGE: CompactString = CompactString::const_new("lrange");
 pub const LLEN: CompactString = CompactString::const_new("llen");
+pub const LINDEX: CompactString = CompactString::const_new("lindex");
+pub const LSET: CompactString = CompactString::const_new("lset");
+pub const LREM: CompactString = CompactString::const_new("lrem");
+pub const LINSERT: CompactString = CompactString::const_new("linsert");
+pub const LPOS: CompactString = CompactString::const_new("lpos");
 pub const HGET: CompactString = CompactString::const_new("hget");
+pub const HSET: CompactString = CompactString::const_new("hset");
 pub const HMGET: CompactString = CompactString::const_new("hmget");
 pub const HMSET: CompactString = CompactString::const_new("hmset");
 pub const HINCRBY: CompactString = CompactString::const_new("hincrby");
+pub const HINCRBYFLOAT: CompactString = CompactString::const_new("hincrbyfloat");
 pub const EXISTS: CompactString = CompactString::const_new("exists");
 pub const HEXISTS: CompactString = CompactString::const_new("hexists");
 pub const HKEYS: CompactString = CompactString::const_new("hkeys");
+pub const HRANDFIELD: CompactString = CompactString::const_new("hrandfield");
 pub const SADD: CompactString = CompactString::const_new("sadd");
+pub const SREM: CompactString = CompactString::const_new("srem");
+pub const SMOVE: CompactString = CompactString::const_new("smove");
+pub const SPOP: CompactString = CompactString::const_new("spop");
+pub const SRANDMEMBER: CompactString = CompactString::const_new("srandmember");
 pub const SISMEMBER: CompactString = CompactString::const_new("sismember");
+pub const SMISMEMBER: CompactString = CompactString::const_new("smismember");
 pub const SINTER: CompactString = CompactString::const_new("sinter");
 pub const SUNION: CompactString = CompactString::const_new("sunion");
 pub const SDIFF: CompactString = CompactString::const_new("sdiff");
+pub const SINTERSTORE: CompactString = CompactString::const_new("sinterstore");
+pub const SUNIONSTORE: CompactString = CompactString::const_new("sunionstore");
+pub const SDIFFSTORE: CompactString = CompactString::const_new("sdiffstore");
 pub const SCARD: CompactString = CompactString::const_new("scard");
 pub const SMEMBERS: CompactString = CompactString::const_new("smembers");
 pub const ZADD: CompactString = CompactString::const_new("zadd");
+pub const ZREM: CompactString = CompactString::const_new("zrem");
 pub const ZRANGE: CompactString = CompactString::const_new("zrange");
 pub const ZREVRANGE: CompactString = CompactString::const_new("zrevrange");
 pub const ZRANK: CompactString = CompactString::const_new("zrank");
 pub const ZREVRANK: CompactString = CompactString::const_new("zrevrank");
 pub const ZSCORE: CompactString = CompactString::const_new("zscore");
+pub const ZMSCORE: CompactString = CompactString::const_new("zmscore");
 pub const ZRANGEBYSCORE: CompactString = CompactString::const_new("zrangebyscore");
+pub const ZRANGEBYLEX: CompactString = CompactString::const_new("zrangebylex");
+pub const ZREMRANGEBYRANK: CompactString = CompactString::const_new("zremrangebyrank");
+pub const ZREMRANGEBYSCORE: CompactString = CompactString::const_new("zremrangebyscore");
+pub const ZUNIONSTORE: CompactString = CompactString::const_new("zunionstore");
+pub const ZINTERSTORE: CompactString = CompactString::const_new("zinterstore");
 pub const ZINCRBY: CompactString = CompactString::const_new("zincrby");
 pub const ZCARD: CompactString = CompactString::const_new("zcard");
 pub const INFO: CompactString = CompactString::const_new("info");
 pub const LATENCY: CompactString = CompactString::const_new("latency");
+pub const MULTI: CompactString = CompactString::const_new("multi");
+pub const EXEC: CompactString = CompactString::const_new("exec");
+pub const DISCARD: CompactString = CompactString::const_new("discard");
+pub const WATCH: CompactString = CompactString::const_new("watch");
+pub const UNWATCH: CompactString = CompactString::const_new("unwatch");
+pub const SUBSCRIBE: CompactString = CompactString::const_new("subscribe");
+pub const UNSUBSCRIBE: CompactString = CompactString::const_new("unsubscribe");
+pub const PSUBSCRIBE: CompactString = CompactString::const_new("psubscribe");
+pub const PUNSUBSCRIBE: CompactString = CompactString::const_new("punsubscribe");
+pub const PUBLISH: CompactString = CompactString::const_new("publish");
+pub const SAVE: CompactString = CompactString::const_new("save");
+pub const BGSAVE: CompactString = CompactString::const_new("bgsave");
+pub const BGREWRITEAOF: CompactString = CompactString::const_new("bgrewriteaof");
+pub const SHUTDOWN: CompactString = CompactString::const_new("shutdown");
+/// Latency buckets of the commands that never ran: those naming no known
+/// command, and those rejected with an error before running.
+pub const UNKNOWN: CompactString = CompactString::const_new("unknown");
+pub const ERROR: CompactString = CompactString::const_new("error");
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum Info<'a> {
-    LibName(&'a [u8]),
-    LibVersion(&'a [u8]),
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Info<B> {
+    LibName(B),
+    LibVersion(B),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl<B> Info<B> {
+    pub fn map<'s, C>(&'s self, f: impl FnOnce(&'s B) -> C) -> Info<C> {
+        match self {
+            Info::LibName(name) => Info::LibName(f(name)),
+            Info::LibVersion(version) => Info::LibVersion(f(version)),
+        }
+    }
+}
+
+/// The `CLIENT` subcommands.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ClientSub<B> {
+    SetName(B),
+    GetName,
+    Id,
+    List,
+    /// `CLIENT INFO`, the `CLIENT LIST` line of the client itself.
+    Info,
+    SetInfo(Info<B>),
+    NoEvict(bool),
+    /// `CLIENT NO-TOUCH`: whether the client's commands leave the idle time
+    /// of keys alone, but for `TOUCH`.
+    NoTouch(bool),
+}
+
+impl<B> ClientSub<B> {
+    pub fn map<'s, C>(&'s self, f: impl FnOnce(&'s B) -> C) -> ClientSub<C> {
+        match self {
+            ClientSub::SetName(name) => ClientSub::SetName(f(name)),
+            ClientSub::GetName => ClientSub::GetName,
+            ClientSub::Id => ClientSub::Id,
+            ClientSub::List => ClientSub::List,
+            ClientSub::Info => ClientSub::Info,
+            ClientSub::SetInfo(info) => ClientSub::SetInfo(info.map(f)),
+            ClientSub::NoEvict(on) => ClientSub::NoEvict(*on),
+            ClientSub::NoTouch(on) => ClientSub::NoTouch(*on),
+        }
+    }
+}
+
+/// The `DEBUG` subcommands, there for test suites and tooling.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DebugSub<B> {
+    /// Blocks the whole server for the duration.
+    Sleep(Duration),
+    /// Turns the sweep of expired keys on or off, leaving only the lazy
+    /// expiry of keys as they're read.
+    SetActiveExpire(bool),
+    /// How the value of the key is stored.
+    Object(B),
+}
+
+impl<B> DebugSub<B> {
+    pub fn map<'s, C>(&'s self, f: impl FnOnce(&'s B) -> C) -> DebugSub<C> {
+        match self {
+            DebugSub::Sleep(duration) => DebugSub::Sleep(*duration),
+            DebugSub::SetActiveExpire(on) => DebugSub::SetActiveExpire(*on),
+            DebugSub::Object(key) => DebugSub::Object(f(key)),
+        }
+    }
+}
+
+/// The `ACL` subcommands, which only report on the `default` user so far.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AclSub {
+    WhoAmI,
+    List,
+}
+
+/// A `min`/`max` argument of a score range, e.g. `5`, `(5`, `-inf` or `+inf`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+    PosInf,
+    NegInf,
+}
+
+/// A `min`/`max` argument of a lexicographic range: `[a`, `(a`, `-` or `+`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LexBound<B> {
+    Inclusive(B),
+    Exclusive(B),
+    Min,
+    Max,
+}
+
+impl<B> LexBound<B> {
+    pub fn map<'s, C>(&'s self, f: impl FnOnce(&'s B) -> C) -> LexBound<C> {
+        match self {
+            LexBound::Inclusive(bound) => LexBound::Inclusive(f(bound)),
+            LexBound::Exclusive(bound) => LexBound::Exclusive(f(bound)),
+            LexBound::Min => LexBound::Min,
+            LexBound::Max => LexBound::Max,
+        }
+    }
+}
+
+/// How `ZUNIONSTORE`/`ZINTERSTORE` combine the scores of a member found in
+/// several inputs.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Aggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+/// What `GETEX` does to the expiry of the key it reads.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExpiryOption {
+    /// Leaves the expiry as it is.
+    Keep,
+    /// `EX`/`PX`: expires that long from now.
+    In(Duration),
+    /// `EXAT`/`PXAT`: expires at a Unix time, in milliseconds.
+    At(i64),
+    /// Removes the expiry.
+    Persist,
+}
+
+#[derive(Debug, PartialEq)]
 pub enum Command<'a> {
     Ping,
+    /// `HELLO [protover [AUTH username password] [SETNAME clientname]]`.
+    Hello(Option<u8>, Option<(&'a [u8], &'a [u8])>, Option<&'a [u8]>),
+    /// `AUTH password`, as the default user.
+    Auth(&'a [u8]),
+    /// `AUTH username password`.
+    AuthUser(&'a [u8], &'a [u8]),
+    Acl(AclSub),
+    /// `RESET`: the connection goes back to the state it started in.
+    Reset,
+    Debug(DebugSub<&'a [u8]>),
     Docs,
+    CmdCount,
+    CmdDocs(Vec<&'a [u8]>),
+    /// `COMMAND GETKEYS`, with the keys of the command it was given.
+    CmdGetKeys(Vec<&'a [u8]>),
     DbSize,
     Config,
+    ConfigGet(&'a [u8]),
+    ConfigSet(&'a [u8], &'a [u8]),
+    ConfigResetStat,
     Get(&'a [u8]),
+    Strlen(&'a [u8]),
+    GetRange(&'a [u8], isize, isize),
+    SetRange(&'a [u8], usize, &'a [u8]),
+    GetDel(&'a [u8]),
+    GetEx(&'a [u8], ExpiryOption),
     Set(&'a [u8], &'a [u8], Option<Duration>),
     SetNx(&'a [u8], &'a [u8]),
+    /// `SETNX`, which replies whether it set the key where `SET ... NX`
+    /// replies `OK` or nil.
+    Setnx(&'a [u8], &'a [u8]),
     SetXx(&'a [u8], &'a [u8]),
     SetAndGet(&'a [u8], &'a [u8]),
     SetKeepTtl(&'a [u8], &'a [u8]),
@@ -72,39 +297,394 @@ pub enum Command<'a> {
     RpushX(&'a [u8], Vec<&'a [u8]>),
     Lpop(&'a [u8], Option<usize>),
     Rpop(&'a [u8], Option<usize>),
+    /// `BLPOP`: the keys and the timeout, zero to wait for as long as it
+    /// takes.
+    Blpop(Vec<&'a [u8]>, Duration),
+    Brpop(Vec<&'a [u8]>, Duration),
     Lrange(&'a [u8], isize, isize),
     Del(Vec<&'a [u8]>),
+    Unlink(Vec<&'a [u8]>),
+    Touch(Vec<&'a [u8]>),
+    /// `COPY source destination [DB index] [REPLACE]`.
+    Copy(&'a [u8], &'a [u8], Option<usize>, bool),
     Incr(&'a [u8]),
     IncrBy(&'a [u8], i64),
+    IncrByFloat(&'a [u8], f64),
+    Decr(&'a [u8]),
+    DecrBy(&'a [u8], i64),
+    Keys(&'a [u8]),
+    Hscan(&'a [u8], u64, Option<&'a [u8]>, Option<usize>),
+    Sscan(&'a [u8], u64, Option<&'a [u8]>, Option<usize>),
+    Zscan(&'a [u8], u64, Option<&'a [u8]>, Option<usize>),
     FlushDb,
-    ClientSetInfo(Info<'a>),
-    ClientSetName,
+    FlushAll,
+    Select(usize),
+    /// `WAIT numreplicas timeout`. A stub until replication exists: no
+    /// replica can acknowledge, so it always replies `0` right away.
+    Wait(usize, u64),
+    /// `REPLICAOF host port`, or `None` for `REPLICAOF NO ONE`.
+    ReplicaOf(Option<(&'a [u8], u16)>),
+    /// `PSYNC`, which always gets a full resync whatever it asks for.
+    Psync,
+    Move(&'a [u8], usize),
+    ObjectRefcount(&'a [u8]),
+    ObjectIdletime(&'a [u8]),
+    Client(ClientSub<&'a [u8]>),
     Ttl(&'a [u8]),
+    Pttl(&'a [u8]),
+    Expire(&'a [u8], i64),
+    Pexpire(&'a [u8], i64),
+    /// Deadline in milliseconds since the Unix epoch.
+    PexpireAt(&'a [u8], i64),
     LLen(&'a [u8]),
+    Lindex(&'a [u8], isize),
+    Lset(&'a [u8], isize, &'a [u8]),
+    Lrem(&'a [u8], i64, &'a [u8]),
+    Linsert(&'a [u8], bool, &'a [u8], &'a [u8]),
+    Lpos(&'a [u8], &'a [u8], Option<i64>, Option<usize>),
     Hget(&'a [u8], &'a [u8]),
+    Hset(&'a [u8], Vec<&'a [u8]>),
     HMget(&'a [u8], Vec<&'a [u8]>),
     HMset(&'a [u8], Vec<&'a [u8]>),
     HgetAll(&'a [u8]),
     HincrBy(&'a [u8], &'a [u8], i64),
-    Exists(&'a [u8]),
+    HincrByFloat(&'a [u8], &'a [u8], f64),
+    Exists(Vec<&'a [u8]>),
     Hexists(&'a [u8], &'a [u8]),
     Hkeys(&'a [u8]),
+    Hrandfield(&'a [u8], Option<i64>, bool),
     Sadd(&'a [u8], Vec<&'a [u8]>),
+    Srem(&'a [u8], Vec<&'a [u8]>),
+    Smove(&'a [u8], &'a [u8], &'a [u8]),
+    Spop(&'a [u8], Option<usize>),
+    Srandmember(&'a [u8], Option<i64>),
     Sismember(&'a [u8], &'a [u8]),
+    Smismember(&'a [u8], Vec<&'a [u8]>),
     Sinter(Vec<&'a [u8]>),
     Sunion(Vec<&'a [u8]>),
     Sdiff(Vec<&'a [u8]>),
+    Sinterstore(&'a [u8], Vec<&'a [u8]>),
+    Sunionstore(&'a [u8], Vec<&'a [u8]>),
+    Sdiffstore(&'a [u8], Vec<&'a [u8]>),
     Scard(&'a [u8]),
     Smembers(&'a [u8]),
     Zadd(&'a [u8], Vec<(i64, &'a [u8])>),
+    Zrem(&'a [u8], Vec<&'a [u8]>),
     Zrange(&'a [u8], isize, isize, bool),
     Zrevrange(&'a [u8], isize, isize, bool),
     Zrank(&'a [u8], &'a [u8]),
     Zrevrank(&'a [u8], &'a [u8]),
     Zscore(&'a [u8], &'a [u8]),
-    Zrangebyscore(&'a [u8], i64, i64, bool),
+    Zmscore(&'a [u8], Vec<&'a [u8]>),
+    Zrangebyscore(&'a [u8], ScoreBound, ScoreBound, bool),
+    Zrangebylex(&'a [u8], LexBound<&'a [u8]>, LexBound<&'a [u8]>),
+    Zremrangebyrank(&'a [u8], isize, isize),
+    Zremrangebyscore(&'a [u8], ScoreBound, ScoreBound),
+    Zunionstore(&'a [u8], Vec<&'a [u8]>, Option<Vec<f64>>, Aggregate),
+    Zinterstore(&'a [u8], Vec<&'a [u8]>, Option<Vec<f64>>, Aggregate),
     Zincrby(&'a [u8], i64, &'a [u8]),
     Zcard(&'a [u8]),
-    InfoCmd,
+    /// `INFO`, with the section asked for in lowercase.
+    InfoCmd(Option<CompactString>),
     LatencyHistogram(Vec<&'a [u8]>),
+    Multi,
+    Exec,
+    Discard,
+    Watch(Vec<&'a [u8]>),
+    Unwatch,
+    Subscribe(Vec<&'a [u8]>),
+    Unsubscribe(Vec<&'a [u8]>),
+    PSubscribe(Vec<&'a [u8]>),
+    PUnsubscribe(Vec<&'a [u8]>),
+    Publish(&'a [u8], &'a [u8]),
+    Save,
+    Bgsave,
+    Bgrewriteaof,
+    /// Whether to save first, `None` to follow the `save` config.
+    Shutdown(Option<bool>),
+}
+
+impl<'a> Command<'a> {
+    /// Keys the command reads or writes, in argument order.
+    pub fn keys(&self) -> Vec<&'a [u8]> {
+        match self {
+            Command::Ping
+            | Command::Hello(..)
+            | Command::Debug(DebugSub::Sleep(_) | DebugSub::SetActiveExpire(_))
+            | Command::Reset
+            | Command::Acl(_)
+            | Command::Auth(_)
+            | Command::AuthUser(..)
+            | Command::Docs
+            | Command::CmdCount
+            | Command::CmdDocs(_)
+            | Command::CmdGetKeys(_)
+            | Command::DbSize
+            | Command::Config
+            | Command::ConfigGet(_)
+            | Command::ConfigSet(..)
+            | Command::ConfigResetStat
+            | Command::Keys(_)
+            | Command::FlushDb
+            | Command::FlushAll
+            | Command::Select(_)
+            | Command::Wait(..)
+            | Command::ReplicaOf(_)
+            | Command::Psync
+            | Command::Client(_)
+            | Command::InfoCmd(_)
+            | Command::LatencyHistogram(_)
+            | Command::Multi
+            | Command::Exec
+            | Command::Discard
+            | Command::Unwatch
+            | Command::Subscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::PSubscribe(_)
+            | Command::PUnsubscribe(_)
+            | Command::Publish(..)
+            | Command::Save
+            | Command::Bgsave
+            | Command::Bgrewriteaof
+            | Command::Shutdown(_) => vec![],
+            Command::Get(key)
+            | Command::Strlen(key)
+            | Command::GetRange(key, ..)
+            | Command::SetRange(key, ..)
+            | Command::GetDel(key)
+            | Command::GetEx(key, _)
+            | Command::Set(key, ..)
+            | Command::SetNx(key, _)
+            | Command::Setnx(key, _)
+            | Command::SetXx(key, _)
+            | Command::SetAndGet(key, _)
+            | Command::SetKeepTtl(key, _)
+            | Command::Lpush(key, _)
+            | Command::Rpush(key, _)
+            | Command::LpushX(key, _)
+            | Command::RpushX(key, _)
+            | Command::Lpop(key, _)
+            | Command::Rpop(key, _)
+            | Command::Lrange(key, ..)
+            | Command::Incr(key)
+            | Command::IncrBy(key, _)
+            | Command::IncrByFloat(key, _)
+            | Command::Decr(key)
+            | Command::DecrBy(key, _)
+            | Command::Hscan(key, ..)
+            | Command::Sscan(key, ..)
+            | Command::Zscan(key, ..)
+            | Command::Move(key, _)
+            | Command::ObjectRefcount(key)
+            | Command::ObjectIdletime(key)
+            | Command::Debug(DebugSub::Object(key))
+            | Command::Ttl(key)
+            | Command::Pttl(key)
+            | Command::Expire(key, _)
+            | Command::Pexpire(key, _)
+            | Command::PexpireAt(key, _)
+            | Command::LLen(key)
+            | Command::Lindex(key, _)
+            | Command::Lset(key, ..)
+            | Command::Lrem(key, ..)
+            | Command::Linsert(key, ..)
+            | Command::Lpos(key, ..)
+            | Command::Hget(key, _)
+            | Command::Hset(key, _)
+            | Command::HMget(key, _)
+            | Command::HMset(key, _)
+            | Command::HgetAll(key)
+            | Command::HincrBy(key, ..)
+            | Command::HincrByFloat(key, ..)
+            | Command::Hexists(key, _)
+            | Command::Hkeys(key)
+            | Command::Hrandfield(key, ..)
+            | Command::Sadd(key, _)
+            | Command::Srem(key, _)
+            | Command::Spop(key, _)
+            | Command::Srandmember(key, _)
+            | Command::Sismember(key, _)
+            | Command::Smismember(key, _)
+            | Command::Scard(key)
+            | Command::Smembers(key)
+            | Command::Zadd(key, _)
+            | Command::Zrem(key, _)
+            | Command::Zrange(key, ..)
+            | Command::Zrevrange(key, ..)
+            | Command::Zrank(key, _)
+            | Command::Zrevrank(key, _)
+            | Command::Zscore(key, _)
+            | Command::Zmscore(key, _)
+            | Command::Zrangebyscore(key, ..)
+            | Command::Zrangebylex(key, ..)
+            | Command::Zremrangebyrank(key, ..)
+            | Command::Zremrangebyscore(key, ..)
+            | Command::Zincrby(key, ..)
+            | Command::Zcard(key) => vec![*key],
+            Command::Copy(src, dst, ..) | Command::Smove(src, dst, _) => vec![*src, *dst],
+            Command::Del(keys)
+            | Command::Unlink(keys)
+            | Command::Touch(keys)
+            | Command::Exists(keys)
+            | Command::Sinter(keys)
+            | Command::Sunion(keys)
+            | Command::Sdiff(keys)
+            | Command::Blpop(keys, _)
+            | Command::Brpop(keys, _)
+            | Command::Watch(keys) => keys.clone(),
+            Command::Sinterstore(dst, keys)
+            | Command::Sunionstore(dst, keys)
+            | Command::Sdiffstore(dst, keys)
+            | Command::Zunionstore(dst, keys, ..)
+            | Command::Zinterstore(dst, keys, ..) => {
+                std::iter::once(*dst).chain(keys.iter().copied()).collect()
+            }
+        }
+    }
+
+    /// Keys the command may modify. Flushes name no keys, they touch whole
+    /// databases.
+    pub fn written_keys(&self) -> Vec<&'a [u8]> {
+        match self {
+            Command::Ping
+            | Command::Hello(..)
+            | Command::Debug(_)
+            | Command::Reset
+            | Command::Acl(_)
+            | Command::Auth(_)
+            | Command::AuthUser(..)
+            | Command::Docs
+            | Command::CmdCount
+            | Command::CmdDocs(_)
+            | Command::CmdGetKeys(_)
+            | Command::DbSize
+            | Command::Config
+            | Command::ConfigGet(_)
+            | Command::ConfigSet(..)
+            | Command::ConfigResetStat
+            | Command::Get(_)
+            | Command::GetEx(_, ExpiryOption::Keep)
+            | Command::Strlen(_)
+            | Command::GetRange(..)
+            | Command::Lrange(..)
+            | Command::Touch(_)
+            | Command::Keys(_)
+            | Command::Hscan(..)
+            | Command::Sscan(..)
+            | Command::Zscan(..)
+            | Command::FlushDb
+            | Command::FlushAll
+            | Command::Select(_)
+            | Command::Wait(..)
+            | Command::ReplicaOf(_)
+            | Command::Psync
+            | Command::ObjectRefcount(_)
+            | Command::ObjectIdletime(_)
+            | Command::Client(_)
+            | Command::Ttl(_)
+            | Command::Pttl(_)
+            | Command::LLen(_)
+            | Command::Lindex(..)
+            | Command::Lpos(..)
+            | Command::Hget(..)
+            | Command::HMget(..)
+            | Command::HgetAll(_)
+            | Command::Exists(_)
+            | Command::Hexists(..)
+            | Command::Hkeys(_)
+            | Command::Hrandfield(..)
+            | Command::Srandmember(..)
+            | Command::Sismember(..)
+            | Command::Smismember(..)
+            | Command::Sinter(_)
+            | Command::Sunion(_)
+            | Command::Sdiff(_)
+            | Command::Scard(_)
+            | Command::Smembers(_)
+            | Command::Zrange(..)
+            | Command::Zrevrange(..)
+            | Command::Zrank(..)
+            | Command::Zrevrank(..)
+            | Command::Zscore(..)
+            | Command::Zmscore(..)
+            | Command::Zrangebyscore(..)
+            | Command::Zrangebylex(..)
+            | Command::Zcard(_)
+            | Command::InfoCmd(_)
+            | Command::LatencyHistogram(_)
+            | Command::Multi
+            | Command::Exec
+            | Command::Discard
+            | Command::Watch(_)
+            | Command::Unwatch
+            | Command::Subscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::PSubscribe(_)
+            | Command::PUnsubscribe(_)
+            | Command::Publish(..)
+            | Command::Save
+            | Command::Bgsave
+            | Command::Bgrewriteaof
+            | Command::Shutdown(_) => vec![],
+            Command::SetRange(key, ..)
+            | Command::GetDel(key)
+            | Command::GetEx(key, _)
+            | Command::Set(key, ..)
+            | Command::SetNx(key, _)
+            | Command::Setnx(key, _)
+            | Command::SetXx(key, _)
+            | Command::SetAndGet(key, _)
+            | Command::SetKeepTtl(key, _)
+            | Command::Lpush(key, _)
+            | Command::Rpush(key, _)
+            | Command::LpushX(key, _)
+            | Command::RpushX(key, _)
+            | Command::Lpop(key, _)
+            | Command::Rpop(key, _)
+            | Command::Incr(key)
+            | Command::IncrBy(key, _)
+            | Command::IncrByFloat(key, _)
+            | Command::Decr(key)
+            | Command::DecrBy(key, _)
+            | Command::Move(key, _)
+            | Command::Expire(key, _)
+            | Command::Pexpire(key, _)
+            | Command::PexpireAt(key, _)
+            | Command::Lset(key, ..)
+            | Command::Lrem(key, ..)
+            | Command::Linsert(key, ..)
+            | Command::Hset(key, _)
+            | Command::HMset(key, _)
+            | Command::HincrBy(key, ..)
+            | Command::HincrByFloat(key, ..)
+            | Command::Sadd(key, _)
+            | Command::Srem(key, _)
+            | Command::Spop(key, _)
+            | Command::Zadd(key, _)
+            | Command::Zrem(key, _)
+            | Command::Zremrangebyrank(key, ..)
+            | Command::Zremrangebyscore(key, ..)
+            | Command::Zincrby(key, ..)
+            | Command::Copy(_, key, None, _)
+            | Command::Sinterstore(key, _)
+            | Command::Sunionstore(key, _)
+            | Command::Sdiffstore(key, _)
+            | Command::Zunionstore(key, ..)
+            | Command::Zinterstore(key, ..) => vec![*key],
+            Command::Smove(src, dst, _) => vec![*src, *dst],
+            // a copy into a database named by index is written where it
+            // runs, as the database isn't known here
+            Command::Copy(_, _, Some(_), _) => vec![],
+            Command::Del(keys)
+            | Command::Unlink(keys)
+            | Command::Blpop(keys, _)
+            | Command::Brpop(keys, _) => keys.clone(),
+        }
+    }
+
+    /// Whether the command can change the dataset, by writing keys or
+    /// flushing databases.
+    pub fn is_write(&self) -> bool {
+        matches!(self, Command::FlushDb | Command::FlushAll) || !self.written_keys().is_empty()
+    }
 }
@@ -0,0 +1,590 @@
+use crate::cmd::{
+    AclSub, Aggregate, ClientSub, Command, DebugSub, ExpiryOption, LexBound, ScoreBound,
+};
+use bytes::Bytes;
+use compact_str::CompactString;
+use std::time::Duration;
+
+/// A `Command` that owns its arguments, so it can outlive the read buffer
+/// it was parsed from, e.g. to be queued by a transaction or logged. The hot
+/// path keeps using the borrowed form and only converts when it has to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OwnedCommand {
+    Ping,
+    Hello(Option<u8>, Option<(Bytes, Bytes)>, Option<Bytes>),
+    Auth(Bytes),
+    AuthUser(Bytes, Bytes),
+    Acl(AclSub),
+    Reset,
+    Debug(DebugSub<Bytes>),
+    Docs,
+    CmdCount,
+    CmdDocs(Vec<Bytes>),
+    CmdGetKeys(Vec<Bytes>),
+    DbSize,
+    Config,
+    ConfigGet(Bytes),
+    ConfigSet(Bytes, Bytes),
+    ConfigResetStat,
+    Get(Bytes),
+    Strlen(Bytes),
+    GetRange(Bytes, isize, isize),
+    SetRange(Bytes, usize, Bytes),
+    GetDel(Bytes),
+    GetEx(Bytes, ExpiryOption),
+    Set(Bytes, Bytes, Option<Duration>),
+    SetNx(Bytes, Bytes),
+    Setnx(Bytes, Bytes),
+    SetXx(Bytes, Bytes),
+    SetAndGet(Bytes, Bytes),
+    SetKeepTtl(Bytes, Bytes),
+    Lpush(Bytes, Vec<Bytes>),
+    Rpush(Bytes, Vec<Bytes>),
+    LpushX(Bytes, Vec<Bytes>),
+    RpushX(Bytes, Vec<Bytes>),
+    Lpop(Bytes, Option<usize>),
+    Rpop(Bytes, Option<usize>),
+    Blpop(Vec<Bytes>, Duration),
+    Brpop(Vec<Bytes>, Duration),
+    Lrange(Bytes, isize, isize),
+    Del(Vec<Bytes>),
+    Unlink(Vec<Bytes>),
+    Touch(Vec<Bytes>),
+    Copy(Bytes, Bytes, Option<usize>, bool),
+    Incr(Bytes),
+    IncrBy(Bytes, i64),
+    IncrByFloat(Bytes, f64),
+    Decr(Bytes),
+    DecrBy(Bytes, i64),
+    Keys(Bytes),
+    Hscan(Bytes, u64, Option<Bytes>, Option<usize>),
+    Sscan(Bytes, u64, Option<Bytes>, Option<usize>),
+    Zscan(Bytes, u64, Option<Bytes>, Option<usize>),
+    FlushDb,
+    FlushAll,
+    Select(usize),
+    Wait(usize, u64),
+    ReplicaOf(Option<(Bytes, u16)>),
+    Psync,
+    Move(Bytes, usize),
+    ObjectRefcount(Bytes),
+    ObjectIdletime(Bytes),
+    Client(ClientSub<Bytes>),
+    Ttl(Bytes),
+    Pttl(Bytes),
+    Expire(Bytes, i64),
+    Pexpire(Bytes, i64),
+    PexpireAt(Bytes, i64),
+    LLen(Bytes),
+    Lindex(Bytes, isize),
+    Lset(Bytes, isize, Bytes),
+    Lrem(Bytes, i64, Bytes),
+    Linsert(Bytes, bool, Bytes, Bytes),
+    Lpos(Bytes, Bytes, Option<i64>, Option<usize>),
+    Hget(Bytes, Bytes),
+    Hset(Bytes, Vec<Bytes>),
+    HMget(Bytes, Vec<Bytes>),
+    HMset(Bytes, Vec<Bytes>),
+    HgetAll(Bytes),
+    HincrBy(Bytes, Bytes, i64),
+    HincrByFloat(Bytes, Bytes, f64),
+    Exists(Vec<Bytes>),
+    Hexists(Bytes, Bytes),
+    Hkeys(Bytes),
+    Hrandfield(Bytes, Option<i64>, bool),
+    Sadd(Bytes, Vec<Bytes>),
+    Srem(Bytes, Vec<Bytes>),
+    Smove(Bytes, Bytes, Bytes),
+    Spop(Bytes, Option<usize>),
+    Srandmember(Bytes, Option<i64>),
+    Sismember(Bytes, Bytes),
+    Smismember(Bytes, Vec<Bytes>),
+    Sinter(Vec<Bytes>),
+    Sunion(Vec<Bytes>),
+    Sdiff(Vec<Bytes>),
+    Sinterstore(Bytes, Vec<Bytes>),
+    Sunionstore(Bytes, Vec<Bytes>),
+    Sdiffstore(Bytes, Vec<Bytes>),
+    Scard(Bytes),
+    Smembers(Bytes),
+    Zadd(Bytes, Vec<(i64, Bytes)>),
+    Zrem(Bytes, Vec<Bytes>),
+    Zrange(Bytes, isize, isize, bool),
+    Zrevrange(Bytes, isize, isize, bool),
+    Zrank(Bytes, Bytes),
+    Zrevrank(Bytes, Bytes),
+    Zscore(Bytes, Bytes),
+    Zmscore(Bytes, Vec<Bytes>),
+    Zrangebyscore(Bytes, ScoreBound, ScoreBound, bool),
+    Zrangebylex(Bytes, LexBound<Bytes>, LexBound<Bytes>),
+    Zremrangebyrank(Bytes, isize, isize),
+    Zremrangebyscore(Bytes, ScoreBound, ScoreBound),
+    Zunionstore(Bytes, Vec<Bytes>, Option<Vec<f64>>, Aggregate),
+    Zinterstore(Bytes, Vec<Bytes>, Option<Vec<f64>>, Aggregate),
+    Zincrby(Bytes, i64, Bytes),
+    Zcard(Bytes),
+    InfoCmd(Option<CompactString>),
+    LatencyHistogram(Vec<Bytes>),
+    Multi,
+    Exec,
+    Discard,
+    Watch(Vec<Bytes>),
+    Unwatch,
+    Subscribe(Vec<Bytes>),
+    Unsubscribe(Vec<Bytes>),
+    PSubscribe(Vec<Bytes>),
+    PUnsubscribe(Vec<Bytes>),
+    Publish(Bytes, Bytes),
+    Save,
+    Bgsave,
+    Bgrewriteaof,
+    Shutdown(Option<bool>),
+}
+
+fn owned_all(values: &[&[u8]]) -> Vec<Bytes> {
+    values.iter().map(|v| Bytes::copy_from_slice(v)).collect()
+}
+
+fn borrowed_all(values: &[Bytes]) -> Vec<&[u8]> {
+    values.iter().map(Bytes::as_ref).collect()
+}
+
+impl Command<'_> {
+    pub fn to_owned(&self) -> OwnedCommand {
+        match self {
+            Command::Ping => OwnedCommand::Ping,
+            Command::Reset => OwnedCommand::Reset,
+            Command::Debug(a) => OwnedCommand::Debug(a.map(|key| Bytes::copy_from_slice(key))),
+            Command::Hello(a, b, c) => OwnedCommand::Hello(
+                *a,
+                b.map(|(user, password)| {
+                    (
+                        Bytes::copy_from_slice(user),
+                        Bytes::copy_from_slice(password),
+                    )
+                }),
+                c.map(Bytes::copy_from_slice),
+            ),
+            Command::Auth(a) => OwnedCommand::Auth(Bytes::copy_from_slice(a)),
+            Command::Acl(a) => OwnedCommand::Acl(*a),
+            Command::AuthUser(a, b) => {
+                OwnedCommand::AuthUser(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::Docs => OwnedCommand::Docs,
+            Command::CmdCount => OwnedCommand::CmdCount,
+            Command::CmdDocs(a) => OwnedCommand::CmdDocs(owned_all(a)),
+            Command::CmdGetKeys(a) => OwnedCommand::CmdGetKeys(owned_all(a)),
+            Command::DbSize => OwnedCommand::DbSize,
+            Command::Config => OwnedCommand::Config,
+            Command::ConfigGet(a) => OwnedCommand::ConfigGet(Bytes::copy_from_slice(a)),
+            Command::ConfigSet(a, b) => {
+                OwnedCommand::ConfigSet(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::ConfigResetStat => OwnedCommand::ConfigResetStat,
+            Command::Get(a) => OwnedCommand::Get(Bytes::copy_from_slice(a)),
+            Command::Strlen(a) => OwnedCommand::Strlen(Bytes::copy_from_slice(a)),
+            Command::GetRange(a, b, c) => OwnedCommand::GetRange(Bytes::copy_from_slice(a), *b, *c),
+            Command::SetRange(a, b, c) => {
+                OwnedCommand::SetRange(Bytes::copy_from_slice(a), *b, Bytes::copy_from_slice(c))
+            }
+            Command::GetDel(a) => OwnedCommand::GetDel(Bytes::copy_from_slice(a)),
+            Command::GetEx(a, b) => OwnedCommand::GetEx(Bytes::copy_from_slice(a), *b),
+            Command::Set(a, b, c) => {
+                OwnedCommand::Set(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b), *c)
+            }
+            Command::SetNx(a, b) => {
+                OwnedCommand::SetNx(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::Setnx(a, b) => {
+                OwnedCommand::Setnx(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::SetXx(a, b) => {
+                OwnedCommand::SetXx(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::SetAndGet(a, b) => {
+                OwnedCommand::SetAndGet(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::SetKeepTtl(a, b) => {
+                OwnedCommand::SetKeepTtl(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::Lpush(a, b) => OwnedCommand::Lpush(Bytes::copy_from_slice(a), owned_all(b)),
+            Command::Rpush(a, b) => OwnedCommand::Rpush(Bytes::copy_from_slice(a), owned_all(b)),
+            Command::LpushX(a, b) => OwnedCommand::LpushX(Bytes::copy_from_slice(a), owned_all(b)),
+            Command::RpushX(a, b) => OwnedCommand::RpushX(Bytes::copy_from_slice(a), owned_all(b)),
+            Command::Lpop(a, b) => OwnedCommand::Lpop(Bytes::copy_from_slice(a), *b),
+            Command::Rpop(a, b) => OwnedCommand::Rpop(Bytes::copy_from_slice(a), *b),
+            Command::Blpop(a, b) => OwnedCommand::Blpop(owned_all(a), *b),
+            Command::Brpop(a, b) => OwnedCommand::Brpop(owned_all(a), *b),
+            Command::Lrange(a, b, c) => OwnedCommand::Lrange(Bytes::copy_from_slice(a), *b, *c),
+            Command::Del(a) => OwnedCommand::Del(owned_all(a)),
+            Command::Unlink(a) => OwnedCommand::Unlink(owned_all(a)),
+            Command::Touch(a) => OwnedCommand::Touch(owned_all(a)),
+            Command::Copy(a, b, c, d) => {
+                OwnedCommand::Copy(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b), *c, *d)
+            }
+            Command::Incr(a) => OwnedCommand::Incr(Bytes::copy_from_slice(a)),
+            Command::IncrBy(a, b) => OwnedCommand::IncrBy(Bytes::copy_from_slice(a), *b),
+            Command::IncrByFloat(a, b) => OwnedCommand::IncrByFloat(Bytes::copy_from_slice(a), *b),
+            Command::Decr(a) => OwnedCommand::Decr(Bytes::copy_from_slice(a)),
+            Command::DecrBy(a, b) => OwnedCommand::DecrBy(Bytes::copy_from_slice(a), *b),
+            Command::Keys(a) => OwnedCommand::Keys(Bytes::copy_from_slice(a)),
+            Command::Hscan(a, b, c, d) => OwnedCommand::Hscan(
+                Bytes::copy_from_slice(a),
+                *b,
+                c.map(Bytes::copy_from_slice),
+                *d,
+            ),
+            Command::Sscan(a, b, c, d) => OwnedCommand::Sscan(
+                Bytes::copy_from_slice(a),
+                *b,
+                c.map(Bytes::copy_from_slice),
+                *d,
+            ),
+            Command::Zscan(a, b, c, d) => OwnedCommand::Zscan(
+                Bytes::copy_from_slice(a),
+                *b,
+                c.map(Bytes::copy_from_slice),
+                *d,
+            ),
+            Command::FlushDb => OwnedCommand::FlushDb,
+            Command::FlushAll => OwnedCommand::FlushAll,
+            Command::Select(a) => OwnedCommand::Select(*a),
+            Command::Wait(a, b) => OwnedCommand::Wait(*a, *b),
+            Command::ReplicaOf(a) => {
+                OwnedCommand::ReplicaOf(a.map(|(host, port)| (Bytes::copy_from_slice(host), port)))
+            }
+            Command::Psync => OwnedCommand::Psync,
+            Command::Move(a, b) => OwnedCommand::Move(Bytes::copy_from_slice(a), *b),
+            Command::ObjectRefcount(a) => OwnedCommand::ObjectRefcount(Bytes::copy_from_slice(a)),
+            Command::ObjectIdletime(a) => OwnedCommand::ObjectIdletime(Bytes::copy_from_slice(a)),
+            Command::Client(a) => {
+                OwnedCommand::Client(a.map(|bytes| Bytes::copy_from_slice(bytes)))
+            }
+            Command::Ttl(a) => OwnedCommand::Ttl(Bytes::copy_from_slice(a)),
+            Command::Pttl(a) => OwnedCommand::Pttl(Bytes::copy_from_slice(a)),
+            Command::Expire(a, b) => OwnedCommand::Expire(Bytes::copy_from_slice(a), *b),
+            Command::Pexpire(a, b) => OwnedCommand::Pexpire(Bytes::copy_from_slice(a), *b),
+            Command::PexpireAt(a, b) => OwnedCommand::PexpireAt(Bytes::copy_from_slice(a), *b),
+            Command::LLen(a) => OwnedCommand::LLen(Bytes::copy_from_slice(a)),
+            Command::Lindex(a, b) => OwnedCommand::Lindex(Bytes::copy_from_slice(a), *b),
+            Command::Lset(a, b, c) => {
+                OwnedCommand::Lset(Bytes::copy_from_slice(a), *b, Bytes::copy_from_slice(c))
+            }
+            Command::Lrem(a, b, c) => {
+                OwnedCommand::Lrem(Bytes::copy_from_slice(a), *b, Bytes::copy_from_slice(c))
+            }
+            Command::Linsert(a, b, c, d) => OwnedCommand::Linsert(
+                Bytes::copy_from_slice(a),
+                *b,
+                Bytes::copy_from_slice(c),
+                Bytes::copy_from_slice(d),
+            ),
+            Command::Lpos(a, b, c, d) => {
+                OwnedCommand::Lpos(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b), *c, *d)
+            }
+            Command::Hget(a, b) => {
+                OwnedCommand::Hget(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::HMget(a, b) => OwnedCommand::HMget(Bytes::copy_from_slice(a), owned_all(b)),
+            Command::Hset(a, b) => OwnedCommand::Hset(Bytes::copy_from_slice(a), owned_all(b)),
+            Command::HMset(a, b) => OwnedCommand::HMset(Bytes::copy_from_slice(a), owned_all(b)),
+            Command::HgetAll(a) => OwnedCommand::HgetAll(Bytes::copy_from_slice(a)),
+            Command::HincrBy(a, b, c) => {
+                OwnedCommand::HincrBy(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b), *c)
+            }
+            Command::HincrByFloat(a, b, c) => {
+                OwnedCommand::HincrByFloat(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b), *c)
+            }
+            Command::Exists(a) => OwnedCommand::Exists(owned_all(a)),
+            Command::Hexists(a, b) => {
+                OwnedCommand::Hexists(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::Hkeys(a) => OwnedCommand::Hkeys(Bytes::copy_from_slice(a)),
+            Command::Hrandfield(a, b, c) => {
+                OwnedCommand::Hrandfield(Bytes::copy_from_slice(a), *b, *c)
+            }
+            Command::Sadd(a, b) => OwnedCommand::Sadd(Bytes::copy_from_slice(a), owned_all(b)),
+            Command::Srem(a, b) => OwnedCommand::Srem(Bytes::copy_from_slice(a), owned_all(b)),
+            Command::Smove(a, b, c) => OwnedCommand::Smove(
+                Bytes::copy_from_slice(a),
+                Bytes::copy_from_slice(b),
+                Bytes::copy_from_slice(c),
+            ),
+            Command::Spop(a, b) => OwnedCommand::Spop(Bytes::copy_from_slice(a), *b),
+            Command::Srandmember(a, b) => OwnedCommand::Srandmember(Bytes::copy_from_slice(a), *b),
+            Command::Sismember(a, b) => {
+                OwnedCommand::Sismember(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::Smismember(a, b) => {
+                OwnedCommand::Smismember(Bytes::copy_from_slice(a), owned_all(b))
+            }
+            Command::Sinter(a) => OwnedCommand::Sinter(owned_all(a)),
+            Command::Sunion(a) => OwnedCommand::Sunion(owned_all(a)),
+            Command::Sdiff(a) => OwnedCommand::Sdiff(owned_all(a)),
+            Command::Sinterstore(a, b) => {
+                OwnedCommand::Sinterstore(Bytes::copy_from_slice(a), owned_all(b))
+            }
+            Command::Sunionstore(a, b) => {
+                OwnedCommand::Sunionstore(Bytes::copy_from_slice(a), owned_all(b))
+            }
+            Command::Sdiffstore(a, b) => {
+                OwnedCommand::Sdiffstore(Bytes::copy_from_slice(a), owned_all(b))
+            }
+            Command::Scard(a) => OwnedCommand::Scard(Bytes::copy_from_slice(a)),
+            Command::Smembers(a) => OwnedCommand::Smembers(Bytes::copy_from_slice(a)),
+            Command::Zadd(a, b) => OwnedCommand::Zadd(
+                Bytes::copy_from_slice(a),
+                b.iter()
+                    .map(|(score, member)| (*score, Bytes::copy_from_slice(member)))
+                    .collect(),
+            ),
+            Command::Zrem(a, b) => OwnedCommand::Zrem(Bytes::copy_from_slice(a), owned_all(b)),
+            Command::Zrange(a, b, c, d) => {
+                OwnedCommand::Zrange(Bytes::copy_from_slice(a), *b, *c, *d)
+            }
+            Command::Zrevrange(a, b, c, d) => {
+                OwnedCommand::Zrevrange(Bytes::copy_from_slice(a), *b, *c, *d)
+            }
+            Command::Zrank(a, b) => {
+                OwnedCommand::Zrank(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::Zrevrank(a, b) => {
+                OwnedCommand::Zrevrank(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::Zscore(a, b) => {
+                OwnedCommand::Zscore(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::Zmscore(a, b) => {
+                OwnedCommand::Zmscore(Bytes::copy_from_slice(a), owned_all(b))
+            }
+            Command::Zrangebyscore(a, b, c, d) => {
+                OwnedCommand::Zrangebyscore(Bytes::copy_from_slice(a), *b, *c, *d)
+            }
+            Command::Zrangebylex(a, b, c) => OwnedCommand::Zrangebylex(
+                Bytes::copy_from_slice(a),
+                b.map(|bytes| Bytes::copy_from_slice(bytes)),
+                c.map(|bytes| Bytes::copy_from_slice(bytes)),
+            ),
+            Command::Zremrangebyrank(a, b, c) => {
+                OwnedCommand::Zremrangebyrank(Bytes::copy_from_slice(a), *b, *c)
+            }
+            Command::Zremrangebyscore(a, b, c) => {
+                OwnedCommand::Zremrangebyscore(Bytes::copy_from_slice(a), *b, *c)
+            }
+            Command::Zunionstore(a, b, c, d) => {
+                OwnedCommand::Zunionstore(Bytes::copy_from_slice(a), owned_all(b), c.clone(), *d)
+            }
+            Command::Zinterstore(a, b, c, d) => {
+                OwnedCommand::Zinterstore(Bytes::copy_from_slice(a), owned_all(b), c.clone(), *d)
+            }
+            Command::Zincrby(a, b, c) => {
+                OwnedCommand::Zincrby(Bytes::copy_from_slice(a), *b, Bytes::copy_from_slice(c))
+            }
+            Command::Zcard(a) => OwnedCommand::Zcard(Bytes::copy_from_slice(a)),
+            Command::InfoCmd(a) => OwnedCommand::InfoCmd(a.clone()),
+            Command::LatencyHistogram(a) => OwnedCommand::LatencyHistogram(owned_all(a)),
+            Command::Multi => OwnedCommand::Multi,
+            Command::Exec => OwnedCommand::Exec,
+            Command::Discard => OwnedCommand::Discard,
+            Command::Watch(a) => OwnedCommand::Watch(owned_all(a)),
+            Command::Unwatch => OwnedCommand::Unwatch,
+            Command::Subscribe(a) => OwnedCommand::Subscribe(owned_all(a)),
+            Command::Unsubscribe(a) => OwnedCommand::Unsubscribe(owned_all(a)),
+            Command::PSubscribe(a) => OwnedCommand::PSubscribe(owned_all(a)),
+            Command::PUnsubscribe(a) => OwnedCommand::PUnsubscribe(owned_all(a)),
+            Command::Publish(a, b) => {
+                OwnedCommand::Publish(Bytes::copy_from_slice(a), Bytes::copy_from_slice(b))
+            }
+            Command::Save => OwnedCommand::Save,
+            Command::Bgsave => OwnedCommand::Bgsave,
+            Command::Bgrewriteaof => OwnedCommand::Bgrewriteaof,
+            Command::Shutdown(a) => OwnedCommand::Shutdown(*a),
+        }
+    }
+}
+
+impl OwnedCommand {
+    /// Borrows the arguments back into a `Command` for the regular handlers.
+    pub fn as_command(&self) -> Command<'_> {
+        match self {
+            OwnedCommand::Ping => Command::Ping,
+            OwnedCommand::Reset => Command::Reset,
+            OwnedCommand::Debug(a) => Command::Debug(a.map(|key| key.as_ref())),
+            OwnedCommand::Hello(a, b, c) => Command::Hello(
+                *a,
+                b.as_ref()
+                    .map(|(user, password)| (user.as_ref(), password.as_ref())),
+                c.as_deref(),
+            ),
+            OwnedCommand::Auth(a) => Command::Auth(a),
+            OwnedCommand::Acl(a) => Command::Acl(*a),
+            OwnedCommand::AuthUser(a, b) => Command::AuthUser(a, b),
+            OwnedCommand::Docs => Command::Docs,
+            OwnedCommand::CmdCount => Command::CmdCount,
+            OwnedCommand::CmdDocs(a) => Command::CmdDocs(borrowed_all(a)),
+            OwnedCommand::CmdGetKeys(a) => Command::CmdGetKeys(borrowed_all(a)),
+            OwnedCommand::DbSize => Command::DbSize,
+            OwnedCommand::Config => Command::Config,
+            OwnedCommand::ConfigGet(a) => Command::ConfigGet(a),
+            OwnedCommand::ConfigSet(a, b) => Command::ConfigSet(a, b),
+            OwnedCommand::ConfigResetStat => Command::ConfigResetStat,
+            OwnedCommand::Get(a) => Command::Get(a),
+            OwnedCommand::Strlen(a) => Command::Strlen(a),
+            OwnedCommand::GetRange(a, b, c) => Command::GetRange(a, *b, *c),
+            OwnedCommand::SetRange(a, b, c) => Command::SetRange(a, *b, c),
+            OwnedCommand::GetDel(a) => Command::GetDel(a),
+            OwnedCommand::GetEx(a, b) => Command::GetEx(a, *b),
+            OwnedCommand::Set(a, b, c) => Command::Set(a, b, *c),
+            OwnedCommand::SetNx(a, b) => Command::SetNx(a, b),
+            OwnedCommand::Setnx(a, b) => Command::Setnx(a, b),
+            OwnedCommand::SetXx(a, b) => Command::SetXx(a, b),
+            OwnedCommand::SetAndGet(a, b) => Command::SetAndGet(a, b),
+            OwnedCommand::SetKeepTtl(a, b) => Command::SetKeepTtl(a, b),
+            OwnedCommand::Lpush(a, b) => Command::Lpush(a, borrowed_all(b)),
+            OwnedCommand::Rpush(a, b) => Command::Rpush(a, borrowed_all(b)),
+            OwnedCommand::LpushX(a, b) => Command::LpushX(a, borrowed_all(b)),
+            OwnedCommand::RpushX(a, b) => Command::RpushX(a, borrowed_all(b)),
+            OwnedCommand::Lpop(a, b) => Command::Lpop(a, *b),
+            OwnedCommand::Rpop(a, b) => Command::Rpop(a, *b),
+            OwnedCommand::Blpop(a, b) => Command::Blpop(borrowed_all(a), *b),
+            OwnedCommand::Brpop(a, b) => Command::Brpop(borrowed_all(a), *b),
+            OwnedCommand::Lrange(a, b, c) => Command::Lrange(a, *b, *c),
+            OwnedCommand::Del(a) => Command::Del(borrowed_all(a)),
+            OwnedCommand::Unlink(a) => Command::Unlink(borrowed_all(a)),
+            OwnedCommand::Touch(a) => Command::Touch(borrowed_all(a)),
+            OwnedCommand::Copy(a, b, c, d) => Command::Copy(a, b, *c, *d),
+            OwnedCommand::Incr(a) => Command::Incr(a),
+            OwnedCommand::IncrBy(a, b) => Command::IncrBy(a, *b),
+            OwnedCommand::IncrByFloat(a, b) => Command::IncrByFloat(a, *b),
+            OwnedCommand::Decr(a) => Command::Decr(a),
+            OwnedCommand::DecrBy(a, b) => Command::DecrBy(a, *b),
+            OwnedCommand::Keys(a) => Command::Keys(a),
+            OwnedCommand::Hscan(a, b, c, d) => Command::Hscan(a, *b, c.as_deref(), *d),
+            OwnedCommand::Sscan(a, b, c, d) => Command::Sscan(a, *b, c.as_deref(), *d),
+            OwnedCommand::Zscan(a, b, c, d) => Command::Zscan(a, *b, c.as_deref(), *d),
+            OwnedCommand::FlushDb => Command::FlushDb,
+            OwnedCommand::FlushAll => Command::FlushAll,
+            OwnedCommand::Select(a) => Command::Select(*a),
+            OwnedCommand::Wait(a, b) => Command::Wait(*a, *b),
+            OwnedCommand::ReplicaOf(a) => {
+                Command::ReplicaOf(a.as_ref().map(|(host, port)| (host.as_ref(), *port)))
+            }
+            OwnedCommand::Psync => Command::Psync,
+            OwnedCommand::Move(a, b) => Command::Move(a, *b),
+            OwnedCommand::ObjectRefcount(a) => Command::ObjectRefcount(a),
+            OwnedCommand::ObjectIdletime(a) => Command::ObjectIdletime(a),
+            OwnedCommand::Client(a) => Command::Client(a.map(|bytes| bytes.as_ref())),
+            OwnedCommand::Ttl(a) => Command::Ttl(a),
+            OwnedCommand::Pttl(a) => Command::Pttl(a),
+            OwnedCommand::Expire(a, b) => Command::Expire(a, *b),
+            OwnedCommand::Pexpire(a, b) => Command::Pexpire(a, *b),
+            OwnedCommand::PexpireAt(a, b) => Command::PexpireAt(a, *b),
+            OwnedCommand::LLen(a) => Command::LLen(a),
+            OwnedCommand::Lindex(a, b) => Command::Lindex(a, *b),
+            OwnedCommand::Lset(a, b, c) => Command::Lset(a, *b, c),
+            OwnedCommand::Lrem(a, b, c) => Command::Lrem(a, *b, c),
+            OwnedCommand::Linsert(a, b, c, d) => Command::Linsert(a, *b, c, d),
+            OwnedCommand::Lpos(a, b, c, d) => Command::Lpos(a, b, *c, *d),
+            OwnedCommand::Hget(a, b) => Command::Hget(a, b),
+            OwnedCommand::HMget(a, b) => Command::HMget(a, borrowed_all(b)),
+            OwnedCommand::Hset(a, b) => Command::Hset(a, borrowed_all(b)),
+            OwnedCommand::HMset(a, b) => Command::HMset(a, borrowed_all(b)),
+            OwnedCommand::HgetAll(a) => Command::HgetAll(a),
+            OwnedCommand::HincrBy(a, b, c) => Command::HincrBy(a, b, *c),
+            OwnedCommand::HincrByFloat(a, b, c) => Command::HincrByFloat(a, b, *c),
+            OwnedCommand::Exists(a) => Command::Exists(borrowed_all(a)),
+            OwnedCommand::Hexists(a, b) => Command::Hexists(a, b),
+            OwnedCommand::Hkeys(a) => Command::Hkeys(a),
+            OwnedCommand::Hrandfield(a, b, c) => Command::Hrandfield(a, *b, *c),
+            OwnedCommand::Sadd(a, b) => Command::Sadd(a, borrowed_all(b)),
+            OwnedCommand::Srem(a, b) => Command::Srem(a, borrowed_all(b)),
+            OwnedCommand::Smove(a, b, c) => Command::Smove(a, b, c),
+            OwnedCommand::Spop(a, b) => Command::Spop(a, *b),
+            OwnedCommand::Srandmember(a, b) => Command::Srandmember(a, *b),
+            OwnedCommand::Sismember(a, b) => Command::Sismember(a, b),
+            OwnedCommand::Smismember(a, b) => Command::Smismember(a, borrowed_all(b)),
+            OwnedCommand::Sinter(a) => Command::Sinter(borrowed_all(a)),
+            OwnedCommand::Sunion(a) => Command::Sunion(borrowed_all(a)),
+            OwnedCommand::Sdiff(a) => Command::Sdiff(borrowed_all(a)),
+            OwnedCommand::Sinterstore(a, b) => Command::Sinterstore(a, borrowed_all(b)),
+            OwnedCommand::Sunionstore(a, b) => Command::Sunionstore(a, borrowed_all(b)),
+            OwnedCommand::Sdiffstore(a, b) => Command::Sdiffstore(a, borrowed_all(b)),
+            OwnedCommand::Scard(a) => Command::Scard(a),
+            OwnedCommand::Smembers(a) => Command::Smembers(a),
+            OwnedCommand::Zadd(a, b) => Command::Zadd(
+                a,
+                b.iter()
+                    .map(|(score, member)| (*score, member.as_ref()))
+                    .collect(),
+            ),
+            OwnedCommand::Zrem(a, b) => Command::Zrem(a, borrowed_all(b)),
+            OwnedCommand::Zrange(a, b, c, d) => Command::Zrange(a, *b, *c, *d),
+            OwnedCommand::Zrevrange(a, b, c, d) => Command::Zrevrange(a, *b, *c, *d),
+            OwnedCommand::Zrank(a, b) => Command::Zrank(a, b),
+            OwnedCommand::Zrevrank(a, b) => Command::Zrevrank(a, b),
+            OwnedCommand::Zscore(a, b) => Command::Zscore(a, b),
+            OwnedCommand::Zmscore(a, b) => Command::Zmscore(a, borrowed_all(b)),
+            OwnedCommand::Zrangebyscore(a, b, c, d) => Command::Zrangebyscore(a, *b, *c, *d),
+            OwnedCommand::Zrangebylex(a, b, c) => Command::Zrangebylex(
+                a,
+                b.map(|bytes| bytes.as_ref()),
+                c.map(|bytes| bytes.as_ref()),
+            ),
+            OwnedCommand::Zremrangebyrank(a, b, c) => Command::Zremrangebyrank(a, *b, *c),
+            OwnedCommand::Zremrangebyscore(a, b, c) => Command::Zremrangebyscore(a, *b, *c),
+            OwnedCommand::Zunionstore(a, b, c, d) => {
+                Command::Zunionstore(a, borrowed_all(b), c.clone(), *d)
+            }
+            OwnedCommand::Zinterstore(a, b, c, d) => {
+                Command::Zinterstore(a, borrowed_all(b), c.clone(), *d)
+            }
+            OwnedCommand::Zincrby(a, b, c) => Command::Zincrby(a, *b, c),
+            OwnedCommand::Zcard(a) => Command::Zcard(a),
+            OwnedCommand::InfoCmd(a) => Command::InfoCmd(a.clone()),
+            OwnedCommand::LatencyHistogram(a) => Command::LatencyHistogram(borrowed_all(a)),
+            OwnedCommand::Multi => Command::Multi,
+            OwnedCommand::Exec => Command::Exec,
+            OwnedCommand::Discard => Command::Discard,
+            OwnedCommand::Watch(a) => Command::Watch(borrowed_all(a)),
+            OwnedCommand::Unwatch => Command::Unwatch,
+            OwnedCommand::Subscribe(a) => Command::Subscribe(borrowed_all(a)),
+            OwnedCommand::Unsubscribe(a) => Command::Unsubscribe(borrowed_all(a)),
+            OwnedCommand::PSubscribe(a) => Command::PSubscribe(borrowed_all(a)),
+            OwnedCommand::PUnsubscribe(a) => Command::PUnsubscribe(borrowed_all(a)),
+            OwnedCommand::Publish(a, b) => Command::Publish(a, b),
+            OwnedCommand::Save => Command::Save,
+            OwnedCommand::Bgsave => Command::Bgsave,
+            OwnedCommand::Bgrewriteaof => Command::Bgrewriteaof,
+            OwnedCommand::Shutdown(a) => Command::Shutdown(*a),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::parser::parse;
+
+    #[test]
+    fn test_round_trip() {
+        let raw_cmds: [&[u8]; 4] = [
+            b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\nb\r\n",
+            b"*4\r\n$6\r\nEXISTS\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\na\r\n",
+            b"*4\r\n$11\r\nZRANGEBYLEX\r\n$1\r\nz\r\n$2\r\n[a\r\n$1\r\n+\r\n",
+            b"*4\r\n$6\r\nCLIENT\r\n$7\r\nSETINFO\r\n$8\r\nLIB-NAME\r\n$5\r\nredis\r\n",
+        ];
+        for raw_cmd in raw_cmds {
+            let cmd = parse(raw_cmd, usize::MAX).unwrap().0;
+            assert_eq!(cmd.to_owned().as_command(), cmd);
+        }
+    }
+
+    #[test]
+    fn test_outlives_read_buffer() {
+        let owned = {
+            let buf = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n".to_vec();
+            parse(&buf, usize::MAX).unwrap().0.to_owned()
+        };
+        assert_eq!(owned, OwnedCommand::Get(Bytes::from_static(b"key")));
+    }
+}
@@ -1,9 +1,12 @@
-use crate::cmd::Command;
+use crate::cmd::{
+    AclSub, Aggregate, ClientSub, Command, DebugSub, ExpiryOption, LexBound, ScoreBound,
+};
 use crate::err::RedisError;
+use compact_str::CompactString;
 
 use crate::cmd::Info::{LibName, LibVersion};
 use nom::{
-    Err, IResult,
+    Err, IResult, Needed,
     bytes::complete::{tag, take_while},
     character::complete::digit0,
     combinator::opt,
@@ -15,117 +18,602 @@ use std::str::FromStr;
 use std::time::Duration;
 use std::{fmt, num::ParseIntError};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum CmdCode {
     Ping,
+    Hello,
+    Auth,
+    Acl,
+    Reset,
+    Debug,
     Set,
     Get,
+    Strlen,
+    GetRange,
+    SetRange,
+    GetSet,
+    GetDel,
+    GetEx,
     SetEx,
+    PsetEx,
+    Setnx,
     Lpush,
     Rpush,
     LpushX,
     RpushX,
     Lpop,
     Rpop,
+    Blpop,
+    Brpop,
     Lrange,
     Hget,
     Hset,
     HMget,
     HMSet,
     Del,
+    Unlink,
+    Touch,
+    Copy,
     Incr,
     IncrBy,
+    IncrByFloat,
+    Decr,
+    DecrBy,
+    Keys,
+    Hscan,
+    Sscan,
+    Zscan,
     DbSize,
     Config,
     CommandDocs,
     FlushDb,
-    ClientSetInfo,
+    FlushAll,
+    Select,
+    Wait,
+    ReplicaOf,
+    Psync,
+    Move,
+    Object,
+    Client,
     Ttl,
+    Pttl,
+    Expire,
+    Pexpire,
+    PexpireAt,
     LLen,
+    Lindex,
+    Lset,
+    Lrem,
+    Linsert,
+    Lpos,
     HgetAll,
     HincrBy,
+    HincrByFloat,
     Exists,
     Hexists,
     Hkeys,
+    Hrandfield,
     Sadd,
+    Srem,
+    Smove,
+    Spop,
+    Srandmember,
     Sismember,
+    Smismember,
     Sinter,
     Sunion,
     Sdiff,
+    Sinterstore,
+    Sunionstore,
+    Sdiffstore,
     Scard,
     Smembers,
     Zadd,
+    Zrem,
     Zrange,
     Zrevrange,
     Zrank,
     Zrevrank,
     Zscore,
+    Zmscore,
     Zrangebyscore,
+    Zrangebylex,
+    Zremrangebyrank,
+    Zremrangebyscore,
+    Zunionstore,
+    Zinterstore,
     Zincrby,
     Zcard,
     Info,
     Latency,
+    Multi,
+    Exec,
+    Discard,
+    Watch,
+    Unwatch,
+    Subscribe,
+    Unsubscribe,
+    PSubscribe,
+    PUnsubscribe,
+    Publish,
+    Save,
+    Bgsave,
+    Bgrewriteaof,
+    Shutdown,
 }
 
-fn cmd(i: &[u8]) -> IResult<&[u8], CmdCode, ParseFailure> {
-    let (i, cmd_str) = string(i)?;
-    let v = match cmd_str {
-        b"PING" => CmdCode::Ping,
-        b"SETEX" => CmdCode::SetEx,
-        b"SET" => CmdCode::Set,
-        b"GET" => CmdCode::Get,
-        b"LPUSHX" => CmdCode::LpushX,
-        b"RPUSHX" => CmdCode::RpushX,
-        b"LPUSH" => CmdCode::Lpush,
-        b"RPUSH" => CmdCode::Rpush,
-        b"LPOP" => CmdCode::Lpop,
-        b"RPOP" => CmdCode::Rpop,
-        b"LRANGE" => CmdCode::Lrange,
-        b"HGET" => CmdCode::Hget,
-        b"HSET" => CmdCode::Hset,
-        b"HMGET" => CmdCode::HMget,
-        b"HMSET" => CmdCode::HMSet,
-        b"HGETALL" => CmdCode::HgetAll,
-        b"HINCRBY" => CmdCode::HincrBy,
-        b"EXISTS" => CmdCode::Exists,
-        b"HEXISTS" => CmdCode::Hexists,
-        b"HKEYS" => CmdCode::Hkeys,
-        b"SADD" => CmdCode::Sadd,
-        b"SISMEMBER" => CmdCode::Sismember,
-        b"SINTER" => CmdCode::Sinter,
-        b"SUNION" => CmdCode::Sunion,
-        b"SDIFF" => CmdCode::Sdiff,
-        b"SCARD" => CmdCode::Scard,
-        b"SMEMBERS" => CmdCode::Smembers,
-        b"ZADD" => CmdCode::Zadd,
-        b"ZRANGE" => CmdCode::Zrange,
-        b"ZREVRANGE" => CmdCode::Zrevrange,
-        b"ZRANK" => CmdCode::Zrank,
-        b"ZREVRANK" => CmdCode::Zrevrank,
-        b"ZSCORE" => CmdCode::Zscore,
-        b"ZRANGEBYSCORE" => CmdCode::Zrangebyscore,
-        b"ZINCRBY" => CmdCode::Zincrby,
-        b"ZCARD" => CmdCode::Zcard,
-        b"DEL" => CmdCode::Del,
-        b"INCRBY" => CmdCode::IncrBy,
-        b"INCR" => CmdCode::Incr,
-        b"DBSIZE" => CmdCode::DbSize,
-        b"COMMAND" => CmdCode::CommandDocs,
-        b"CONFIG" => CmdCode::Config,
-        b"FLUSHDB" => CmdCode::FlushDb,
-        b"CLIENT" => CmdCode::ClientSetInfo,
-        b"INFO" => CmdCode::Info,
-        b"LATENCY" => CmdCode::Latency,
-        b"TTL" => CmdCode::Ttl,
-        b"LLEN" => CmdCode::LLen,
-        unknown => {
-            return Err(nom::Err::Error(ParseFailure(format!(
-                "unknown command: {}",
-                String::from_utf8_lossy(unknown)
-            ))));
+impl CmdCode {
+    /// How many arguments the command takes, counting its name, as Redis
+    /// puts it: a negative arity `-n` means at least `n`.
+    fn arity(self) -> isize {
+        match self {
+            CmdCode::DbSize
+            | CmdCode::Multi
+            | CmdCode::Reset
+            | CmdCode::Exec
+            | CmdCode::Discard
+            | CmdCode::Unwatch
+            | CmdCode::Save
+            | CmdCode::Bgrewriteaof => 1,
+            CmdCode::Get
+            | CmdCode::Strlen
+            | CmdCode::GetDel
+            | CmdCode::Incr
+            | CmdCode::Decr
+            | CmdCode::Keys
+            | CmdCode::Select
+            | CmdCode::Ttl
+            | CmdCode::Pttl
+            | CmdCode::LLen
+            | CmdCode::HgetAll
+            | CmdCode::Hkeys
+            | CmdCode::Scard
+            | CmdCode::Smembers
+            | CmdCode::Zcard => 2,
+            CmdCode::GetSet
+            | CmdCode::Hget
+            | CmdCode::IncrBy
+            | CmdCode::IncrByFloat
+            | CmdCode::DecrBy
+            | CmdCode::Move
+            | CmdCode::Lindex
+            | CmdCode::Hexists
+            | CmdCode::Sismember
+            | CmdCode::Zscore
+            | CmdCode::Setnx
+            | CmdCode::Wait
+            | CmdCode::ReplicaOf
+            | CmdCode::Psync
+            | CmdCode::Publish => 3,
+            CmdCode::GetRange
+            | CmdCode::SetRange
+            | CmdCode::SetEx
+            | CmdCode::PsetEx
+            | CmdCode::Lrange
+            | CmdCode::Lset
+            | CmdCode::Lrem
+            | CmdCode::HincrBy
+            | CmdCode::HincrByFloat
+            | CmdCode::Smove
+            | CmdCode::Zremrangebyrank
+            | CmdCode::Zremrangebyscore
+            | CmdCode::Zincrby => 4,
+            CmdCode::Linsert => 5,
+            CmdCode::Ping
+            | CmdCode::Hello
+            | CmdCode::CommandDocs
+            | CmdCode::FlushDb
+            | CmdCode::FlushAll
+            | CmdCode::Info
+            | CmdCode::Unsubscribe
+            | CmdCode::PUnsubscribe
+            | CmdCode::Bgsave
+            | CmdCode::Shutdown => -1,
+            CmdCode::GetEx
+            | CmdCode::Lpop
+            | CmdCode::Rpop
+            | CmdCode::Del
+            | CmdCode::Unlink
+            | CmdCode::Touch
+            | CmdCode::Config
+            | CmdCode::Object
+            | CmdCode::Client
+            | CmdCode::Exists
+            | CmdCode::Hrandfield
+            | CmdCode::Spop
+            | CmdCode::Srandmember
+            | CmdCode::Sinter
+            | CmdCode::Sunion
+            | CmdCode::Sdiff
+            | CmdCode::Latency
+            | CmdCode::Debug
+            | CmdCode::Acl
+            | CmdCode::Auth
+            | CmdCode::Watch
+            | CmdCode::Subscribe
+            | CmdCode::PSubscribe => -2,
+            CmdCode::Set
+            | CmdCode::Lpush
+            | CmdCode::Rpush
+            | CmdCode::LpushX
+            | CmdCode::RpushX
+            | CmdCode::HMget
+            | CmdCode::Copy
+            | CmdCode::Hscan
+            | CmdCode::Sscan
+            | CmdCode::Zscan
+            | CmdCode::Expire
+            | CmdCode::Pexpire
+            | CmdCode::PexpireAt
+            | CmdCode::Lpos
+            | CmdCode::Sadd
+            | CmdCode::Srem
+            | CmdCode::Smismember
+            | CmdCode::Sinterstore
+            | CmdCode::Sunionstore
+            | CmdCode::Sdiffstore
+            | CmdCode::Blpop
+            | CmdCode::Brpop
+            | CmdCode::Zrem
+            | CmdCode::Zrank
+            | CmdCode::Zrevrank
+            | CmdCode::Zmscore => -3,
+            CmdCode::Hset
+            | CmdCode::HMSet
+            | CmdCode::Zadd
+            | CmdCode::Zrange
+            | CmdCode::Zrevrange
+            | CmdCode::Zrangebyscore
+            | CmdCode::Zrangebylex
+            | CmdCode::Zunionstore
+            | CmdCode::Zinterstore => -4,
         }
-    };
-    Ok((i, v))
+    }
+
+    fn accepts(self, argc: usize) -> bool {
+        if let CmdCode::Hset | CmdCode::HMSet = self
+            && !argc.is_multiple_of(2)
+        {
+            // the fields come with values
+            return false;
+        }
+        match self.arity() {
+            arity if arity < 0 => argc >= arity.unsigned_abs(),
+            arity => argc == arity as usize,
+        }
+    }
+}
+
+/// Where a command keeps its keys, as Redis's `firstkey`, `lastkey` and
+/// `keystep` put it: positions count the command name as 0, and a negative
+/// `last` counts back from the end, so `-1` is the last argument.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct KeySpec {
+    first: usize,
+    last: isize,
+    step: usize,
+    /// The positions depend on the arguments, like the `numkeys` of
+    /// `ZUNIONSTORE`, so only the parsed command knows all of its keys.
+    movable: bool,
+}
+
+impl KeySpec {
+    const NONE: KeySpec = KeySpec::range(0, 0, 0);
+
+    const fn range(first: usize, last: isize, step: usize) -> KeySpec {
+        KeySpec {
+            first,
+            last,
+            step,
+            movable: false,
+        }
+    }
+
+    const fn single() -> KeySpec {
+        KeySpec::range(1, 1, 1)
+    }
+
+    const fn all() -> KeySpec {
+        KeySpec::range(1, -1, 1)
+    }
+
+    const fn movable(self) -> KeySpec {
+        KeySpec {
+            movable: true,
+            ..self
+        }
+    }
+
+    /// The keys among `argv`, the command name included.
+    fn keys<'a>(self, argv: &[&'a [u8]]) -> Vec<&'a [u8]> {
+        if self.step == 0 {
+            return vec![];
+        }
+        let last = if self.last < 0 {
+            argv.len().checked_sub(self.last.unsigned_abs())
+        } else {
+            Some(self.last as usize)
+        };
+        let Some(last) = last.filter(|&last| last < argv.len()) else {
+            return vec![];
+        };
+        argv.iter()
+            .take(last + 1)
+            .skip(self.first)
+            .step_by(self.step)
+            .copied()
+            .collect()
+    }
+}
+
+fn key_spec(cmd: &CmdCode) -> KeySpec {
+    match cmd {
+        CmdCode::Ping
+        | CmdCode::Hello
+        | CmdCode::Auth
+        | CmdCode::Reset
+        | CmdCode::Acl
+        | CmdCode::Keys
+        | CmdCode::DbSize
+        | CmdCode::Config
+        | CmdCode::CommandDocs
+        | CmdCode::FlushDb
+        | CmdCode::FlushAll
+        | CmdCode::Select
+        | CmdCode::Wait
+        | CmdCode::ReplicaOf
+        | CmdCode::Psync
+        | CmdCode::Client
+        | CmdCode::Info
+        | CmdCode::Latency
+        | CmdCode::Multi
+        | CmdCode::Exec
+        | CmdCode::Discard
+        | CmdCode::Unwatch
+        | CmdCode::Subscribe
+        | CmdCode::Unsubscribe
+        | CmdCode::PSubscribe
+        | CmdCode::PUnsubscribe
+        | CmdCode::Publish
+        | CmdCode::Save
+        | CmdCode::Bgsave
+        | CmdCode::Bgrewriteaof
+        | CmdCode::Shutdown => KeySpec::NONE,
+        CmdCode::Set
+        | CmdCode::Get
+        | CmdCode::Strlen
+        | CmdCode::GetRange
+        | CmdCode::SetRange
+        | CmdCode::GetSet
+        | CmdCode::GetDel
+        | CmdCode::GetEx
+        | CmdCode::SetEx
+        | CmdCode::PsetEx
+        | CmdCode::Setnx
+        | CmdCode::Lpush
+        | CmdCode::Rpush
+        | CmdCode::LpushX
+        | CmdCode::RpushX
+        | CmdCode::Lpop
+        | CmdCode::Rpop
+        | CmdCode::Lrange
+        | CmdCode::Hget
+        | CmdCode::Hset
+        | CmdCode::HMget
+        | CmdCode::HMSet
+        | CmdCode::Incr
+        | CmdCode::IncrBy
+        | CmdCode::IncrByFloat
+        | CmdCode::Decr
+        | CmdCode::DecrBy
+        | CmdCode::Hscan
+        | CmdCode::Sscan
+        | CmdCode::Zscan
+        | CmdCode::Move
+        | CmdCode::Ttl
+        | CmdCode::Pttl
+        | CmdCode::Expire
+        | CmdCode::Pexpire
+        | CmdCode::PexpireAt
+        | CmdCode::LLen
+        | CmdCode::Lindex
+        | CmdCode::Lset
+        | CmdCode::Lrem
+        | CmdCode::Linsert
+        | CmdCode::Lpos
+        | CmdCode::HgetAll
+        | CmdCode::HincrBy
+        | CmdCode::HincrByFloat
+        | CmdCode::Hexists
+        | CmdCode::Hkeys
+        | CmdCode::Hrandfield
+        | CmdCode::Sadd
+        | CmdCode::Srem
+        | CmdCode::Spop
+        | CmdCode::Srandmember
+        | CmdCode::Sismember
+        | CmdCode::Smismember
+        | CmdCode::Scard
+        | CmdCode::Smembers
+        | CmdCode::Zadd
+        | CmdCode::Zrem
+        | CmdCode::Zrange
+        | CmdCode::Zrevrange
+        | CmdCode::Zrank
+        | CmdCode::Zrevrank
+        | CmdCode::Zscore
+        | CmdCode::Zmscore
+        | CmdCode::Zrangebyscore
+        | CmdCode::Zrangebylex
+        | CmdCode::Zremrangebyrank
+        | CmdCode::Zremrangebyscore
+        | CmdCode::Zincrby
+        | CmdCode::Zcard => KeySpec::single(),
+        CmdCode::Copy | CmdCode::Smove => KeySpec::range(1, 2, 1),
+        CmdCode::Del
+        | CmdCode::Unlink
+        | CmdCode::Touch
+        | CmdCode::Exists
+        | CmdCode::Watch
+        | CmdCode::Sinter
+        | CmdCode::Sunion
+        | CmdCode::Sdiff
+        | CmdCode::Sinterstore
+        | CmdCode::Sunionstore
+        | CmdCode::Sdiffstore => KeySpec::all(),
+        // the timeout comes last
+        CmdCode::Blpop | CmdCode::Brpop => KeySpec::range(1, -2, 1),
+        // the key follows a subcommand, which may not take one
+        CmdCode::Object | CmdCode::Debug => KeySpec::range(2, 2, 1).movable(),
+        CmdCode::Zunionstore | CmdCode::Zinterstore => KeySpec::single().movable(),
+    }
+}
+
+/// Every command name the server recognizes, so `COMMAND COUNT` and
+/// `COMMAND DOCS` pick up new entries by themselves.
+const COMMANDS: &[(&[u8], CmdCode)] = &[
+    (b"PING", CmdCode::Ping),
+    (b"HELLO", CmdCode::Hello),
+    (b"AUTH", CmdCode::Auth),
+    (b"ACL", CmdCode::Acl),
+    (b"RESET", CmdCode::Reset),
+    (b"DEBUG", CmdCode::Debug),
+    (b"SETEX", CmdCode::SetEx),
+    (b"PSETEX", CmdCode::PsetEx),
+    (b"SETNX", CmdCode::Setnx),
+    (b"SET", CmdCode::Set),
+    (b"GET", CmdCode::Get),
+    (b"STRLEN", CmdCode::Strlen),
+    (b"GETRANGE", CmdCode::GetRange),
+    (b"SETRANGE", CmdCode::SetRange),
+    (b"GETSET", CmdCode::GetSet),
+    (b"GETDEL", CmdCode::GetDel),
+    (b"GETEX", CmdCode::GetEx),
+    (b"LPUSHX", CmdCode::LpushX),
+    (b"RPUSHX", CmdCode::RpushX),
+    (b"LPUSH", CmdCode::Lpush),
+    (b"RPUSH", CmdCode::Rpush),
+    (b"LPOP", CmdCode::Lpop),
+    (b"RPOP", CmdCode::Rpop),
+    (b"BLPOP", CmdCode::Blpop),
+    (b"BRPOP", CmdCode::Brpop),
+    (b"LRANGE", CmdCode::Lrange),
+    (b"HGET", CmdCode::Hget),
+    (b"HSET", CmdCode::Hset),
+    (b"HMGET", CmdCode::HMget),
+    (b"HMSET", CmdCode::HMSet),
+    (b"HGETALL", CmdCode::HgetAll),
+    (b"HINCRBY", CmdCode::HincrBy),
+    (b"HINCRBYFLOAT", CmdCode::HincrByFloat),
+    (b"EXISTS", CmdCode::Exists),
+    (b"HEXISTS", CmdCode::Hexists),
+    (b"HKEYS", CmdCode::Hkeys),
+    (b"HRANDFIELD", CmdCode::Hrandfield),
+    (b"SADD", CmdCode::Sadd),
+    (b"SREM", CmdCode::Srem),
+    (b"SMOVE", CmdCode::Smove),
+    (b"SPOP", CmdCode::Spop),
+    (b"SRANDMEMBER", CmdCode::Srandmember),
+    (b"SISMEMBER", CmdCode::Sismember),
+    (b"SMISMEMBER", CmdCode::Smismember),
+    (b"SINTER", CmdCode::Sinter),
+    (b"SUNION", CmdCode::Sunion),
+    (b"SDIFF", CmdCode::Sdiff),
+    (b"SINTERSTORE", CmdCode::Sinterstore),
+    (b"SUNIONSTORE", CmdCode::Sunionstore),
+    (b"SDIFFSTORE", CmdCode::Sdiffstore),
+    (b"SCARD", CmdCode::Scard),
+    (b"SMEMBERS", CmdCode::Smembers),
+    (b"ZADD", CmdCode::Zadd),
+    (b"ZREM", CmdCode::Zrem),
+    (b"ZRANGE", CmdCode::Zrange),
+    (b"ZREVRANGE", CmdCode::Zrevrange),
+    (b"ZRANK", CmdCode::Zrank),
+    (b"ZREVRANK", CmdCode::Zrevrank),
+    (b"ZSCORE", CmdCode::Zscore),
+    (b"ZMSCORE", CmdCode::Zmscore),
+    (b"ZRANGEBYSCORE", CmdCode::Zrangebyscore),
+    (b"ZRANGEBYLEX", CmdCode::Zrangebylex),
+    (b"ZREMRANGEBYRANK", CmdCode::Zremrangebyrank),
+    (b"ZREMRANGEBYSCORE", CmdCode::Zremrangebyscore),
+    (b"ZUNIONSTORE", CmdCode::Zunionstore),
+    (b"ZINTERSTORE", CmdCode::Zinterstore),
+    (b"ZINCRBY", CmdCode::Zincrby),
+    (b"ZCARD", CmdCode::Zcard),
+    (b"DEL", CmdCode::Del),
+    (b"UNLINK", CmdCode::Unlink),
+    (b"TOUCH", CmdCode::Touch),
+    (b"COPY", CmdCode::Copy),
+    (b"INCRBYFLOAT", CmdCode::IncrByFloat),
+    (b"INCRBY", CmdCode::IncrBy),
+    (b"INCR", CmdCode::Incr),
+    (b"DECRBY", CmdCode::DecrBy),
+    (b"DECR", CmdCode::Decr),
+    (b"KEYS", CmdCode::Keys),
+    (b"HSCAN", CmdCode::Hscan),
+    (b"SSCAN", CmdCode::Sscan),
+    (b"ZSCAN", CmdCode::Zscan),
+    (b"DBSIZE", CmdCode::DbSize),
+    (b"COMMAND", CmdCode::CommandDocs),
+    (b"CONFIG", CmdCode::Config),
+    (b"FLUSHDB", CmdCode::FlushDb),
+    (b"FLUSHALL", CmdCode::FlushAll),
+    (b"SELECT", CmdCode::Select),
+    (b"WAIT", CmdCode::Wait),
+    (b"REPLICAOF", CmdCode::ReplicaOf),
+    (b"SLAVEOF", CmdCode::ReplicaOf),
+    (b"PSYNC", CmdCode::Psync),
+    (b"MOVE", CmdCode::Move),
+    (b"OBJECT", CmdCode::Object),
+    (b"CLIENT", CmdCode::Client),
+    (b"INFO", CmdCode::Info),
+    (b"LATENCY", CmdCode::Latency),
+    (b"TTL", CmdCode::Ttl),
+    (b"PTTL", CmdCode::Pttl),
+    (b"EXPIRE", CmdCode::Expire),
+    (b"PEXPIRE", CmdCode::Pexpire),
+    (b"PEXPIREAT", CmdCode::PexpireAt),
+    (b"LLEN", CmdCode::LLen),
+    (b"LINDEX", CmdCode::Lindex),
+    (b"LSET", CmdCode::Lset),
+    (b"LREM", CmdCode::Lrem),
+    (b"LINSERT", CmdCode::Linsert),
+    (b"LPOS", CmdCode::Lpos),
+    (b"MULTI", CmdCode::Multi),
+    (b"EXEC", CmdCode::Exec),
+    (b"DISCARD", CmdCode::Discard),
+    (b"WATCH", CmdCode::Watch),
+    (b"UNWATCH", CmdCode::Unwatch),
+    (b"SUBSCRIBE", CmdCode::Subscribe),
+    (b"UNSUBSCRIBE", CmdCode::Unsubscribe),
+    (b"PSUBSCRIBE", CmdCode::PSubscribe),
+    (b"PUNSUBSCRIBE", CmdCode::PUnsubscribe),
+    (b"PUBLISH", CmdCode::Publish),
+    (b"SAVE", CmdCode::Save),
+    (b"BGSAVE", CmdCode::Bgsave),
+    (b"BGREWRITEAOF", CmdCode::Bgrewriteaof),
+    (b"SHUTDOWN", CmdCode::Shutdown),
+];
+
+fn cmd(i: &[u8]) -> IResult<&[u8], (&'static [u8], CmdCode), ParseFailure> {
+    let (i, cmd_str) = string(i)?;
+    match COMMANDS.iter().find(|(name, _)| *name == cmd_str) {
+        Some((name, code)) => Ok((i, (name, *code))),
+        None => Err(nom::Err::Error(ParseFailure(format!(
+            "unknown command: {}",
+            String::from_utf8_lossy(cmd_str)
+        )))),
+    }
+}
+
+/// Names of all recognized commands, uppercase.
+pub fn command_names() -> impl Iterator<Item = &'static [u8]> {
+    COMMANDS.iter().map(|(name, _)| *name)
+}
+
+/// Parses a decimal number from raw bytes without panicking on garbage.
+fn parse_number<T: FromStr>(v: &[u8]) -> Option<T> {
+    str::from_utf8(v).ok().and_then(|s| T::from_str(s).ok())
 }
 
 fn u_number<T>(i: &[u8]) -> IResult<&[u8], T, ParseFailure>
@@ -133,22 +621,91 @@ where
     T: FromStr<Err = ParseIntError>,
 {
     let (i, v) = string(i)?;
-    Ok((
-        i,
-        T::from_str(str::from_utf8(v).expect("invalid number")).expect("invalid number"),
-    ))
+    match parse_number(v) {
+        Some(n) => Ok((i, n)),
+        // the argument is there but malformed, so don't let `opt` swallow it
+        None => Err(nom::Err::Failure(ParseFailure(
+            "value is not an integer or out of range".to_string(),
+        ))),
+    }
+}
+
+/// The most items a negative SRANDMEMBER or HRANDFIELD count may ask for.
+/// Those replies repeat items, so their size doesn't depend on the key's.
+const MAX_RAND_REPEATS: u64 = 1 << 24;
+
+fn rand_count(i: &[u8]) -> IResult<&[u8], i64, ParseFailure> {
+    let (i, count) = u_number::<i64>(i)?;
+    if count < 0 && count.unsigned_abs() > MAX_RAND_REPEATS {
+        return Err(nom::Err::Failure(ParseFailure(
+            "value is out of range".to_string(),
+        )));
+    }
+    Ok((i, count))
+}
+
+fn f_number(i: &[u8]) -> IResult<&[u8], f64, ParseFailure> {
+    let (i, v) = string(i)?;
+    match str::from_utf8(v).ok().and_then(|s| f64::from_str(s).ok()) {
+        Some(n) => Ok((i, n)),
+        None => Err(nom::Err::Error(ParseFailure(
+            "value is not a valid float".to_string(),
+        ))),
+    }
+}
+
+fn score_bound(i: &[u8]) -> IResult<&[u8], ScoreBound, ParseFailure> {
+    let (i, v) = string(i)?;
+    let (exclusive, v) = match v.strip_prefix(b"(") {
+        Some(rest) => (true, rest),
+        None => (false, v),
+    };
+    let bound = match str::from_utf8(v).ok().and_then(|s| f64::from_str(s).ok()) {
+        Some(n) if n == f64::INFINITY => ScoreBound::PosInf,
+        Some(n) if n == f64::NEG_INFINITY => ScoreBound::NegInf,
+        Some(n) if !n.is_nan() && exclusive => ScoreBound::Exclusive(n),
+        Some(n) if !n.is_nan() => ScoreBound::Inclusive(n),
+        _ => {
+            return Err(nom::Err::Error(ParseFailure(
+                "min or max is not a float".to_string(),
+            )));
+        }
+    };
+    Ok((i, bound))
 }
 
 fn value(i: &[u8]) -> IResult<&[u8], &[u8], ParseFailure> {
     let (i, _) = tag("$")(i)?;
     let (i, size_str) = digit0(i)?;
-    let str_size =
-        usize::from_str(str::from_utf8(size_str).expect("invalid number")).expect("invalid number");
+    let Some(str_size) = parse_number::<usize>(size_str) else {
+        return Err(nom::Err::Error(ParseFailure(
+            "invalid bulk length".to_string(),
+        )));
+    };
     let (i, _) = tag("\r\n")(i)?;
+    if i.len() < str_size {
+        return Err(nom::Err::Incomplete(Needed::new(str_size - i.len())));
+    }
     let value = &i[0..str_size];
     Ok((&i[str_size..], value))
 }
 
+fn lex_bound(i: &[u8]) -> IResult<&[u8], LexBound<&[u8]>, ParseFailure> {
+    let (i, v) = string(i)?;
+    let bound = match v {
+        b"-" => LexBound::Min,
+        b"+" => LexBound::Max,
+        [b'[', rest @ ..] => LexBound::Inclusive(rest),
+        [b'(', rest @ ..] => LexBound::Exclusive(rest),
+        _ => {
+            return Err(nom::Err::Failure(ParseFailure(
+                "min or max not valid string range item".to_string(),
+            )));
+        }
+    };
+    Ok((i, bound))
+}
+
 fn string(i: &[u8]) -> IResult<&[u8], &[u8], ParseFailure> {
     let (i, value) = value(i)?;
     let (i, _) = tag("\r\n")(i)?;
@@ -166,6 +723,103 @@ where
     Ok((i, f(key, raw_values)))
 }
 
+/// Parses `dest numkeys key [key ...] [WEIGHTS w [w ...]] [AGGREGATE SUM|MIN|MAX]`.
+fn zstore<'a, F>(i: &'a [u8], f: F) -> IResult<&'a [u8], Command<'a>, ParseFailure>
+where
+    F: Fn(&'a [u8], Vec<&'a [u8]>, Option<Vec<f64>>, Aggregate) -> Command<'a>,
+{
+    let (mut i, dest) = string(i)?;
+    let (i2, numkeys) = u_number::<usize>(i)?;
+    i = i2;
+    if numkeys == 0 {
+        return Err(nom::Err::Failure(ParseFailure(
+            "at least 1 input key is needed".to_string(),
+        )));
+    }
+    // numkeys is only trusted as far as there are arguments to back it
+    let mut keys = Vec::new();
+    for _ in 0..numkeys {
+        let (i2, key) =
+            string(i).map_err(|_| nom::Err::Failure(ParseFailure("syntax error".to_string())))?;
+        i = i2;
+        keys.push(key);
+    }
+    let mut weights = None;
+    let mut aggregate = Aggregate::default();
+    loop {
+        let (i2, maybe_opt) = opt(string)(i)?;
+        i = i2;
+        match maybe_opt {
+            None => break,
+            Some(o) if o.eq_ignore_ascii_case(b"WEIGHTS") => {
+                let mut ws = Vec::with_capacity(keys.len());
+                for _ in 0..numkeys {
+                    let (i2, w) = f_number(i).map_err(|_| {
+                        nom::Err::Failure(ParseFailure("weight value is not a float".to_string()))
+                    })?;
+                    i = i2;
+                    ws.push(w);
+                }
+                weights = Some(ws);
+            }
+            Some(o) if o.eq_ignore_ascii_case(b"AGGREGATE") => {
+                let (i2, a) = string(i)?;
+                i = i2;
+                aggregate = if a.eq_ignore_ascii_case(b"SUM") {
+                    Aggregate::Sum
+                } else if a.eq_ignore_ascii_case(b"MIN") {
+                    Aggregate::Min
+                } else if a.eq_ignore_ascii_case(b"MAX") {
+                    Aggregate::Max
+                } else {
+                    return Err(nom::Err::Failure(ParseFailure("syntax error".to_string())));
+                };
+            }
+            Some(_) => {
+                return Err(nom::Err::Failure(ParseFailure("syntax error".to_string())));
+            }
+        }
+    }
+    Ok((i, f(dest, keys, weights, aggregate)))
+}
+
+/// Parses `key cursor [MATCH pattern] [COUNT count]`.
+fn scan<'a, F>(i: &'a [u8], f: F) -> IResult<&'a [u8], Command<'a>, ParseFailure>
+where
+    F: Fn(&'a [u8], u64, Option<&'a [u8]>, Option<usize>) -> Command<'a>,
+{
+    let (mut i, key) = string(i)?;
+    let (i2, cursor) = u_number::<u64>(i)
+        .map_err(|_| nom::Err::Failure(ParseFailure("invalid cursor".to_string())))?;
+    i = i2;
+    let mut pattern = None;
+    let mut count = None;
+    loop {
+        let (i2, maybe_opt) = opt(string)(i)?;
+        i = i2;
+        match maybe_opt {
+            None => break,
+            Some(o) if o.eq_ignore_ascii_case(b"MATCH") => {
+                let (i2, p) = string(i)?;
+                i = i2;
+                pattern = Some(p);
+            }
+            Some(o) if o.eq_ignore_ascii_case(b"COUNT") => {
+                let (i2, c) = u_number::<usize>(i)?;
+                if c == 0 {
+                    return Err(nom::Err::Failure(ParseFailure("syntax error".to_string())));
+                }
+                i = i2;
+                count = Some(c);
+            }
+            Some(_) => {
+                return Err(nom::Err::Failure(ParseFailure("syntax error".to_string())));
+            }
+        }
+    }
+    Ok((i, f(key, cursor, pattern, count)))
+}
+
 fn pop<'a, F>(i: &'a [u8], f: F) -> IResult<&'a [u8], Command<'a>, ParseFailure>
 where
     F: Fn(&'a [u8], Option<usize>) -> Command<'a>,
@@ -176,20 +830,135 @@ where
     Ok((i, f(key, count)))
 }
 
+/// The keys and the timeout in seconds of `BLPOP`/`BRPOP`.
+fn blocking_pop<'a, F>(i: &'a [u8], f: F) -> IResult<&'a [u8], Command<'a>, ParseFailure>
+where
+    F: Fn(Vec<&'a [u8]>, Duration) -> Command<'a>,
+{
+    let (i, mut keys) = separated_list0(tag("\r\n"), value)(i)?;
+    let Some(timeout) = keys.pop() else {
+        return Err(nom::Err::Failure(ParseFailure("syntax error".to_string())));
+    };
+    let Some(seconds) = str::from_utf8(timeout)
+        .ok()
+        .and_then(|s| f64::from_str(s).ok())
+    else {
+        return Err(nom::Err::Failure(ParseFailure(
+            "timeout is not a float or out of range".to_string(),
+        )));
+    };
+    if seconds < 0.0 {
+        return Err(nom::Err::Failure(ParseFailure(
+            "timeout is negative".to_string(),
+        )));
+    }
+    // the deadline has to be representable, in milliseconds like Redis's
+    if seconds * 1000.0 >= i64::MAX as f64 {
+        return Err(nom::Err::Failure(ParseFailure(
+            "timeout is out of range".to_string(),
+        )));
+    }
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(timeout) => Ok((i, f(keys, timeout))),
+        Err(_) => Err(nom::Err::Failure(ParseFailure(
+            "timeout is not a float or out of range".to_string(),
+        ))),
+    }
+}
+
+/// An `ON`/`OFF` switch, like the one of `CLIENT NO-TOUCH`.
+fn on_off(i: &[u8]) -> IResult<&[u8], bool, ParseFailure> {
+    let (i, switch) = string(i)?;
+    if switch.eq_ignore_ascii_case(b"ON") {
+        Ok((i, true))
+    } else if switch.eq_ignore_ascii_case(b"OFF") {
+        Ok((i, false))
+    } else {
+        Err(nom::Err::Failure(ParseFailure("syntax error".to_string())))
+    }
+}
+
+/// Skips the optional `ASYNC`/`SYNC` token of the flush commands; freeing
+/// always happens inline, so both mean the same here.
+fn flush_mode(i: &[u8]) -> IResult<&[u8], (), ParseFailure> {
+    let (i, mode) = opt(string)(i)?;
+    match mode {
+        None => Ok((i, ())),
+        Some(m) if m.eq_ignore_ascii_case(b"ASYNC") || m.eq_ignore_ascii_case(b"SYNC") => {
+            Ok((i, ()))
+        }
+        Some(_) => Err(nom::Err::Failure(ParseFailure("syntax error".to_string()))),
+    }
+}
+
+/// Parses `[EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | PERSIST]`, naming `command` in the error
+/// for a time that isn't positive.
+fn expiry_option<'a>(i: &'a [u8], command: &str) -> IResult<&'a [u8], ExpiryOption, ParseFailure> {
+    let (i, option) = opt(string)(i)?;
+    let Some(option) = option else {
+        return Ok((i, ExpiryOption::Keep));
+    };
+    if option.eq_ignore_ascii_case(b"PERSIST") {
+        return Ok((i, ExpiryOption::Persist));
+    }
+    let (unit, absolute) = match option.to_ascii_uppercase().as_slice() {
+        b"EX" => (1000, false),
+        b"PX" => (1, false),
+        b"EXAT" => (1000, true),
+        b"PXAT" => (1, true),
+        _ => return Err(nom::Err::Failure(ParseFailure("syntax error".to_string()))),
+    };
+    let (i, millis) = expire_millis(i, unit, command)?;
+    if absolute {
+        Ok((i, ExpiryOption::At(millis)))
+    } else {
+        Ok((i, ExpiryOption::In(Duration::from_millis(millis as u64))))
+    }
+}
+
+/// Parses a time counted in `unit` milliseconds into milliseconds, which
+/// have to be positive.
+fn expire_millis<'a>(
+    i: &'a [u8],
+    unit: i64,
+    command: &str,
+) -> IResult<&'a [u8], i64, ParseFailure> {
+    let (i, time) = u_number::<i64>(i)?;
+    match time.checked_mul(unit).filter(|&millis| millis > 0) {
+        Some(millis) => Ok((i, millis)),
+        None => Err(nom::Err::Failure(ParseFailure(format!(
+            "invalid expire time in '{command}' command"
+        )))),
+    }
+}
+
 fn cmd_len(i: &[u8]) -> IResult<&[u8], usize, ParseFailure> {
     let (i, _) = tag([b'*'])(i)?;
     let (i, _u) = take_while(|c: u8| (48..=57).contains(&c))(i)?;
     let (i, _) = tag("\r\n")(i)?;
-    Ok((
-        i,
-        usize::from_str(str::from_utf8(_u).expect("invalid number")).expect("invalid number"),
-    ))
+    match parse_number(_u) {
+        Some(len) => Ok((i, len)),
+        None => Err(nom::Err::Error(ParseFailure(
+            "invalid multibulk length".to_string(),
+        ))),
+    }
+}
+
+/// Whether the command at the start of `i` is one the server knows, however
+/// wrong its arguments.
+pub fn names_known_command(i: &[u8]) -> bool {
+    opt(cmd_len)(i).is_ok_and(|(i, _)| cmd(i).is_ok())
 }
 
 fn root(i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
     let (i, _) = opt(cmd_len)(i)?;
-    let (i, cmd) = cmd(i)?;
-    match cmd {
+    let (i, (_, code)) = cmd(i)?;
+    arguments(code, i)
+}
+
+fn arguments(code: CmdCode, i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
+    match code {
         CmdCode::Set => {
             let (i, key) = string(i)?;
             let (i, value) = string(i)?;
@@ -225,16 +994,55 @@ fn root(i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
                 )))),
             }
         }
+        CmdCode::Setnx => {
+            let (i, key) = string(i)?;
+            let (i, value) = string(i)?;
+            Ok((i, Command::Setnx(key, value)))
+        }
         CmdCode::Get => {
             let (i, key) = string(i)?;
             Ok((i, Command::Get(key)))
         }
-        CmdCode::SetEx => {
+        CmdCode::Strlen => {
+            let (i, key) = string(i)?;
+            Ok((i, Command::Strlen(key)))
+        }
+        CmdCode::GetRange => {
+            let (i, key) = string(i)?;
+            let (i, start) = u_number::<isize>(i)?;
+            let (i, end) = u_number::<isize>(i)?;
+            Ok((i, Command::GetRange(key, start, end)))
+        }
+        CmdCode::SetRange => {
+            let (i, key) = string(i)?;
+            let (i, offset) = u_number::<usize>(i)?;
+            let (i, value) = string(i)?;
+            Ok((i, Command::SetRange(key, offset, value)))
+        }
+        CmdCode::GetSet => {
+            let (i, key) = string(i)?;
+            let (i, value) = string(i)?;
+            Ok((i, Command::SetAndGet(key, value)))
+        }
+        CmdCode::GetDel => {
+            let (i, key) = string(i)?;
+            Ok((i, Command::GetDel(key)))
+        }
+        CmdCode::GetEx => {
+            let (i, key) = string(i)?;
+            let (i, expiry) = expiry_option(i, "getex")?;
+            Ok((i, Command::GetEx(key, expiry)))
+        }
+        CmdCode::SetEx | CmdCode::PsetEx => {
+            let (unit, name) = match code {
+                CmdCode::SetEx => (1000, "setex"),
+                _ => (1, "psetex"),
+            };
             let (i, key) = string(i)?;
-            let (i, ttl) = u_number(i)?;
+            let (i, millis) = expire_millis(i, unit, name)?;
             let (i, value) = string(i)?;
-            let cmd = Command::Set(key, value, Some(Duration::from_secs(ttl)));
-            Ok((i, cmd))
+            let ttl = Duration::from_millis(millis as u64);
+            Ok((i, Command::Set(key, value, Some(ttl))))
         }
         CmdCode::Lpush => push(i, Command::Lpush),
         CmdCode::Rpush => push(i, Command::Rpush),
@@ -242,8 +1050,127 @@ fn root(i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
         CmdCode::RpushX => push(i, Command::RpushX),
         CmdCode::Lpop => pop(i, Command::Lpop),
         CmdCode::Rpop => pop(i, Command::Rpop),
-        CmdCode::CommandDocs => Ok((i, Command::Docs)),
+        CmdCode::Blpop => blocking_pop(i, Command::Blpop),
+        CmdCode::Brpop => blocking_pop(i, Command::Brpop),
+        CmdCode::CommandDocs => {
+            let (i, sub) = opt(string)(i)?;
+            match sub {
+                Some(sub) if sub.eq_ignore_ascii_case(b"COUNT") => Ok((i, Command::CmdCount)),
+                Some(sub) if sub.eq_ignore_ascii_case(b"DOCS") => {
+                    let (i, names) = separated_list0(tag("\r\n"), value)(i)?;
+                    Ok((i, Command::CmdDocs(names)))
+                }
+                Some(sub) if sub.eq_ignore_ascii_case(b"GETKEYS") => {
+                    // the rest is a command of its own, parsed only for its keys
+                    let (_, argv) = separated_list0(tag("\r\n"), value)(i)?;
+                    let Ok((rest, (_, code))) = cmd(i) else {
+                        return Err(nom::Err::Failure(ParseFailure(
+                            "Invalid command specified".to_string(),
+                        )));
+                    };
+                    if !code.accepts(argv.len()) {
+                        return Err(nom::Err::Failure(ParseFailure(
+                            "Invalid number of arguments specified for command".to_string(),
+                        )));
+                    }
+                    let (i, command) = arguments(code, rest)?;
+                    let spec = key_spec(&code);
+                    let keys = if spec.movable {
+                        command.keys()
+                    } else {
+                        spec.keys(&argv)
+                    };
+                    if keys.is_empty() {
+                        return Err(nom::Err::Failure(ParseFailure(
+                            "The command has no key arguments".to_string(),
+                        )));
+                    }
+                    Ok((i, Command::CmdGetKeys(keys)))
+                }
+                _ => Ok((i, Command::Docs)),
+            }
+        }
         CmdCode::Ping => Ok((i, Command::Ping)),
+        CmdCode::Reset => Ok((i, Command::Reset)),
+        CmdCode::Debug => {
+            let (i, sub) = string(i)?;
+            if sub.eq_ignore_ascii_case(b"SLEEP") {
+                let (i, seconds) = f_number(i)?;
+                let Ok(duration) = Duration::try_from_secs_f64(seconds) else {
+                    return Err(nom::Err::Failure(ParseFailure(
+                        "value is not a valid float".to_string(),
+                    )));
+                };
+                Ok((i, Command::Debug(DebugSub::Sleep(duration))))
+            } else if sub.eq_ignore_ascii_case(b"SET-ACTIVE-EXPIRE") {
+                let (i, on) = u_number::<u8>(i)?;
+                Ok((i, Command::Debug(DebugSub::SetActiveExpire(on != 0))))
+            } else if sub.eq_ignore_ascii_case(b"OBJECT") {
+                let (i, key) = string(i)?;
+                Ok((i, Command::Debug(DebugSub::Object(key))))
+            } else {
+                Err(nom::Err::Failure(ParseFailure(format!(
+                    "unknown subcommand '{}'. Try DEBUG HELP.",
+                    String::from_utf8_lossy(sub)
+                ))))
+            }
+        }
+        CmdCode::Auth => {
+            let (i, first) = string(i)?;
+            let (i, second) = opt(string)(i)?;
+            let (i, extra) = opt(string)(i)?;
+            match (second, extra) {
+                (None, _) => Ok((i, Command::Auth(first))),
+                (Some(password), None) => Ok((i, Command::AuthUser(first, password))),
+                (Some(_), Some(_)) => {
+                    Err(nom::Err::Failure(ParseFailure("syntax error".to_string())))
+                }
+            }
+        }
+        CmdCode::Acl => {
+            let (i, sub) = string(i)?;
+            if sub.eq_ignore_ascii_case(b"WHOAMI") {
+                Ok((i, Command::Acl(AclSub::WhoAmI)))
+            } else if sub.eq_ignore_ascii_case(b"LIST") {
+                Ok((i, Command::Acl(AclSub::List)))
+            } else {
+                Err(nom::Err::Failure(ParseFailure(format!(
+                    "unknown subcommand '{}'. Try ACL HELP.",
+                    String::from_utf8_lossy(sub)
+                ))))
+            }
+        }
+        CmdCode::Hello => {
+            let (mut i, protocol) = opt(u_number::<u8>)(i)?;
+            let (mut auth, mut name) = (None, None);
+            // the options only follow a protocol version
+            while protocol.is_some() {
+                let (i2, maybe_opt) = opt(string)(i)?;
+                i = i2;
+                let syntax_error = |opt: &[u8]| {
+                    nom::Err::Failure(ParseFailure(format!(
+                        "Syntax error in HELLO option '{}'",
+                        String::from_utf8_lossy(opt)
+                    )))
+                };
+                match maybe_opt {
+                    None => break,
+                    Some(o) if o.eq_ignore_ascii_case(b"AUTH") => {
+                        let (i2, user) = string(i).map_err(|_| syntax_error(o))?;
+                        let (i2, password) = string(i2).map_err(|_| syntax_error(o))?;
+                        i = i2;
+                        auth = Some((user, password));
+                    }
+                    Some(o) if o.eq_ignore_ascii_case(b"SETNAME") => {
+                        let (i2, client_name) = string(i).map_err(|_| syntax_error(o))?;
+                        i = i2;
+                        name = Some(client_name);
+                    }
+                    Some(o) => return Err(syntax_error(o)),
+                }
+            }
+            Ok((i, Command::Hello(protocol, auth, name)))
+        }
         CmdCode::Incr => {
             let (i, key) = string(i)?;
             Ok((i, Command::Incr(key)))
@@ -253,26 +1180,123 @@ fn root(i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
             let (i, incr_by) = u_number::<i64>(i)?;
             Ok((i, Command::IncrBy(key, incr_by)))
         }
+        CmdCode::Decr => {
+            let (i, key) = string(i)?;
+            Ok((i, Command::Decr(key)))
+        }
+        CmdCode::DecrBy => {
+            let (i, key) = string(i)?;
+            let (i, decr_by) = u_number::<i64>(i)?;
+            Ok((i, Command::DecrBy(key, decr_by)))
+        }
+        CmdCode::Keys => {
+            let (i, pattern) = string(i)?;
+            Ok((i, Command::Keys(pattern)))
+        }
+        CmdCode::Hscan => scan(i, Command::Hscan),
+        CmdCode::Sscan => scan(i, Command::Sscan),
+        CmdCode::Zscan => scan(i, Command::Zscan),
+        CmdCode::IncrByFloat => {
+            let (i, key) = string(i)?;
+            let (i, incr_by) = f_number(i)?;
+            Ok((i, Command::IncrByFloat(key, incr_by)))
+        }
         CmdCode::Del => {
             let (i, raw_values) = separated_list0(tag("\r\n"), value)(i)?;
             let values = raw_values.to_vec();
             Ok((i, Command::Del(values)))
         }
+        CmdCode::Unlink => {
+            let (i, keys) = separated_list0(tag("\r\n"), value)(i)?;
+            Ok((i, Command::Unlink(keys)))
+        }
+        CmdCode::Touch => {
+            let (i, keys) = separated_list0(tag("\r\n"), value)(i)?;
+            Ok((i, Command::Touch(keys)))
+        }
+        CmdCode::Copy => {
+            let (i, src) = string(i)?;
+            let (mut i, dst) = string(i)?;
+            let (mut db, mut replace) = (None, false);
+            loop {
+                let (i2, maybe_opt) = opt(string)(i)?;
+                i = i2;
+                match maybe_opt {
+                    None => break,
+                    Some(o) if o.eq_ignore_ascii_case(b"REPLACE") => replace = true,
+                    // a trailing `DB` without an index is a syntax error,
+                    // like any other unknown option
+                    Some(o) if o.eq_ignore_ascii_case(b"DB") && !i.is_empty() => {
+                        let (i2, index) = u_number::<usize>(i)?;
+                        i = i2;
+                        db = Some(index);
+                    }
+                    Some(_) => {
+                        return Err(nom::Err::Failure(ParseFailure("syntax error".to_string())));
+                    }
+                }
+            }
+            Ok((i, Command::Copy(src, dst, db, replace)))
+        }
         CmdCode::DbSize => Ok((i, Command::DbSize)),
+        CmdCode::Multi => Ok((i, Command::Multi)),
+        CmdCode::Exec => Ok((i, Command::Exec)),
+        CmdCode::Discard => Ok((i, Command::Discard)),
+        CmdCode::Watch => {
+            let (i, keys) = separated_list0(tag("\r\n"), value)(i)?;
+            Ok((i, Command::Watch(keys)))
+        }
+        CmdCode::Unwatch => Ok((i, Command::Unwatch)),
+        CmdCode::Save => Ok((i, Command::Save)),
+        CmdCode::Bgsave => Ok((i, Command::Bgsave)),
+        CmdCode::Bgrewriteaof => Ok((i, Command::Bgrewriteaof)),
+        CmdCode::Shutdown => {
+            let (i, mode) = opt(string)(i)?;
+            let save = match mode {
+                None => None,
+                Some(m) if m.eq_ignore_ascii_case(b"SAVE") => Some(true),
+                Some(m) if m.eq_ignore_ascii_case(b"NOSAVE") => Some(false),
+                Some(_) => {
+                    return Err(nom::Err::Failure(ParseFailure("syntax error".to_string())));
+                }
+            };
+            Ok((i, Command::Shutdown(save)))
+        }
+        CmdCode::Subscribe => {
+            let (i, channels) = separated_list0(tag("\r\n"), value)(i)?;
+            Ok((i, Command::Subscribe(channels)))
+        }
+        CmdCode::Unsubscribe => {
+            let (i, channels) = separated_list0(tag("\r\n"), value)(i)?;
+            Ok((i, Command::Unsubscribe(channels)))
+        }
+        CmdCode::PSubscribe => {
+            let (i, patterns) = separated_list0(tag("\r\n"), value)(i)?;
+            Ok((i, Command::PSubscribe(patterns)))
+        }
+        CmdCode::PUnsubscribe => {
+            let (i, patterns) = separated_list0(tag("\r\n"), value)(i)?;
+            Ok((i, Command::PUnsubscribe(patterns)))
+        }
+        CmdCode::Publish => {
+            let (i, channel) = string(i)?;
+            let (i, message) = string(i)?;
+            Ok((i, Command::Publish(channel, message)))
+        }
         CmdCode::Hget => {
             let (i, key) = string(i)?;
             let (i, field) = string(i)?;
             Ok((i, Command::Hget(key, field)))
         }
+        // the pairs are counted by `CmdCode::accepts`
         CmdCode::Hset => {
             let (i, key) = string(i)?;
             let (i, fields_and_values) = separated_list0(tag("\r\n"), value)(i)?;
-            if fields_and_values.len() % 2 != 0 {
-                return Err(nom::Err::Error(ParseFailure(format!(
-                    "Hset unexpected number of args: {}, should be divisible by 2",
-                    fields_and_values.len()
-                ))));
-            }
+            Ok((i, Command::Hset(key, fields_and_values)))
+        }
+        CmdCode::HMSet => {
+            let (i, key) = string(i)?;
+            let (i, fields_and_values) = separated_list0(tag("\r\n"), value)(i)?;
             Ok((i, Command::HMset(key, fields_and_values)))
         }
         CmdCode::HMget => {
@@ -280,17 +1304,6 @@ fn root(i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
             let (i, fields) = separated_list0(tag("\r\n"), value)(i)?;
             Ok((i, Command::HMget(key, fields)))
         }
-        CmdCode::HMSet => {
-            let (i, key) = string(i)?;
-            let (i, fields_and_values) = separated_list0(tag("\r\n"), string)(i)?;
-            if fields_and_values.len() % 2 != 0 {
-                return Err(nom::Err::Error(ParseFailure(format!(
-                    "Hset unexpected number of args: {}, should be divisible by 2",
-                    fields_and_values.len()
-                ))));
-            }
-            Ok((i, Command::HMset(key, fields_and_values)))
-        }
         CmdCode::HgetAll => {
             let (i, key) = string(i)?;
             Ok((i, Command::HgetAll(key)))
@@ -301,9 +1314,15 @@ fn root(i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
             let (i, incr_by) = u_number::<i64>(i)?;
             Ok((i, Command::HincrBy(key, field, incr_by)))
         }
-        CmdCode::Exists => {
+        CmdCode::HincrByFloat => {
             let (i, key) = string(i)?;
-            Ok((i, Command::Exists(key)))
+            let (i, field) = string(i)?;
+            let (i, incr_by) = f_number(i)?;
+            Ok((i, Command::HincrByFloat(key, field, incr_by)))
+        }
+        CmdCode::Exists => {
+            let (i, keys) = separated_list0(tag("\r\n"), value)(i)?;
+            Ok((i, Command::Exists(keys)))
         }
         CmdCode::Hexists => {
             let (i, key) = string(i)?;
@@ -314,12 +1333,42 @@ fn root(i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
             let (i, key) = string(i)?;
             Ok((i, Command::Hkeys(key)))
         }
+        CmdCode::Hrandfield => {
+            let (i, key) = string(i)?;
+            let (i, count) = opt(rand_count)(i)?;
+            let (i, flag) = match count {
+                None => (i, None),
+                Some(_) => opt(string)(i)?,
+            };
+            let withvalues = match flag {
+                None => false,
+                Some(f) if f.eq_ignore_ascii_case(b"WITHVALUES") => true,
+                Some(_) => {
+                    return Err(nom::Err::Failure(ParseFailure("syntax error".to_string())));
+                }
+            };
+            Ok((i, Command::Hrandfield(key, count, withvalues)))
+        }
         CmdCode::Sadd => push(i, Command::Sadd),
+        CmdCode::Srem => push(i, Command::Srem),
+        CmdCode::Smove => {
+            let (i, src) = string(i)?;
+            let (i, dst) = string(i)?;
+            let (i, member) = string(i)?;
+            Ok((i, Command::Smove(src, dst, member)))
+        }
+        CmdCode::Spop => pop(i, Command::Spop),
+        CmdCode::Srandmember => {
+            let (i, key) = string(i)?;
+            let (i, count) = opt(rand_count)(i)?;
+            Ok((i, Command::Srandmember(key, count)))
+        }
         CmdCode::Sismember => {
             let (i, key) = string(i)?;
             let (i, member) = string(i)?;
             Ok((i, Command::Sismember(key, member)))
         }
+        CmdCode::Smismember => push(i, Command::Smismember),
         CmdCode::Sinter => {
             let (i, keys) = separated_list0(tag("\r\n"), value)(i)?;
             Ok((i, Command::Sinter(keys.to_vec())))
@@ -332,6 +1381,9 @@ fn root(i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
             let (i, keys) = separated_list0(tag("\r\n"), value)(i)?;
             Ok((i, Command::Sdiff(keys.to_vec())))
         }
+        CmdCode::Sinterstore => push(i, Command::Sinterstore),
+        CmdCode::Sunionstore => push(i, Command::Sunionstore),
+        CmdCode::Sdiffstore => push(i, Command::Sdiffstore),
         CmdCode::Scard => {
             let (i, key) = string(i)?;
             Ok((i, Command::Scard(key)))
@@ -345,12 +1397,19 @@ fn root(i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
             let (i, raw) = separated_list0(tag("\r\n"), value)(i)?;
             let mut members = Vec::new();
             for chunk in raw.chunks(2) {
-                let score = i64::from_str(str::from_utf8(chunk[0]).expect("invalid score"))
-                    .expect("invalid score");
-                members.push((score, chunk[1]));
+                let &[score, member] = chunk else {
+                    return Err(nom::Err::Failure(ParseFailure("syntax error".to_string())));
+                };
+                let Some(score) = parse_number(score) else {
+                    return Err(nom::Err::Failure(ParseFailure(
+                        "value is not an integer or out of range".to_string(),
+                    )));
+                };
+                members.push((score, member));
             }
             Ok((i, Command::Zadd(key, members)))
         }
+        CmdCode::Zrem => push(i, Command::Zrem),
         CmdCode::Zrange => {
             let (i, key) = string(i)?;
             let (i, start) = u_number::<isize>(i)?;
@@ -405,14 +1464,35 @@ fn root(i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
             let (i, member) = string(i)?;
             Ok((i, Command::Zscore(key, member)))
         }
+        CmdCode::Zmscore => push(i, Command::Zmscore),
         CmdCode::Zrangebyscore => {
             let (i, key) = string(i)?;
-            let (i, min) = u_number::<i64>(i)?;
-            let (i, max) = u_number::<i64>(i)?;
+            let (i, min) = score_bound(i)?;
+            let (i, max) = score_bound(i)?;
             let (i, maybe_flag) = opt(string)(i)?;
             let withscores = matches!(maybe_flag, Some(f) if f.eq_ignore_ascii_case(b"WITHSCORES"));
             Ok((i, Command::Zrangebyscore(key, min, max, withscores)))
         }
+        CmdCode::Zrangebylex => {
+            let (i, key) = string(i)?;
+            let (i, min) = lex_bound(i)?;
+            let (i, max) = lex_bound(i)?;
+            Ok((i, Command::Zrangebylex(key, min, max)))
+        }
+        CmdCode::Zremrangebyrank => {
+            let (i, key) = string(i)?;
+            let (i, start) = u_number::<isize>(i)?;
+            let (i, stop) = u_number::<isize>(i)?;
+            Ok((i, Command::Zremrangebyrank(key, start, stop)))
+        }
+        CmdCode::Zremrangebyscore => {
+            let (i, key) = string(i)?;
+            let (i, min) = score_bound(i)?;
+            let (i, max) = score_bound(i)?;
+            Ok((i, Command::Zremrangebyscore(key, min, max)))
+        }
+        CmdCode::Zunionstore => zstore(i, Command::Zunionstore),
+        CmdCode::Zinterstore => zstore(i, Command::Zinterstore),
         CmdCode::Zincrby => {
             let (i, key) = string(i)?;
             let (i, incr) = u_number::<i64>(i)?;
@@ -426,33 +1506,143 @@ fn root(i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
             let (i, commands) = separated_list0(tag("\r\n"), value)(i)?;
             Ok((i, Command::LatencyHistogram(commands)))
         }
-        CmdCode::Config => Ok((i, Command::Config)),
+        CmdCode::Config => {
+            let (i, sub) = opt(string)(i)?;
+            match sub {
+                Some(sub) if sub.eq_ignore_ascii_case(b"GET") => {
+                    let (i, pattern) = string(i)?;
+                    Ok((i, Command::ConfigGet(pattern)))
+                }
+                Some(sub) if sub.eq_ignore_ascii_case(b"SET") => {
+                    let (i, param) = string(i)?;
+                    let (i, value) = string(i)?;
+                    Ok((i, Command::ConfigSet(param, value)))
+                }
+                Some(sub) if sub.eq_ignore_ascii_case(b"RESETSTAT") => {
+                    Ok((i, Command::ConfigResetStat))
+                }
+                // other subcommands still get the empty stub reply
+                _ => Ok((i, Command::Config)),
+            }
+        }
         CmdCode::Info => {
-            let (i, _section) = opt(string)(i)?;
-            Ok((i, Command::InfoCmd))
+            let (i, section) = opt(string)(i)?;
+            let section = section
+                .map(|s| CompactString::new(String::from_utf8_lossy(s).to_ascii_lowercase()));
+            Ok((i, Command::InfoCmd(section)))
+        }
+        CmdCode::FlushDb => {
+            let (i, _) = flush_mode(i)?;
+            Ok((i, Command::FlushDb))
+        }
+        CmdCode::FlushAll => {
+            let (i, _) = flush_mode(i)?;
+            Ok((i, Command::FlushAll))
+        }
+        CmdCode::Select => {
+            let (i, index) = u_number::<usize>(i)?;
+            Ok((i, Command::Select(index)))
+        }
+        CmdCode::Wait => {
+            let (i, replicas) = u_number::<usize>(i)?;
+            let (i, timeout) = u_number::<u64>(i)?;
+            Ok((i, Command::Wait(replicas, timeout)))
+        }
+        CmdCode::ReplicaOf => {
+            let (i, host) = string(i)?;
+            let (rest, port) = string(i)?;
+            if host.eq_ignore_ascii_case(b"NO") && port.eq_ignore_ascii_case(b"ONE") {
+                return Ok((rest, Command::ReplicaOf(None)));
+            }
+            let (i, port) = u_number::<u16>(i)?;
+            Ok((i, Command::ReplicaOf(Some((host, port)))))
+        }
+        CmdCode::Psync => {
+            let (i, _replid) = string(i)?;
+            let (i, _offset) = string(i)?;
+            Ok((i, Command::Psync))
         }
-        CmdCode::FlushDb => Ok((i, Command::FlushDb)),
-        CmdCode::ClientSetInfo => {
+        CmdCode::Move => {
+            let (i, key) = string(i)?;
+            let (i, index) = u_number::<usize>(i)?;
+            Ok((i, Command::Move(key, index)))
+        }
+        CmdCode::Object => {
             let (i, sub) = string(i)?;
-            if sub.eq_ignore_ascii_case(b"SETNAME") {
-                let (i, _name) = string(i)?;
-                Ok((i, Command::ClientSetName))
+            let (i, key) = string(i)?;
+            if sub.eq_ignore_ascii_case(b"REFCOUNT") {
+                Ok((i, Command::ObjectRefcount(key)))
+            } else if sub.eq_ignore_ascii_case(b"IDLETIME") {
+                Ok((i, Command::ObjectIdletime(key)))
             } else {
-                // SETINFO LIB-NAME/LIB-VER <value>
+                Err(nom::Err::Failure(ParseFailure(
+                    "unknown OBJECT subcommand".to_string(),
+                )))
+            }
+        }
+        CmdCode::Client => {
+            let (i, sub) = string(i)?;
+            if sub.eq_ignore_ascii_case(b"SETNAME") {
+                let (i, name) = string(i)?;
+                Ok((i, Command::Client(ClientSub::SetName(name))))
+            } else if sub.eq_ignore_ascii_case(b"GETNAME") {
+                Ok((i, Command::Client(ClientSub::GetName)))
+            } else if sub.eq_ignore_ascii_case(b"ID") {
+                Ok((i, Command::Client(ClientSub::Id)))
+            } else if sub.eq_ignore_ascii_case(b"LIST") {
+                Ok((i, Command::Client(ClientSub::List)))
+            } else if sub.eq_ignore_ascii_case(b"INFO") {
+                Ok((i, Command::Client(ClientSub::Info)))
+            } else if sub.eq_ignore_ascii_case(b"NO-EVICT") {
+                let (i, on) = on_off(i)?;
+                Ok((i, Command::Client(ClientSub::NoEvict(on))))
+            } else if sub.eq_ignore_ascii_case(b"NO-TOUCH") {
+                let (i, on) = on_off(i)?;
+                Ok((i, Command::Client(ClientSub::NoTouch(on))))
+            } else if sub.eq_ignore_ascii_case(b"SETINFO") {
                 let (i, param) = string(i)?;
                 let (i, value) = string(i)?;
-                let param = if param.eq_ignore_ascii_case(b"LIB-NAME") {
+                let info = if param.eq_ignore_ascii_case(b"LIB-NAME") {
                     LibName(value)
-                } else {
+                } else if param.eq_ignore_ascii_case(b"LIB-VER") {
                     LibVersion(value)
+                } else {
+                    return Err(nom::Err::Failure(ParseFailure(format!(
+                        "Unrecognized option '{}'",
+                        String::from_utf8_lossy(param)
+                    ))));
                 };
-                Ok((i, Command::ClientSetInfo(param)))
+                Ok((i, Command::Client(ClientSub::SetInfo(info))))
+            } else {
+                Err(nom::Err::Failure(ParseFailure(format!(
+                    "unknown subcommand '{}'. Try CLIENT HELP.",
+                    String::from_utf8_lossy(sub)
+                ))))
             }
         }
         CmdCode::Ttl => {
             let (i, key) = string(i)?;
             Ok((i, Command::Ttl(key)))
         }
+        CmdCode::Pttl => {
+            let (i, key) = string(i)?;
+            Ok((i, Command::Pttl(key)))
+        }
+        CmdCode::Expire => {
+            let (i, key) = string(i)?;
+            let (i, seconds) = u_number::<i64>(i)?;
+            Ok((i, Command::Expire(key, seconds)))
+        }
+        CmdCode::Pexpire => {
+            let (i, key) = string(i)?;
+            let (i, millis) = u_number::<i64>(i)?;
+            Ok((i, Command::Pexpire(key, millis)))
+        }
+        CmdCode::PexpireAt => {
+            let (i, key) = string(i)?;
+            let (i, unix_millis) = u_number::<i64>(i)?;
+            Ok((i, Command::PexpireAt(key, unix_millis)))
+        }
         CmdCode::Lrange => {
             let (i, key) = string(i)?;
             let (i, start) = u_number(i)?;
@@ -463,13 +1653,77 @@ fn root(i: &[u8]) -> IResult<&[u8], Command<'_>, ParseFailure> {
             let (i, key) = string(i)?;
             Ok((i, Command::LLen(key)))
         }
-        CmdCode::Zcard => {
+        CmdCode::Lindex => {
             let (i, key) = string(i)?;
-            Ok((i, Command::Zcard(key)))
+            let (i, index) = u_number(i)?;
+            Ok((i, Command::Lindex(key, index)))
         }
-    }
-}
-
+        CmdCode::Lset => {
+            let (i, key) = string(i)?;
+            let (i, index) = u_number(i)?;
+            let (i, value) = string(i)?;
+            Ok((i, Command::Lset(key, index, value)))
+        }
+        CmdCode::Lrem => {
+            let (i, key) = string(i)?;
+            let (i, count) = u_number(i)?;
+            let (i, value) = string(i)?;
+            Ok((i, Command::Lrem(key, count, value)))
+        }
+        CmdCode::Linsert => {
+            let (i, key) = string(i)?;
+            let (i, position) = string(i)?;
+            let before = if position.eq_ignore_ascii_case(b"BEFORE") {
+                true
+            } else if position.eq_ignore_ascii_case(b"AFTER") {
+                false
+            } else {
+                return Err(nom::Err::Error(ParseFailure("syntax error".to_string())));
+            };
+            let (i, pivot) = string(i)?;
+            let (i, value) = string(i)?;
+            Ok((i, Command::Linsert(key, before, pivot, value)))
+        }
+        CmdCode::Lpos => {
+            let (mut i, key) = string(i)?;
+            let (i2, element) = string(i)?;
+            i = i2;
+            let mut rank = None;
+            let mut count = None;
+            loop {
+                let (i2, maybe_opt) = opt(string)(i)?;
+                i = i2;
+                match maybe_opt {
+                    None => break,
+                    Some(o) if o.eq_ignore_ascii_case(b"RANK") => {
+                        let (i2, r) = u_number::<i64>(i)?;
+                        if r == 0 {
+                            return Err(nom::Err::Failure(ParseFailure(
+                                "RANK can't be zero".to_string(),
+                            )));
+                        }
+                        i = i2;
+                        rank = Some(r);
+                    }
+                    Some(o) if o.eq_ignore_ascii_case(b"COUNT") => {
+                        let (i2, c) = u_number::<usize>(i)?;
+                        i = i2;
+                        count = Some(c);
+                    }
+                    Some(_) => {
+                        return Err(nom::Err::Failure(ParseFailure("syntax error".to_string())));
+                    }
+                }
+            }
+            Ok((i, Command::Lpos(key, element, rank, count)))
+        }
+        CmdCode::Zcard => {
+            let (i, key) = string(i)?;
+            Ok((i, Command::Zcard(key)))
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ParseFailure(String);
 
@@ -479,8 +1733,28 @@ impl fmt::Display for ParseFailure {
     }
 }
 
-fn inline_to_resp(i: &[u8]) -> Vec<u8> {
-    let line = i.strip_suffix(b"\r\n").unwrap_or(i);
+/// Rewrites the inline command (`GET k\r\n`) at `at` in `buf` as the array
+/// `parse` reads, so its arguments borrow from `buf` like those of any other
+/// command. Leaves arrays as they are.
+pub fn expand_inline(buf: &mut Vec<u8>, at: usize) -> Result<(), RedisError> {
+    let i = &buf[at..];
+    if i.starts_with(b"*") || i.starts_with(b"$") {
+        return Ok(());
+    }
+    // the line is cut short until its newline arrives
+    let line_len = i
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or(RedisError::IncompleteInput)?
+        + 1;
+    let resp = inline_to_resp(&i[..line_len]);
+    buf.splice(at..at + line_len, resp);
+    Ok(())
+}
+
+fn inline_to_resp(line: &[u8]) -> Vec<u8> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
     let parts: Vec<&[u8]> = line.split(|&b| b == b' ').collect();
     let mut buf = format!("*{}\r\n", parts.len()).into_bytes();
     for part in parts {
@@ -491,16 +1765,93 @@ fn inline_to_resp(i: &[u8]) -> Vec<u8> {
     buf
 }
 
-pub fn parse(i: &[u8]) -> Result<Command<'_>, RedisError> {
-    if !i.starts_with(b"*") && !i.starts_with(b"$") {
-        let resp = inline_to_resp(i);
-        let (_, cmd) = root(&resp)?;
-        // SAFETY: inline commands (PING, DBSIZE, etc.) don't borrow from input
-        let cmd: Command<'static> = unsafe { std::mem::transmute(cmd) };
-        return Ok(cmd);
+/// Returns the length of the `*<n>\r\n` array of bulk strings at the start
+/// of `i`, or `IncompleteInput` if part of it is yet to arrive. Bulk strings
+/// longer than `max_bulk_len` are a protocol error, so that a client can't
+/// make the server buffer them.
+pub fn frame_len(i: &[u8], max_bulk_len: usize) -> Result<usize, RedisError> {
+    let (args, mut end) = frame_header(i, 0, b'*', "invalid multibulk length")?;
+    for _ in 0..args {
+        let (len, start) = frame_header(i, end, b'$', "invalid bulk length")?;
+        if len > max_bulk_len {
+            return Err(RedisError::Protocol("invalid bulk length".to_string()));
+        }
+        end = start
+            .checked_add(len)
+            .and_then(|end| end.checked_add(2))
+            .ok_or_else(|| RedisError::Protocol("invalid bulk length".to_string()))?;
+        if i.len() < end {
+            return Err(RedisError::IncompleteInput);
+        }
+        if &i[end - 2..end] != b"\r\n" {
+            return Err(RedisError::Protocol("invalid bulk terminator".to_string()));
+        }
+    }
+    Ok(end)
+}
+
+/// Reads the `<prefix><number>\r\n` line at `at`, returning the number and
+/// where the line ends.
+fn frame_header(
+    i: &[u8],
+    at: usize,
+    prefix: u8,
+    invalid: &str,
+) -> Result<(usize, usize), RedisError> {
+    let rest = &i[at..];
+    let Some(line_len) = rest.windows(2).position(|w| w == b"\r\n") else {
+        return Err(RedisError::IncompleteInput);
+    };
+    match rest[..line_len].split_first() {
+        Some((first, digits)) if *first == prefix => match parse_number(digits) {
+            Some(number) => Ok((number, at + line_len + 2)),
+            None => Err(RedisError::Protocol(invalid.to_string())),
+        },
+        _ => Err(RedisError::Protocol(invalid.to_string())),
+    }
+}
+
+/// Rejects `$`-prefixed strings at the start of `i` longer than
+/// `max_bulk_len`, like `frame_len` does within arrays, as soon as their
+/// header arrives. Malformed headers are left for the parser to report.
+fn check_bulk_lens(i: &[u8], max_bulk_len: usize) -> Result<(), RedisError> {
+    let mut at = 0;
+    while i.get(at) == Some(&b'$') {
+        let Ok((len, start)) = frame_header(i, at, b'$', "invalid bulk length") else {
+            return Ok(());
+        };
+        if len > max_bulk_len {
+            return Err(RedisError::Protocol("invalid bulk length".to_string()));
+        }
+        at = start.saturating_add(len).saturating_add(2);
+    }
+    Ok(())
+}
+
+/// Parses the first command in `i`, returning it along with how many bytes
+/// of `i` it took, so pipelined commands after it can be parsed next. Inline
+/// commands have to go through `expand_inline` first.
+pub fn parse(i: &[u8], max_bulk_len: usize) -> Result<(Command<'_>, usize), RedisError> {
+    if i.starts_with(b"*") {
+        // parsing only the frame keeps arguments of the next command out of
+        // variadic ones, and a frame cut short from passing as a command
+        let len = frame_len(i, max_bulk_len)?;
+        let (rest, argc) = cmd_len(&i[..len])?;
+        let (rest, (name, code)) = cmd(rest)?;
+        if !code.accepts(argc) {
+            return Err(RedisError::Parse(format!(
+                "wrong number of arguments for '{}' command",
+                String::from_utf8_lossy(name).to_lowercase()
+            )));
+        }
+        let (_, cmd) = arguments(code, rest)?;
+        return Ok((cmd, len));
     }
-    let (_, cmd) = root(i)?;
-    Ok(cmd)
+    check_bulk_lens(i, max_bulk_len)?;
+    let (rest, cmd) = root(i)?;
+    // lists of values stop before the terminator of their last value
+    let rest = rest.strip_prefix(b"\r\n").unwrap_or(rest);
+    Ok((cmd, i.len() - rest.len()))
 }
 
 impl From<nom::Err<ParseFailure>> for RedisError {
@@ -508,7 +1859,8 @@ impl From<nom::Err<ParseFailure>> for RedisError {
         match value {
             Err::Incomplete(_) => RedisError::IncompleteInput,
             Err::Error(ParseFailure(s)) => RedisError::Parse(format!("invalid input: {s}")),
-            Err::Failure(_) => todo!(),
+            // failures are raised on purpose, with the message Redis replies
+            Err::Failure(ParseFailure(s)) => RedisError::Parse(s),
         }
     }
 }
@@ -539,23 +1891,44 @@ mod tests {
     use super::*;
     use crate::cmd::Command;
 
+    const MAX_BULK_LEN: usize = 512 << 20;
+
+    fn parse(i: &[u8]) -> Result<(Command<'_>, usize), RedisError> {
+        super::parse(i, MAX_BULK_LEN)
+    }
+
     #[test]
     fn test_get() {
         let raw_cmd = "$3\r\nGET\r\n$3\r\naaa\r\n".as_bytes();
-        assert_eq!(parse(raw_cmd).unwrap(), Command::Get("aaa".as_bytes()));
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Get("aaa".as_bytes()));
+    }
+
+    #[test]
+    fn test_strlen() {
+        let raw_cmd = "*2\r\n$6\r\nSTRLEN\r\n$1\r\nk\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Strlen("k".as_bytes()));
+    }
+
+    #[test]
+    fn test_getset() {
+        let raw_cmd = "*3\r\n$6\r\nGETSET\r\n$1\r\nk\r\n$1\r\nv\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::SetAndGet("k".as_bytes(), "v".as_bytes())
+        );
     }
 
     #[test]
     fn test_ping() {
         let raw_cmd = "$4\r\nPING\r\n".as_bytes();
-        assert_eq!(parse(raw_cmd).unwrap(), Command::Ping);
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Ping);
     }
 
     #[test]
     fn test_set() {
         let raw_cmd = "$3\r\nSET\r\n$3\r\naaa\r\n$3\r\naaa\r\n".as_bytes();
         assert_eq!(
-            parse(raw_cmd).unwrap(),
+            parse(raw_cmd).unwrap().0,
             Command::Set("aaa".as_bytes(), "aaa".as_bytes(), None)
         );
     }
@@ -564,7 +1937,7 @@ mod tests {
     fn test_setex() {
         let raw_cmd = "$5\r\nSETEX\r\n$3\r\naaa\r\n$1\r\n5\r\n$3\r\naaa\r\n".as_bytes();
         assert_eq!(
-            parse(raw_cmd).unwrap(),
+            parse(raw_cmd).unwrap().0,
             Command::Set(
                 "aaa".as_bytes(),
                 "aaa".as_bytes(),
@@ -573,13 +1946,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_setex_nonpositive_ttl() {
+        let err = parse(b"*4\r\n$5\r\nSETEX\r\n$1\r\nk\r\n$1\r\n0\r\n$1\r\nv\r\n").unwrap_err();
+        assert_eq!(err.to_string(), "invalid expire time in 'setex' command");
+        let err = parse(b"*4\r\n$6\r\nPSETEX\r\n$1\r\nk\r\n$2\r\n-1\r\n$1\r\nv\r\n").unwrap_err();
+        assert_eq!(err.to_string(), "invalid expire time in 'psetex' command");
+        let err = parse(b"*4\r\n$5\r\nSETEX\r\n$1\r\nk\r\n$1\r\nx\r\n$1\r\nv\r\n").unwrap_err();
+        assert_eq!(err.to_string(), "value is not an integer or out of range");
+        assert_eq!(
+            parse(b"*4\r\n$6\r\nPSETEX\r\n$1\r\nk\r\n$3\r\n100\r\n$1\r\nv\r\n")
+                .unwrap()
+                .0,
+            Command::Set(b"k", b"v", Some(Duration::from_millis(100)))
+        );
+    }
+
+    #[test]
+    fn test_setnx() {
+        assert_eq!(
+            parse(b"*3\r\n$5\r\nSETNX\r\n$1\r\nk\r\n$1\r\nv\r\n")
+                .unwrap()
+                .0,
+            Command::Setnx(b"k", b"v")
+        );
+        let err = parse(b"*2\r\n$5\r\nSETNX\r\n$1\r\nk\r\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "wrong number of arguments for 'setnx' command"
+        );
+    }
+
     #[test]
     fn test_lpush() {
         let raw_cmd =
             "$5\r\nLPUSH\r\n$3\r\naaa\r\n$1\r\n1\r\n$1\r\n2\r\n$1\r\n3\r\n$1\r\n4\r\n$1\r\n5\r\n"
                 .as_bytes();
         assert_eq!(
-            parse(raw_cmd).unwrap(),
+            parse(raw_cmd).unwrap().0,
             Command::Lpush(
                 "aaa".as_bytes(),
                 ["1", "2", "3", "4", "5"]
@@ -596,7 +2000,7 @@ mod tests {
             "$5\r\nRPUSH\r\n$3\r\naaa\r\n$1\r\n1\r\n$1\r\n2\r\n$1\r\n3\r\n$1\r\n4\r\n$1\r\n5\r\n"
                 .as_bytes();
         assert_eq!(
-            parse(raw_cmd).unwrap(),
+            parse(raw_cmd).unwrap().0,
             Command::Rpush(
                 "aaa".as_bytes(),
                 ["1", "2", "3", "4", "5"]
@@ -613,7 +2017,7 @@ mod tests {
             "$6\r\nLPUSHX\r\n$3\r\naaa\r\n$1\r\n1\r\n$1\r\n2\r\n$1\r\n3\r\n$1\r\n4\r\n$1\r\n5\r\n"
                 .as_bytes();
         assert_eq!(
-            parse(raw_cmd).unwrap(),
+            parse(raw_cmd).unwrap().0,
             Command::LpushX(
                 "aaa".as_bytes(),
                 ["1", "2", "3", "4", "5"]
@@ -630,7 +2034,7 @@ mod tests {
             "$6\r\nRPUSHX\r\n$3\r\naaa\r\n$1\r\n1\r\n$1\r\n2\r\n$1\r\n3\r\n$1\r\n4\r\n$1\r\n5\r\n"
                 .as_bytes();
         assert_eq!(
-            parse(raw_cmd).unwrap(),
+            parse(raw_cmd).unwrap().0,
             Command::RpushX(
                 "aaa".as_bytes(),
                 ["1", "2", "3", "4", "5"]
@@ -645,7 +2049,7 @@ mod tests {
     fn test_lpop() {
         let raw_cmd = "$4\r\nLPOP\r\n$2\r\naa\r\n$1\r\n2\r\n".as_bytes();
         assert_eq!(
-            parse(raw_cmd).unwrap(),
+            parse(raw_cmd).unwrap().0,
             Command::Lpop("aa".as_bytes(), Some(2))
         );
     }
@@ -654,23 +2058,954 @@ mod tests {
     fn test_rpop() {
         let raw_cmd = "$4\r\nRPOP\r\n$2\r\naa\r\n$1\r\n2\r\n".as_bytes();
         assert_eq!(
-            parse(raw_cmd).unwrap(),
+            parse(raw_cmd).unwrap().0,
             Command::Rpop("aa".as_bytes(), Some(2))
         );
     }
 
+    #[test]
+    fn test_lpop_invalid_count() {
+        let raw_cmd = "*3\r\n$4\r\nLPOP\r\n$1\r\nk\r\n$10\r\nnotanumber\r\n".as_bytes();
+        assert!(matches!(parse(raw_cmd), Err(RedisError::Parse(_))));
+    }
+
+    #[test]
+    fn test_invalid_lengths() {
+        assert!(matches!(
+            parse("*x\r\n$4\r\nPING\r\n".as_bytes()),
+            Err(RedisError::Protocol(_))
+        ));
+        assert!(matches!(
+            parse("*2\r\n$3\r\nGET\r\n$-3\r\nkey\r\n".as_bytes()),
+            Err(RedisError::Protocol(_))
+        ));
+        assert!(matches!(
+            parse("*2\r\n$3\r\nGET\r\n$10\r\nkey\r\n".as_bytes()),
+            Err(RedisError::IncompleteInput)
+        ));
+    }
+
+    #[test]
+    fn test_lindex() {
+        let raw_cmd = "*3\r\n$6\r\nLINDEX\r\n$1\r\nk\r\n$2\r\n-1\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Lindex("k".as_bytes(), -1)
+        );
+    }
+
+    #[test]
+    fn test_linsert() {
+        let raw_cmd =
+            "*5\r\n$7\r\nLINSERT\r\n$1\r\nk\r\n$5\r\nafter\r\n$1\r\np\r\n$1\r\nv\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Linsert("k".as_bytes(), false, "p".as_bytes(), "v".as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_lpos() {
+        let raw_cmd = "*7\r\n$4\r\nLPOS\r\n$1\r\nk\r\n$1\r\nx\r\n$4\r\nRANK\r\n$2\r\n-1\r\n$5\r\nCOUNT\r\n$1\r\n0\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Lpos("k".as_bytes(), "x".as_bytes(), Some(-1), Some(0))
+        );
+    }
+
+    #[test]
+    fn test_zmscore() {
+        let raw_cmd = "*4\r\n$7\r\nZMSCORE\r\n$1\r\nz\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Zmscore("z".as_bytes(), vec!["a".as_bytes(), "b".as_bytes()])
+        );
+    }
+
+    #[test]
+    fn test_zremrangebyrank() {
+        let raw_cmd =
+            "*4\r\n$15\r\nZREMRANGEBYRANK\r\n$1\r\nz\r\n$1\r\n0\r\n$2\r\n-2\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Zremrangebyrank("z".as_bytes(), 0, -2)
+        );
+    }
+
+    #[test]
+    fn test_zremrangebyscore() {
+        let raw_cmd =
+            "*4\r\n$16\r\nZREMRANGEBYSCORE\r\n$1\r\nz\r\n$4\r\n-inf\r\n$3\r\n(10\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Zremrangebyscore(
+                "z".as_bytes(),
+                ScoreBound::NegInf,
+                ScoreBound::Exclusive(10.0)
+            )
+        );
+    }
+
+    #[test]
+    fn test_zunionstore() {
+        let raw_cmd = "*10\r\n$11\r\nZUNIONSTORE\r\n$1\r\nd\r\n$1\r\n2\r\n$1\r\na\r\n$1\r\nb\r\n$7\r\nWEIGHTS\r\n$1\r\n2\r\n$3\r\n0.5\r\n$9\r\nAGGREGATE\r\n$3\r\nmax\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Zunionstore(
+                "d".as_bytes(),
+                vec!["a".as_bytes(), "b".as_bytes()],
+                Some(vec![2.0, 0.5]),
+                Aggregate::Max
+            )
+        );
+
+        let raw_cmd =
+            "*4\r\n$11\r\nZUNIONSTORE\r\n$1\r\nd\r\n$20\r\n18446744073709551615\r\n$1\r\na\r\n"
+                .as_bytes();
+        assert!(matches!(
+            parse(raw_cmd),
+            Err(RedisError::Parse(msg)) if msg == "syntax error"
+        ));
+    }
+
+    #[test]
+    fn test_zinterstore_defaults() {
+        let raw_cmd = "*4\r\n$11\r\nZINTERSTORE\r\n$1\r\nd\r\n$1\r\n1\r\n$1\r\na\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Zinterstore("d".as_bytes(), vec!["a".as_bytes()], None, Aggregate::Sum)
+        );
+    }
+
+    #[test]
+    fn test_zrangebylex() {
+        let raw_cmd = "*4\r\n$11\r\nZRANGEBYLEX\r\n$1\r\nz\r\n$1\r\n-\r\n$2\r\n(c\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Zrangebylex("z".as_bytes(), LexBound::Min, LexBound::Exclusive(b"c"))
+        );
+
+        let raw_cmd = "*4\r\n$11\r\nZRANGEBYLEX\r\n$1\r\nz\r\n$1\r\na\r\n$1\r\n+\r\n".as_bytes();
+        assert!(parse(raw_cmd).is_err());
+    }
+
+    #[test]
+    fn test_zrem() {
+        let raw_cmd = "*4\r\n$4\r\nZREM\r\n$1\r\nz\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Zrem("z".as_bytes(), vec!["a".as_bytes(), "b".as_bytes()])
+        );
+    }
+
+    #[test]
+    fn test_sinterstore() {
+        let raw_cmd = "*4\r\n$11\r\nSINTERSTORE\r\n$1\r\nd\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Sinterstore("d".as_bytes(), vec!["a".as_bytes(), "b".as_bytes()])
+        );
+    }
+
+    #[test]
+    fn test_smove() {
+        let raw_cmd = "*4\r\n$5\r\nSMOVE\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nm\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Smove("a".as_bytes(), "b".as_bytes(), "m".as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_smismember() {
+        let raw_cmd = "*4\r\n$10\r\nSMISMEMBER\r\n$1\r\ns\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Smismember("s".as_bytes(), vec!["a".as_bytes(), "b".as_bytes()])
+        );
+    }
+
+    #[test]
+    fn test_hrandfield() {
+        let raw_cmd = "*2\r\n$10\r\nHRANDFIELD\r\n$1\r\nh\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Hrandfield("h".as_bytes(), None, false)
+        );
+
+        let raw_cmd =
+            "*4\r\n$10\r\nHRANDFIELD\r\n$1\r\nh\r\n$2\r\n-3\r\n$10\r\nwithvalues\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Hrandfield("h".as_bytes(), Some(-3), true)
+        );
+
+        let raw_cmd =
+            "*3\r\n$10\r\nHRANDFIELD\r\n$1\r\nh\r\n$20\r\n-9223372036854775807\r\n".as_bytes();
+        assert!(matches!(
+            parse(raw_cmd),
+            Err(RedisError::Parse(msg)) if msg == "value is out of range"
+        ));
+    }
+
+    #[test]
+    fn test_srandmember() {
+        let raw_cmd = "*3\r\n$11\r\nSRANDMEMBER\r\n$1\r\ns\r\n$2\r\n-5\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Srandmember("s".as_bytes(), Some(-5))
+        );
+
+        let raw_cmd =
+            "*3\r\n$11\r\nSRANDMEMBER\r\n$1\r\ns\r\n$20\r\n-9223372036854775807\r\n".as_bytes();
+        assert!(matches!(
+            parse(raw_cmd),
+            Err(RedisError::Parse(msg)) if msg == "value is out of range"
+        ));
+        // a large positive count is bounded by the set's size
+        let raw_cmd =
+            "*3\r\n$11\r\nSRANDMEMBER\r\n$1\r\ns\r\n$19\r\n9223372036854775807\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Srandmember("s".as_bytes(), Some(i64::MAX))
+        );
+    }
+
     #[test]
     fn test_del() {
         let raw_cmd = "$3\r\nDEL\r\n$3\r\naaa\r\n$3\r\nbbb\r\n$3\r\nccc\r\n".as_bytes();
         assert_eq!(
-            parse(raw_cmd).unwrap(),
+            parse(raw_cmd).unwrap().0,
             Command::Del(vec!["aaa".as_bytes(), "bbb".as_bytes(), "ccc".as_bytes()]),
         );
     }
 
+    #[test]
+    fn test_unlink() {
+        let raw_cmd = "*3\r\n$6\r\nUNLINK\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Unlink(vec!["a".as_bytes(), "b".as_bytes()])
+        );
+    }
+
+    #[test]
+    fn test_flushdb() {
+        let raw_cmd = "*1\r\n$7\r\nFLUSHDB\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::FlushDb);
+        let raw_cmd = "*2\r\n$7\r\nFLUSHDB\r\n$5\r\nasync\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::FlushDb);
+        let raw_cmd = "*2\r\n$7\r\nFLUSHDB\r\n$4\r\nNOPE\r\n".as_bytes();
+        assert!(parse(raw_cmd).is_err());
+    }
+
+    #[test]
+    fn test_flushall() {
+        let raw_cmd = "*1\r\n$8\r\nFLUSHALL\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::FlushAll);
+        let raw_cmd = "*2\r\n$8\r\nFLUSHALL\r\n$4\r\nSYNC\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::FlushAll);
+    }
+
+    #[test]
+    fn test_select() {
+        let raw_cmd = "*2\r\n$6\r\nSELECT\r\n$2\r\n15\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Select(15));
+        let raw_cmd = "*2\r\n$6\r\nSELECT\r\n$2\r\n-1\r\n".as_bytes();
+        assert!(parse(raw_cmd).is_err());
+    }
+
+    #[test]
+    fn test_wait() {
+        let raw_cmd = "*3\r\n$4\r\nWAIT\r\n$1\r\n1\r\n$3\r\n100\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Wait(1, 100));
+        let raw_cmd = "*3\r\n$4\r\nWAIT\r\n$1\r\n1\r\n$2\r\n-1\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap_err().to_string(),
+            "value is not an integer or out of range"
+        );
+        let raw_cmd = "*2\r\n$4\r\nWAIT\r\n$1\r\n1\r\n".as_bytes();
+        assert!(parse(raw_cmd).is_err());
+    }
+
+    #[test]
+    fn test_names_known_command() {
+        assert!(names_known_command(b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n"));
+        // arity is checked later
+        assert!(names_known_command(b"*1\r\n$3\r\nGET\r\n"));
+        assert!(!names_known_command(b"*1\r\n$4\r\nNOPE\r\n"));
+        assert!(!names_known_command(b"*1\r\n"));
+    }
+
+    #[test]
+    fn test_info() {
+        let raw_cmd = "*1\r\n$4\r\nINFO\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::InfoCmd(None));
+        let raw_cmd = "*2\r\n$4\r\nINFO\r\n$6\r\nMemory\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::InfoCmd(Some(CompactString::const_new("memory")))
+        );
+    }
+
+    #[test]
+    fn test_replicaof() {
+        let raw_cmd = "*3\r\n$9\r\nREPLICAOF\r\n$9\r\nlocalhost\r\n$4\r\n6380\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::ReplicaOf(Some((b"localhost", 6380)))
+        );
+        let raw_cmd = "*3\r\n$7\r\nSLAVEOF\r\n$2\r\nno\r\n$3\r\nOne\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::ReplicaOf(None));
+        let raw_cmd = "*3\r\n$9\r\nREPLICAOF\r\n$4\r\nhost\r\n$5\r\n70000\r\n".as_bytes();
+        assert!(parse(raw_cmd).is_err());
+        let raw_cmd = "*3\r\n$5\r\nPSYNC\r\n$1\r\n?\r\n$2\r\n-1\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Psync);
+    }
+
+    #[test]
+    fn test_move() {
+        let raw_cmd = "*3\r\n$4\r\nMOVE\r\n$3\r\nkey\r\n$1\r\n3\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Move("key".as_bytes(), 3)
+        );
+    }
+
+    #[test]
+    fn test_object() {
+        let raw_cmd = "*3\r\n$6\r\nOBJECT\r\n$8\r\nidletime\r\n$1\r\nk\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::ObjectIdletime("k".as_bytes())
+        );
+        let raw_cmd = "*3\r\n$6\r\nOBJECT\r\n$8\r\nREFCOUNT\r\n$1\r\nk\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::ObjectRefcount("k".as_bytes())
+        );
+        let raw_cmd = "*3\r\n$6\r\nOBJECT\r\n$4\r\nFREQ\r\n$1\r\nk\r\n".as_bytes();
+        assert!(parse(raw_cmd).is_err());
+    }
+
+    #[test]
+    fn test_exists() {
+        let raw_cmd = "*4\r\n$6\r\nEXISTS\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\na\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Exists(vec!["a".as_bytes(), "b".as_bytes(), "a".as_bytes()])
+        );
+    }
+
+    #[test]
+    fn test_touch() {
+        let raw_cmd = "*3\r\n$5\r\nTOUCH\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Touch(vec!["a".as_bytes(), "b".as_bytes()])
+        );
+    }
+
+    #[test]
+    fn test_copy() {
+        let raw_cmd = "*4\r\n$4\r\nCOPY\r\n$1\r\na\r\n$1\r\nb\r\n$7\r\nreplace\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Copy("a".as_bytes(), "b".as_bytes(), None, true)
+        );
+        let raw_cmd =
+            "*6\r\n$4\r\nCOPY\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\ndb\r\n$1\r\n3\r\n$7\r\nREPLACE\r\n"
+                .as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Copy("a".as_bytes(), "b".as_bytes(), Some(3), true)
+        );
+        let raw_cmd =
+            "*5\r\n$4\r\nCOPY\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\nDB\r\n$1\r\nx\r\n".as_bytes();
+        assert!(matches!(
+            parse(raw_cmd),
+            Err(RedisError::Parse(msg)) if msg == "value is not an integer or out of range"
+        ));
+        let raw_cmd = "*4\r\n$4\r\nCOPY\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\nDB\r\n".as_bytes();
+        assert!(matches!(
+            parse(raw_cmd),
+            Err(RedisError::Parse(msg)) if msg == "syntax error"
+        ));
+    }
+
+    #[test]
+    fn test_transaction() {
+        let raw_cmd = "*3\r\n$5\r\nWATCH\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Watch(vec!["a".as_bytes(), "b".as_bytes()])
+        );
+        assert_eq!(
+            parse(b"*1\r\n$7\r\nUNWATCH\r\n").unwrap().0,
+            Command::Unwatch
+        );
+        assert_eq!(parse(b"*1\r\n$5\r\nMULTI\r\n").unwrap().0, Command::Multi);
+        assert_eq!(parse(b"*1\r\n$4\r\nEXEC\r\n").unwrap().0, Command::Exec);
+        assert_eq!(
+            parse(b"*1\r\n$7\r\nDISCARD\r\n").unwrap().0,
+            Command::Discard
+        );
+    }
+
+    #[test]
+    fn test_pubsub() {
+        let raw_cmd = "*3\r\n$9\r\nSUBSCRIBE\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Subscribe(vec!["a".as_bytes(), "b".as_bytes()])
+        );
+        assert_eq!(
+            parse(b"*1\r\n$11\r\nUNSUBSCRIBE\r\n").unwrap().0,
+            Command::Unsubscribe(vec![])
+        );
+        let raw_cmd = "*2\r\n$10\r\nPSUBSCRIBE\r\n$3\r\na.*\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::PSubscribe(vec!["a.*".as_bytes()])
+        );
+        let raw_cmd = "*3\r\n$7\r\nPUBLISH\r\n$1\r\na\r\n$2\r\nhi\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Publish("a".as_bytes(), "hi".as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_pttl() {
+        let raw_cmd = "$4\r\nPTTL\r\n$3\r\naaa\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Pttl("aaa".as_bytes()));
+    }
+
+    #[test]
+    fn test_expire() {
+        let raw_cmd = "$6\r\nEXPIRE\r\n$3\r\naaa\r\n$2\r\n-5\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Expire("aaa".as_bytes(), -5)
+        );
+    }
+
+    #[test]
+    fn test_shutdown() {
+        assert_eq!(
+            parse(b"*1\r\n$8\r\nSHUTDOWN\r\n").unwrap().0,
+            Command::Shutdown(None)
+        );
+        assert_eq!(
+            parse(b"*2\r\n$8\r\nSHUTDOWN\r\n$6\r\nnosave\r\n")
+                .unwrap()
+                .0,
+            Command::Shutdown(Some(false))
+        );
+        assert!(parse(b"*2\r\n$8\r\nSHUTDOWN\r\n$3\r\nNOW\r\n").is_err());
+    }
+
+    #[test]
+    fn test_pipelined() {
+        let raw_cmd = b"*3\r\n$3\r\nDEL\r\n$1\r\na\r\n$1\r\nb\r\n*1\r\n$4\r\nPING\r\n";
+        let (cmd, len) = parse(raw_cmd).unwrap();
+        assert_eq!(cmd, Command::Del(vec![b"a", b"b"]));
+        let rest = &raw_cmd[len..];
+        assert_eq!(parse(rest).unwrap(), (Command::Ping, rest.len()));
+    }
+
+    #[test]
+    fn test_consumed() {
+        let raw_cmd = b"*4\r\n$5\r\nRPUSH\r\n$1\r\nl\r\n$1\r\na\r\n$2\r\nbc\r\n";
+        for end in 1..raw_cmd.len() {
+            assert!(matches!(
+                parse(&raw_cmd[..end]),
+                Err(RedisError::IncompleteInput)
+            ));
+        }
+        let mut pipelined = raw_cmd.to_vec();
+        pipelined.extend_from_slice(b"*1\r\n$4\r\nPING\r\n");
+        assert_eq!(
+            parse(&pipelined).unwrap(),
+            (Command::Rpush(b"l", vec![b"a", b"bc"]), raw_cmd.len())
+        );
+    }
+
+    #[test]
+    fn test_inline_split_across_reads() {
+        let mut buf = b"PI".to_vec();
+        assert!(matches!(
+            expand_inline(&mut buf, 0),
+            Err(RedisError::IncompleteInput)
+        ));
+        buf.extend_from_slice(b"NG\r");
+        assert!(matches!(
+            expand_inline(&mut buf, 0),
+            Err(RedisError::IncompleteInput)
+        ));
+        buf.extend_from_slice(b"\n");
+        expand_inline(&mut buf, 0).unwrap();
+        assert_eq!(parse(&buf).unwrap(), (Command::Ping, buf.len()));
+    }
+
+    #[test]
+    fn test_inline_with_arguments() {
+        let mut buf = b"*1\r\n$4\r\nPING\r\nGET somekey\nPING\r\n".to_vec();
+        expand_inline(&mut buf, 0).unwrap();
+        let (_, at) = parse(&buf).unwrap();
+        expand_inline(&mut buf, at).unwrap();
+        let (cmd, len) = parse(&buf[at..]).unwrap();
+        assert_eq!(cmd, Command::Get(b"somekey"));
+        let at = at + len;
+        expand_inline(&mut buf, at).unwrap();
+        assert_eq!(parse(&buf[at..]).unwrap(), (Command::Ping, buf.len() - at));
+    }
+
+    #[test]
+    fn test_wrong_number_of_arguments() {
+        let err = parse(b"*3\r\n$4\r\nZADD\r\n$1\r\nk\r\n$1\r\n5\r\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "wrong number of arguments for 'zadd' command"
+        );
+        let err = parse(b"*3\r\n$5\r\nHMSET\r\n$1\r\nk\r\n$1\r\nf\r\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "wrong number of arguments for 'hmset' command"
+        );
+        let err = parse(b"*3\r\n$3\r\nGET\r\n$1\r\na\r\n$1\r\nb\r\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "wrong number of arguments for 'get' command"
+        );
+    }
+
+    #[test]
+    fn test_hset_hmset_pairs() {
+        let pairs = vec![b"f1".as_slice(), b"v1", b"f2", b"v2"];
+        assert_eq!(
+            parse(
+                b"*6\r\n$4\r\nHSET\r\n$1\r\nk\r\n$2\r\nf1\r\n$2\r\nv1\r\n$2\r\nf2\r\n$2\r\nv2\r\n"
+            )
+            .unwrap()
+            .0,
+            Command::Hset(b"k", pairs.clone())
+        );
+        assert_eq!(
+            parse(
+                b"*6\r\n$5\r\nHMSET\r\n$1\r\nk\r\n$2\r\nf1\r\n$2\r\nv1\r\n$2\r\nf2\r\n$2\r\nv2\r\n"
+            )
+            .unwrap()
+            .0,
+            Command::HMset(b"k", pairs)
+        );
+        let err =
+            parse(b"*5\r\n$4\r\nHSET\r\n$1\r\nk\r\n$1\r\nf\r\n$1\r\nv\r\n$1\r\ng\r\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "wrong number of arguments for 'hset' command"
+        );
+    }
+
+    #[test]
+    fn test_getex() {
+        assert_eq!(
+            parse(b"*2\r\n$5\r\nGETEX\r\n$1\r\nk\r\n").unwrap().0,
+            Command::GetEx(b"k", ExpiryOption::Keep)
+        );
+        assert_eq!(
+            parse(b"*4\r\n$5\r\nGETEX\r\n$1\r\nk\r\n$2\r\nex\r\n$2\r\n10\r\n")
+                .unwrap()
+                .0,
+            Command::GetEx(b"k", ExpiryOption::In(Duration::from_secs(10)))
+        );
+        assert_eq!(
+            parse(b"*4\r\n$5\r\nGETEX\r\n$1\r\nk\r\n$4\r\nEXAT\r\n$2\r\n10\r\n")
+                .unwrap()
+                .0,
+            Command::GetEx(b"k", ExpiryOption::At(10_000))
+        );
+        assert_eq!(
+            parse(b"*3\r\n$5\r\nGETEX\r\n$1\r\nk\r\n$7\r\nPERSIST\r\n")
+                .unwrap()
+                .0,
+            Command::GetEx(b"k", ExpiryOption::Persist)
+        );
+        assert!(parse(b"*4\r\n$5\r\nGETEX\r\n$1\r\nk\r\n$2\r\nPX\r\n$1\r\n0\r\n").is_err());
+        assert!(parse(b"*3\r\n$5\r\nGETEX\r\n$1\r\nk\r\n$2\r\nPX\r\n").is_err());
+        assert!(parse(b"*4\r\n$5\r\nGETEX\r\n$1\r\nk\r\n$2\r\nKX\r\n$1\r\n1\r\n").is_err());
+    }
+
+    #[test]
+    fn test_zadd_odd_arguments() {
+        assert_eq!(
+            parse(b"*4\r\n$4\r\nZADD\r\n$1\r\nk\r\n$1\r\n5\r\n$1\r\na\r\n")
+                .unwrap()
+                .0,
+            Command::Zadd(b"k", vec![(5, b"a".as_slice())])
+        );
+        assert!(matches!(
+            parse(b"*5\r\n$4\r\nZADD\r\n$1\r\nk\r\n$1\r\n5\r\n$1\r\na\r\n$1\r\n6\r\n"),
+            Err(RedisError::Parse(_))
+        ));
+        assert!(matches!(
+            parse(b"*4\r\n$4\r\nZADD\r\n$1\r\nk\r\n$1\r\nx\r\n$1\r\na\r\n"),
+            Err(RedisError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_frame_len() {
+        assert_eq!(
+            frame_len(b"*1\r\n$4\r\nPING\r\n*1", MAX_BULK_LEN).unwrap(),
+            14
+        );
+        assert_eq!(frame_len(b"*0\r\n", MAX_BULK_LEN).unwrap(), 4);
+        assert!(matches!(
+            frame_len(b"*1\r\n$4\r\nPINGxx", MAX_BULK_LEN),
+            Err(RedisError::Protocol(_))
+        ));
+        assert!(matches!(
+            frame_len(b"*1\r\n$18446744073709551615\r\n", MAX_BULK_LEN),
+            Err(RedisError::Protocol(_))
+        ));
+    }
+
+    #[test]
+    fn test_max_bulk_len() {
+        let raw_cmd = b"*2\r\n$3\r\nGET\r\n$5\r\nabcde\r\n";
+        assert_eq!(frame_len(raw_cmd, 5).unwrap(), raw_cmd.len());
+        // rejected before the bulk string arrives
+        assert!(matches!(
+            super::parse(&raw_cmd[..18], 4),
+            Err(RedisError::Protocol(_))
+        ));
+
+        // the same goes for the `$`-prefixed form without an array header
+        let raw_cmd = b"$3\r\nGET\r\n$5\r\nabcde\r\n";
+        assert_eq!(super::parse(raw_cmd, 5).unwrap().0, Command::Get(b"abcde"));
+        assert!(matches!(
+            super::parse(&raw_cmd[..14], 4),
+            Err(RedisError::Protocol(_))
+        ));
+        assert!(matches!(
+            super::parse(&raw_cmd[..14], 5),
+            Err(RedisError::IncompleteInput)
+        ));
+    }
+
+    #[test]
+    fn test_hscan() {
+        let raw_cmd = "*3\r\n$5\r\nHSCAN\r\n$1\r\nh\r\n$1\r\n0\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Hscan("h".as_bytes(), 0, None, None)
+        );
+
+        let raw_cmd = "*7\r\n$5\r\nZSCAN\r\n$1\r\nz\r\n$2\r\n20\r\n$5\r\nCOUNT\r\n$1\r\n5\r\n$5\r\nMATCH\r\n$2\r\na*\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Zscan("z".as_bytes(), 20, Some("a*".as_bytes()), Some(5))
+        );
+    }
+
+    #[test]
+    fn test_keys() {
+        let raw_cmd = "*2\r\n$4\r\nKEYS\r\n$5\r\nh?llo\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Keys("h?llo".as_bytes()));
+    }
+
+    #[test]
+    fn test_decrby() {
+        let raw_cmd = "*3\r\n$6\r\nDECRBY\r\n$1\r\nk\r\n$2\r\n10\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::DecrBy("k".as_bytes(), 10)
+        );
+
+        let raw_cmd = "*2\r\n$4\r\nDECR\r\n$1\r\nk\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Decr("k".as_bytes()));
+    }
+
+    #[test]
+    fn test_incrbyfloat() {
+        let raw_cmd = "*3\r\n$11\r\nINCRBYFLOAT\r\n$1\r\nk\r\n$4\r\n-1.5\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::IncrByFloat("k".as_bytes(), -1.5)
+        );
+    }
+
+    #[test]
+    fn test_zrangebyscore_bounds() {
+        let raw_cmd =
+            "*4\r\n$13\r\nZRANGEBYSCORE\r\n$1\r\nz\r\n$2\r\n(5\r\n$4\r\n+inf\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Zrangebyscore(
+                "z".as_bytes(),
+                ScoreBound::Exclusive(5.0),
+                ScoreBound::PosInf,
+                false
+            )
+        );
+
+        let raw_cmd =
+            "*4\r\n$13\r\nZRANGEBYSCORE\r\n$1\r\nz\r\n$4\r\n-inf\r\n$2\r\n-5\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Zrangebyscore(
+                "z".as_bytes(),
+                ScoreBound::NegInf,
+                ScoreBound::Inclusive(-5.0),
+                false
+            )
+        );
+    }
+
     #[test]
     fn test_conf() {
         let raw_cmd = "$6\r\nCONFIG\r\n$3\r\nGET\r\n$3\r\nbbb\r\n".as_bytes();
-        assert_eq!(parse(raw_cmd).unwrap(), Command::Config);
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::ConfigGet("bbb".as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_hello() {
+        let raw_cmd = "*2\r\n$5\r\nHELLO\r\n$1\r\n3\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Hello(Some(3), None, None)
+        );
+        let raw_cmd = "*1\r\n$5\r\nHELLO\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Hello(None, None, None));
+        let raw_cmd = "*7\r\n$5\r\nHELLO\r\n$1\r\n2\r\n$7\r\nsetname\r\n$1\r\nc\r\n$4\r\nAUTH\r\n$7\r\ndefault\r\n$2\r\npw\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Hello(Some(2), Some((b"default", b"pw")), Some(b"c"))
+        );
+        let raw_cmd = "*4\r\n$5\r\nHELLO\r\n$1\r\n3\r\n$4\r\nAUTH\r\n$7\r\ndefault\r\n".as_bytes();
+        assert!(matches!(
+            parse(raw_cmd),
+            Err(RedisError::Parse(msg)) if msg == "Syntax error in HELLO option 'AUTH'"
+        ));
+    }
+
+    #[test]
+    fn test_command() {
+        let raw_cmd = "*2\r\n$7\r\nCOMMAND\r\n$5\r\nCOUNT\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::CmdCount);
+        let raw_cmd = "*3\r\n$7\r\nCOMMAND\r\n$4\r\nDOCS\r\n$3\r\nget\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::CmdDocs(vec!["get".as_bytes()])
+        );
+        let raw_cmd = "*1\r\n$7\r\nCOMMAND\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Docs);
+    }
+
+    #[test]
+    fn test_auth() {
+        let raw_cmd = "*2\r\n$4\r\nAUTH\r\n$2\r\npw\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Auth(b"pw"));
+        let raw_cmd = "*3\r\n$4\r\nAUTH\r\n$7\r\ndefault\r\n$2\r\npw\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::AuthUser(b"default", b"pw")
+        );
+        let raw_cmd = "*4\r\n$4\r\nAUTH\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n".as_bytes();
+        assert!(matches!(parse(raw_cmd), Err(RedisError::Parse(_))));
+    }
+
+    #[test]
+    fn test_client() {
+        let raw_cmd = "*3\r\n$6\r\nCLIENT\r\n$7\r\nsetname\r\n$4\r\nconn\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Client(ClientSub::SetName(b"conn"))
+        );
+        let raw_cmd = "*2\r\n$6\r\nCLIENT\r\n$7\r\nGETNAME\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Client(ClientSub::GetName)
+        );
+        let raw_cmd = "*2\r\n$6\r\nCLIENT\r\n$2\r\nID\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Client(ClientSub::Id));
+        let raw_cmd = "*2\r\n$6\r\nCLIENT\r\n$4\r\nLIST\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Client(ClientSub::List));
+        let raw_cmd =
+            "*4\r\n$6\r\nCLIENT\r\n$7\r\nSETINFO\r\n$7\r\nLIB-VER\r\n$3\r\n1.0\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Client(ClientSub::SetInfo(LibVersion(b"1.0")))
+        );
+        let raw_cmd = "*4\r\n$6\r\nCLIENT\r\n$7\r\nSETINFO\r\n$3\r\nFOO\r\n$1\r\nx\r\n".as_bytes();
+        assert!(matches!(parse(raw_cmd), Err(RedisError::Parse(_))));
+        let raw_cmd = "*2\r\n$6\r\nCLIENT\r\n$4\r\nINFO\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Client(ClientSub::Info));
+        let raw_cmd = "*3\r\n$6\r\nCLIENT\r\n$8\r\nno-touch\r\n$2\r\non\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Client(ClientSub::NoTouch(true))
+        );
+        let raw_cmd = "*3\r\n$6\r\nCLIENT\r\n$8\r\nNO-EVICT\r\n$3\r\nOFF\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Client(ClientSub::NoEvict(false))
+        );
+        let raw_cmd = "*3\r\n$6\r\nCLIENT\r\n$8\r\nNO-EVICT\r\n$5\r\nmaybe\r\n".as_bytes();
+        assert!(matches!(parse(raw_cmd), Err(RedisError::Parse(_))));
+        let raw_cmd = "*2\r\n$6\r\nCLIENT\r\n$4\r\nNOPE\r\n".as_bytes();
+        assert!(matches!(parse(raw_cmd), Err(RedisError::Parse(_))));
+    }
+
+    #[test]
+    fn test_blocking_pop() {
+        let raw_cmd = "*4\r\n$5\r\nBLPOP\r\n$1\r\na\r\n$1\r\nb\r\n$3\r\n1.5\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Blpop(vec![b"a", b"b"], Duration::from_millis(1500))
+        );
+        let raw_cmd = "*3\r\n$5\r\nBRPOP\r\n$1\r\na\r\n$1\r\n0\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Brpop(vec![b"a"], Duration::ZERO)
+        );
+        let raw_cmd = "*3\r\n$5\r\nBLPOP\r\n$1\r\na\r\n$2\r\n-1\r\n".as_bytes();
+        assert!(matches!(
+            parse(raw_cmd),
+            Err(RedisError::Parse(msg)) if msg == "timeout is negative"
+        ));
+        let raw_cmd = "*3\r\n$5\r\nBLPOP\r\n$1\r\na\r\n$1\r\nx\r\n".as_bytes();
+        assert!(matches!(
+            parse(raw_cmd),
+            Err(RedisError::Parse(msg)) if msg == "timeout is not a float or out of range"
+        ));
+        let raw_cmd = "*3\r\n$5\r\nBLPOP\r\n$1\r\na\r\n$4\r\n1e19\r\n".as_bytes();
+        assert!(matches!(
+            parse(raw_cmd),
+            Err(RedisError::Parse(msg)) if msg == "timeout is out of range"
+        ));
+        let raw_cmd = "*2\r\n$5\r\nBLPOP\r\n$1\r\na\r\n".as_bytes();
+        assert!(matches!(parse(raw_cmd), Err(RedisError::Parse(_))));
+    }
+
+    #[test]
+    fn test_reset() {
+        let raw_cmd = "*1\r\n$5\r\nRESET\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Reset);
+    }
+
+    #[test]
+    fn test_debug() {
+        let raw_cmd = "*3\r\n$5\r\nDEBUG\r\n$5\r\nsleep\r\n$3\r\n0.5\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Debug(DebugSub::Sleep(Duration::from_millis(500)))
+        );
+        let raw_cmd = "*3\r\n$5\r\nDEBUG\r\n$5\r\nSLEEP\r\n$2\r\n-1\r\n".as_bytes();
+        assert!(matches!(parse(raw_cmd), Err(RedisError::Parse(_))));
+        let raw_cmd = "*3\r\n$5\r\nDEBUG\r\n$17\r\nSET-ACTIVE-EXPIRE\r\n$1\r\n0\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Debug(DebugSub::SetActiveExpire(false))
+        );
+        let raw_cmd = "*3\r\n$5\r\nDEBUG\r\n$6\r\nobject\r\n$1\r\nk\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::Debug(DebugSub::Object(b"k"))
+        );
+        let raw_cmd = "*2\r\n$5\r\nDEBUG\r\n$4\r\nJMAP\r\n".as_bytes();
+        assert!(matches!(parse(raw_cmd), Err(RedisError::Parse(_))));
+    }
+
+    #[test]
+    fn test_acl() {
+        let raw_cmd = "*2\r\n$3\r\nACL\r\n$6\r\nwhoami\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Acl(AclSub::WhoAmI));
+        let raw_cmd = "*2\r\n$3\r\nACL\r\n$4\r\nLIST\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::Acl(AclSub::List));
+        let raw_cmd = "*2\r\n$3\r\nACL\r\n$7\r\nSETUSER\r\n".as_bytes();
+        assert!(matches!(
+            parse(raw_cmd),
+            Err(RedisError::Parse(msg)) if msg == "unknown subcommand 'SETUSER'. Try ACL HELP."
+        ));
+    }
+
+    #[test]
+    fn test_key_spec() {
+        let argv: Vec<&[u8]> = vec![b"GET", b"k"];
+        assert_eq!(key_spec(&CmdCode::Get), KeySpec::range(1, 1, 1));
+        assert_eq!(key_spec(&CmdCode::Get).keys(&argv), vec![b"k"]);
+
+        let argv: Vec<&[u8]> = vec![b"DEL", b"a", b"b", b"c"];
+        assert_eq!(key_spec(&CmdCode::Del), KeySpec::range(1, -1, 1));
+        assert_eq!(key_spec(&CmdCode::Del).keys(&argv), vec![b"a", b"b", b"c"]);
+
+        let argv: Vec<&[u8]> = vec![b"SMOVE", b"src", b"dst", b"m"];
+        assert_eq!(key_spec(&CmdCode::Smove).keys(&argv), vec![b"src", b"dst"]);
+
+        // keys interleaved with values, the way MSET lays them out
+        let argv: Vec<&[u8]> = vec![b"MSET", b"a", b"1", b"b", b"2"];
+        assert_eq!(KeySpec::range(1, -1, 2).keys(&argv), vec![b"a", b"b"]);
+
+        assert!(key_spec(&CmdCode::Ping).keys(&[b"PING"]).is_empty());
+        assert!(key_spec(&CmdCode::Zunionstore).movable);
+
+        let argv: Vec<&[u8]> = vec![b"BLPOP", b"a", b"b", b"0"];
+        assert_eq!(key_spec(&CmdCode::Blpop).keys(&argv), vec![b"a", b"b"]);
+    }
+
+    #[test]
+    fn test_command_getkeys() {
+        let raw_cmd = "*5\r\n$7\r\nCOMMAND\r\n$7\r\ngetkeys\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\nb\r\n"
+            .as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::CmdGetKeys(vec!["a".as_bytes()])
+        );
+        let raw_cmd = "*6\r\n$7\r\nCOMMAND\r\n$7\r\nGETKEYS\r\n$4\r\nZADD\r\n$1\r\nz\r\n$1\r\n1\r\n$1\r\nm\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::CmdGetKeys(vec!["z".as_bytes()])
+        );
+        let raw_cmd = "*3\r\n$7\r\nCOMMAND\r\n$7\r\nGETKEYS\r\n$4\r\nPING\r\n".as_bytes();
+        assert!(matches!(
+            parse(raw_cmd),
+            Err(RedisError::Parse(msg)) if msg == "The command has no key arguments"
+        ));
+        let raw_cmd = "*3\r\n$7\r\nCOMMAND\r\n$7\r\nGETKEYS\r\n$4\r\nNOPE\r\n".as_bytes();
+        assert!(matches!(
+            parse(raw_cmd),
+            Err(RedisError::Parse(msg)) if msg == "Invalid command specified"
+        ));
+    }
+
+    #[test]
+    fn test_command_names_are_unique() {
+        let mut names: Vec<_> = command_names().collect();
+        let len = names.len();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), len);
+        assert!(names.contains(&"GET".as_bytes()));
+    }
+
+    #[test]
+    fn test_config_resetstat() {
+        let raw_cmd = "*2\r\n$6\r\nCONFIG\r\n$9\r\nRESETSTAT\r\n".as_bytes();
+        assert_eq!(parse(raw_cmd).unwrap().0, Command::ConfigResetStat);
+    }
+
+    #[test]
+    fn test_config_set() {
+        let raw_cmd =
+            "*4\r\n$6\r\nCONFIG\r\n$3\r\nset\r\n$9\r\nmaxmemory\r\n$3\r\n1mb\r\n".as_bytes();
+        assert_eq!(
+            parse(raw_cmd).unwrap().0,
+            Command::ConfigSet("maxmemory".as_bytes(), "1mb".as_bytes())
+        );
     }
 }
@@ -0,0 +1,243 @@
+use crate::eviction::{Policy, parse_bytes};
+use crate::glob::glob_match;
+use crate::notify::KeyspaceEvents;
+use anyhow::bail;
+use compact_str::CompactString;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Parameters known to `CONFIG GET`/`CONFIG SET` and their defaults.
+const DEFAULTS: [(&str, &str); 10] = [
+    ("maxmemory", "0"),
+    ("maxmemory-policy", "noeviction"),
+    // no snapshot points until persistence is configured
+    ("save", ""),
+    ("appendonly", "no"),
+    ("timeout", "0"),
+    ("notify-keyspace-events", ""),
+    ("dbfilename", "dump.rdb"),
+    ("proto-max-bulk-len", "536870912"),
+    ("client-query-buffer-limit", "1073741824"),
+    // no password, so clients start authenticated
+    ("requirepass", ""),
+];
+
+/// Server parameters as set by `CONFIG SET`. Values are validated on the way
+/// in, so the typed accessors can't fail.
+#[derive(Debug)]
+pub struct Config {
+    params: HashMap<CompactString, CompactString>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            params: DEFAULTS
+                .iter()
+                .map(|(name, value)| (CompactString::new(name), CompactString::new(value)))
+                .collect(),
+        }
+    }
+}
+
+impl Config {
+    /// Parameters whose name matches the glob `pattern`, sorted by name.
+    pub fn get(&self, pattern: &[u8]) -> Vec<(&CompactString, &CompactString)> {
+        let pattern = pattern.to_ascii_lowercase();
+        let mut params: Vec<_> = self
+            .params
+            .iter()
+            .filter(|(name, _)| glob_match(&pattern, name.as_bytes()))
+            .collect();
+        params.sort_unstable();
+        params
+    }
+
+    pub fn set(&mut self, name: &[u8], value: &[u8]) -> anyhow::Result<()> {
+        let name = String::from_utf8_lossy(name).to_ascii_lowercase();
+        let value = String::from_utf8_lossy(value);
+        let value = match name.as_str() {
+            // stored in bytes, whatever unit it was given in
+            "maxmemory" => match parse_bytes(&value) {
+                None => bail!("Invalid argument '{value}' for CONFIG SET 'maxmemory'"),
+                Some(bytes) => bytes.to_string(),
+            },
+            "maxmemory-policy" => value.parse::<Policy>()?.name().to_string(),
+            // as in Redis, too low a limit would lock clients out
+            "proto-max-bulk-len" | "client-query-buffer-limit" => match parse_bytes(&value) {
+                Some(bytes) if bytes >= 1 << 20 => bytes.to_string(),
+                _ => bail!("Invalid argument '{value}' for CONFIG SET '{name}'"),
+            },
+            "save" => {
+                let numbers: Vec<_> = value.split_whitespace().collect();
+                if numbers.len() % 2 != 0 || numbers.iter().any(|n| n.parse::<u64>().is_err()) {
+                    bail!("Invalid argument '{value}' for CONFIG SET 'save'");
+                }
+                numbers.join(" ")
+            }
+            "appendonly" => {
+                if !value.eq_ignore_ascii_case("yes") && !value.eq_ignore_ascii_case("no") {
+                    bail!("Invalid argument '{value}' for CONFIG SET 'appendonly'");
+                }
+                value.to_ascii_lowercase()
+            }
+            "timeout" => match value.parse::<u64>() {
+                Err(_) => bail!("Invalid argument '{value}' for CONFIG SET 'timeout'"),
+                Ok(seconds) => seconds.to_string(),
+            },
+            "notify-keyspace-events" => match value.parse::<KeyspaceEvents>() {
+                Err(_) => {
+                    bail!("Invalid argument '{value}' for CONFIG SET 'notify-keyspace-events'")
+                }
+                Ok(events) => events.to_string(),
+            },
+            "dbfilename" => {
+                if value.is_empty() || value.contains('/') {
+                    bail!("Invalid argument '{value}' for CONFIG SET 'dbfilename'");
+                }
+                value.into_owned()
+            }
+            "requirepass" => value.into_owned(),
+            _ => bail!("Unknown option or number of arguments for CONFIG SET - '{name}'"),
+        };
+        self.params
+            .insert(CompactString::new(name), CompactString::new(value));
+        Ok(())
+    }
+
+    fn value(&self, name: &str) -> &str {
+        self.params.get(name).map_or("", CompactString::as_str)
+    }
+
+    pub fn max_memory(&self) -> usize {
+        self.value("maxmemory").parse().unwrap_or_default()
+    }
+
+    pub fn max_memory_policy(&self) -> Policy {
+        self.value("maxmemory-policy").parse().unwrap_or_default()
+    }
+
+    /// The longest bulk string a client may send.
+    pub fn proto_max_bulk_len(&self) -> usize {
+        self.value("proto-max-bulk-len").parse().unwrap_or_default()
+    }
+
+    /// How many bytes of commands a client may have buffered.
+    pub fn client_query_buffer_limit(&self) -> usize {
+        self.value("client-query-buffer-limit")
+            .parse()
+            .unwrap_or_default()
+    }
+
+    /// Where `SAVE` writes the snapshot, relative to the working directory.
+    pub fn db_filename(&self) -> &Path {
+        Path::new(self.value("dbfilename"))
+    }
+
+    /// Whether `save` sets any snapshot points, which also makes shutting
+    /// down take a snapshot.
+    pub fn has_save_points(&self) -> bool {
+        !self.value("save").is_empty()
+    }
+
+    pub fn append_only(&self) -> bool {
+        self.value("appendonly") == "yes"
+    }
+
+    pub fn keyspace_events(&self) -> KeyspaceEvents {
+        self.value("notify-keyspace-events")
+            .parse()
+            .unwrap_or_default()
+    }
+
+    /// Whether clients have to `AUTH` before running commands.
+    pub fn requires_pass(&self) -> bool {
+        !self.value("requirepass").is_empty()
+    }
+
+    /// The rules of the `default` user as `ACL LIST` shows them. Like Redis,
+    /// this never shows the password itself.
+    pub fn default_user_rules(&self) -> &'static str {
+        if self.requires_pass() {
+            "user default on sanitize-payload ~* &* +@all"
+        } else {
+            "user default on nopass sanitize-payload ~* &* +@all"
+        }
+    }
+
+    /// Whether `user` may log in with `password`. The `default` user is the
+    /// only one there is, and takes any password when none is required.
+    pub fn check_user(&self, user: &[u8], password: &[u8]) -> bool {
+        user == b"default" && (!self.requires_pass() || self.check_password(password))
+    }
+
+    /// Whether `password` is the one `requirepass` sets, compared in a time
+    /// that depends only on the length of `password`.
+    pub fn check_password(&self, password: &[u8]) -> bool {
+        let expected = self.value("requirepass").as_bytes();
+        let mut diff = u8::from(expected.len() != password.len());
+        for (i, b) in password.iter().enumerate() {
+            diff |= b ^ expected.get(i).copied().unwrap_or(0);
+        }
+        diff == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_glob() {
+        let config = Config::default();
+        let names: Vec<_> = config.get(b"max*").into_iter().map(|(n, _)| n).collect();
+        assert_eq!(names, ["maxmemory", "maxmemory-policy"]);
+        assert_eq!(config.get(b"TIMEOUT"), [(&"timeout".into(), &"0".into())]);
+        assert!(config.get(b"nope").is_empty());
+    }
+
+    #[test]
+    fn test_set_validates_and_normalizes() {
+        let mut config = Config::default();
+        config.set(b"MAXMEMORY", b"1kb").unwrap();
+        assert_eq!(config.max_memory(), 1024);
+        assert_eq!(config.value("maxmemory"), "1024");
+        config.set(b"maxmemory-policy", b"ALLKEYS-LRU").unwrap();
+        assert_eq!(config.max_memory_policy(), Policy::AllKeysLru);
+        config.set(b"save", b"3600  1 300 100").unwrap();
+        assert_eq!(config.value("save"), "3600 1 300 100");
+        config.set(b"appendonly", b"YES").unwrap();
+        assert_eq!(config.value("appendonly"), "yes");
+        config.set(b"notify-keyspace-events", b"EKg").unwrap();
+        assert_eq!(config.value("notify-keyspace-events"), "gKE");
+        assert!(config.keyspace_events().is_enabled(KeyspaceEvents::GENERIC));
+        config.set(b"proto-max-bulk-len", b"1mb").unwrap();
+        assert_eq!(config.proto_max_bulk_len(), 1 << 20);
+
+        assert!(config.set(b"maxmemory", b"lots").is_err());
+        assert!(config.set(b"save", b"3600").is_err());
+        assert!(config.set(b"appendonly", b"maybe").is_err());
+        assert!(config.set(b"timeout", b"-1").is_err());
+        assert!(config.set(b"notify-keyspace-events", b"Kq").is_err());
+        assert!(config.set(b"dbfilename", b"../dump.rdb").is_err());
+        assert!(config.set(b"client-query-buffer-limit", b"1kb").is_err());
+        assert!(config.set(b"nope", b"1").is_err());
+        assert_eq!(config.max_memory(), 1024);
+    }
+
+    #[test]
+    fn test_check_password() {
+        let mut config = Config::default();
+        assert!(!config.requires_pass());
+        config.set(b"requirepass", b"secret").unwrap();
+        assert!(config.requires_pass());
+        assert!(config.check_password(b"secret"));
+        assert!(!config.check_password(b"secreT"));
+        assert!(!config.check_password(b"secret\0"));
+        assert!(!config.check_password(b"secre"));
+        assert!(!config.check_password(b""));
+        assert!(config.check_user(b"default", b"secret"));
+        assert!(!config.check_user(b"default", b"other"));
+        assert!(!config.check_user(b"admin", b"secret"));
+    }
+}
@@ -1,3 +1,7 @@
+use crate::err::WrongType;
+use crate::numerical_ops::{add_float, format_float};
+use crate::random::Rng;
+use crate::scan::scan_page;
 use crate::stored_value::StoredValue;
 use anyhow::bail;
 use bytes::Bytes;
@@ -9,12 +13,32 @@ pub trait HMapDictOps {
         &self,
         key: &[u8],
         fields: &[&[u8]],
-    ) -> anyhow::Result<Option<(Vec<&Bytes>, usize)>>;
-    fn dict_mset(&mut self, key: &[u8], fields_and_values: &[&[u8]]) -> anyhow::Result<()>;
+    ) -> anyhow::Result<(Vec<Option<&Bytes>>, usize)>;
+    /// Sets the fields to the values, returning how many fields are new.
+    fn dict_mset(&mut self, key: &[u8], fields_and_values: &[&[u8]]) -> anyhow::Result<usize>;
     fn dict_get_all(&self, key: &[u8]) -> anyhow::Result<Option<(Vec<&Bytes>, usize)>>;
     fn dict_incr_by(&mut self, key: &[u8], field: &[u8], incr_by: i64) -> anyhow::Result<Bytes>;
+    fn dict_incr_by_float(
+        &mut self,
+        key: &[u8],
+        field: &[u8],
+        incr_by: f64,
+    ) -> anyhow::Result<Bytes>;
     fn dict_exists(&self, key: &[u8], field: &[u8]) -> anyhow::Result<bool>;
     fn dict_keys(&self, key: &[u8]) -> anyhow::Result<Option<(Vec<&Bytes>, usize)>>;
+    fn dict_rand(
+        &self,
+        key: &[u8],
+        count: i64,
+        rng: &mut Rng,
+    ) -> anyhow::Result<Vec<(&Bytes, &Bytes)>>;
+    fn dict_scan(
+        &self,
+        key: &[u8],
+        cursor: u64,
+        pattern: Option<&[u8]>,
+        count: Option<usize>,
+    ) -> anyhow::Result<(u64, Vec<Bytes>)>;
 }
 
 impl HMapDictOps for HashMap<Bytes, StoredValue> {
@@ -30,31 +54,36 @@ impl HMapDictOps for HashMap<Bytes, StoredValue> {
         &self,
         key: &[u8],
         fields: &[&[u8]],
-    ) -> anyhow::Result<Option<(Vec<&Bytes>, usize)>> {
+    ) -> anyhow::Result<(Vec<Option<&Bytes>>, usize)> {
         match self.get(key) {
-            None => Ok(None),
+            None => Ok((vec![None; fields.len()], fields.len())),
             Some(StoredValue::Dict(dict)) => {
-                let values: Vec<&Bytes> = fields.iter().filter_map(|f| dict.get(*f)).collect();
+                let values: Vec<Option<&Bytes>> = fields.iter().map(|f| dict.get(*f)).collect();
                 let len = values.len();
-                Ok(Some((values, len)))
+                Ok((values, len))
             }
             _ => bail!("stored value isn't a dict"),
         }
     }
 
-    fn dict_mset(&mut self, key: &[u8], fields_and_values: &[&[u8]]) -> anyhow::Result<()> {
+    fn dict_mset(&mut self, key: &[u8], fields_and_values: &[&[u8]]) -> anyhow::Result<usize> {
+        if !fields_and_values.len().is_multiple_of(2) {
+            bail!("wrong number of arguments for 'hset' command");
+        }
         let stored_value = self
             .entry(Bytes::copy_from_slice(key))
             .or_insert(StoredValue::Dict(Default::default()));
         match stored_value {
             StoredValue::Dict(dict) => {
-                for chunk in fields_and_values.chunks(2) {
-                    dict.insert(
-                        Bytes::copy_from_slice(chunk[0]),
-                        Bytes::copy_from_slice(chunk[1]),
+                let mut added = 0;
+                for pair in fields_and_values.chunks_exact(2) {
+                    let previous = dict.insert(
+                        Bytes::copy_from_slice(pair[0]),
+                        Bytes::copy_from_slice(pair[1]),
                     );
+                    added += usize::from(previous.is_none());
                 }
-                Ok(())
+                Ok(added)
             }
             _ => bail!("stored value isn't a dict"),
         }
@@ -86,15 +115,51 @@ impl HMapDictOps for HashMap<Bytes, StoredValue> {
                             .map_err(|_| anyhow::anyhow!("hash value is not an integer"))?
                     }
                 };
-                let new_value = (current + incr_by).to_string();
+                let Some(new_value) = current.checked_add(incr_by) else {
+                    bail!("increment or decrement would overflow");
+                };
+                let new_value = new_value.to_string();
                 let value_bytes = Bytes::copy_from_slice(new_value.as_bytes());
                 dict.insert(field_key, value_bytes.clone());
                 Ok(value_bytes)
             }
-            _ => bail!("stored value isn't a dict"),
+            _ => bail!(WrongType),
         }
     }
 
+    fn dict_incr_by_float(
+        &mut self,
+        key: &[u8],
+        field: &[u8],
+        incr_by: f64,
+    ) -> anyhow::Result<Bytes> {
+        let current = match self.get(key) {
+            None => 0.0,
+            Some(StoredValue::Dict(dict)) => match dict.get(field) {
+                None => 0.0,
+                Some(bytes) => {
+                    match str::from_utf8(bytes)
+                        .ok()
+                        .and_then(|s| s.parse::<f64>().ok())
+                    {
+                        Some(num) if num.is_finite() => num,
+                        _ => bail!("hash value is not a float"),
+                    }
+                }
+            },
+            Some(_) => bail!(WrongType),
+        };
+        let value_bytes = Bytes::from(format_float(add_float(current, incr_by)?));
+        // the hash is only created once the increment is known to be valid
+        if let StoredValue::Dict(dict) = self
+            .entry(Bytes::copy_from_slice(key))
+            .or_insert(StoredValue::Dict(Default::default()))
+        {
+            dict.insert(Bytes::copy_from_slice(field), value_bytes.clone());
+        }
+        Ok(value_bytes)
+    }
+
     fn dict_exists(&self, key: &[u8], field: &[u8]) -> anyhow::Result<bool> {
         match self.get(key) {
             None => Ok(false),
@@ -114,4 +179,67 @@ impl HMapDictOps for HashMap<Bytes, StoredValue> {
             _ => bail!("stored value isn't a dict"),
         }
     }
+
+    /// Random fields with their values, picked like `SRANDMEMBER` members.
+    fn dict_rand(
+        &self,
+        key: &[u8],
+        count: i64,
+        rng: &mut Rng,
+    ) -> anyhow::Result<Vec<(&Bytes, &Bytes)>> {
+        match self.get(key) {
+            None => Ok(vec![]),
+            Some(StoredValue::Dict(dict)) => Ok(rng.sample(dict.iter(), count)),
+            _ => bail!("stored value isn't a dict"),
+        }
+    }
+
+    fn dict_scan(
+        &self,
+        key: &[u8],
+        cursor: u64,
+        pattern: Option<&[u8]>,
+        count: Option<usize>,
+    ) -> anyhow::Result<(u64, Vec<Bytes>)> {
+        match self.get(key) {
+            None => Ok((0, vec![])),
+            Some(StoredValue::Dict(dict)) => {
+                // sorted so the cursor means the same thing across calls
+                let mut fields: Vec<(&Bytes, &Bytes)> = dict.iter().collect();
+                fields.sort_unstable();
+                let items = fields
+                    .into_iter()
+                    .map(|(field, value)| (field.clone(), Some(value.clone())));
+                Ok(scan_page(items, cursor, pattern, count))
+            }
+            _ => bail!("stored value isn't a dict"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dict_incr_by_float_validates_first() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        assert!(hmap.dict_incr_by_float(b"h", b"f", f64::INFINITY).is_err());
+        assert!(!hmap.contains_key(b"h".as_slice()));
+        assert_eq!(
+            hmap.dict_incr_by_float(b"h", b"f", 0.1).unwrap().as_ref(),
+            b"0.1"
+        );
+    }
+
+    #[test]
+    fn test_dict_incr_by_overflow_is_an_error() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        hmap.dict_incr_by(b"h", b"f", i64::MAX).unwrap();
+        assert!(hmap.dict_incr_by(b"h", b"f", 1).is_err());
+        assert_eq!(
+            hmap.dict_get(b"h", b"f").unwrap().unwrap().as_ref(),
+            i64::MAX.to_string().as_bytes()
+        );
+    }
 }
@@ -7,6 +7,9 @@ use std::num::ParseIntError;
 pub enum RedisError {
     IncompleteInput,
     Parse(String),
+    /// Input that can't be framed as commands, after which the client is
+    /// disconnected.
+    Protocol(String),
     IO(String),
 }
 
@@ -15,6 +18,7 @@ impl fmt::Display for RedisError {
         match self {
             RedisError::Parse(message) => write!(f, "{message}"),
             RedisError::IncompleteInput => write!(f, "incomplete input"),
+            RedisError::Protocol(message) => write!(f, "Protocol error: {message}"),
             RedisError::IO(message) => write!(f, "{message}"),
         }
     }
@@ -26,6 +30,19 @@ impl Error for RedisError {
     }
 }
 
+/// A command run against a key holding another type, for the commands that
+/// can also fail otherwise, to tell the two apart.
+#[derive(Debug)]
+pub struct WrongType;
+
+impl fmt::Display for WrongType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Operation against a key holding the wrong kind of value")
+    }
+}
+
+impl Error for WrongType {}
+
 impl From<io::Error> for RedisError {
     fn from(value: io::Error) -> Self {
         RedisError::IO(format!("IO error: {value}"))
@@ -0,0 +1,260 @@
+use crate::hmap_ops::AccessMap;
+use crate::random::Rng;
+use crate::stored_value::StoredValue;
+use anyhow::bail;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Keys sampled when looking for a victim, Redis' `maxmemory-samples`
+/// default.
+const SAMPLES: usize = 5;
+
+/// Keys evicted at most per command, so that one command going over the
+/// limit by a lot doesn't stall the server; the next ones evict the rest.
+const EVICTIONS_PER_CALL: usize = 64;
+
+/// What to do once `maxmemory` is exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Policy {
+    #[default]
+    NoEviction,
+    AllKeysLru,
+}
+
+impl Policy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Policy::NoEviction => "noeviction",
+            Policy::AllKeysLru => "allkeys-lru",
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("noeviction") {
+            Ok(Policy::NoEviction)
+        } else if s.eq_ignore_ascii_case("allkeys-lru") {
+            Ok(Policy::AllKeysLru)
+        } else {
+            bail!("unsupported maxmemory policy '{s}'")
+        }
+    }
+}
+
+/// Parses a `maxmemory` value: plain bytes or a number with a `k`, `kb`,
+/// `m`, `mb`, `g` or `gb` suffix, where the `b` forms are powers of 1024.
+pub fn parse_bytes(s: &str) -> Option<usize> {
+    let lower = s.to_ascii_lowercase();
+    let digits = lower.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = match &lower[digits.len()..] {
+        "" => 1,
+        "k" => 1_000,
+        "kb" => 1 << 10,
+        "m" => 1_000_000,
+        "mb" => 1 << 20,
+        "g" => 1_000_000_000,
+        "gb" => 1 << 30,
+        _ => return None,
+    };
+    digits.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+/// The `maxmemory` limit, in bytes with 0 meaning unlimited, and how it's
+/// enforced.
+#[derive(Debug, Default)]
+pub struct MaxMemory {
+    pub limit: usize,
+    pub policy: Policy,
+    /// Keys evicted since startup, for `INFO`.
+    pub evicted_keys: u64,
+    /// Keys eviction samples from with their database index, taken from
+    /// the databases whenever it runs out. Keys deleted since are dropped
+    /// as they are sampled, and keys added since wait for the next round,
+    /// being recently used anyway.
+    candidates: Vec<(usize, Bytes)>,
+}
+
+impl MaxMemory {
+    pub fn is_enforced(&self) -> bool {
+        self.limit > 0 && self.policy != Policy::NoEviction
+    }
+
+    /// Evicts approximately least recently used keys until `used` reports at
+    /// most the limit, up to a few keys per call. Each round samples a few
+    /// keys and drops the one idle the longest. It gives up once an eviction
+    /// doesn't lower `used`, as some of the memory counted is not the keys'.
+    /// Returns the evicted keys with their database index.
+    pub fn evict(
+        &mut self,
+        dbs: &mut [HashMap<Bytes, StoredValue>],
+        access_maps: &mut [AccessMap],
+        rng: &mut Rng,
+        mut used: impl FnMut(&[HashMap<Bytes, StoredValue>]) -> anyhow::Result<usize>,
+    ) -> anyhow::Result<Vec<(usize, Bytes)>> {
+        let mut evicted = Vec::with_capacity(EVICTIONS_PER_CALL);
+        let mut last_used = None;
+        while evicted.len() < EVICTIONS_PER_CALL {
+            let now_used = used(dbs)?;
+            if now_used <= self.limit || last_used.is_some_and(|last| now_used >= last) {
+                break;
+            }
+            let Some(slot) = self.pick_victim(dbs, access_maps, rng) else {
+                // a round over every key is done, start the next one unless
+                // the databases are empty
+                if !self.refill(dbs) {
+                    break;
+                }
+                // the candidates take memory of their own
+                last_used = None;
+                continue;
+            };
+            let (index, key) = self.candidates.swap_remove(slot);
+            dbs[index].remove(&key);
+            access_maps[index].remove(&key);
+            evicted.push((index, key));
+            last_used = Some(now_used);
+        }
+        self.evicted_keys += evicted.len() as u64;
+        Ok(evicted)
+    }
+
+    /// The candidate slot of the key idle the longest of a few sampled ones,
+    /// or `None` once no candidate is left.
+    fn pick_victim(
+        &mut self,
+        dbs: &[HashMap<Bytes, StoredValue>],
+        access_maps: &[AccessMap],
+        rng: &mut Rng,
+    ) -> Option<usize> {
+        while !self.candidates.is_empty() {
+            let mut best = None;
+            let mut stale = Vec::new();
+            for slot in rng.sample_distinct(self.candidates.len(), SAMPLES) {
+                let (index, key) = &self.candidates[slot];
+                if !dbs[*index].contains_key(key) {
+                    stale.push(slot);
+                    continue;
+                }
+                // a key without an access stamp sorts first, as `None` < `Some`
+                let used_at = access_maps[*index].get(key).copied();
+                if best.is_none_or(|(_, best_used_at)| used_at < best_used_at) {
+                    best = Some((slot, used_at));
+                }
+            }
+            // keys deleted since leave from the highest slot down, so the
+            // ones left to remove stay put
+            for slot in stale.into_iter().rev() {
+                self.candidates.swap_remove(slot);
+                // the victim may have been the last slot, moved to this one
+                if let Some((best_slot, _)) = &mut best
+                    && *best_slot == self.candidates.len()
+                {
+                    *best_slot = slot;
+                }
+            }
+            if let Some((slot, _)) = best {
+                return Some(slot);
+            }
+        }
+        None
+    }
+
+    /// Takes every key as a candidate again. Returns whether there are any.
+    fn refill(&mut self, dbs: &[HashMap<Bytes, StoredValue>]) -> bool {
+        self.candidates = dbs
+            .iter()
+            .enumerate()
+            .flat_map(|(index, hmap)| hmap.keys().map(move |key| (index, key.clone())))
+            .collect();
+        !self.candidates.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hmap_ops::{HMapOps, TtlMap};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_bytes() {
+        assert_eq!(parse_bytes("0"), Some(0));
+        assert_eq!(parse_bytes("100"), Some(100));
+        assert_eq!(parse_bytes("2k"), Some(2_000));
+        assert_eq!(parse_bytes("2KB"), Some(2_048));
+        assert_eq!(parse_bytes("1mb"), Some(1 << 20));
+        assert_eq!(parse_bytes("1gb"), Some(1 << 30));
+        assert_eq!(parse_bytes("1tb"), None);
+        assert_eq!(parse_bytes("mb"), None);
+        assert_eq!(parse_bytes("-1"), None);
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let mut dbs = vec![HashMap::new(), HashMap::new()];
+        let mut access_maps = vec![AccessMap::new(), AccessMap::new()];
+        let mut ttl_map = TtlMap::new();
+        let start = Instant::now();
+        // "a" is the oldest, "d" the freshest, spread over both databases
+        for (i, key) in [b"a", b"b", b"c", b"d"].into_iter().enumerate() {
+            let db = i % 2;
+            dbs[db].insert_alloc(key, b"v", None, &mut ttl_map);
+            let used_at = start + Duration::from_secs(i as u64);
+            dbs[db].record_access(&mut access_maps[db], &[key], used_at);
+        }
+
+        let mut max_memory = MaxMemory {
+            limit: 200,
+            policy: Policy::AllKeysLru,
+            ..MaxMemory::default()
+        };
+        let mut rng = Rng::with_seed(1);
+        // pretend every key costs 100 bytes
+        let used = |dbs: &[HashMap<Bytes, StoredValue>]| {
+            Ok(dbs.iter().map(HashMap::len).sum::<usize>() * 100)
+        };
+        let evicted = max_memory
+            .evict(&mut dbs, &mut access_maps, &mut rng, used)
+            .unwrap();
+
+        assert_eq!(evicted.len(), 2);
+        assert_eq!(max_memory.evicted_keys, 2);
+        assert!(dbs[1].contains_key(b"d".as_slice()));
+        assert!(dbs[0].contains_key(b"c".as_slice()));
+        assert_eq!(access_maps[0].len() + access_maps[1].len(), 2);
+    }
+
+    #[test]
+    fn test_evict_gives_up() {
+        let mut dbs = vec![HashMap::new()];
+        let mut access_maps = vec![AccessMap::new()];
+        let mut ttl_map = TtlMap::new();
+        for i in 0..1000 {
+            dbs[0].insert_alloc(format!("k{i}").as_bytes(), b"v", None, &mut ttl_map);
+        }
+        let mut max_memory = MaxMemory {
+            limit: 1,
+            policy: Policy::AllKeysLru,
+            ..MaxMemory::default()
+        };
+        let mut rng = Rng::with_seed(1);
+
+        // one call evicts a few keys at most
+        let used = |dbs: &[HashMap<Bytes, StoredValue>]| Ok(dbs[0].len());
+        let evicted = max_memory
+            .evict(&mut dbs, &mut access_maps, &mut rng, used)
+            .unwrap();
+        assert_eq!(evicted.len(), EVICTIONS_PER_CALL);
+        assert_eq!(dbs[0].len(), 1000 - EVICTIONS_PER_CALL);
+
+        // memory evictions don't free isn't worth emptying the databases for
+        let evicted = max_memory
+            .evict(&mut dbs, &mut access_maps, &mut rng, |_| Ok(100))
+            .unwrap();
+        assert_eq!(evicted.len(), 1);
+    }
+}
@@ -0,0 +1,130 @@
+/// Matches `key` against a Redis-style glob `pattern`: `*` matches any run of
+/// bytes, `?` any single byte, `[abc]`, `[a-z]` and `[^abc]` a byte from (or
+/// outside) a class, and `\` escapes the next byte. Works on raw bytes, since
+/// keys needn't be UTF-8.
+pub fn glob_match(pattern: &[u8], key: &[u8]) -> bool {
+    let (mut p, mut k) = (0, 0);
+    // where to resume after the last `*`: the pattern past it and the key
+    // position it has absorbed up to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while k < key.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    backtrack = Some((p + 1, k));
+                    p += 1;
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    k += 1;
+                    continue;
+                }
+                b'[' => {
+                    let (matched, next) = match_class(pattern, p + 1, key[k]);
+                    if matched {
+                        p = next;
+                        k += 1;
+                        continue;
+                    }
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    if pattern[p + 1] == key[k] {
+                        p += 2;
+                        k += 1;
+                        continue;
+                    }
+                }
+                byte => {
+                    if byte == key[k] {
+                        p += 1;
+                        k += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        // mismatch: let the last `*` swallow one more byte, if there was one
+        match backtrack {
+            Some((star_p, star_k)) => {
+                backtrack = Some((star_p, star_k + 1));
+                p = star_p;
+                k = star_k + 1;
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// Matches `byte` against the class starting right after a `[` at `start`.
+/// Returns whether it matched and where the pattern continues after the
+/// closing `]`; an unterminated class runs to the end of the pattern.
+fn match_class(pattern: &[u8], start: usize, byte: u8) -> (bool, usize) {
+    let mut p = start;
+    let negated = pattern.get(p) == Some(&b'^');
+    if negated {
+        p += 1;
+    }
+    let mut matched = false;
+    while p < pattern.len() && pattern[p] != b']' {
+        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+            matched |= pattern[p + 1] == byte;
+            p += 2;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+            let (lo, hi) = (
+                pattern[p].min(pattern[p + 2]),
+                pattern[p].max(pattern[p + 2]),
+            );
+            matched |= (lo..=hi).contains(&byte);
+            p += 3;
+        } else {
+            matched |= pattern[p] == byte;
+            p += 1;
+        }
+    }
+    // step over the closing bracket
+    let next = (p + 1).min(pattern.len());
+    (matched != negated, next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcards() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"*", b"anything"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(glob_match(b"h?llo", b"hallo"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+        assert!(glob_match(b"h*llo", b"hllo"));
+        assert!(glob_match(b"h*llo", b"heeeello"));
+        assert!(!glob_match(b"h*llo", b"hello!"));
+        assert!(glob_match(b"*:*:end", b"a:b:c:end"));
+        assert!(!glob_match(b"abc", b"ab"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(glob_match(b"h[ae]llo", b"hello"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[ae]llo", b"hillo"));
+        assert!(glob_match(b"h[^e]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(!glob_match(b"h[a-b]llo", b"hcllo"));
+        assert!(glob_match(b"key[0-9]", b"key7"));
+    }
+
+    #[test]
+    fn test_escapes_and_bytes() {
+        assert!(glob_match(b"h\\*llo", b"h*llo"));
+        assert!(!glob_match(b"h\\*llo", b"hello"));
+        assert!(glob_match(b"what\\?", b"what?"));
+        assert!(glob_match(b"[\\]]", b"]"));
+        assert!(glob_match(b"\xff*", b"\xff\xfe"));
+        assert!(glob_match(b"?", b"\x80"));
+    }
+}
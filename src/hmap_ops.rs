@@ -1,37 +1,142 @@
 use crate::StoredValue;
+use crate::cmd::ExpiryOption;
+use crate::err::WrongType;
+use crate::glob::glob_match;
+use crate::persistence::unix_now;
 use anyhow::bail;
 use bytes::Bytes;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::time::{Duration, Instant};
 
+/// Deadlines of keys with a TTL, ordered by expiry. Several keys can share the
+/// same deadline, and entries may be stale if a key was overwritten since.
+pub type TtlMap = BTreeMap<Instant, Vec<Bytes>>;
+
+/// When each key was last used by a command, for `OBJECT IDLETIME`. Kept next
+/// to the database rather than in `StoredValue` so lookups don't have to
+/// care about it.
+pub type AccessMap = HashMap<Bytes, Instant>;
+
+/// Remaining time to live of a key, as reported by `TTL`.
+pub enum Ttl {
+    /// The key doesn't exist or its deadline has already passed.
+    Missing,
+    /// The key exists but has no expiry.
+    Persistent,
+    Expires(Duration),
+}
+
+/// Resolves inclusive, possibly negative, `GETRANGE` offsets against a string
+/// of `len` bytes. Returns `None` when the range selects nothing.
+fn normalize_str_range(len: usize, start: isize, end: isize) -> Option<(usize, usize)> {
+    if len == 0 {
+        return None;
+    }
+    let len = len as isize;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.clamp(0, len - 1);
+    if start > end {
+        return None;
+    }
+    Some((start as usize, end as usize))
+}
+
 pub trait HMapOps<K, V> {
-    fn set_if_not_exist(&mut self, key: &[u8], value: &[u8]);
+    /// Sets `key` unless it exists, returning whether it did.
+    fn set_if_not_exist(&mut self, key: &[u8], value: &[u8]) -> bool;
 
-    fn update_if_exist(&mut self, key: &[u8], value: &[u8]);
+    /// Overwrites `key` only if it exists, returning whether it did.
+    fn update_if_exist(&mut self, key: &[u8], value: &[u8]) -> bool;
 
     fn insert_alloc(
         &mut self,
         key: &[u8],
         value: &[u8],
         maybe_end_of_life: Option<Instant>,
+        ttl_map: &mut TtlMap,
     ) -> Option<StoredValue>;
 
     fn delete_all<'a>(&'a mut self, keys: impl Iterator<Item = &'a [u8]>) -> usize;
 
-    fn get_ttl(&self, key: &[u8]) -> anyhow::Result<Option<Duration>>;
+    fn get_ttl(&self, key: &[u8]) -> Ttl;
+
+    fn get_live(&mut self, key: &[u8]) -> Option<&StoredValue>;
+
+    fn set_expiry(
+        &mut self,
+        key: &[u8],
+        deadline: Instant,
+        ttl_map: &mut TtlMap,
+    ) -> anyhow::Result<bool>;
+
+    fn get_range(&mut self, key: &[u8], start: isize, end: isize) -> anyhow::Result<Bytes>;
+
+    fn set_range(
+        &mut self,
+        key: &[u8],
+        offset: usize,
+        value: &[u8],
+        max_len: usize,
+    ) -> anyhow::Result<usize>;
+
+    fn get_del(&mut self, key: &[u8]) -> anyhow::Result<Option<Bytes>>;
+
+    fn get_ex(
+        &mut self,
+        key: &[u8],
+        expiry: ExpiryOption,
+        ttl_map: &mut TtlMap,
+    ) -> anyhow::Result<Option<Bytes>>;
+
+    fn remove_expired(&mut self, ttl_map: &mut TtlMap, now: Instant) -> Vec<Bytes>;
+
+    fn record_access(&self, access_map: &mut AccessMap, keys: &[&[u8]], now: Instant);
+
+    fn keys_matching(&self, pattern: &[u8]) -> Vec<&Bytes>;
+
+    fn touch<'a>(&'a mut self, keys: impl Iterator<Item = &'a [u8]>) -> usize;
+
+    fn move_to(&mut self, key: &[u8], dst: &mut Self, dst_ttl_map: &mut TtlMap) -> bool;
+
+    fn copy(
+        &mut self,
+        src: &[u8],
+        dst: &[u8],
+        replace: bool,
+        ttl_map: &mut TtlMap,
+    ) -> anyhow::Result<bool>;
+
+    fn copy_to(
+        &mut self,
+        src: &[u8],
+        dst_db: &mut Self,
+        dst: &[u8],
+        replace: bool,
+        dst_ttl_map: &mut TtlMap,
+    ) -> bool;
 }
 
 impl HMapOps<Bytes, StoredValue> for HashMap<Bytes, StoredValue> {
-    fn set_if_not_exist(&mut self, key: &[u8], value: &[u8]) {
-        if !self.contains_key(key) {
-            self.insert_alloc(key, value, None);
+    fn set_if_not_exist(&mut self, key: &[u8], value: &[u8]) -> bool {
+        if self.get_live(key).is_some() {
+            return false;
         }
+        self.insert(
+            Bytes::copy_from_slice(key),
+            StoredValue::Plain(Bytes::copy_from_slice(value)),
+        );
+        true
     }
 
-    fn update_if_exist(&mut self, key: &[u8], value: &[u8]) {
-        if self.contains_key(key) {
-            self.insert_alloc(key, value, None);
+    fn update_if_exist(&mut self, key: &[u8], value: &[u8]) -> bool {
+        if self.get_live(key).is_none() {
+            return false;
         }
+        self.insert(
+            Bytes::copy_from_slice(key),
+            StoredValue::Plain(Bytes::copy_from_slice(value)),
+        );
+        true
     }
 
     fn insert_alloc(
@@ -39,37 +144,590 @@ impl HMapOps<Bytes, StoredValue> for HashMap<Bytes, StoredValue> {
         key: &[u8],
         value: &[u8],
         maybe_end_of_life: Option<Instant>,
+        ttl_map: &mut TtlMap,
     ) -> Option<StoredValue> {
+        let key = Bytes::copy_from_slice(key);
         let value = Bytes::copy_from_slice(value);
         self.insert(
-            Bytes::copy_from_slice(key),
+            key.clone(),
             match maybe_end_of_life {
                 None => StoredValue::Plain(value),
-                Some(instant) => StoredValue::TtlPlain(value, instant),
+                Some(instant) => {
+                    ttl_map.entry(instant).or_default().push(key);
+                    StoredValue::TtlPlain(value, instant)
+                }
             },
         )
     }
 
+    /// Removes `keys` and returns how many of them were live. Keys whose
+    /// deadline already passed are dropped too, but don't count as deleted.
     fn delete_all<'a>(&'a mut self, keys: impl Iterator<Item = &'a [u8]>) -> usize {
+        let now = Instant::now();
         let mut count = 0;
         for key in keys {
-            if self.remove(key).is_some() {
-                count += 1;
+            match self.remove(key) {
+                None => {}
+                Some(StoredValue::TtlPlain(_, deadline)) if deadline <= now => {}
+                Some(_) => count += 1,
             }
         }
         count
     }
 
-    fn get_ttl(&self, key: &[u8]) -> anyhow::Result<Option<Duration>> {
+    fn get_ttl(&self, key: &[u8]) -> Ttl {
         match self.get(key) {
-            None => Ok(None),
+            None => Ttl::Missing,
             Some(StoredValue::TtlPlain(_bytes, i)) => {
-                let now = Instant::now();
-                let diff = i.duration_since(now);
-                Ok(Some(diff))
+                match i.checked_duration_since(Instant::now()) {
+                    None => Ttl::Missing,
+                    Some(diff) => Ttl::Expires(diff),
+                }
+            }
+            Some(_) => Ttl::Persistent,
+        }
+    }
+
+    fn get_live(&mut self, key: &[u8]) -> Option<&StoredValue> {
+        if let Some(StoredValue::TtlPlain(_, deadline)) = self.get(key)
+            && *deadline <= Instant::now()
+        {
+            self.remove(key);
+            return None;
+        }
+        self.get(key)
+    }
+
+    fn set_expiry(
+        &mut self,
+        key: &[u8],
+        deadline: Instant,
+        ttl_map: &mut TtlMap,
+    ) -> anyhow::Result<bool> {
+        if self.get_live(key).is_none() {
+            return Ok(false);
+        }
+        let Some(stored_value) = self.get_mut(key) else {
+            return Ok(false);
+        };
+        match stored_value {
+            StoredValue::Plain(bytes) => {
+                *stored_value = StoredValue::TtlPlain(bytes.clone(), deadline);
+            }
+            StoredValue::TtlPlain(_, instant) => *instant = deadline,
+            _ => bail!("expiry is only supported for string values"),
+        }
+        ttl_map
+            .entry(deadline)
+            .or_default()
+            .push(Bytes::copy_from_slice(key));
+        Ok(true)
+    }
+
+    fn get_range(&mut self, key: &[u8], start: isize, end: isize) -> anyhow::Result<Bytes> {
+        match self.get_live(key) {
+            None => Ok(Bytes::new()),
+            Some(StoredValue::Plain(bytes)) | Some(StoredValue::TtlPlain(bytes, _)) => {
+                Ok(match normalize_str_range(bytes.len(), start, end) {
+                    None => Bytes::new(),
+                    Some((start, end)) => bytes.slice(start..=end),
+                })
+            }
+            Some(_) => bail!("expected STRING"),
+        }
+    }
+
+    fn set_range(
+        &mut self,
+        key: &[u8],
+        offset: usize,
+        value: &[u8],
+        max_len: usize,
+    ) -> anyhow::Result<usize> {
+        let current = match self.get_live(key) {
+            None => None,
+            Some(StoredValue::Plain(bytes)) | Some(StoredValue::TtlPlain(bytes, _)) => Some(bytes),
+            Some(_) => bail!(WrongType),
+        };
+        // an empty write neither modifies nor creates the key
+        if value.is_empty() {
+            return Ok(current.map_or(0, |bytes| bytes.len()));
+        }
+        let Some(end) = offset
+            .checked_add(value.len())
+            .filter(|&end| end <= max_len)
+        else {
+            bail!("string exceeds maximum allowed size (proto-max-bulk-len)");
+        };
+        let mut new_value = current.map(|bytes| bytes.to_vec()).unwrap_or_default();
+        if new_value.len() < end {
+            new_value.resize(end, 0);
+        }
+        new_value[offset..end].copy_from_slice(value);
+        let len = new_value.len();
+        let new_value = Bytes::from(new_value);
+
+        match self.get_mut(key) {
+            Some(StoredValue::Plain(bytes)) | Some(StoredValue::TtlPlain(bytes, _)) => {
+                *bytes = new_value;
+            }
+            _ => {
+                self.insert(Bytes::copy_from_slice(key), StoredValue::Plain(new_value));
+            }
+        }
+        Ok(len)
+    }
+
+    fn get_del(&mut self, key: &[u8]) -> anyhow::Result<Option<Bytes>> {
+        match self.get_live(key) {
+            None => return Ok(None),
+            Some(StoredValue::Plain(_)) | Some(StoredValue::TtlPlain(_, _)) => {}
+            Some(_) => bail!("expected STRING"),
+        }
+        match self.remove(key) {
+            Some(StoredValue::Plain(bytes)) | Some(StoredValue::TtlPlain(bytes, _)) => {
+                Ok(Some(bytes))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads a string like `GET` and then applies `expiry` to it. A deadline
+    /// already passed deletes the key, after its value was read.
+    fn get_ex(
+        &mut self,
+        key: &[u8],
+        expiry: ExpiryOption,
+        ttl_map: &mut TtlMap,
+    ) -> anyhow::Result<Option<Bytes>> {
+        let value = match self.get_live(key) {
+            None => return Ok(None),
+            Some(StoredValue::Plain(bytes)) | Some(StoredValue::TtlPlain(bytes, _)) => {
+                bytes.clone()
+            }
+            Some(_) => bail!("expected STRING"),
+        };
+        let ttl = match expiry {
+            ExpiryOption::Keep => return Ok(Some(value)),
+            ExpiryOption::Persist => {
+                self.insert(
+                    Bytes::copy_from_slice(key),
+                    StoredValue::Plain(value.clone()),
+                );
+                return Ok(Some(value));
+            }
+            ExpiryOption::In(ttl) => Some(ttl),
+            ExpiryOption::At(unix_millis) => Duration::from_millis(unix_millis as u64)
+                .checked_sub(unix_now())
+                .filter(|ttl| !ttl.is_zero()),
+        };
+        match ttl {
+            None => {
+                self.remove(key);
+            }
+            Some(ttl) => {
+                let deadline = Instant::now() + ttl;
+                let key = Bytes::copy_from_slice(key);
+                ttl_map.entry(deadline).or_default().push(key.clone());
+                self.insert(key, StoredValue::TtlPlain(value.clone(), deadline));
+            }
+        }
+        Ok(Some(value))
+    }
+
+    /// Removes the keys whose deadline is at or before `now` and returns them.
+    fn remove_expired(&mut self, ttl_map: &mut TtlMap, now: Instant) -> Vec<Bytes> {
+        let mut removed = Vec::new();
+        while let Some(entry) = ttl_map.first_entry()
+            && *entry.key() <= now
+        {
+            for key in entry.remove() {
+                // the key may have been overwritten or re-expired since the
+                // deadline was registered, so trust the stored instant only
+                if let Some(StoredValue::TtlPlain(_, deadline)) = self.get(&key)
+                    && *deadline <= now
+                {
+                    self.remove(&key);
+                    removed.push(key);
+                }
+            }
+        }
+        removed
+    }
+
+    /// Stamps `keys` as used at `now`, dropping the stamps of keys that no
+    /// longer exist.
+    fn record_access(&self, access_map: &mut AccessMap, keys: &[&[u8]], now: Instant) {
+        for key in keys {
+            match self.get_key_value(*key) {
+                Some((key, _)) => {
+                    access_map.insert(key.clone(), now);
+                }
+                None => {
+                    access_map.remove(*key);
+                }
             }
-            Some(StoredValue::Plain(_)) => Ok(None),
-            _ => bail!("cannot get the TTL for the stored value"),
         }
     }
+
+    fn copy(
+        &mut self,
+        src: &[u8],
+        dst: &[u8],
+        replace: bool,
+        ttl_map: &mut TtlMap,
+    ) -> anyhow::Result<bool> {
+        if src == dst {
+            bail!("source and destination objects are the same");
+        }
+        let Some(value) = self.get_live(src).cloned() else {
+            return Ok(false);
+        };
+        if !replace && self.get_live(dst).is_some() {
+            return Ok(false);
+        }
+        insert_copy(self, dst, value, ttl_map);
+        Ok(true)
+    }
+
+    /// Copies `src` into the `dst_db` database as `dst`, keeping its TTL.
+    /// Nothing is copied if `src` is missing here, or if `dst` exists in
+    /// `dst_db` and isn't to be replaced.
+    fn copy_to(
+        &mut self,
+        src: &[u8],
+        dst_db: &mut Self,
+        dst: &[u8],
+        replace: bool,
+        dst_ttl_map: &mut TtlMap,
+    ) -> bool {
+        let Some(value) = self.get_live(src).cloned() else {
+            return false;
+        };
+        if !replace && dst_db.get_live(dst).is_some() {
+            return false;
+        }
+        insert_copy(dst_db, dst, value, dst_ttl_map);
+        true
+    }
+
+    /// Moves `key` into the `dst` database, keeping its TTL. Nothing moves if
+    /// the key is missing here or already exists in `dst`.
+    fn move_to(&mut self, key: &[u8], dst: &mut Self, dst_ttl_map: &mut TtlMap) -> bool {
+        if self.get_live(key).is_none() || dst.get_live(key).is_some() {
+            return false;
+        }
+        let Some((key, value)) = self.remove_entry(key) else {
+            return false;
+        };
+        if let StoredValue::TtlPlain(_, deadline) = &value {
+            dst_ttl_map.entry(*deadline).or_default().push(key.clone());
+        }
+        dst.insert(key, value);
+        true
+    }
+
+    /// Counts the live `keys`, repeats included. This is the place to bump
+    /// a key's access time once eviction tracks one.
+    fn touch<'a>(&'a mut self, keys: impl Iterator<Item = &'a [u8]>) -> usize {
+        keys.filter(|key| self.get_live(key).is_some()).count()
+    }
+
+    fn keys_matching(&self, pattern: &[u8]) -> Vec<&Bytes> {
+        let now = Instant::now();
+        self.iter()
+            .filter(|(_, value)| !matches!(value, StoredValue::TtlPlain(_, deadline) if *deadline <= now))
+            .map(|(key, _)| key)
+            .filter(|key| glob_match(pattern, key))
+            .collect()
+    }
+}
+
+/// Stores a copied `value` as `dst`, tracking its deadline if it has one.
+fn insert_copy(
+    hmap: &mut HashMap<Bytes, StoredValue>,
+    dst: &[u8],
+    value: StoredValue,
+    ttl_map: &mut TtlMap,
+) {
+    let dst = Bytes::copy_from_slice(dst);
+    if let StoredValue::TtlPlain(_, deadline) = &value {
+        ttl_map.entry(*deadline).or_default().push(dst.clone());
+    }
+    hmap.insert(dst, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_live_removes_expired() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        let mut ttl_map = TtlMap::new();
+        let past = Instant::now() - Duration::from_secs(1);
+        hmap.insert_alloc(b"k", b"v", Some(past), &mut ttl_map);
+
+        // would underflow with `duration_since`
+        assert!(matches!(hmap.get_ttl(b"k"), Ttl::Missing));
+        assert!(hmap.get_live(b"k").is_none());
+        assert!(!hmap.contains_key(b"k".as_slice()));
+    }
+
+    #[test]
+    fn test_remove_expired_shrinks_len() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        let mut ttl_map = TtlMap::new();
+        let now = Instant::now();
+        let past = now - Duration::from_secs(1);
+        hmap.insert_alloc(b"k", b"v", Some(past), &mut ttl_map);
+        hmap.insert_alloc(b"p", b"v", None, &mut ttl_map);
+        assert_eq!(hmap.len(), 2);
+
+        assert_eq!(
+            hmap.remove_expired(&mut ttl_map, now),
+            vec![Bytes::from("k")]
+        );
+        assert_eq!(hmap.len(), 1);
+        hmap.remove(b"p".as_slice());
+        assert_eq!(hmap.len(), 0);
+    }
+
+    #[test]
+    fn test_copy_every_type() {
+        use crate::dict_ops::HMapDictOps;
+        use crate::list_ops::HMapListOps;
+        use crate::set_ops::HMapSetOps;
+        use crate::sorted_set_ops::HMapSortedSetOps;
+
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        let mut ttl_map = TtlMap::new();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        hmap.insert_alloc(b"plain", b"v", None, &mut ttl_map);
+        hmap.insert_alloc(b"ttl", b"v", Some(deadline), &mut ttl_map);
+        hmap.append(b"list", vec![b"a", b"b"]).unwrap();
+        hmap.dict_mset(b"dict", &[b"f", b"v"]).unwrap();
+        hmap.set_add(b"set", vec![b"a", b"b"]).unwrap();
+        hmap.zset_add(b"zset", &[(1, b"a"), (2, b"b")]).unwrap();
+
+        for key in ["plain", "ttl", "list", "dict", "set", "zset"] {
+            let dst = format!("{key}:copy");
+            assert!(
+                hmap.copy(key.as_bytes(), dst.as_bytes(), false, &mut ttl_map)
+                    .unwrap()
+            );
+            assert_eq!(hmap.get(key.as_bytes()), hmap.get(dst.as_bytes()));
+        }
+        // copies are independent of their source
+        hmap.zset_add(b"zset:copy", &[(3, b"c")]).unwrap();
+        assert_eq!(hmap.zcard(b"zset").unwrap(), Some(2));
+        assert!(ttl_map[&deadline].iter().any(|k| k.as_ref() == b"ttl:copy"));
+    }
+
+    #[test]
+    fn test_copy_without_replace() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        let mut ttl_map = TtlMap::new();
+        hmap.insert_alloc(b"a", b"1", None, &mut ttl_map);
+        hmap.insert_alloc(b"b", b"2", None, &mut ttl_map);
+
+        assert!(!hmap.copy(b"a", b"b", false, &mut ttl_map).unwrap());
+        assert!(!hmap.copy(b"missing", b"c", false, &mut ttl_map).unwrap());
+        assert!(hmap.copy(b"a", b"a", true, &mut ttl_map).is_err());
+        assert!(hmap.copy(b"a", b"b", true, &mut ttl_map).unwrap());
+        assert_eq!(
+            hmap.get(b"b".as_slice()),
+            Some(&StoredValue::Plain(Bytes::from("1")))
+        );
+    }
+
+    #[test]
+    fn test_copy_to_another_db() {
+        let mut src: HashMap<Bytes, StoredValue> = HashMap::new();
+        let mut dst: HashMap<Bytes, StoredValue> = HashMap::new();
+        let (mut src_ttl_map, mut dst_ttl_map) = (TtlMap::new(), TtlMap::new());
+        let deadline = Instant::now() + Duration::from_secs(60);
+        src.insert_alloc(b"a", b"1", Some(deadline), &mut src_ttl_map);
+        dst.insert_alloc(b"a", b"2", None, &mut dst_ttl_map);
+
+        // the same name is another key in another database
+        assert!(!src.copy_to(b"a", &mut dst, b"a", false, &mut dst_ttl_map));
+        assert!(src.copy_to(b"a", &mut dst, b"a", true, &mut dst_ttl_map));
+        assert_eq!(src.get(b"a".as_slice()), dst.get(b"a".as_slice()));
+        assert!(dst_ttl_map[&deadline].iter().any(|k| k.as_ref() == b"a"));
+        assert!(!src.copy_to(b"missing", &mut dst, b"b", true, &mut dst_ttl_map));
+    }
+
+    #[test]
+    fn test_record_access() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        let mut ttl_map = TtlMap::new();
+        let mut access_map = AccessMap::new();
+        let (then, now) = (Instant::now(), Instant::now() + Duration::from_secs(5));
+        hmap.insert_alloc(b"a", b"1", None, &mut ttl_map);
+        hmap.insert_alloc(b"b", b"2", None, &mut ttl_map);
+
+        hmap.record_access(&mut access_map, &[b"a", b"b", b"missing"], then);
+        assert_eq!(access_map.len(), 2);
+        hmap.remove(b"b".as_slice());
+        hmap.record_access(&mut access_map, &[b"a", b"b"], now);
+        assert_eq!(access_map.len(), 1);
+        assert_eq!(access_map[b"a".as_slice()], now);
+    }
+
+    #[test]
+    fn test_move_to_keeps_ttl() {
+        let mut src: HashMap<Bytes, StoredValue> = HashMap::new();
+        let mut dst: HashMap<Bytes, StoredValue> = HashMap::new();
+        let (mut src_ttl_map, mut dst_ttl_map) = (TtlMap::new(), TtlMap::new());
+        let deadline = Instant::now() + Duration::from_secs(60);
+        src.insert_alloc(b"a", b"1", Some(deadline), &mut src_ttl_map);
+        src.insert_alloc(b"b", b"2", None, &mut src_ttl_map);
+        dst.insert_alloc(b"b", b"3", None, &mut dst_ttl_map);
+
+        assert!(src.move_to(b"a", &mut dst, &mut dst_ttl_map));
+        assert!(!src.contains_key(b"a".as_slice()));
+        assert_eq!(
+            dst.get(b"a".as_slice()),
+            Some(&StoredValue::TtlPlain(Bytes::from("1"), deadline))
+        );
+        assert_eq!(dst_ttl_map[&deadline], vec![Bytes::from("a")]);
+
+        assert!(!src.move_to(b"b", &mut dst, &mut dst_ttl_map));
+        assert!(!src.move_to(b"missing", &mut dst, &mut dst_ttl_map));
+        assert_eq!(src.len(), 1);
+    }
+
+    #[test]
+    fn test_delete_all_counts_removed_keys() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        let mut ttl_map = TtlMap::new();
+        let past = Instant::now() - Duration::from_secs(1);
+        hmap.insert_alloc(b"a", b"1", None, &mut ttl_map);
+        hmap.insert_alloc(b"b", b"2", None, &mut ttl_map);
+        hmap.insert_alloc(b"expired", b"3", Some(past), &mut ttl_map);
+
+        let keys: [&[u8]; 5] = [b"a", b"missing", b"b", b"a", b"expired"];
+        assert_eq!(hmap.delete_all(keys.into_iter()), 2);
+        assert!(hmap.is_empty());
+    }
+
+    #[test]
+    fn test_get_ex() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        let mut ttl_map = TtlMap::new();
+        hmap.insert_alloc(b"k", b"v", None, &mut ttl_map);
+        let get_ex = |hmap: &mut HashMap<_, _>, ttl_map: &mut TtlMap, expiry| {
+            hmap.get_ex(b"k", expiry, ttl_map).unwrap()
+        };
+
+        let value = get_ex(&mut hmap, &mut ttl_map, ExpiryOption::Keep);
+        assert_eq!(value.as_deref(), Some(b"v".as_slice()));
+        assert!(matches!(hmap.get_ttl(b"k"), Ttl::Persistent));
+
+        get_ex(
+            &mut hmap,
+            &mut ttl_map,
+            ExpiryOption::In(Duration::from_secs(60)),
+        );
+        assert!(matches!(hmap.get_ttl(b"k"), Ttl::Expires(_)));
+        assert_eq!(ttl_map.len(), 1);
+
+        get_ex(&mut hmap, &mut ttl_map, ExpiryOption::Persist);
+        assert!(matches!(hmap.get_ttl(b"k"), Ttl::Persistent));
+
+        // a deadline in the past reads the value one last time
+        let value = get_ex(&mut hmap, &mut ttl_map, ExpiryOption::At(1));
+        assert_eq!(value.as_deref(), Some(b"v".as_slice()));
+        assert!(get_ex(&mut hmap, &mut ttl_map, ExpiryOption::Keep).is_none());
+
+        hmap.insert(
+            Bytes::from_static(b"list"),
+            StoredValue::List(Default::default()),
+        );
+        assert!(
+            hmap.get_ex(b"list", ExpiryOption::Persist, &mut ttl_map)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_set_if_not_exist() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        let mut ttl_map = TtlMap::new();
+        assert!(hmap.set_if_not_exist(b"k", b"v"));
+        assert!(!hmap.set_if_not_exist(b"k", b"w"));
+        assert_eq!(
+            hmap.get(b"k".as_slice()),
+            Some(&StoredValue::Plain(Bytes::from_static(b"v")))
+        );
+
+        // an expired key counts as absent
+        hmap.insert_alloc(b"old", b"v", Some(Instant::now()), &mut ttl_map);
+        assert!(hmap.set_if_not_exist(b"old", b"w"));
+    }
+
+    #[test]
+    fn test_update_if_exist() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        let mut ttl_map = TtlMap::new();
+        assert!(!hmap.update_if_exist(b"k", b"v"));
+        assert!(!hmap.contains_key(b"k".as_slice()));
+
+        hmap.insert_alloc(b"k", b"v", None, &mut ttl_map);
+        assert!(hmap.update_if_exist(b"k", b"w"));
+        assert_eq!(
+            hmap.get(b"k".as_slice()),
+            Some(&StoredValue::Plain(Bytes::from_static(b"w")))
+        );
+
+        hmap.insert_alloc(b"old", b"v", Some(Instant::now()), &mut ttl_map);
+        assert!(!hmap.update_if_exist(b"old", b"w"));
+    }
+
+    #[test]
+    fn test_set_range_max_len() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        assert_eq!(hmap.set_range(b"k", 2, b"ab", 4).unwrap(), 4);
+        assert!(hmap.set_range(b"k", 3, b"ab", 4).is_err());
+        assert!(hmap.set_range(b"k", usize::MAX, b"ab", usize::MAX).is_err());
+        // an empty write is no write at all
+        assert_eq!(hmap.set_range(b"k", 10, b"", 4).unwrap(), 4);
+
+        hmap.insert(
+            Bytes::from_static(b"l"),
+            StoredValue::List(Default::default()),
+        );
+        let err = hmap.set_range(b"l", 0, b"a", 4).unwrap_err();
+        assert!(err.is::<WrongType>());
+    }
+
+    #[test]
+    fn test_normalize_str_range() {
+        assert_eq!(normalize_str_range(10, 0, 3), Some((0, 3)));
+        assert_eq!(normalize_str_range(10, -3, -1), Some((7, 9)));
+        assert_eq!(normalize_str_range(10, 0, 100), Some((0, 9)));
+        assert_eq!(normalize_str_range(10, -100, 2), Some((0, 2)));
+        assert_eq!(normalize_str_range(10, 5, 2), None);
+        assert_eq!(normalize_str_range(10, -1, -5), None);
+        assert_eq!(normalize_str_range(0, 0, -1), None);
+    }
+
+    #[test]
+    fn test_remove_expired_ignores_stale_deadlines() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        let mut ttl_map = TtlMap::new();
+        let now = Instant::now();
+        hmap.insert_alloc(b"k", b"v", Some(now), &mut ttl_map);
+        hmap.insert_alloc(
+            b"k",
+            b"v2",
+            Some(now + Duration::from_secs(60)),
+            &mut ttl_map,
+        );
+
+        assert!(hmap.remove_expired(&mut ttl_map, now).is_empty());
+        assert!(hmap.get_live(b"k").is_some());
+        assert_eq!(ttl_map.len(), 1);
+    }
 }
@@ -0,0 +1,212 @@
+use crate::eviction::MaxMemory;
+use crate::replication::Replication;
+use crate::stats::{CommandStats, Stats};
+use crate::stored_value::StoredValue;
+use bytes::Bytes;
+use compact_str::CompactString;
+use histogram::Histogram;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// The server state `INFO` reports on.
+pub struct InfoReport<'a> {
+    pub uptime_since: Instant,
+    pub connected_clients: usize,
+    pub blocked_clients: usize,
+    /// Bytes allocated, as jemalloc reports them.
+    pub used_memory: usize,
+    pub max_memory: &'a MaxMemory,
+    pub stats: &'a Stats,
+    pub replication: &'a Replication,
+    pub latency_histograms: &'a HashMap<CompactString, Histogram>,
+    pub dbs: &'a [HashMap<Bytes, StoredValue>],
+}
+
+/// Section names `INFO` takes, in the order the full report lists them.
+const SECTIONS: [&str; 8] = [
+    "server",
+    "clients",
+    "memory",
+    "stats",
+    "replication",
+    "cpu",
+    "commandstats",
+    "keyspace",
+];
+
+impl InfoReport<'_> {
+    /// The report for `INFO section`: the one section named, ignoring case,
+    /// nothing for an unknown name, or every section for none or `default`,
+    /// `all` or `everything`.
+    pub fn render(&self, section: Option<&str>) -> String {
+        let section = section.map(str::to_ascii_lowercase);
+        match section.as_deref() {
+            None | Some("default" | "all" | "everything") => SECTIONS
+                .iter()
+                .filter_map(|name| self.section(name))
+                .collect::<Vec<_>>()
+                .join("\r\n"),
+            Some(name) => self.section(name).unwrap_or_default(),
+        }
+    }
+
+    fn section(&self, name: &str) -> Option<String> {
+        let section = match name {
+            "server" => self.server(),
+            "clients" => self.clients(),
+            "memory" => self.memory(),
+            "stats" => self.stats(),
+            "replication" => self.replication(),
+            "cpu" => self.cpu(),
+            "commandstats" => self.commandstats(),
+            "keyspace" => self.keyspace(),
+            _ => return None,
+        };
+        Some(section)
+    }
+
+    fn server(&self) -> String {
+        let uptime_in_seconds = self.uptime_since.elapsed().as_secs();
+        let uptime_in_days = uptime_in_seconds / 24;
+        format!(
+            "# Server\r\n\
+             redis_version:reddis2-0.0.1\r\n\
+             redis_mode:standalone\r\n\
+             os:Rust/mio\r\n\
+             arch_bits:64\r\n\
+             tcp_port:6379\r\n\
+             uptime_in_seconds:{uptime_in_seconds}\r\n\
+             uptime_in_days:{uptime_in_days}\r\n\
+             hz:10\r\n\
+             executable:/usr/local/bin/reddis2\r\n\
+             config_file:\r\n"
+        )
+    }
+
+    fn clients(&self) -> String {
+        format!(
+            "# Clients\r\n\
+             connected_clients:{}\r\n\
+             blocked_clients:{}\r\n\
+             tracking_clients:0\r\n\
+             maxclients:10000\r\n",
+            self.connected_clients, self.blocked_clients
+        )
+    }
+
+    fn memory(&self) -> String {
+        let used_memory = self.used_memory;
+        let used_memory_human = format!("{}K", f64::trunc(used_memory as f64 / 1024.0));
+        let maxmemory = self.max_memory.limit;
+        let maxmemory_human = format!("{}K", f64::trunc(maxmemory as f64 / 1024.0));
+        let maxmemory_policy = self.max_memory.policy.name();
+        format!(
+            "# Memory\r\n\
+             used_memory:{used_memory}\r\n\
+             used_memory_human:{used_memory_human}\r\n\
+             used_memory_peak:2048000\r\n\
+             maxmemory:{maxmemory}\r\n\
+             maxmemory_human:{maxmemory_human}\r\n\
+             maxmemory_policy:{maxmemory_policy}\r\n\
+             mem_fragmentation_ratio:2.00\r\n"
+        )
+    }
+
+    fn stats(&self) -> String {
+        let Stats {
+            total_connections_received,
+            total_commands_processed,
+            keyspace_hits,
+            keyspace_misses,
+            expired_keys,
+            ..
+        } = self.stats;
+        let instantaneous_ops_per_sec = self.stats.ops_per_sec(Instant::now());
+        let evicted_keys = self.max_memory.evicted_keys;
+        format!(
+            "# Stats\r\n\
+             total_connections_received:{total_connections_received}\r\n\
+             total_commands_processed:{total_commands_processed}\r\n\
+             instantaneous_ops_per_sec:{instantaneous_ops_per_sec}\r\n\
+             rejected_connections:0\r\n\
+             expired_keys:{expired_keys}\r\n\
+             evicted_keys:{evicted_keys}\r\n\
+             keyspace_hits:{keyspace_hits}\r\n\
+             keyspace_misses:{keyspace_misses}\r\n"
+        )
+    }
+
+    fn replication(&self) -> String {
+        format!("# Replication\r\n{}", self.replication.info())
+    }
+
+    fn cpu(&self) -> String {
+        "# CPU\r\n\
+         used_cpu_sys:0.420000\r\n\
+         used_cpu_user:0.690000\r\n"
+            .to_string()
+    }
+
+    fn commandstats(&self) -> String {
+        CommandStats::make(self.latency_histograms)
+    }
+
+    fn keyspace(&self) -> String {
+        let keyspace: String = self
+            .dbs
+            .iter()
+            .enumerate()
+            .filter(|(_, db)| !db.is_empty())
+            .map(|(index, db)| format!("db{index}:keys={},expires=0,avg_ttl=0\r\n", db.len()))
+            .collect();
+        format!("# Keyspace\r\n{keyspace}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+
+    #[test]
+    fn test_render() {
+        let max_memory = MaxMemory::default();
+        let stats = Stats::default();
+        let replication = Replication::new(&mut Rng::with_seed(1));
+        let latency_histograms = HashMap::new();
+        let mut dbs = vec![HashMap::new(); 2];
+        dbs[1].insert(
+            Bytes::from_static(b"k"),
+            StoredValue::Plain(Bytes::from_static(b"v")),
+        );
+        let report = InfoReport {
+            uptime_since: Instant::now(),
+            connected_clients: 3,
+            blocked_clients: 1,
+            used_memory: 2048,
+            max_memory: &max_memory,
+            stats: &stats,
+            replication: &replication,
+            latency_histograms: &latency_histograms,
+            dbs: &dbs,
+        };
+
+        assert_eq!(
+            report.render(Some("Keyspace")),
+            "# Keyspace\r\ndb1:keys=1,expires=0,avg_ttl=0\r\n"
+        );
+        assert!(
+            report
+                .render(Some("clients"))
+                .starts_with("# Clients\r\nconnected_clients:3\r\nblocked_clients:1\r\n")
+        );
+        assert_eq!(report.render(Some("nosuchsection")), "");
+
+        let full = report.render(None);
+        assert!(full.starts_with("# Server\r\n"));
+        assert!(full.contains("\r\n\r\n# Replication\r\nrole:master\r\n"));
+        assert!(full.ends_with("# Keyspace\r\ndb1:keys=1,expires=0,avg_ttl=0\r\n"));
+        assert_eq!(report.render(Some("ALL")), full);
+        assert_eq!(report.render(Some("everything")), full);
+    }
+}
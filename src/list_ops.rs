@@ -1,7 +1,7 @@
 use crate::stored_value::StoredValue;
 use anyhow::bail;
 use bytes::Bytes;
-use std::collections::{HashMap, LinkedList};
+use std::collections::{HashMap, VecDeque};
 
 pub enum Popped {
     None,
@@ -9,21 +9,82 @@ pub enum Popped {
     Single(Bytes),
 }
 
+pub enum ListSet {
+    Done,
+    NoSuchKey,
+    OutOfRange,
+}
+
 pub trait HMapListOps {
     fn append(&mut self, key: &[u8], values: Vec<&[u8]>) -> anyhow::Result<usize>;
 
     fn prepend(&mut self, key: &[u8], values: Vec<&[u8]>) -> anyhow::Result<usize>;
 
+    fn append_existing(&mut self, key: &[u8], values: Vec<&[u8]>) -> anyhow::Result<usize>;
+
+    fn prepend_existing(&mut self, key: &[u8], values: Vec<&[u8]>) -> anyhow::Result<usize>;
+
     fn pop_front(&mut self, key: &[u8], n: Option<usize>) -> anyhow::Result<Popped>;
 
     fn pop_back(&mut self, key: &[u8], n: Option<usize>) -> anyhow::Result<Popped>;
+
+    fn range(
+        &self,
+        key: &[u8],
+        start: isize,
+        stop: isize,
+    ) -> anyhow::Result<Option<(Vec<&Bytes>, usize)>>;
+
+    fn index(&self, key: &[u8], index: isize) -> anyhow::Result<Option<Bytes>>;
+
+    fn set(&mut self, key: &[u8], index: isize, value: &[u8]) -> anyhow::Result<ListSet>;
+
+    fn rem(&mut self, key: &[u8], count: i64, value: &[u8]) -> anyhow::Result<usize>;
+
+    fn list_insert(
+        &mut self,
+        key: &[u8],
+        before: bool,
+        pivot: &[u8],
+        value: &[u8],
+    ) -> anyhow::Result<i64>;
+
+    fn pos(
+        &self,
+        key: &[u8],
+        element: &[u8],
+        rank: i64,
+        count: usize,
+    ) -> anyhow::Result<Vec<usize>>;
+}
+
+/// Resolves a possibly negative list index, `None` when out of range.
+fn normalize_index(len: usize, index: isize) -> Option<usize> {
+    let index = if index < 0 {
+        len as isize + index
+    } else {
+        index
+    };
+    (0..len as isize).contains(&index).then_some(index as usize)
+}
+
+/// Resolves inclusive, possibly negative, list offsets against a list of
+/// `len` elements. Returns `None` when the range selects nothing.
+fn normalize_range(len: usize, start: isize, stop: isize) -> Option<(usize, usize)> {
+    let len = len as isize;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
 }
 
 impl HMapListOps for HashMap<Bytes, StoredValue> {
     fn append(&mut self, key: &[u8], values: Vec<&[u8]>) -> anyhow::Result<usize> {
         let entry = self
             .entry(Bytes::copy_from_slice(key))
-            .or_insert(StoredValue::List(LinkedList::new()));
+            .or_insert(StoredValue::List(VecDeque::new()));
         let values_len = values.len();
         match entry {
             StoredValue::List(l) => {
@@ -40,7 +101,7 @@ impl HMapListOps for HashMap<Bytes, StoredValue> {
     fn prepend(&mut self, key: &[u8], values: Vec<&[u8]>) -> anyhow::Result<usize> {
         let entry = self
             .entry(Bytes::copy_from_slice(key))
-            .or_insert(StoredValue::List(LinkedList::new()));
+            .or_insert(StoredValue::List(VecDeque::new()));
         let values_len = values.len();
         match entry {
             StoredValue::List(l) => {
@@ -53,6 +114,32 @@ impl HMapListOps for HashMap<Bytes, StoredValue> {
         }
     }
 
+    fn append_existing(&mut self, key: &[u8], values: Vec<&[u8]>) -> anyhow::Result<usize> {
+        match self.get_mut(key) {
+            None => Ok(0),
+            Some(StoredValue::List(l)) => {
+                for value in values {
+                    l.push_back(Bytes::copy_from_slice(value));
+                }
+                Ok(l.len())
+            }
+            _ => bail!("cannot RPUSHX to a value that is not a LIST"),
+        }
+    }
+
+    fn prepend_existing(&mut self, key: &[u8], values: Vec<&[u8]>) -> anyhow::Result<usize> {
+        match self.get_mut(key) {
+            None => Ok(0),
+            Some(StoredValue::List(l)) => {
+                for value in values {
+                    l.push_front(Bytes::copy_from_slice(value));
+                }
+                Ok(l.len())
+            }
+            _ => bail!("cannot LPUSHX to a value that is not a LIST"),
+        }
+    }
+
     fn pop_front(&mut self, key: &[u8], n: Option<usize>) -> anyhow::Result<Popped> {
         let ll = match self.get_mut(key) {
             None => return Ok(Popped::None),
@@ -101,4 +188,249 @@ impl HMapListOps for HashMap<Bytes, StoredValue> {
             Popped::None
         })
     }
+
+    fn range(
+        &self,
+        key: &[u8],
+        start: isize,
+        stop: isize,
+    ) -> anyhow::Result<Option<(Vec<&Bytes>, usize)>> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(StoredValue::List(list)) => {
+                let values: Vec<&Bytes> = match normalize_range(list.len(), start, stop) {
+                    None => vec![],
+                    Some((start, stop)) => list.range(start..=stop).collect(),
+                };
+                let len = values.len();
+                Ok(Some((values, len)))
+            }
+            _ => bail!("stored value isn't a list"),
+        }
+    }
+
+    fn index(&self, key: &[u8], index: isize) -> anyhow::Result<Option<Bytes>> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(StoredValue::List(list)) => Ok(normalize_index(list.len(), index)
+                .and_then(|index| list.get(index))
+                .cloned()),
+            _ => bail!("stored value isn't a list"),
+        }
+    }
+
+    fn set(&mut self, key: &[u8], index: isize, value: &[u8]) -> anyhow::Result<ListSet> {
+        match self.get_mut(key) {
+            None => Ok(ListSet::NoSuchKey),
+            Some(StoredValue::List(list)) => match normalize_index(list.len(), index) {
+                None => Ok(ListSet::OutOfRange),
+                Some(index) => {
+                    list[index] = Bytes::copy_from_slice(value);
+                    Ok(ListSet::Done)
+                }
+            },
+            _ => bail!("stored value isn't a list"),
+        }
+    }
+
+    fn rem(&mut self, key: &[u8], count: i64, value: &[u8]) -> anyhow::Result<usize> {
+        let list = match self.get_mut(key) {
+            None => return Ok(0),
+            Some(StoredValue::List(list)) => list,
+            _ => bail!("stored value isn't a list"),
+        };
+        // 0 removes every occurrence, otherwise |count| of them from the
+        // head (positive) or from the tail (negative)
+        let limit = match count.unsigned_abs() {
+            0 => usize::MAX,
+            n => n as usize,
+        };
+        let mut removed = 0;
+        if count >= 0 {
+            list.retain(|v| {
+                if removed < limit && v == value {
+                    removed += 1;
+                    return false;
+                }
+                true
+            });
+        } else {
+            let mut index = list.len();
+            while index > 0 && removed < limit {
+                index -= 1;
+                if list[index] == value {
+                    list.remove(index);
+                    removed += 1;
+                }
+            }
+        }
+        if list.is_empty() {
+            self.remove(key);
+        }
+        Ok(removed)
+    }
+
+    fn list_insert(
+        &mut self,
+        key: &[u8],
+        before: bool,
+        pivot: &[u8],
+        value: &[u8],
+    ) -> anyhow::Result<i64> {
+        let list = match self.get_mut(key) {
+            None => return Ok(0),
+            Some(StoredValue::List(list)) => list,
+            _ => bail!("stored value isn't a list"),
+        };
+        let Some(position) = list.iter().position(|v| v == pivot) else {
+            return Ok(-1);
+        };
+        let index = if before { position } else { position + 1 };
+        list.insert(index, Bytes::copy_from_slice(value));
+        Ok(list.len() as i64)
+    }
+
+    /// Indices of up to `count` (0 meaning all) matches of `element`,
+    /// skipping the first `|rank| - 1` ones; a negative rank scans from the
+    /// tail. Indices always count from the head.
+    fn pos(
+        &self,
+        key: &[u8],
+        element: &[u8],
+        rank: i64,
+        count: usize,
+    ) -> anyhow::Result<Vec<usize>> {
+        let list = match self.get(key) {
+            None => return Ok(vec![]),
+            Some(StoredValue::List(list)) => list,
+            _ => bail!("stored value isn't a list"),
+        };
+        let skip = rank.unsigned_abs().saturating_sub(1) as usize;
+        let take = if count == 0 { usize::MAX } else { count };
+        let matches = list
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| *v == element)
+            .map(|(index, _)| index);
+        Ok(if rank < 0 {
+            matches.rev().skip(skip).take(take).collect()
+        } else {
+            matches.skip(skip).take(take).collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_range() {
+        assert_eq!(normalize_range(3, 0, -1), Some((0, 2)));
+        assert_eq!(normalize_range(3, -2, -1), Some((1, 2)));
+        assert_eq!(normalize_range(3, -100, 100), Some((0, 2)));
+        assert_eq!(normalize_range(3, 5, 10), None);
+        assert_eq!(normalize_range(3, 2, 1), None);
+        assert_eq!(normalize_range(0, 0, -1), None);
+    }
+
+    #[test]
+    fn test_normalize_index() {
+        assert_eq!(normalize_index(3, 0), Some(0));
+        assert_eq!(normalize_index(3, -1), Some(2));
+        assert_eq!(normalize_index(3, -3), Some(0));
+        assert_eq!(normalize_index(3, -4), None);
+        assert_eq!(normalize_index(3, 3), None);
+        assert_eq!(normalize_index(0, 0), None);
+    }
+
+    fn list_of(hmap: &HashMap<Bytes, StoredValue>, key: &[u8]) -> Vec<Bytes> {
+        match hmap.range(key, 0, -1).unwrap() {
+            None => vec![],
+            Some((values, _)) => values.into_iter().cloned().collect(),
+        }
+    }
+
+    #[test]
+    fn test_rem() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        let values: Vec<&[u8]> = vec![b"a", b"x", b"b", b"x", b"c", b"x"];
+
+        hmap.append(b"head", values.clone()).unwrap();
+        assert_eq!(hmap.rem(b"head", 2, b"x").unwrap(), 2);
+        assert_eq!(list_of(&hmap, b"head"), vec!["a", "b", "c", "x"]);
+
+        hmap.append(b"tail", values.clone()).unwrap();
+        assert_eq!(hmap.rem(b"tail", -2, b"x").unwrap(), 2);
+        assert_eq!(list_of(&hmap, b"tail"), vec!["a", "x", "b", "c"]);
+
+        hmap.append(b"all", values).unwrap();
+        assert_eq!(hmap.rem(b"all", 0, b"x").unwrap(), 3);
+        assert_eq!(list_of(&hmap, b"all"), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_rem_deletes_empty_list() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        hmap.append(b"list", vec![b"x", b"x"]).unwrap();
+        assert_eq!(hmap.rem(b"list", 0, b"x").unwrap(), 2);
+        assert!(!hmap.contains_key(b"list".as_slice()));
+        assert_eq!(hmap.rem(b"list", 0, b"x").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        hmap.append(b"list", vec![b"a", b"c"]).unwrap();
+        assert_eq!(hmap.list_insert(b"list", true, b"c", b"b").unwrap(), 3);
+        assert_eq!(hmap.list_insert(b"list", false, b"c", b"d").unwrap(), 4);
+        assert_eq!(list_of(&hmap, b"list"), vec!["a", "b", "c", "d"]);
+        assert_eq!(hmap.list_insert(b"list", true, b"z", b"y").unwrap(), -1);
+        assert_eq!(hmap.list_insert(b"ghost", true, b"a", b"y").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_pos() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        hmap.append(b"list", vec![b"a", b"x", b"b", b"x", b"x"])
+            .unwrap();
+        assert_eq!(hmap.pos(b"list", b"x", 1, 1).unwrap(), vec![1]);
+        assert_eq!(hmap.pos(b"list", b"x", 2, 1).unwrap(), vec![3]);
+        assert_eq!(hmap.pos(b"list", b"x", -1, 1).unwrap(), vec![4]);
+        assert_eq!(hmap.pos(b"list", b"x", 1, 0).unwrap(), vec![1, 3, 4]);
+        assert_eq!(hmap.pos(b"list", b"x", -2, 0).unwrap(), vec![3, 1]);
+        assert!(hmap.pos(b"list", b"z", 1, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_push_existing() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        assert_eq!(hmap.prepend_existing(b"list", vec![b"a"]).unwrap(), 0);
+        assert!(!hmap.contains_key(b"list".as_slice()));
+
+        hmap.append(b"list", vec![b"b"]).unwrap();
+        assert_eq!(hmap.prepend_existing(b"list", vec![b"a"]).unwrap(), 2);
+        assert_eq!(hmap.append_existing(b"list", vec![b"c"]).unwrap(), 3);
+        assert_eq!(list_of(&hmap, b"list"), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_range_large_list() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        let values: Vec<String> = (0..100_000).map(|i| i.to_string()).collect();
+        hmap.append(b"list", values.iter().map(|v| v.as_bytes()).collect())
+            .unwrap();
+
+        let (tail, len) = hmap.range(b"list", -10, -1).unwrap().unwrap();
+        assert_eq!(len, 10);
+        assert_eq!(tail[0].as_ref(), b"99990");
+
+        let (middle, len) = hmap.range(b"list", 50_000, 50_002).unwrap().unwrap();
+        assert_eq!(len, 3);
+        assert_eq!(middle[2].as_ref(), b"50002");
+
+        let (all, len) = hmap.range(b"list", 0, -1).unwrap().unwrap();
+        assert_eq!(len, 100_000);
+        assert_eq!(all[99_999].as_ref(), b"99999");
+    }
 }
@@ -1,35 +1,66 @@
+mod aof;
+mod blocking;
 mod cmd;
+mod config;
 mod dict_ops;
 mod err;
+mod eviction;
+mod glob;
 mod hmap_ops;
+mod info;
 mod list_ops;
 mod memory;
+mod notify;
 mod numerical_ops;
 mod ops;
+mod persistence;
+mod pubsub;
+mod random;
+mod ranked_set;
+mod replication;
+mod scan;
 mod set_ops;
+mod signal;
 mod sorted_set_ops;
 mod stats;
 mod stored_value;
+mod transaction;
 
-use crate::cmd::Command;
+use crate::aof::Aof;
+use crate::blocking::{Blocking, End};
+use crate::cmd::owned::OwnedCommand;
+use crate::cmd::{AclSub, ClientSub, Command, DebugSub, ExpiryOption, Info};
+use crate::config::Config;
 use crate::dict_ops::HMapDictOps;
-use crate::err::RedisError;
-use hmap_ops::HMapOps;
+use crate::err::{RedisError, WrongType};
+use crate::eviction::MaxMemory;
+use crate::info::InfoReport;
+use hmap_ops::{AccessMap, HMapOps, Ttl, TtlMap};
 
-use crate::list_ops::{HMapListOps, Popped};
+use crate::list_ops::{HMapListOps, ListSet, Popped};
 use crate::memory::memory_usage;
+use crate::notify::KeyspaceEvents;
 use crate::numerical_ops::HMapNumericalOps;
+use crate::ops::Protocol;
+use crate::pubsub::PubSub;
+use crate::random::Rng;
+use crate::replication::{MasterLink, Replication};
 use crate::set_ops::HMapSetOps;
+use crate::signal::Signals;
 use crate::sorted_set_ops::HMapSortedSetOps;
+use crate::stats::Stats;
 use crate::stored_value::StoredValue;
+use crate::transaction::{Exec, Transaction, Versions};
 use anyhow::Context;
 use bytes::Bytes;
 use compact_str::CompactString;
 use histogram::Histogram;
-use mio::net::TcpListener;
-use mio::{Events, Interest, Poll, Token};
-use std::collections::{BTreeMap, HashMap};
-use std::time::Instant;
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Registry, Token};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 #[cfg(not(target_env = "msvc"))]
 use tikv_jemallocator::Jemalloc;
 use tracing::{info, trace, warn};
@@ -39,19 +70,187 @@ use tracing::{info, trace, warn};
 static GLOBAL: Jemalloc = Jemalloc;
 
 const SERVER: Token = Token(0);
+const SIGNALS: Token = Token(usize::MAX);
+/// The link to the master, while the server is a replica.
+const MASTER: Token = Token(usize::MAX - 1);
+/// Number of logical databases addressable with `SELECT`.
+const DATABASES: usize = 16;
 
-struct Client {
-    ops: ops::Ops,
+/// A connection, or with an in-memory stream, the stand-in client replaying
+/// the AOF.
+struct Client<S = TcpStream> {
+    ops: ops::Ops<S>,
     read_buf: Vec<u8>,
+    token: Token,
+    /// Database the client `SELECT`ed, 0 by default.
+    db_index: usize,
+    transaction: Transaction,
+    /// Whether the poll reports the socket writable too, which it only does
+    /// while replies are pending.
+    awaiting_writable: bool,
+    /// Whether the client may run commands, which takes an `AUTH` first
+    /// while `requirepass` is set.
+    authenticated: bool,
+    /// Set by `CLIENT SETNAME`, empty until then.
+    name: Bytes,
+    /// Where the connection comes from, `None` for the stand-in clients.
+    addr: Option<SocketAddr>,
+    connected_at: Instant,
+    /// The library identity `CLIENT SETINFO` reports, empty until then.
+    lib_name: Bytes,
+    lib_ver: Bytes,
+    /// Set by `CLIENT NO-EVICT`. Clients are never evicted here, so it's only
+    /// reported.
+    no_evict: bool,
+    /// Set by `CLIENT NO-TOUCH`: the client's commands, but for `TOUCH`,
+    /// leave the idle time of keys alone.
+    no_touch: bool,
+}
+
+impl<S> Client<S> {
+    /// The line `CLIENT LIST` shows for the client.
+    fn describe(&self, now: Instant) -> String {
+        let addr = self.addr.map(|addr| addr.to_string()).unwrap_or_default();
+        let mut flags = String::new();
+        if self.no_evict {
+            flags.push('e');
+        }
+        if self.no_touch {
+            flags.push('T');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+        format!(
+            "id={} addr={addr} name={} age={} flags={flags} db={} lib-name={} lib-ver={}",
+            self.token.0,
+            String::from_utf8_lossy(&self.name),
+            now.duration_since(self.connected_at).as_secs(),
+            self.db_index,
+            String::from_utf8_lossy(&self.lib_name),
+            String::from_utf8_lossy(&self.lib_ver)
+        )
+    }
+}
+
+impl Client {
+    /// Writes the pending replies, waiting for the socket to be writable
+    /// again if it can't take them all.
+    fn flush(&mut self, registry: &Registry) -> std::io::Result<()> {
+        let drained = self.ops.flush()?;
+        if drained == self.awaiting_writable {
+            let interest = if drained {
+                Interest::READABLE
+            } else {
+                Interest::READABLE | Interest::WRITABLE
+            };
+            registry.reregister(self.ops.stream_mut(), self.token, interest)?;
+            self.awaiting_writable = !drained;
+        }
+        Ok(())
+    }
+
+    /// Drops what a disconnected client held on the server.
+    fn disconnect(self, server: &mut Server, registry: &Registry) -> std::io::Result<()> {
+        let Client {
+            ops,
+            token,
+            mut transaction,
+            ..
+        } = self;
+        transaction.unwatch(&mut server.versions);
+        server.pubsub.unsubscribe_all(token);
+        server.replication.detach(token);
+        server.blocking.remove(token);
+        trace!("[{token:?}] disconnected");
+        registry.deregister(&mut ops.unwrap_stream())
+    }
+}
+
+/// State shared by all clients.
+struct Server {
+    uptime_since: Instant,
+    dbs: Vec<HashMap<Bytes, StoredValue>>,
+    // deadlines of each database, indexed like `dbs`
+    ttl_maps: Vec<TtlMap>,
+    access_maps: Vec<AccessMap>,
+    versions: Vec<Versions>,
+    rng: Rng,
+    config: Config,
+    max_memory: MaxMemory,
+    latency_histograms: HashMap<CompactString, Histogram>,
+    connected_clients: usize,
+    /// The connections, but for the one whose commands are running, which
+    /// is lent out meanwhile.
+    clients: HashMap<Token, Client>,
+    blocking: Blocking,
+    pubsub: PubSub,
+    aof: Aof,
+    replication: Replication,
+    stats: Stats,
+    /// Whether the event loop sweeps expired keys, which `DEBUG
+    /// SET-ACTIVE-EXPIRE 0` turns off.
+    active_expire: bool,
+    /// Set once shutting down is prepared, the event loop stops right after.
+    shutting_down: bool,
 }
 
 fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
 
-    let uptime_since = Instant::now();
+    let mut rng = Rng::from_entropy();
+    let replication = Replication::new(&mut rng);
+    let mut server = Server {
+        uptime_since: Instant::now(),
+        dbs: vec![HashMap::default(); DATABASES],
+        ttl_maps: vec![TtlMap::default(); DATABASES],
+        access_maps: vec![AccessMap::default(); DATABASES],
+        versions: vec![Versions::default(); DATABASES],
+        rng,
+        config: Config::default(),
+        max_memory: MaxMemory::default(),
+        latency_histograms: HashMap::new(),
+        connected_clients: 0,
+        clients: HashMap::new(),
+        blocking: Blocking::default(),
+        pubsub: PubSub::default(),
+        aof: Aof::default(),
+        replication,
+        stats: Stats::default(),
+        active_expire: true,
+        shutting_down: false,
+    };
 
-    let mut hmap: HashMap<Bytes, StoredValue> = HashMap::default();
-    let _ttl_map: BTreeMap<Instant, Bytes> = BTreeMap::default();
+    // an AOF means appendonly was on when the server stopped, and it's more
+    // recent than any snapshot
+    match std::fs::read(aof::FILENAME) {
+        Ok(log) => {
+            match replay_aof(&mut server, &log) {
+                Ok(replayed) => info!("Replayed {replayed} commands from {}", aof::FILENAME),
+                Err(e) => {
+                    let keys: usize = server.dbs.iter().map(HashMap::len).sum();
+                    warn!("{e:#}, starting with the {keys} keys replayed before the error");
+                }
+            }
+            // compacting the log right away also drops a torn tail
+            server.aof.rewrite(&server.dbs)?;
+            server.config.set(b"appendonly", b"yes")?;
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            // a damaged snapshot mustn't keep the server down, it starts with
+            // whatever could be read
+            let snapshot = server.config.db_filename();
+            match persistence::load(snapshot, &mut server.dbs, &mut server.ttl_maps) {
+                Ok(false) => {}
+                Ok(true) => info!("Loaded the snapshot from {}", snapshot.display()),
+                Err(e) => {
+                    let keys: usize = server.dbs.iter().map(HashMap::len).sum();
+                    warn!("{e:#}, starting with the {keys} keys read before the error");
+                }
+            }
+        }
+        Err(e) => Err(e).with_context(|| format!("can't read {}", aof::FILENAME))?,
+    }
 
     let addr = "127.0.0.1:6379".parse()?;
     let mut listener = TcpListener::bind(addr)?;
@@ -61,20 +260,81 @@ fn main() -> anyhow::Result<()> {
 
     poll.registry()
         .register(&mut listener, SERVER, Interest::READABLE)?;
+    let mut signals = Signals::install()?;
+    poll.registry()
+        .register(&mut signals, SIGNALS, Interest::READABLE)?;
 
-    let mut clients: HashMap<Token, Client> = HashMap::new();
+    let mut master_link: Option<MasterLink> = None;
     let mut next_token_id: usize = 1;
     let mut buf = [0u8; 1024];
 
-    let mut latency_histograms = HashMap::<CompactString, Histogram>::new();
-
     info!("TCP server listening on {addr}");
 
     loop {
-        poll.poll(&mut events, None)?;
+        // wake up in time for the nearest deadline so expired keys are swept
+        // even when no client is active, to connect to the master, or to let
+        // a blocked client go
+        let timeout = server
+            .ttl_maps
+            .iter()
+            .filter(|_| server.active_expire)
+            .filter_map(|ttl_map| ttl_map.keys().next().copied())
+            .chain(server.replication.connect_at())
+            .chain(server.blocking.next_deadline())
+            .min()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        match poll.poll(&mut events, timeout) {
+            // a signal landed mid-poll, its pipe event comes with the next one
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            result => result?,
+        }
+        let mut signaled = false;
+        // clients that may have replies to write
+        let mut to_flush = HashSet::new();
+
+        let now = Instant::now();
+        let mut expired = 0;
+        for index in (0..DATABASES).filter(|_| server.active_expire) {
+            expired += expire_keys(
+                &mut server.dbs[index],
+                &mut server.ttl_maps[index],
+                &mut server.access_maps[index],
+                &mut server.versions[index],
+                &mut server.pubsub,
+                index,
+                now,
+            );
+        }
+        if expired > 0 {
+            trace!("{expired} expired keys removed");
+            server.stats.expired_keys += expired;
+        }
+        for token in server.blocking.timed_out(now) {
+            if let Some(client) = server.clients.get_mut(&token) {
+                client.ops.write_null_array()?;
+            }
+            server.blocking.unblock(token);
+        }
 
         for event in &events {
+            // the rest would run after the final save
+            if server.shutting_down {
+                break;
+            }
             match event.token() {
+                SIGNALS => signaled |= signals.received(),
+                MASTER => {
+                    let Some(link) = &mut master_link else {
+                        continue;
+                    };
+                    if let Err(e) = follow_master(&mut server, link, poll.registry(), event) {
+                        warn!("{e:#}, lost the link to the master");
+                        if let Some(mut link) = master_link.take() {
+                            poll.registry().deregister(link.stream_mut())?;
+                        }
+                        server.replication.link_lost(Instant::now());
+                    }
+                }
                 // New connection coming in
                 SERVER => loop {
                     let (mut stream, addr) = match listener.accept() {
@@ -84,41 +344,48 @@ fn main() -> anyhow::Result<()> {
                     };
                     let token = Token(next_token_id);
                     next_token_id += 1;
+                    server.stats.total_connections_received += 1;
 
                     trace!("[{token:?}] Connected: {addr}");
 
-                    poll.registry().register(
-                        &mut stream,
-                        token,
-                        Interest::READABLE | Interest::WRITABLE,
-                    )?;
+                    poll.registry()
+                        .register(&mut stream, token, Interest::READABLE)?;
 
-                    clients.insert(
+                    server.clients.insert(
                         token,
                         Client {
                             ops: ops::Ops::new(stream),
                             read_buf: Vec::with_capacity(4096),
+                            token,
+                            db_index: 0,
+                            transaction: Transaction::default(),
+                            awaiting_writable: false,
+                            authenticated: !server.config.requires_pass(),
+                            name: Bytes::new(),
+                            addr: Some(addr),
+                            connected_at: Instant::now(),
+                            lib_name: Bytes::new(),
+                            lib_ver: Bytes::new(),
+                            no_evict: false,
+                            no_touch: false,
                         },
                     );
+                    server.connected_clients = server.clients.len();
                 },
 
                 // Activity on an existing client connection
                 token => {
                     let mut closed = false;
-                    // for huge responses
-                    let mut to_return = Vec::<u8>::new();
-
-                    let cmd_instant = Instant::now();
-                    let current_command: CompactString;
-
-                    let clients_len = clients.len();
-                    let client = clients
-                        .get_mut(&token)
+                    // lent out so its commands can see the other clients
+                    let mut client = server
+                        .clients
+                        .remove(&token)
                         .with_context(|| format!("client not registered: {:?}", token))?;
 
                     if event.is_readable() {
+                        let query_buffer_limit = server.config.client_query_buffer_limit();
                         loop {
-                            let cmd = match client.ops.read(&mut buf) {
+                            match client.ops.read(&mut buf) {
                                 Ok(0) => {
                                     // Connection closed by peer
                                     closed = true;
@@ -126,14 +393,11 @@ fn main() -> anyhow::Result<()> {
                                 }
                                 Ok(n) => {
                                     client.read_buf.extend_from_slice(&buf[..n]);
-                                    let maybe_command = cmd::parser::parse(&client.read_buf);
-                                    match maybe_command {
-                                        Err(RedisError::IncompleteInput) => continue,
-                                        Err(err) => {
-                                            client.ops.generic_error(err.to_string())?;
-                                            break;
-                                        }
-                                        Ok(command) => command,
+                                    if client.read_buf.len() > query_buffer_limit {
+                                        warn!("[{token:?}] Closing, query buffer limit reached");
+                                        client.read_buf.clear();
+                                        closed = true;
+                                        break;
                                     }
                                 }
                                 Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
@@ -142,548 +406,1969 @@ fn main() -> anyhow::Result<()> {
                                     closed = true;
                                     break;
                                 }
-                            };
-                            match cmd {
-                                Command::Get(key) => {
-                                    match hmap.get(key) {
-                                        None => client.ops.key_not_found()?,
-                                        Some(StoredValue::Plain(bytes)) => {
-                                            client.ops.write_bulk_string(bytes)?;
-                                        }
-                                        Some(_) => client.ops.wrong_type("expected STRING")?,
-                                    };
-                                    current_command = cmd::GET;
-                                }
-                                Command::Set(key, value, maybe_ttl) => {
-                                    hmap.insert_alloc(
-                                        key,
-                                        value,
-                                        maybe_ttl.map(|dur| Instant::now() + dur),
-                                    );
-                                    client.ops.ok()?;
-                                    current_command = cmd::SET;
-                                }
-                                Command::SetNx(key, value) => {
-                                    hmap.set_if_not_exist(key, value);
-                                    client.ops.ok()?;
-                                    current_command = cmd::SET;
-                                }
-                                Command::SetXx(key, value) => {
-                                    hmap.update_if_exist(key, value);
-                                    client.ops.ok()?;
-                                    current_command = cmd::SET;
-                                }
-                                Command::SetAndGet(key, value) => {
-                                    match hmap.insert_alloc(key, value, None) {
-                                        None => {
-                                            client.ops.key_not_found()?;
-                                        }
-                                        Some(StoredValue::Plain(bytes)) => {
-                                            client.ops.write_bulk_string(&bytes)?;
-                                        }
-                                        Some(StoredValue::TtlPlain(bytes, _)) => {
-                                            client.ops.write_bulk_string(bytes)?;
-                                        }
-                                        _ => client.ops.wrong_type("expected STRING")?,
-                                    }
-                                    current_command = cmd::SET;
-                                }
-                                Command::SetKeepTtl(key, value) => {
-                                    match hmap.get_mut(key) {
-                                        None => {
-                                            hmap.insert_alloc(key, value, None);
-                                            client.ops.ok()?;
-                                        }
-                                        Some(StoredValue::Plain(bytes)) => {
-                                            client.ops.write_bulk_string(&bytes)?;
-                                            *bytes = Bytes::copy_from_slice(bytes);
-                                        }
-                                        Some(StoredValue::TtlPlain(bytes, _)) => {
-                                            client.ops.write_bulk_string(&bytes)?;
-                                            *bytes = Bytes::copy_from_slice(bytes);
-                                        }
-                                        _ => client.ops.wrong_type("expected STRING")?,
-                                    };
-                                    current_command = cmd::SET;
-                                }
-                                Command::Ping => {
-                                    client.ops.pong()?;
-                                    current_command = cmd::PING;
-                                }
-                                Command::FlushDb => {
-                                    client.ops.ok()?;
-                                    current_command = cmd::FLUSHDB;
-                                }
-                                Command::Docs => {
-                                    client.ops.write_array(std::iter::empty::<&[u8]>(), 0)?;
-                                    current_command = cmd::DOCS;
-                                }
-                                Command::DbSize => {
-                                    client.ops.write_integer(hmap.keys().len())?;
-                                    current_command = cmd::DBSIZE;
-                                }
-                                Command::Config => {
-                                    client.ops.write_array(std::iter::empty::<&[u8]>(), 0)?;
-                                    current_command = cmd::CONFIG;
-                                }
-                                Command::Lpush(key, values) => {
-                                    match hmap.prepend(key, values) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(values_len) => client.ops.write_integer(values_len)?,
-                                    };
-                                    current_command = cmd::LPUSH;
-                                }
-                                Command::Rpush(key, values) => {
-                                    match hmap.append(key, values) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(values_len) => client.ops.write_integer(values_len)?,
-                                    };
-                                    current_command = cmd::RPUSH;
-                                }
-                                Command::LpushX(_, _) => {
-                                    todo!("Command::LpushX is not implemented")
-                                }
-                                Command::RpushX(_, _) => {
-                                    todo!("Command::RpushX is not implemented")
-                                }
-                                Command::Lpop(key, maybe_count) => {
-                                    match hmap.pop_front(key, maybe_count) {
-                                        Ok(Popped::None) => client.ops.key_not_found()?,
-                                        Ok(Popped::Single(bytes)) => {
-                                            client.ops.write_bulk_string(bytes)?
-                                        }
-                                        Ok(Popped::Multiple(values)) => {
-                                            client.ops.write_array(values.iter(), values.len())?
-                                        }
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                    }
-                                    current_command = cmd::LPOP;
-                                }
-                                Command::Rpop(key, maybe_count) => {
-                                    match hmap.pop_back(key, maybe_count) {
-                                        Ok(Popped::None) => client.ops.key_not_found()?,
-                                        Ok(Popped::Single(bytes)) => {
-                                            client.ops.write_bulk_string(bytes)?
-                                        }
-                                        Ok(Popped::Multiple(values)) => {
-                                            client.ops.write_array(values.iter(), values.len())?
-                                        }
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                    }
-                                    current_command = cmd::RPOP;
-                                }
-                                Command::Del(keys) => {
-                                    let count = hmap.delete_all(keys.into_iter());
-                                    client.ops.write_integer(count)?;
-                                    current_command = cmd::DEL;
-                                }
-                                Command::Incr(key) => {
-                                    match hmap.incr_by(key, 1) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some(value)) => client
-                                            .ops
-                                            .write_integer(String::from_utf8_lossy(&value))?,
-                                    };
-                                    current_command = cmd::INCR;
-                                }
-                                Command::IncrBy(key, incr_by) => {
-                                    match hmap.incr_by(key, incr_by) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some(value)) => client
-                                            .ops
-                                            .write_integer(String::from_utf8_lossy(&value))?,
-                                    };
-                                    current_command = cmd::INCR;
-                                }
-                                Command::ClientSetInfo(_) | Command::ClientSetName => {
-                                    client.ops.ok()?;
-                                    current_command = cmd::CLIENT;
-                                }
-                                Command::Ttl(key) => {
-                                    match hmap.get_ttl(key) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some(value)) => {
-                                            client.ops.write_integer(value.as_secs())?
-                                        }
-                                    };
-                                    current_command = cmd::TTL;
-                                }
-                                Command::Lrange(key, start, end) => {
-                                    match hmap.get(key) {
-                                        None => client.ops.key_not_found()?,
-                                        Some(StoredValue::List(ll)) => {
-                                            let start = if start < 0 {
-                                                ll.len() as isize - start
-                                            } else {
-                                                start
-                                            };
-                                            let end = if end < 0 {
-                                                ll.len() as isize - end
-                                            } else {
-                                                end
-                                            };
-                                            // @todo optimise this...
-                                            info!(
-                                                "lpop: key = {}, start = {}, end = {}, ll={:?}",
-                                                String::from_utf8_lossy(key),
-                                                start,
-                                                end,
-                                                &ll
-                                            );
-
-                                            let vec: Vec<_> = if start <= end {
-                                                ll.iter()
-                                                    .skip(start as usize)
-                                                    .take(end as usize + 1)
-                                                    .collect()
-                                            } else {
-                                                ll.iter()
-                                                    .skip(end as usize)
-                                                    .take(start as usize + 1)
-                                                    .rev()
-                                                    .collect()
-                                            };
-                                            client.ops.write_array(vec.iter(), vec.len())?;
-                                        }
-                                        _ => client.ops.wrong_type("stored value isn't a list")?,
-                                    };
-                                    current_command = cmd::LRANGE;
-                                }
-                                Command::LLen(key) => {
-                                    match hmap.get(key) {
-                                        None => client.ops.key_not_found()?,
-                                        Some(StoredValue::List(ll)) => {
-                                            client.ops.write_integer(ll.len())?;
-                                        }
-                                        _ => client.ops.wrong_type("stored value isn't a list")?,
-                                    };
-                                    current_command = cmd::LLEN;
-                                }
-                                Command::Hget(key, field) => {
-                                    match hmap.dict_get(key, field) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some(value)) => client.ops.write_bulk_string(value)?,
-                                    };
-                                    current_command = cmd::HGET;
-                                }
-                                Command::HMget(key, fields) => {
-                                    match hmap.dict_mget(key, &fields) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some((values, len))) => {
-                                            client.ops.write_array(values.into_iter(), len)?
-                                        }
-                                    };
-                                    current_command = cmd::HMGET;
-                                }
-                                Command::HMset(key, fields_and_values) => {
-                                    match hmap.dict_mset(key, &fields_and_values) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(()) => client.ops.ok()?,
-                                    }
-                                    current_command = cmd::HMSET;
-                                }
-                                Command::HgetAll(key) => {
-                                    match hmap.dict_get_all(key) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some((values, len))) => {
-                                            client.ops.write_array(values.into_iter(), len)?
-                                        }
-                                    };
-                                    current_command = cmd::HMSET;
-                                }
-                                Command::HincrBy(key, field, incr_by) => {
-                                    match hmap.dict_incr_by(key, field, incr_by) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(value) => client
-                                            .ops
-                                            .write_integer(String::from_utf8_lossy(&value))?,
-                                    }
-                                    current_command = cmd::HINCRBY;
-                                }
-                                Command::Exists(key) => {
-                                    let exists = if hmap.contains_key(key) { 1 } else { 0 };
-                                    client.ops.write_integer(exists)?;
-                                    current_command = cmd::EXISTS;
-                                }
-                                Command::Hexists(key, field) => {
-                                    match hmap.dict_exists(key, field) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(exists) => {
-                                            let v = if exists { 1 } else { 0 };
-                                            client.ops.write_integer(v)?;
-                                        }
-                                    }
-                                    current_command = cmd::HEXISTS;
-                                }
-                                Command::Hkeys(key) => {
-                                    match hmap.dict_keys(key) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some((keys, len))) => {
-                                            client.ops.write_array(keys.into_iter(), len)?
-                                        }
-                                    };
-                                    current_command = cmd::HKEYS;
-                                }
-                                Command::Sadd(key, members) => {
-                                    match hmap.set_add(key, members) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(added) => client.ops.write_integer(added)?,
-                                    };
-                                    current_command = cmd::SADD;
-                                }
-                                Command::Sismember(key, member) => {
-                                    match hmap.set_is_member(key, member) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(exists) => {
-                                            let v = if exists { 1 } else { 0 };
-                                            client.ops.write_integer(v)?;
-                                        }
-                                    }
-                                    current_command = cmd::SISMEMBER;
-                                }
-                                Command::Sinter(keys) => {
-                                    match hmap.set_inter(&keys) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok((values, len)) => {
-                                            client.ops.write_array(values.into_iter(), len)?
-                                        }
-                                    };
-                                    current_command = cmd::SINTER
-                                }
-                                Command::Sunion(keys) => {
-                                    match hmap.set_union(&keys) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok((values, len)) => {
-                                            client.ops.write_array(values.into_iter(), len)?
-                                        }
-                                    };
-                                    current_command = cmd::SUNION;
-                                }
-                                Command::Sdiff(keys) => {
-                                    match hmap.set_diff(&keys) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok((values, len)) => {
-                                            client.ops.write_array(values.into_iter(), len)?
-                                        }
-                                    };
-                                    current_command = cmd::SDIFF;
-                                }
-                                Command::Scard(key) => {
-                                    match hmap.set_card(key) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some(len)) => client.ops.write_integer(len)?,
-                                    };
-                                    current_command = cmd::SCARD;
-                                }
-                                Command::Smembers(key) => {
-                                    match hmap.set_members(key) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some((members, len))) => {
-                                            client.ops.write_array(members.into_iter(), len)?
-                                        }
-                                    };
-                                    current_command = cmd::SMEMBERS;
-                                }
-                                Command::Zadd(key, members) => {
-                                    match hmap.zset_add(key, &members) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(added) => client.ops.write_integer(added)?,
-                                    };
-                                    current_command = cmd::ZADD;
-                                }
-                                Command::Zrange(key, start, stop, withscores) => {
-                                    match hmap.zset_range(key, start, stop, withscores) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some((values, len))) => {
-                                            client.ops.write_array(values.iter(), len)?
-                                        }
-                                    };
-                                    current_command = cmd::ZRANGE;
-                                }
-                                Command::Zrevrange(key, start, stop, withscores) => {
-                                    match hmap.zset_revrange(key, start, stop, withscores) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some((values, len))) => {
-                                            client.ops.write_array(values.iter(), len)?
-                                        }
-                                    };
-                                    current_command = cmd::ZREVRANGE;
-                                }
-                                Command::Zrank(key, member) => {
-                                    match hmap.zset_rank(key, member) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some(rank)) => client.ops.write_integer(rank)?,
-                                    };
-                                    current_command = cmd::ZRANK;
-                                }
-                                Command::Zrevrank(key, member) => {
-                                    match hmap.zset_revrank(key, member) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some(rank)) => client.ops.write_integer(rank)?,
-                                    };
-                                    current_command = cmd::ZREVRANK;
-                                }
-                                Command::Zscore(key, member) => {
-                                    match hmap.zset_score(key, member) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some(score)) => {
-                                            client.ops.write_bulk_string(score.to_string())?
-                                        }
-                                    };
-                                    current_command = cmd::ZSCORE;
-                                }
-                                Command::Zrangebyscore(key, min, max, withscores) => {
-                                    match hmap.zset_range_by_score(key, min, max, withscores) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some((values, len))) => {
-                                            client.ops.write_array(values.iter(), len)?
-                                        }
-                                    };
-                                    current_command = cmd::ZRANGEBYSCORE;
-                                }
-                                Command::Zincrby(key, incr, member) => {
-                                    match hmap.zset_incr_by(key, incr, member) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(score) => {
-                                            client.ops.write_bulk_string(score.to_string())?
-                                        }
-                                    };
-                                    current_command = cmd::ZINCRBY;
-                                }
-                                Command::Zcard(key) => {
-                                    match hmap.zcard(key) {
-                                        Err(e) => client.ops.wrong_type(e.to_string())?,
-                                        Ok(None) => client.ops.key_not_found()?,
-                                        Ok(Some(value)) => client.ops.write_integer(value)?,
-                                    };
-                                    current_command = cmd::ZCARD;
-                                }
-                                Command::InfoCmd => {
-                                    let (alloc, _) = memory_usage()?;
-                                    let alloc_readable =
-                                        format!("{}K", f64::trunc(alloc as f64 / 1024.0));
-                                    let uptime_in_seconds = uptime_since.elapsed().as_secs();
-                                    let uptime_in_days = uptime_since.elapsed().as_secs() / 24;
-                                    let command_stats =
-                                        stats::CommandStats::make(&latency_histograms);
-
-                                    let info = format!(
-                                        "# Server\r\n\
-                                         redis_version:reddis2-0.0.1\r\n\
-                                         redis_mode:standalone\r\n\
-                                         os:Rust/mio\r\n\
-                                         arch_bits:64\r\n\
-                                         tcp_port:6379\r\n\
-                                         uptime_in_seconds:{uptime_in_seconds}\r\n\
-                                         uptime_in_days:{uptime_in_days}\r\n\
-                                         hz:10\r\n\
-                                         executable:/usr/local/bin/reddis2\r\n\
-                                         config_file:\r\n\
-                                         \r\n\
-                                         # Clients\r\n\
-                                         connected_clients:{clients_len}\r\n\
-                                         blocked_clients:0\r\n\
-                                         tracking_clients:0\r\n\
-                                         maxclients:10000\r\n\
-                                         \r\n\
-                                         # Memory\r\n\
-                                         used_memory:{alloc}\r\n\
-                                         used_memory_human:{alloc_readable}\r\n\
-                                         used_memory_peak:2048000\r\n\
-                                         maxmemory:0\r\n\
-                                         maxmemory_human:0B\r\n\
-                                         maxmemory_policy:noeviction\r\n\
-                                         mem_fragmentation_ratio:2.00\r\n\
-                                         \r\n\
-                                         # Stats\r\n\
-                                         total_connections_received:100\r\n\
-                                         total_commands_processed:1337\r\n\
-                                         instantaneous_ops_per_sec:42\r\n\
-                                         rejected_connections:0\r\n\
-                                         expired_keys:0\r\n\
-                                         evicted_keys:0\r\n\
-                                         keyspace_hits:500\r\n\
-                                         keyspace_misses:50\r\n\
-                                         \r\n\
-                                         # Replication\r\n\
-                                         role:master\r\n\
-                                         connected_slaves:0\r\n\
-                                         \r\n\
-                                         # CPU\r\n\
-                                         used_cpu_sys:0.420000\r\n\
-                                         used_cpu_user:0.690000\r\n\
-                                         \r\n\
-                                         {command_stats}\
-                                         \r\n\
-                                         # Keyspace\r\n\
-                                         db0:keys={},expires=0,avg_ttl=0\r\n",
-                                        hmap.len(),
-                                    );
-                                    client.ops.write_bulk_string(&info)?;
-                                    current_command = cmd::INFO;
-                                }
-                                Command::LatencyHistogram(commands) => {
-                                    client
-                                        .ops
-                                        .write_latency_histogram(&latency_histograms, &commands)?;
-                                    current_command = cmd::LATENCY;
-                                }
                             }
-                            client.read_buf.clear();
-
-                            let latency = cmd_instant.elapsed().as_micros() as u64;
-                            latency_histograms
-                                .entry(current_command.clone())
-                                .or_insert(Histogram::new(2, 30)?)
-                                .increment(latency)
-                                .with_context(|| {
-                                    format!("can't store latency {latency} for {current_command}")
-                                })?;
-
-                            trace!(
-                                "[{token:?}] command is executed, buffer cleared, latency: {}usecs",
-                                latency
-                            );
-                            break;
                         }
+                        closed |= run_buffered(&mut server, &mut client)?;
                     }
 
-                    if event.is_writable() && !to_return.is_empty() {
-                        match client.ops.ok() {
-                            Ok(()) => {
-                                trace!("[{token:?}] Echoed {} bytes", to_return.len());
-                                to_return.clear();
-                            }
-                            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
-                            Err(e) => {
-                                warn!("[{token:?}] Write error: {e}");
-                                closed = true;
-                            }
+                    if closed {
+                        client.disconnect(&mut server, poll.registry())?;
+                        server.connected_clients = server.clients.len();
+                    } else {
+                        server.clients.insert(token, client);
+                        // replies to what was read, or the rest of earlier
+                        // ones if the socket became writable
+                        to_flush.insert(token);
+                    }
+                }
+            }
+        }
+
+        // clients let go run what they sent meanwhile, which may block them
+        // again or let others go
+        loop {
+            let resumed = server.blocking.take_resumed();
+            if resumed.is_empty() {
+                break;
+            }
+            for token in resumed {
+                let Some(mut client) = server.clients.remove(&token) else {
+                    continue;
+                };
+                if run_buffered(&mut server, &mut client)? {
+                    client.disconnect(&mut server, poll.registry())?;
+                    server.connected_clients = server.clients.len();
+                } else {
+                    server.clients.insert(token, client);
+                    to_flush.insert(token);
+                }
+            }
+        }
+
+        if server.replication.take_connect(Instant::now()) {
+            if let Some(mut link) = master_link.take() {
+                poll.registry().deregister(link.stream_mut())?;
+            }
+            if let Some((host, port)) = server.replication.master() {
+                match MasterLink::connect(host, port, poll.registry(), MASTER) {
+                    Ok(link) => {
+                        info!("Connecting to the master at {host}:{port}");
+                        master_link = Some(link);
+                    }
+                    Err(e) => {
+                        warn!("{e:#}, can't connect to the master at {host}:{port}");
+                        server.replication.link_lost(Instant::now());
+                    }
+                }
+            }
+        }
+
+        // the write stream goes out to the replica like messages do
+        if let Some((replica, stream)) = server.replication.take_outbox()
+            && let Some(client) = server.clients.get_mut(&replica)
+        {
+            client.ops.write_raw(&stream)?;
+            to_flush.insert(replica);
+        }
+
+        // no client is borrowed anymore, so the messages published by
+        // commands and expirations can be written to the subscribers
+        for (subscriber, message) in server.pubsub.take_outbox() {
+            if let Some(subscriber) = server.clients.get_mut(&subscriber)
+                && let Err(e) = subscriber.ops.write_message(&message)
+            {
+                warn!("[{:?}] Write error: {e}", subscriber.token);
+            }
+            to_flush.insert(subscriber);
+        }
+
+        for token in to_flush {
+            if let Some(client) = server.clients.get_mut(&token)
+                && let Err(e) = client.flush(poll.registry())
+            {
+                warn!("[{token:?}] Write error: {e}");
+                if let Some(client) = server.clients.remove(&token) {
+                    client.disconnect(&mut server, poll.registry())?;
+                    server.connected_clients = server.clients.len();
+                }
+            }
+        }
+
+        if signaled {
+            match prepare_shutdown(&mut server, None) {
+                Ok(()) => server.shutting_down = true,
+                Err(e) => warn!("{e:#}, not shutting down"),
+            }
+        }
+        if server.shutting_down {
+            break;
+        }
+    }
+
+    for (token, mut client) in std::mem::take(&mut server.clients) {
+        // best effort, a client that isn't reading won't get the rest
+        if let Err(e) = client.ops.flush() {
+            warn!("[{token:?}] Write error: {e}");
+        }
+        poll.registry()
+            .deregister(&mut client.ops.unwrap_stream())?;
+    }
+    if let Some(mut link) = master_link {
+        poll.registry().deregister(link.stream_mut())?;
+    }
+    poll.registry().deregister(&mut listener)?;
+    info!("Server is shut down");
+    Ok(())
+}
+
+/// Persists what stopping would lose: a snapshot, when `save` asks for one
+/// or by default when the config has snapshot points, and the AOF writes the
+/// OS may still buffer. A failed save keeps the server up, as the data would
+/// be lost otherwise.
+fn prepare_shutdown(server: &mut Server, save: Option<bool>) -> anyhow::Result<()> {
+    info!("Preparing to shut down");
+    if save.unwrap_or_else(|| server.config.has_save_points()) {
+        persistence::save(server.config.db_filename(), &server.dbs)?;
+    }
+    server.aof.sync().context("can't flush the AOF")
+}
+
+/// Runs the commands `client` sent, until one is cut short or blocks the
+/// client. Returns whether the client has to be disconnected, for input that
+/// can't be framed.
+fn run_buffered(server: &mut Server, client: &mut Client) -> anyhow::Result<bool> {
+    let token = client.token;
+    let max_bulk_len = server.config.proto_max_bulk_len();
+    let mut closed = false;
+    // the commands borrow the buffer, so the client lends it out while they
+    // run
+    let mut read_buf = std::mem::take(&mut client.read_buf);
+    let mut consumed = 0;
+    while consumed < read_buf.len() && !server.shutting_down && !server.blocking.is_blocked(token) {
+        let parse_instant = Instant::now();
+        let parsed = cmd::parser::expand_inline(&mut read_buf, consumed)
+            .and_then(|()| cmd::parser::parse(&read_buf[consumed..], max_bulk_len));
+        match parsed {
+            // the rest of the command is yet to arrive
+            Err(RedisError::IncompleteInput) => break,
+            Err(err @ RedisError::Protocol(_)) => {
+                client.ops.generic_error(err.to_string())?;
+                record_latency(
+                    &mut server.latency_histograms,
+                    token,
+                    cmd::ERROR,
+                    parse_instant,
+                )?;
+                // the client gets the error before it's disconnected, if it's
+                // reading
+                if let Err(e) = client.ops.flush() {
+                    warn!("[{token:?}] Write error: {e}");
+                }
+                consumed = read_buf.len();
+                closed = true;
+            }
+            Err(err) => {
+                client.transaction.fail();
+                client.ops.generic_error(err.to_string())?;
+                let frame = &read_buf[consumed..];
+                let bucket = if cmd::parser::names_known_command(frame) {
+                    cmd::ERROR
+                } else {
+                    cmd::UNKNOWN
+                };
+                record_latency(&mut server.latency_histograms, token, bucket, parse_instant)?;
+                // skip the malformed command, or all of the buffer if there's
+                // no telling where the next one starts
+                consumed += cmd::parser::frame_len(&read_buf[consumed..], max_bulk_len)
+                    .unwrap_or(read_buf.len() - consumed);
+            }
+            Ok((command, len)) => {
+                consumed += len;
+                dispatch(server, client, command)?;
+                // what it pushed goes to the clients waiting for it first
+                serve_blocked(server)?;
+            }
+        }
+    }
+    client.read_buf = read_buf;
+    client.read_buf.drain(..consumed);
+    Ok(closed)
+}
+
+/// Hands the elements pushed to lists clients wait on to those clients, in
+/// the order they blocked, as if they ran `LPOP`/`RPOP` right then.
+fn serve_blocked(server: &mut Server) -> anyhow::Result<()> {
+    for (db_index, key) in server.blocking.take_ready() {
+        while let Some((token, end)) = server.blocking.waiter(db_index, &key) {
+            let Some(client) = server.clients.get_mut(&token) else {
+                break;
+            };
+            // a list replaced by another type keeps them waiting
+            let Ok(Some((_, value))) = blocking::pop_first(&mut server.dbs[db_index], &[&key], end)
+            else {
+                break;
+            };
+            client
+                .ops
+                .write_array([key.as_ref(), value.as_ref()].iter(), 2)?;
+            server.blocking.unblock(token);
+            let popped = match end {
+                End::Front => OwnedCommand::Lpop(key.clone(), None),
+                End::Back => OwnedCommand::Rpop(key.clone(), None),
+            };
+            if let Err(e) = server.aof.append(db_index, &popped) {
+                warn!("[{token:?}] can't append to the AOF: {e}");
+            }
+            server.replication.feed(db_index, &popped);
+            server.versions[db_index].touch(&key);
+            server
+                .pubsub
+                .notify(KeyspaceEvents::LIST, end.event(), db_index, &key);
+        }
+    }
+    Ok(())
+}
+
+/// Runs the transaction commands and `SHUTDOWN`, and queues every other
+/// command while a transaction is open instead of executing it.
+fn dispatch(server: &mut Server, client: &mut Client, cmd: Command) -> anyhow::Result<()> {
+    let cmd_instant = Instant::now();
+    if !client.authenticated
+        && !matches!(
+            cmd,
+            Command::Auth(_)
+                | Command::AuthUser(..)
+                | Command::Hello(..)
+                | Command::Ping
+                | Command::Reset
+        )
+    {
+        client.transaction.fail();
+        client.ops.no_auth()?;
+        return record_latency(
+            &mut server.latency_histograms,
+            client.token,
+            cmd::ERROR,
+            cmd_instant,
+        );
+    }
+    // a RESP2 connection can't tell replies from messages, so it only
+    // manages its subscriptions until it leaves them all
+    if client.ops.protocol == Protocol::Resp2
+        && server.pubsub.subscription_count(client.token) > 0
+        && !matches!(
+            cmd,
+            Command::Subscribe(_)
+                | Command::Unsubscribe(_)
+                | Command::PSubscribe(_)
+                | Command::PUnsubscribe(_)
+                | Command::Ping
+                | Command::Reset
+        )
+    {
+        client.ops.generic_error(
+            "only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING are allowed in this context",
+        )?;
+        return record_latency(
+            &mut server.latency_histograms,
+            client.token,
+            cmd::ERROR,
+            cmd_instant,
+        );
+    }
+    // only the master's stream changes a replica's data
+    if server.replication.is_replica() && cmd.is_write() {
+        client.transaction.fail();
+        client.ops.read_only()?;
+        return record_latency(
+            &mut server.latency_histograms,
+            client.token,
+            cmd::ERROR,
+            cmd_instant,
+        );
+    }
+    let current_command = match cmd {
+        Command::Multi => {
+            if client.transaction.is_open() {
+                client.ops.generic_error("MULTI calls can not be nested")?;
+            } else {
+                client.transaction.begin();
+                client.ops.ok()?;
+            }
+            cmd::MULTI
+        }
+        Command::Exec => {
+            match client.transaction.exec(&mut server.versions) {
+                Exec::NotStarted => client.ops.generic_error("EXEC without MULTI")?,
+                Exec::Failed => client.ops.exec_abort()?,
+                Exec::Aborted => client.ops.write_null_array()?,
+                Exec::Run(queued) => {
+                    client.ops.write_array_header(queued.len())?;
+                    for queued_cmd in &queued {
+                        execute(server, client, queued_cmd.as_command())?;
+                        // a transaction can't wait, so a blocking pop times
+                        // out right away
+                        if server.blocking.remove(client.token) {
+                            client.ops.write_null_array()?;
                         }
                     }
+                }
+            }
+            cmd::EXEC
+        }
+        Command::Discard => {
+            if client.transaction.is_open() {
+                client.transaction.discard(&mut server.versions);
+                client.ops.ok()?;
+            } else {
+                client.ops.generic_error("DISCARD without MULTI")?;
+            }
+            cmd::DISCARD
+        }
+        Command::Watch(keys) => {
+            if client.transaction.is_open() {
+                client
+                    .ops
+                    .generic_error("WATCH inside MULTI is not allowed")?;
+            } else {
+                for key in keys {
+                    client
+                        .transaction
+                        .watch(&mut server.versions, client.db_index, key);
+                }
+                client.ops.ok()?;
+            }
+            cmd::WATCH
+        }
+        Command::Reset => {
+            client.transaction.discard(&mut server.versions);
+            server.pubsub.unsubscribe_all(client.token);
+            client.db_index = 0;
+            client.ops.protocol = Protocol::Resp2;
+            client.authenticated = !server.config.requires_pass();
+            client.no_evict = false;
+            client.no_touch = false;
+            client.ops.write_simple_string("RESET")?;
+            cmd::RESET
+        }
+        Command::Shutdown(save) => {
+            // no reply when it works, the connection just closes
+            match prepare_shutdown(server, save) {
+                Ok(()) => server.shutting_down = true,
+                Err(e) => {
+                    warn!("{e:#}, not shutting down");
+                    client
+                        .ops
+                        .generic_error("Errors trying to SHUTDOWN. Check logs.")?;
+                }
+            }
+            cmd::SHUTDOWN
+        }
+        cmd if client.transaction.is_open() => {
+            client.transaction.queue(cmd.to_owned());
+            client.ops.queued()?;
+            return Ok(());
+        }
+        cmd => return execute(server, client, cmd),
+    };
+    server.stats.command_processed(cmd_instant);
+    record_latency(
+        &mut server.latency_histograms,
+        client.token,
+        current_command,
+        cmd_instant,
+    )
+}
 
-                    if closed && let Some(client) = clients.remove(&token) {
-                        poll.registry()
-                            .deregister(&mut client.ops.unwrap_stream())?;
-                        trace!("[{token:?}] disconnected");
+/// Moves the link to the master along on `event`: sends `PSYNC` once
+/// connected, replaces the dataset with the snapshot the master answers with,
+/// then applies its write stream as a client whose replies go nowhere. An
+/// error means the link has to be dropped.
+fn follow_master(
+    server: &mut Server,
+    link: &mut MasterLink,
+    registry: &Registry,
+    event: &mio::event::Event,
+) -> anyhow::Result<()> {
+    if event.is_writable() {
+        link.handshake(registry, MASTER)
+            .context("can't send PSYNC")?;
+    }
+    if !event.is_readable() {
+        return Ok(());
+    }
+    let open = link.receive().context("can't read from the master")?;
+    if let Some(snapshot) = link.take_snapshot()? {
+        for index in 0..DATABASES {
+            server.dbs[index].clear();
+            server.ttl_maps[index].clear();
+            server.access_maps[index].clear();
+            server.versions[index].touch_all();
+        }
+        persistence::decode(&snapshot, &mut server.dbs, &mut server.ttl_maps)
+            .context("can't load the master's snapshot")?;
+        server.replication.link_up = true;
+        let keys: usize = server.dbs.iter().map(HashMap::len).sum();
+        info!("Synced with the master, {keys} keys loaded");
+    }
+    if link.is_streaming() {
+        let mut client = Client {
+            ops: ops::Ops::new(std::io::empty()),
+            read_buf: Vec::new(),
+            token: MASTER,
+            db_index: link.db_index,
+            transaction: Transaction::default(),
+            awaiting_writable: false,
+            authenticated: true,
+            name: Bytes::new(),
+            addr: None,
+            connected_at: Instant::now(),
+            lib_name: Bytes::new(),
+            lib_ver: Bytes::new(),
+            no_evict: false,
+            no_touch: false,
+        };
+        let read_buf = std::mem::take(&mut link.read_buf);
+        let mut consumed = 0;
+        while consumed < read_buf.len() {
+            match cmd::parser::parse(&read_buf[consumed..], usize::MAX) {
+                Err(RedisError::IncompleteInput) => break,
+                Err(e) => anyhow::bail!("malformed write stream: {e}"),
+                Ok((cmd, len)) => {
+                    consumed += len;
+                    execute(server, &mut client, cmd)?;
+                    client.ops.flush()?;
+                }
+            }
+        }
+        link.read_buf = read_buf;
+        link.read_buf.drain(..consumed);
+        link.db_index = client.db_index;
+    }
+    if !open {
+        anyhow::bail!("connection closed by the master");
+    }
+    Ok(())
+}
+
+/// Runs the commands logged in `log` as a client whose replies go nowhere,
+/// returning how many ran. Stops at the first entry that can't be read.
+fn replay_aof(server: &mut Server, log: &[u8]) -> anyhow::Result<usize> {
+    let mut client = Client {
+        ops: ops::Ops::new(std::io::empty()),
+        read_buf: Vec::new(),
+        token: SERVER,
+        db_index: 0,
+        transaction: Transaction::default(),
+        awaiting_writable: false,
+        authenticated: true,
+        name: Bytes::new(),
+        addr: None,
+        connected_at: Instant::now(),
+        lib_name: Bytes::new(),
+        lib_ver: Bytes::new(),
+        no_evict: false,
+        no_touch: false,
+    };
+    let mut replayed = 0;
+    let mut rest = log;
+    while !rest.is_empty() {
+        let (entry, tail) = aof::next_entry(rest)
+            .with_context(|| format!("can't read entry {replayed} of {}", aof::FILENAME))?;
+        let (cmd, _) = cmd::parser::parse(entry, usize::MAX)
+            .with_context(|| format!("can't parse entry {replayed} of {}", aof::FILENAME))?;
+        execute(server, &mut client, cmd)?;
+        client.ops.flush()?;
+        replayed += 1;
+        rest = tail;
+    }
+    // the replay isn't traffic to report on
+    server.latency_histograms.clear();
+    server.stats = Stats::default();
+    Ok(replayed)
+}
+
+fn execute<S: Read + Write>(
+    server: &mut Server,
+    client: &mut Client<S>,
+    cmd: Command,
+) -> anyhow::Result<()> {
+    let Server {
+        uptime_since,
+        dbs,
+        ttl_maps,
+        access_maps,
+        versions,
+        rng,
+        config,
+        max_memory,
+        latency_histograms,
+        connected_clients,
+        clients,
+        blocking,
+        pubsub,
+        aof,
+        replication,
+        stats,
+        active_expire,
+        shutting_down: _,
+    } = server;
+    let cmd_instant = Instant::now();
+    let current_command: CompactString;
+    let mut stats_reset = false;
+    // OBJECT inspects keys without counting as a use
+    let accessed = match &cmd {
+        Command::ObjectRefcount(_)
+        | Command::ObjectIdletime(_)
+        | Command::Debug(DebugSub::Object(_)) => vec![],
+        cmd => cmd.keys(),
+    };
+    let written = cmd.written_keys();
+    let touches = !client.no_touch || matches!(cmd, Command::Touch(_));
+    // as in Redis, only lookups that don't write count as hits or misses
+    if written.is_empty() {
+        for key in &accessed {
+            if dbs[client.db_index].contains_key(*key) {
+                stats.keyspace_hits += 1;
+            } else {
+                stats.keyspace_misses += 1;
+            }
+        }
+    }
+    // kept to be logged once it ran; SPOP replaces it with what it popped,
+    // and GETEX PERSIST with the value it read
+    let mut logged =
+        ((aof.is_enabled() || replication.has_replica()) && cmd.is_write()).then(|| cmd.to_owned());
+    // events fire only for keys that exist before or after the command, so
+    // deleting a missing key stays silent
+    let events: Vec<_> = notify::command_events(&cmd)
+        .into_iter()
+        .filter(|(class, ..)| pubsub.keyspace_events.is_enabled(*class))
+        .map(|(class, event, key)| (class, event, key, dbs[client.db_index].contains_key(key)))
+        .collect();
+    let hmap = &mut dbs[client.db_index];
+    let ttl_map = &mut ttl_maps[client.db_index];
+    match cmd {
+        Command::Get(key) => {
+            match hmap.get_live(key) {
+                None => client.ops.key_not_found()?,
+                Some(StoredValue::Plain(bytes)) | Some(StoredValue::TtlPlain(bytes, _)) => {
+                    client.ops.write_bulk_string(bytes)?;
+                }
+                Some(_) => client.ops.wrong_type("expected STRING")?,
+            };
+            current_command = cmd::GET;
+        }
+        Command::Strlen(key) => {
+            match hmap.get_live(key) {
+                None => client.ops.write_integer(0)?,
+                Some(StoredValue::Plain(bytes)) | Some(StoredValue::TtlPlain(bytes, _)) => {
+                    client.ops.write_integer(bytes.len())?;
+                }
+                Some(_) => client.ops.wrong_type("expected STRING")?,
+            };
+            current_command = cmd::STRLEN;
+        }
+        Command::GetRange(key, start, end) => {
+            match hmap.get_range(key, start, end) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(bytes) => client.ops.write_bulk_string(bytes)?,
+            };
+            current_command = cmd::GETRANGE;
+        }
+        Command::SetRange(key, offset, value) => {
+            match hmap.set_range(key, offset, value, config.proto_max_bulk_len()) {
+                Err(e) if e.is::<WrongType>() => client.ops.wrong_type(e.to_string())?,
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(len) => client.ops.write_integer(len)?,
+            };
+            current_command = cmd::SETRANGE;
+        }
+        Command::GetDel(key) => {
+            match hmap.get_del(key) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some(bytes)) => client.ops.write_bulk_string(bytes)?,
+            };
+            current_command = cmd::GETDEL;
+        }
+        Command::GetEx(key, expiry) => {
+            let value = match hmap.get_ex(key, expiry, ttl_map) {
+                Err(e) => {
+                    client.ops.wrong_type(e.to_string())?;
+                    None
+                }
+                Ok(None) => {
+                    client.ops.key_not_found()?;
+                    None
+                }
+                Ok(Some(bytes)) => {
+                    client.ops.write_bulk_string(&bytes)?;
+                    Some(bytes)
+                }
+            };
+            logged = match (logged, value) {
+                (Some(_), Some(value)) if expiry == ExpiryOption::Persist => {
+                    Some(OwnedCommand::Set(Bytes::copy_from_slice(key), value, None))
+                }
+                (logged, Some(_)) => logged,
+                _ => None,
+            };
+            current_command = cmd::GETEX;
+        }
+        Command::Set(key, value, maybe_ttl) => {
+            hmap.insert_alloc(
+                key,
+                value,
+                maybe_ttl.map(|dur| Instant::now() + dur),
+                ttl_map,
+            );
+            client.ops.ok()?;
+            current_command = cmd::SET;
+        }
+        Command::SetNx(key, value) => {
+            if hmap.set_if_not_exist(key, value) {
+                client.ops.ok()?;
+            } else {
+                client.ops.key_not_found()?;
+            }
+            current_command = cmd::SET;
+        }
+        Command::Setnx(key, value) => {
+            let set = hmap.set_if_not_exist(key, value);
+            client.ops.write_integer(set as u8)?;
+            current_command = cmd::SETNX;
+        }
+        Command::SetXx(key, value) => {
+            if hmap.update_if_exist(key, value) {
+                client.ops.ok()?;
+            } else {
+                client.ops.key_not_found()?;
+            }
+            current_command = cmd::SET;
+        }
+        Command::SetAndGet(key, value) => {
+            // the old value is checked before it's replaced, so a key of
+            // another type is left alone
+            let replaced = match hmap.get_live(key) {
+                None => client.ops.key_not_found().map(|()| true)?,
+                Some(StoredValue::Plain(bytes)) | Some(StoredValue::TtlPlain(bytes, _)) => {
+                    client.ops.write_bulk_string(bytes).map(|()| true)?
+                }
+                Some(_) => client.ops.wrong_type("expected STRING").map(|()| false)?,
+            };
+            if replaced {
+                hmap.insert_alloc(key, value, None, ttl_map);
+            }
+            current_command = cmd::SET;
+        }
+        Command::SetKeepTtl(key, value) => {
+            match hmap.get_mut(key) {
+                None => {
+                    hmap.insert_alloc(key, value, None, ttl_map);
+                    client.ops.ok()?;
+                }
+                Some(StoredValue::Plain(bytes)) => {
+                    client.ops.write_bulk_string(&bytes)?;
+                    *bytes = Bytes::copy_from_slice(bytes);
+                }
+                Some(StoredValue::TtlPlain(bytes, _)) => {
+                    client.ops.write_bulk_string(&bytes)?;
+                    *bytes = Bytes::copy_from_slice(bytes);
+                }
+                _ => client.ops.wrong_type("expected STRING")?,
+            };
+            current_command = cmd::SET;
+        }
+        Command::Ping => {
+            if client.ops.protocol == Protocol::Resp2 && pubsub.subscription_count(client.token) > 0
+            {
+                client.ops.write_array(["pong", ""].into_iter(), 2)?;
+            } else {
+                client.ops.pong()?;
+            }
+            current_command = cmd::PING;
+        }
+        Command::FlushDb => {
+            hmap.clear();
+            ttl_map.clear();
+            access_maps[client.db_index].clear();
+            versions[client.db_index].touch_all();
+            client.ops.ok()?;
+            current_command = cmd::FLUSHDB;
+        }
+        Command::Select(index) => {
+            if index < DATABASES {
+                client.db_index = index;
+                client.ops.ok()?;
+            } else {
+                client.ops.generic_error("DB index is out of range")?;
+            }
+            current_command = cmd::SELECT;
+        }
+        Command::Wait(..) => {
+            client.ops.write_integer(0)?;
+            current_command = cmd::WAIT;
+        }
+        Command::ReplicaOf(master) => {
+            let master = master.map(|(host, port)| (String::from_utf8_lossy(host).into(), port));
+            let following = master.is_some();
+            if replication.follow(master) || !following {
+                client.ops.ok()?;
+            } else {
+                client
+                    .ops
+                    .write_simple_string("OK Already connected to specified master")?;
+            }
+            current_command = cmd::REPLICAOF;
+        }
+        Command::Psync => {
+            let snapshot = persistence::encode(dbs);
+            client
+                .ops
+                .write_full_resync(replication.replid(), replication.offset(), &snapshot)?;
+            if let Some(replaced) = replication.attach(client.token) {
+                warn!("[{replaced:?}] replaced as the replica");
+            }
+            info!("[{:?}] Replica attached", client.token);
+            current_command = cmd::PSYNC;
+        }
+        Command::Move(key, index) => {
+            if index >= DATABASES {
+                client.ops.generic_error("DB index is out of range")?;
+            } else if index == client.db_index {
+                client
+                    .ops
+                    .generic_error("source and destination objects are the same")?;
+            } else {
+                // both maps are borrowed at once, so `hmap` can't be
+                // used here
+                let [src, dst] = dbs
+                    .get_disjoint_mut([client.db_index, index])
+                    .context("database indices must differ")?;
+                let moved = src.move_to(key, dst, &mut ttl_maps[index]);
+                if moved {
+                    versions[index].touch(key);
+                    pubsub.notify(KeyspaceEvents::GENERIC, "move_to", index, key);
+                }
+                if moved && let Some(used_at) = access_maps[client.db_index].remove(key) {
+                    access_maps[index].insert(Bytes::copy_from_slice(key), used_at);
+                }
+                client.ops.write_integer(moved as usize)?;
+            }
+            current_command = cmd::MOVE;
+        }
+        Command::ObjectRefcount(key) => {
+            // values are never shared between keys
+            match hmap.get_live(key) {
+                None => client.ops.generic_error("no such key")?,
+                Some(_) => client.ops.write_integer(1)?,
+            }
+            current_command = cmd::OBJECT;
+        }
+        Command::ObjectIdletime(key) => {
+            if hmap.get_live(key).is_none() {
+                client.ops.generic_error("no such key")?;
+            } else {
+                let idle = access_maps[client.db_index]
+                    .get(key)
+                    .map_or(0, |used_at| used_at.elapsed().as_secs());
+                client.ops.write_integer(idle as usize)?;
+            }
+            current_command = cmd::OBJECT;
+        }
+        Command::FlushAll => {
+            dbs.iter_mut().for_each(HashMap::clear);
+            ttl_maps.iter_mut().for_each(TtlMap::clear);
+            access_maps.iter_mut().for_each(AccessMap::clear);
+            versions.iter_mut().for_each(Versions::touch_all);
+            client.ops.ok()?;
+            current_command = cmd::FLUSHALL;
+        }
+        Command::Docs => {
+            client.ops.write_array(std::iter::empty::<&[u8]>(), 0)?;
+            current_command = cmd::DOCS;
+        }
+        Command::Hello(protocol, auth, name) => {
+            let protocol = match protocol {
+                None => Some(client.ops.protocol),
+                Some(2) => Some(Protocol::Resp2),
+                Some(3) => Some(Protocol::Resp3),
+                Some(_) => None,
+            };
+            // an unsupported protocol version fails before the login
+            if protocol.is_some()
+                && let Some((user, password)) = auth
+                && config.check_user(user, password)
+            {
+                client.authenticated = true;
+            }
+            match protocol {
+                None => client.ops.no_proto()?,
+                Some(_) if auth.is_some() && !client.authenticated => client.ops.wrong_pass()?,
+                Some(_) if !client.authenticated => client.ops.hello_no_auth()?,
+                Some(_) if name.is_some_and(|name| !valid_client_name(name)) => {
+                    client.ops.generic_error(INVALID_CLIENT_NAME)?
+                }
+                Some(protocol) => {
+                    if let Some(name) = name {
+                        client.name = Bytes::copy_from_slice(name);
+                    }
+                    client.ops.protocol = protocol;
+                    client.ops.write_hello(client.token.0)?;
+                }
+            }
+            current_command = cmd::HELLO;
+        }
+        Command::Auth(password) => {
+            if !config.requires_pass() {
+                client.ops.generic_error(
+                    "AUTH <password> called without any password configured for the \
+                     default user. Are you sure your configuration is correct?",
+                )?;
+            } else if config.check_password(password) {
+                client.authenticated = true;
+                client.ops.ok()?;
+            } else {
+                client.ops.wrong_pass()?;
+            }
+            current_command = cmd::AUTH;
+        }
+        Command::AuthUser(user, password) => {
+            if config.check_user(user, password) {
+                client.authenticated = true;
+                client.ops.ok()?;
+            } else {
+                client.ops.wrong_pass()?;
+            }
+            current_command = cmd::AUTH;
+        }
+        Command::Acl(AclSub::WhoAmI) => {
+            client.ops.write_bulk_string("default")?;
+            current_command = cmd::ACL;
+        }
+        Command::Acl(AclSub::List) => {
+            client
+                .ops
+                .write_array([config.default_user_rules()].iter(), 1)?;
+            current_command = cmd::ACL;
+        }
+        Command::Debug(DebugSub::Sleep(duration)) => {
+            // blocking every client is the point
+            std::thread::sleep(duration);
+            client.ops.ok()?;
+            current_command = cmd::DEBUG;
+        }
+        Command::Debug(DebugSub::SetActiveExpire(on)) => {
+            *active_expire = on;
+            client.ops.ok()?;
+            current_command = cmd::DEBUG;
+        }
+        Command::Debug(DebugSub::Object(key)) => {
+            match hmap.get_live(key) {
+                None => client.ops.generic_error("no such key")?,
+                Some(value) => {
+                    let idle = access_maps[client.db_index]
+                        .get(key)
+                        .map_or(0, |used_at| used_at.elapsed().as_secs());
+                    let mut line = format!(
+                        "Value at:{value:p} refcount:1 encoding:{} serializedlength:{} \
+                         lru_seconds_idle:{idle}",
+                        value.encoding(),
+                        persistence::serialized_len(value)
+                    );
+                    // a list is a single buffer, so a quicklist of one node
+                    if let StoredValue::List(_) = value {
+                        line += " ql_nodes:1";
+                    }
+                    client.ops.write_simple_string(line)?;
+                }
+            }
+            current_command = cmd::DEBUG;
+        }
+        Command::CmdCount => {
+            client
+                .ops
+                .write_integer(cmd::parser::command_names().count())?;
+            current_command = cmd::COMMAND;
+        }
+        Command::CmdGetKeys(keys) => {
+            client.ops.write_array(keys.iter(), keys.len())?;
+            current_command = cmd::COMMAND;
+        }
+        Command::CmdDocs(names) => {
+            // no documentation to share, so each command maps to an
+            // empty doc map
+            let known: Vec<Vec<u8>> = cmd::parser::command_names()
+                .filter(|name| {
+                    names.is_empty() || names.iter().any(|n| n.eq_ignore_ascii_case(name))
+                })
+                .map(|name| name.to_ascii_lowercase())
+                .collect();
+            client.ops.write_array_header(known.len() * 2)?;
+            for name in known {
+                client.ops.write_bulk_string(&name)?;
+                client.ops.write_array_header(0)?;
+            }
+            current_command = cmd::DOCS;
+        }
+        Command::DbSize => {
+            // keys past their deadline may still wait for the sweeper
+            stats.expired_keys += expire_keys(
+                hmap,
+                ttl_map,
+                &mut access_maps[client.db_index],
+                &mut versions[client.db_index],
+                pubsub,
+                client.db_index,
+                Instant::now(),
+            );
+            client.ops.write_integer(hmap.len())?;
+            current_command = cmd::DBSIZE;
+        }
+        Command::Config => {
+            client.ops.write_array(std::iter::empty::<&[u8]>(), 0)?;
+            current_command = cmd::CONFIG;
+        }
+        Command::ConfigGet(pattern) => {
+            let params = config.get(pattern);
+            client.ops.write_map(
+                params.iter().flat_map(|(name, value)| [name, value]),
+                params.len(),
+            )?;
+            current_command = cmd::CONFIG;
+        }
+        Command::ConfigResetStat => {
+            latency_histograms.clear();
+            max_memory.evicted_keys = 0;
+            *stats = Stats::default();
+            stats_reset = true;
+            client.ops.ok()?;
+            current_command = cmd::CONFIG;
+        }
+        Command::ConfigSet(param, value) => {
+            match config.set(param, value) {
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(()) => {
+                    max_memory.limit = config.max_memory();
+                    max_memory.policy = config.max_memory_policy();
+                    pubsub.keyspace_events = config.keyspace_events();
+                    // the log starts as a rewrite, so it holds the whole
+                    // dataset from the beginning
+                    let synced = match (config.append_only(), aof.is_enabled()) {
+                        (true, false) => aof.rewrite(dbs),
+                        (false, true) => aof.disable().context("can't remove the AOF"),
+                        _ => Ok(()),
+                    };
+                    match synced {
+                        Err(e) => {
+                            config.set(
+                                b"appendonly",
+                                if aof.is_enabled() { b"yes" } else { b"no" },
+                            )?;
+                            client.ops.generic_error(format!("{e:#}"))?;
+                        }
+                        Ok(()) => client.ops.ok()?,
+                    }
+                }
+            }
+            current_command = cmd::CONFIG;
+        }
+        Command::Lpush(key, values) => {
+            match hmap.prepend(key, values) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(values_len) => client.ops.write_integer(values_len)?,
+            };
+            current_command = cmd::LPUSH;
+        }
+        Command::Rpush(key, values) => {
+            match hmap.append(key, values) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(values_len) => client.ops.write_integer(values_len)?,
+            };
+            current_command = cmd::RPUSH;
+        }
+        Command::LpushX(key, values) => {
+            match hmap.prepend_existing(key, values) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(len) => client.ops.write_integer(len)?,
+            };
+            current_command = cmd::LPUSHX;
+        }
+        Command::RpushX(key, values) => {
+            match hmap.append_existing(key, values) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(len) => client.ops.write_integer(len)?,
+            };
+            current_command = cmd::RPUSHX;
+        }
+        Command::Lpop(key, maybe_count) => {
+            match hmap.pop_front(key, maybe_count) {
+                Ok(Popped::None) => client.ops.key_not_found()?,
+                Ok(Popped::Single(bytes)) => client.ops.write_bulk_string(bytes)?,
+                Ok(Popped::Multiple(values)) => {
+                    client.ops.write_array(values.iter(), values.len())?
+                }
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+            }
+            current_command = cmd::LPOP;
+        }
+        Command::Rpop(key, maybe_count) => {
+            match hmap.pop_back(key, maybe_count) {
+                Ok(Popped::None) => client.ops.key_not_found()?,
+                Ok(Popped::Single(bytes)) => client.ops.write_bulk_string(bytes)?,
+                Ok(Popped::Multiple(values)) => {
+                    client.ops.write_array(values.iter(), values.len())?
+                }
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+            }
+            current_command = cmd::RPOP;
+        }
+        Command::Blpop(keys, timeout) => {
+            let popped = pop_or_block(client, hmap, blocking, pubsub, &keys, End::Front, timeout)?;
+            logged = logged.and(popped);
+            current_command = cmd::BLPOP;
+        }
+        Command::Brpop(keys, timeout) => {
+            let popped = pop_or_block(client, hmap, blocking, pubsub, &keys, End::Back, timeout)?;
+            logged = logged.and(popped);
+            current_command = cmd::BRPOP;
+        }
+        Command::Del(keys) => {
+            let count = hmap.delete_all(keys.into_iter());
+            client.ops.write_integer(count)?;
+            current_command = cmd::DEL;
+        }
+        Command::Unlink(keys) => {
+            // values are dropped inline, so this is just DEL
+            let count = hmap.delete_all(keys.into_iter());
+            client.ops.write_integer(count)?;
+            current_command = cmd::UNLINK;
+        }
+        Command::Touch(keys) => {
+            let count = hmap.touch(keys.into_iter());
+            client.ops.write_integer(count)?;
+            current_command = cmd::TOUCH;
+        }
+        Command::Copy(src, dst, None, replace) => {
+            match hmap.copy(src, dst, replace, ttl_map) {
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(copied) => client.ops.write_integer(copied as i64)?,
+            };
+            current_command = cmd::COPY;
+        }
+        Command::Copy(_, _, Some(index), _) if index >= DATABASES => {
+            client.ops.generic_error("DB index is out of range")?;
+            current_command = cmd::COPY;
+        }
+        Command::Copy(src, dst, Some(index), replace) => {
+            let copied = if index == client.db_index {
+                hmap.copy(src, dst, replace, ttl_map)
+            } else {
+                // both maps are borrowed at once, so `hmap` can't be used
+                // here
+                let [from, to] = dbs
+                    .get_disjoint_mut([client.db_index, index])
+                    .context("database indices must differ")?;
+                Ok(from.copy_to(src, to, dst, replace, &mut ttl_maps[index]))
+            };
+            match copied {
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(copied) => {
+                    // what `written` and the events do for a copy in place
+                    if copied {
+                        versions[index].touch(dst);
+                        blocking.key_written(index, dst);
+                        pubsub.notify(KeyspaceEvents::GENERIC, "copy_to", index, dst);
+                    }
+                    client.ops.write_integer(copied as i64)?;
+                }
+            };
+            current_command = cmd::COPY;
+        }
+        Command::Incr(key) => {
+            match hmap.incr_by(key, 1) {
+                Err(e) if e.is::<WrongType>() => client.ops.wrong_type(e.to_string())?,
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(value) => client.ops.write_integer(value)?,
+            };
+            current_command = cmd::INCR;
+        }
+        Command::IncrBy(key, incr_by) => {
+            match hmap.incr_by(key, incr_by) {
+                Err(e) if e.is::<WrongType>() => client.ops.wrong_type(e.to_string())?,
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(value) => client.ops.write_integer(value)?,
+            };
+            current_command = cmd::INCR;
+        }
+        Command::Decr(key) => {
+            match hmap.incr_by(key, -1) {
+                Err(e) if e.is::<WrongType>() => client.ops.wrong_type(e.to_string())?,
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(value) => client.ops.write_integer(value)?,
+            };
+            current_command = cmd::DECR;
+        }
+        Command::DecrBy(key, decr_by) => {
+            match decr_by
+                .checked_neg()
+                .map(|incr_by| hmap.incr_by(key, incr_by))
+            {
+                None => client
+                    .ops
+                    .generic_error("increment or decrement would overflow")?,
+                Some(Err(e)) if e.is::<WrongType>() => client.ops.wrong_type(e.to_string())?,
+                Some(Err(e)) => client.ops.generic_error(e.to_string())?,
+                Some(Ok(value)) => client.ops.write_integer(value)?,
+            };
+            current_command = cmd::DECRBY;
+        }
+        Command::Keys(pattern) => {
+            let keys = hmap.keys_matching(pattern);
+            client.ops.write_array(keys.iter(), keys.len())?;
+            current_command = cmd::KEYS;
+        }
+        Command::Hscan(key, cursor, pattern, count) => {
+            match hmap.dict_scan(key, cursor, pattern, count) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok((cursor, page)) => client.ops.write_scan(cursor, &page)?,
+            };
+            current_command = cmd::HSCAN;
+        }
+        Command::Sscan(key, cursor, pattern, count) => {
+            match hmap.set_scan(key, cursor, pattern, count) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok((cursor, page)) => client.ops.write_scan(cursor, &page)?,
+            };
+            current_command = cmd::SSCAN;
+        }
+        Command::Zscan(key, cursor, pattern, count) => {
+            match hmap.zset_scan(key, cursor, pattern, count) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok((cursor, page)) => client.ops.write_scan(cursor, &page)?,
+            };
+            current_command = cmd::ZSCAN;
+        }
+        Command::IncrByFloat(key, incr_by) => {
+            match hmap.incr_by_float(key, incr_by) {
+                Err(e) if e.is::<WrongType>() => client.ops.wrong_type(e.to_string())?,
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(value) => client.ops.write_bulk_string(value)?,
+            };
+            current_command = cmd::INCRBYFLOAT;
+        }
+        Command::Client(ClientSub::SetName(name)) => {
+            if !valid_client_name(name) {
+                client.ops.generic_error(INVALID_CLIENT_NAME)?;
+            } else {
+                client.name = Bytes::copy_from_slice(name);
+                client.ops.ok()?;
+            }
+            current_command = cmd::CLIENT;
+        }
+        Command::Client(ClientSub::GetName) => {
+            if client.name.is_empty() {
+                client.ops.write_null()?;
+            } else {
+                client.ops.write_bulk_string(&client.name)?;
+            }
+            current_command = cmd::CLIENT;
+        }
+        Command::Client(ClientSub::Id) => {
+            client.ops.write_integer(client.token.0)?;
+            current_command = cmd::CLIENT;
+        }
+        Command::Client(ClientSub::List) => {
+            let now = Instant::now();
+            let mut lines: Vec<(usize, String)> = clients
+                .values()
+                .map(|other| (other.token.0, other.describe(now)))
+                .chain(std::iter::once((client.token.0, client.describe(now))))
+                .collect();
+            lines.sort_unstable();
+            let list: String = lines.into_iter().map(|(_, line)| line + "\n").collect();
+            client.ops.write_bulk_string(list)?;
+            current_command = cmd::CLIENT;
+        }
+        Command::Client(ClientSub::Info) => {
+            let line = client.describe(Instant::now()) + "\n";
+            client.ops.write_bulk_string(line)?;
+            current_command = cmd::CLIENT;
+        }
+        Command::Client(ClientSub::SetInfo(info)) => {
+            match info {
+                Info::LibName(name) => client.lib_name = Bytes::copy_from_slice(name),
+                Info::LibVersion(version) => client.lib_ver = Bytes::copy_from_slice(version),
+            }
+            client.ops.ok()?;
+            current_command = cmd::CLIENT;
+        }
+        Command::Client(ClientSub::NoEvict(on)) => {
+            client.no_evict = on;
+            client.ops.ok()?;
+            current_command = cmd::CLIENT;
+        }
+        Command::Client(ClientSub::NoTouch(on)) => {
+            client.no_touch = on;
+            client.ops.ok()?;
+            current_command = cmd::CLIENT;
+        }
+        Command::Ttl(key) => {
+            hmap.get_live(key);
+            match hmap.get_ttl(key) {
+                Ttl::Missing => client.ops.write_integer(-2)?,
+                Ttl::Persistent => client.ops.write_integer(-1)?,
+                Ttl::Expires(value) => {
+                    client.ops.write_integer((value.as_millis() + 500) / 1000)?
+                }
+            };
+            current_command = cmd::TTL;
+        }
+        Command::Pttl(key) => {
+            hmap.get_live(key);
+            match hmap.get_ttl(key) {
+                Ttl::Missing => client.ops.write_integer(-2)?,
+                Ttl::Persistent => client.ops.write_integer(-1)?,
+                Ttl::Expires(value) => client.ops.write_integer(value.as_millis())?,
+            };
+            current_command = cmd::PTTL;
+        }
+        Command::Expire(key, seconds) => {
+            // a non-positive timeout deletes the key right away
+            let updated = if seconds > 0 {
+                expiry_deadline(Duration::from_secs(seconds as u64), "expire")
+                    .and_then(|deadline| hmap.set_expiry(key, deadline, ttl_map))
+            } else {
+                Ok(hmap.delete_all(std::iter::once(key)) > 0)
+            };
+            match updated {
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(updated) => client.ops.write_integer(updated as u8)?,
+            };
+            current_command = cmd::EXPIRE;
+        }
+        Command::Pexpire(key, millis) => {
+            let updated = if millis > 0 {
+                expiry_deadline(Duration::from_millis(millis as u64), "pexpire")
+                    .and_then(|deadline| hmap.set_expiry(key, deadline, ttl_map))
+            } else {
+                Ok(hmap.delete_all(std::iter::once(key)) > 0)
+            };
+            match updated {
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(updated) => client.ops.write_integer(updated as u8)?,
+            };
+            current_command = cmd::PEXPIRE;
+        }
+        Command::PexpireAt(key, unix_millis) => {
+            // a deadline already passed deletes the key right away
+            let millis = unix_millis.checked_sub(persistence::unix_now().as_millis() as i64);
+            let updated = match millis {
+                Some(millis) if millis > 0 => {
+                    expiry_deadline(Duration::from_millis(millis as u64), "pexpireat")
+                        .and_then(|deadline| hmap.set_expiry(key, deadline, ttl_map))
+                }
+                _ => Ok(hmap.delete_all(std::iter::once(key)) > 0),
+            };
+            match updated {
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(updated) => client.ops.write_integer(updated as u8)?,
+            };
+            current_command = cmd::PEXPIREAT;
+        }
+        Command::Lrange(key, start, end) => {
+            match hmap.range(key, start, end) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                // a missing key reads as an empty list
+                Ok(None) => client.ops.write_array_header(0)?,
+                Ok(Some((values, len))) => client.ops.write_array(values.into_iter(), len)?,
+            };
+            current_command = cmd::LRANGE;
+        }
+        Command::LLen(key) => {
+            match hmap.get(key) {
+                None => client.ops.key_not_found()?,
+                Some(StoredValue::List(ll)) => {
+                    client.ops.write_integer(ll.len())?;
+                }
+                _ => client.ops.wrong_type("stored value isn't a list")?,
+            };
+            current_command = cmd::LLEN;
+        }
+        Command::Lindex(key, index) => {
+            match hmap.index(key, index) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some(value)) => client.ops.write_bulk_string(value)?,
+            };
+            current_command = cmd::LINDEX;
+        }
+        Command::Lset(key, index, value) => {
+            match hmap.set(key, index, value) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(ListSet::Done) => client.ops.ok()?,
+                Ok(ListSet::NoSuchKey) => client.ops.generic_error("no such key")?,
+                Ok(ListSet::OutOfRange) => client.ops.generic_error("index out of range")?,
+            };
+            current_command = cmd::LSET;
+        }
+        Command::Lrem(key, count, value) => {
+            match hmap.rem(key, count, value) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(removed) => client.ops.write_integer(removed)?,
+            };
+            current_command = cmd::LREM;
+        }
+        Command::Linsert(key, before, pivot, value) => {
+            match hmap.list_insert(key, before, pivot, value) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(len) => client.ops.write_integer(len)?,
+            };
+            current_command = cmd::LINSERT;
+        }
+        Command::Lpos(key, element, rank, count) => {
+            match hmap.pos(key, element, rank.unwrap_or(1), count.unwrap_or(1)) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(positions) if count.is_some() => {
+                    client.ops.write_array_header(positions.len())?;
+                    for position in positions {
+                        client.ops.write_integer(position)?;
+                    }
+                }
+                Ok(positions) => match positions.first() {
+                    None => client.ops.key_not_found()?,
+                    Some(position) => client.ops.write_integer(position)?,
+                },
+            };
+            current_command = cmd::LPOS;
+        }
+        Command::Hget(key, field) => {
+            match hmap.dict_get(key, field) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some(value)) => client.ops.write_bulk_string(value)?,
+            };
+            current_command = cmd::HGET;
+        }
+        Command::HMget(key, fields) => {
+            match hmap.dict_mget(key, &fields) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok((values, len)) => client.ops.write_array_opt(values.into_iter(), len)?,
+            };
+            current_command = cmd::HMGET;
+        }
+        Command::Hset(key, fields_and_values) => {
+            match hmap.dict_mset(key, &fields_and_values) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(added) => client.ops.write_integer(added)?,
+            }
+            current_command = cmd::HSET;
+        }
+        Command::HMset(key, fields_and_values) => {
+            match hmap.dict_mset(key, &fields_and_values) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(_) => client.ops.ok()?,
+            }
+            current_command = cmd::HMSET;
+        }
+        Command::HgetAll(key) => {
+            match hmap.dict_get_all(key) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some((values, len))) => client.ops.write_map(values.into_iter(), len / 2)?,
+            };
+            current_command = cmd::HMSET;
+        }
+        Command::HincrBy(key, field, incr_by) => {
+            match hmap.dict_incr_by(key, field, incr_by) {
+                Err(e) if e.is::<WrongType>() => client.ops.wrong_type(e.to_string())?,
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(value) => client.ops.write_integer(String::from_utf8_lossy(&value))?,
+            }
+            current_command = cmd::HINCRBY;
+        }
+        Command::HincrByFloat(key, field, incr_by) => {
+            match hmap.dict_incr_by_float(key, field, incr_by) {
+                Err(e) if e.is::<WrongType>() => client.ops.wrong_type(e.to_string())?,
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(value) => client.ops.write_bulk_string(value)?,
+            }
+            current_command = cmd::HINCRBYFLOAT;
+        }
+        Command::Exists(keys) => {
+            // repeated keys count once per mention
+            let exists = keys
+                .into_iter()
+                .filter(|key| hmap.get_live(key).is_some())
+                .count();
+            client.ops.write_integer(exists)?;
+            current_command = cmd::EXISTS;
+        }
+        Command::Hexists(key, field) => {
+            match hmap.dict_exists(key, field) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(exists) => {
+                    let v = if exists { 1 } else { 0 };
+                    client.ops.write_integer(v)?;
+                }
+            }
+            current_command = cmd::HEXISTS;
+        }
+        Command::Hkeys(key) => {
+            match hmap.dict_keys(key) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some((keys, len))) => client.ops.write_array(keys.into_iter(), len)?,
+            };
+            current_command = cmd::HKEYS;
+        }
+        Command::Hrandfield(key, maybe_count, withvalues) => {
+            match hmap.dict_rand(key, maybe_count.unwrap_or(1), rng) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(fields) if withvalues => client
+                    .ops
+                    .write_array(fields.iter().flat_map(|(f, v)| [f, v]), fields.len() * 2)?,
+                Ok(fields) if maybe_count.is_some() => client
+                    .ops
+                    .write_array(fields.iter().map(|(f, _)| f), fields.len())?,
+                Ok(fields) => match fields.first() {
+                    None => client.ops.key_not_found()?,
+                    Some((field, _)) => client.ops.write_bulk_string(field)?,
+                },
+            };
+            current_command = cmd::HRANDFIELD;
+        }
+        Command::Sadd(key, members) => {
+            match hmap.set_add(key, members) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(added) => client.ops.write_integer(added)?,
+            };
+            current_command = cmd::SADD;
+        }
+        Command::Srem(key, members) => {
+            match hmap.set_rem(key, members) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(removed) => client.ops.write_integer(removed)?,
+            };
+            current_command = cmd::SREM;
+        }
+        Command::Smove(src, dst, member) => {
+            match hmap.set_move(src, dst, member) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(moved) => client.ops.write_integer(moved as i64)?,
+            };
+            current_command = cmd::SMOVE;
+        }
+        Command::Spop(key, maybe_count) => {
+            let popped = match hmap.set_pop(key, maybe_count, rng) {
+                Ok(Popped::None) => {
+                    client.ops.key_not_found()?;
+                    vec![]
+                }
+                Ok(Popped::Single(bytes)) => {
+                    client.ops.write_bulk_string(&bytes)?;
+                    vec![bytes]
+                }
+                Ok(Popped::Multiple(values)) => {
+                    client.ops.write_array(values.iter(), values.len())?;
+                    values
+                }
+                Err(e) => {
+                    client.ops.wrong_type(e.to_string())?;
+                    vec![]
+                }
+            };
+            logged = match logged {
+                Some(_) if !popped.is_empty() => {
+                    Some(OwnedCommand::Srem(Bytes::copy_from_slice(key), popped))
+                }
+                _ => None,
+            };
+            current_command = cmd::SPOP;
+        }
+        Command::Srandmember(key, maybe_count) => {
+            match hmap.set_rand(key, maybe_count.unwrap_or(1), rng) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(members) if maybe_count.is_some() => {
+                    client.ops.write_array(members.iter(), members.len())?
+                }
+                Ok(members) => match members.first() {
+                    None => client.ops.key_not_found()?,
+                    Some(member) => client.ops.write_bulk_string(member)?,
+                },
+            };
+            current_command = cmd::SRANDMEMBER;
+        }
+        Command::Sismember(key, member) => {
+            match hmap.set_is_member(key, member) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(exists) => client.ops.write_integer(exists as u8)?,
+            }
+            current_command = cmd::SISMEMBER;
+        }
+        Command::Smismember(key, members) => {
+            match hmap.set_mis_member(key, members) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(found) => client
+                    .ops
+                    .write_integer_array(found.iter().map(|&exists| exists as i64), found.len())?,
+            }
+            current_command = cmd::SMISMEMBER;
+        }
+        Command::Sinter(keys) => {
+            match hmap.set_inter(&keys) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok((values, len)) => client.ops.write_array(values.into_iter(), len)?,
+            };
+            current_command = cmd::SINTER
+        }
+        Command::Sunion(keys) => {
+            match hmap.set_union(&keys) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok((values, len)) => client.ops.write_array(values.into_iter(), len)?,
+            };
+            current_command = cmd::SUNION;
+        }
+        Command::Sdiff(keys) => {
+            match hmap.set_diff(&keys) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok((values, len)) => client.ops.write_array(values.into_iter(), len)?,
+            };
+            current_command = cmd::SDIFF;
+        }
+        Command::Sinterstore(dst, keys) => {
+            match hmap.set_inter_store(dst, &keys) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(len) => client.ops.write_integer(len)?,
+            };
+            current_command = cmd::SINTERSTORE;
+        }
+        Command::Sunionstore(dst, keys) => {
+            match hmap.set_union_store(dst, &keys) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(len) => client.ops.write_integer(len)?,
+            };
+            current_command = cmd::SUNIONSTORE;
+        }
+        Command::Sdiffstore(dst, keys) => {
+            match hmap.set_diff_store(dst, &keys) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(len) => client.ops.write_integer(len)?,
+            };
+            current_command = cmd::SDIFFSTORE;
+        }
+        Command::Scard(key) => {
+            match hmap.set_card(key) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some(len)) => client.ops.write_integer(len)?,
+            };
+            current_command = cmd::SCARD;
+        }
+        Command::Smembers(key) => {
+            match hmap.set_members(key) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some((members, len))) => client.ops.write_array(members.into_iter(), len)?,
+            };
+            current_command = cmd::SMEMBERS;
+        }
+        Command::Zadd(key, members) => {
+            match hmap.zset_add(key, &members) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(added) => client.ops.write_integer(added)?,
+            };
+            current_command = cmd::ZADD;
+        }
+        Command::Zrem(key, members) => {
+            match hmap.zset_rem(key, members) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(removed) => client.ops.write_integer(removed)?,
+            };
+            current_command = cmd::ZREM;
+        }
+        Command::Zrange(key, start, stop, withscores) => {
+            match hmap.zset_range(key, start, stop, withscores) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some((values, len))) => client.ops.write_array(values.iter(), len)?,
+            };
+            current_command = cmd::ZRANGE;
+        }
+        Command::Zrevrange(key, start, stop, withscores) => {
+            match hmap.zset_revrange(key, start, stop, withscores) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some((values, len))) => client.ops.write_array(values.iter(), len)?,
+            };
+            current_command = cmd::ZREVRANGE;
+        }
+        Command::Zrank(key, member) => {
+            match hmap.zset_rank(key, member) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some(rank)) => client.ops.write_integer(rank)?,
+            };
+            current_command = cmd::ZRANK;
+        }
+        Command::Zrevrank(key, member) => {
+            match hmap.zset_revrank(key, member) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some(rank)) => client.ops.write_integer(rank)?,
+            };
+            current_command = cmd::ZREVRANK;
+        }
+        Command::Zscore(key, member) => {
+            match hmap.zset_score(key, member) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some(score)) => client.ops.write_double(score)?,
+            };
+            current_command = cmd::ZSCORE;
+        }
+        Command::Zmscore(key, members) => {
+            match hmap.zset_mscore(key, members) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(scores) => {
+                    client.ops.write_array_header(scores.len())?;
+                    for score in scores {
+                        match score {
+                            None => client.ops.write_null()?,
+                            Some(score) => client.ops.write_double(score)?,
+                        }
                     }
                 }
+            };
+            current_command = cmd::ZMSCORE;
+        }
+        Command::Zrangebyscore(key, min, max, withscores) => {
+            match hmap.zset_range_by_score(key, min, max, withscores) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some((values, len))) => client.ops.write_array(values.iter(), len)?,
+            };
+            current_command = cmd::ZRANGEBYSCORE;
+        }
+        Command::Zrangebylex(key, min, max) => {
+            match hmap.zset_range_by_lex(key, min, max) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.write_array_header(0)?,
+                Ok(Some((values, len))) => client.ops.write_array(values.iter(), len)?,
+            };
+            current_command = cmd::ZRANGEBYLEX;
+        }
+        Command::Zremrangebyrank(key, start, stop) => {
+            match hmap.zset_rem_range_by_rank(key, start, stop) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(removed) => client.ops.write_integer(removed)?,
+            };
+            current_command = cmd::ZREMRANGEBYRANK;
+        }
+        Command::Zremrangebyscore(key, min, max) => {
+            match hmap.zset_rem_range_by_score(key, min, max) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(removed) => client.ops.write_integer(removed)?,
+            };
+            current_command = cmd::ZREMRANGEBYSCORE;
+        }
+        Command::Zunionstore(dst, keys, weights, aggregate) => {
+            match hmap.zset_union_store(dst, &keys, weights.as_deref(), aggregate) {
+                Err(e) if e.is::<WrongType>() => client.ops.wrong_type(e.to_string())?,
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(len) => client.ops.write_integer(len)?,
+            };
+            current_command = cmd::ZUNIONSTORE;
+        }
+        Command::Zinterstore(dst, keys, weights, aggregate) => {
+            match hmap.zset_inter_store(dst, &keys, weights.as_deref(), aggregate) {
+                Err(e) if e.is::<WrongType>() => client.ops.wrong_type(e.to_string())?,
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(len) => client.ops.write_integer(len)?,
+            };
+            current_command = cmd::ZINTERSTORE;
+        }
+        Command::Zincrby(key, incr, member) => {
+            match hmap.zset_incr_by(key, incr, member) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(score) => client.ops.write_double(score)?,
+            };
+            current_command = cmd::ZINCRBY;
+        }
+        Command::Zcard(key) => {
+            match hmap.zcard(key) {
+                Err(e) => client.ops.wrong_type(e.to_string())?,
+                Ok(None) => client.ops.key_not_found()?,
+                Ok(Some(value)) => client.ops.write_integer(value)?,
+            };
+            current_command = cmd::ZCARD;
+        }
+        Command::InfoCmd(section) => {
+            let (used_memory, _) = memory_usage()?;
+            let report = InfoReport {
+                uptime_since: *uptime_since,
+                connected_clients: *connected_clients,
+                blocked_clients: blocking.count(),
+                used_memory,
+                max_memory,
+                stats,
+                replication,
+                latency_histograms,
+                dbs,
+            };
+            client
+                .ops
+                .write_bulk_string(report.render(section.as_deref()))?;
+            current_command = cmd::INFO;
+        }
+        Command::LatencyHistogram(commands) => {
+            client
+                .ops
+                .write_latency_histogram(latency_histograms, &commands)?;
+            current_command = cmd::LATENCY;
+        }
+        Command::Unwatch => {
+            client.transaction.unwatch(versions);
+            client.ops.ok()?;
+            current_command = cmd::UNWATCH;
+        }
+        Command::Subscribe(channels) => {
+            for channel in channels {
+                let count = pubsub.subscribe(client.token, channel);
+                client
+                    .ops
+                    .write_subscription("subscribe", Some(channel), count)?;
+            }
+            current_command = cmd::SUBSCRIBE;
+        }
+        Command::Unsubscribe(channels) => {
+            // without arguments, from every channel
+            let channels = if channels.is_empty() {
+                pubsub.channels_of(client.token)
+            } else {
+                channels.into_iter().map(Bytes::copy_from_slice).collect()
+            };
+            if channels.is_empty() {
+                let count = pubsub.subscription_count(client.token);
+                client.ops.write_subscription("unsubscribe", None, count)?;
+            }
+            for channel in channels {
+                let count = pubsub.unsubscribe(client.token, &channel);
+                client
+                    .ops
+                    .write_subscription("unsubscribe", Some(&channel), count)?;
+            }
+            current_command = cmd::UNSUBSCRIBE;
+        }
+        Command::PSubscribe(patterns) => {
+            for pattern in patterns {
+                let count = pubsub.psubscribe(client.token, pattern);
+                client
+                    .ops
+                    .write_subscription("psubscribe", Some(pattern), count)?;
+            }
+            current_command = cmd::PSUBSCRIBE;
+        }
+        Command::PUnsubscribe(patterns) => {
+            // without arguments, from every pattern
+            let patterns = if patterns.is_empty() {
+                pubsub.patterns_of(client.token)
+            } else {
+                patterns.into_iter().map(Bytes::copy_from_slice).collect()
+            };
+            if patterns.is_empty() {
+                let count = pubsub.subscription_count(client.token);
+                client.ops.write_subscription("punsubscribe", None, count)?;
+            }
+            for pattern in patterns {
+                let count = pubsub.punsubscribe(client.token, &pattern);
+                client
+                    .ops
+                    .write_subscription("punsubscribe", Some(&pattern), count)?;
+            }
+            current_command = cmd::PUNSUBSCRIBE;
+        }
+        Command::Publish(channel, message) => {
+            let receivers = pubsub.publish(channel, message);
+            client.ops.write_integer(receivers)?;
+            current_command = cmd::PUBLISH;
+        }
+        Command::Save => {
+            match persistence::save(config.db_filename(), dbs) {
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(()) => client.ops.ok()?,
+            }
+            current_command = cmd::SAVE;
+        }
+        Command::Bgsave => {
+            // written right away for now, there's no forking
+            match persistence::save(config.db_filename(), dbs) {
+                Err(e) => client.ops.generic_error(e.to_string())?,
+                Ok(()) => client
+                    .ops
+                    .write_simple_string("Background saving started")?,
+            }
+            current_command = cmd::BGSAVE;
+        }
+        Command::Bgrewriteaof => {
+            // a log written while appendonly is off would go stale, and
+            // still be replayed on the next start
+            if !aof.is_enabled() {
+                client
+                    .ops
+                    .generic_error("BGREWRITEAOF requires appendonly yes")?;
+            } else {
+                // rewritten right away too, like BGSAVE
+                match aof.rewrite(dbs) {
+                    Err(e) => client.ops.generic_error(format!("{e:#}"))?,
+                    Ok(()) => client
+                        .ops
+                        .write_simple_string("Background append only file rewriting started")?,
+                }
             }
+            current_command = cmd::BGREWRITEAOF;
         }
+        Command::Multi
+        | Command::Exec
+        | Command::Discard
+        | Command::Watch(_)
+        | Command::Reset
+        | Command::Shutdown(_) => unreachable!("run by dispatch"),
+    }
+    for key in written {
+        versions[client.db_index].touch(key);
+        blocking.key_written(client.db_index, key);
     }
+    if let Some(logged) = logged {
+        if let Err(e) = aof.append(client.db_index, &logged) {
+            warn!("[{:?}] can't append to the AOF: {e}", client.token);
+        }
+        replication.feed(client.db_index, &logged);
+    }
+    for (class, event, key, existed) in events {
+        if existed || dbs[client.db_index].contains_key(key) {
+            pubsub.notify(class, event, client.db_index, key);
+        }
+    }
+    if touches {
+        dbs[client.db_index].record_access(
+            &mut access_maps[client.db_index],
+            &accessed,
+            cmd_instant,
+        );
+    }
+    if max_memory.is_enforced() {
+        let evicted = max_memory.evict(dbs, access_maps, rng, |_| Ok(memory_usage()?.0))?;
+        if !evicted.is_empty() {
+            trace!("[{:?}] {} keys evicted", client.token, evicted.len());
+        }
+        for (index, key) in evicted {
+            let del = OwnedCommand::Del(vec![key.clone()]);
+            if let Err(e) = aof.append(index, &del) {
+                warn!("[{:?}] can't append to the AOF: {e}", client.token);
+            }
+            replication.feed(index, &del);
+            versions[index].touch(&key);
+            pubsub.notify(KeyspaceEvents::EVICTED, "evicted", index, &key);
+        }
+    }
+
+    // RESETSTAT leaves the stats empty, not even counting itself
+    if stats_reset {
+        return Ok(());
+    }
+    stats.command_processed(cmd_instant);
+    record_latency(
+        latency_histograms,
+        client.token,
+        current_command,
+        cmd_instant,
+    )
+}
+
+const INVALID_CLIENT_NAME: &str =
+    "Client names cannot contain spaces, newlines or special characters.";
+
+/// Whether `CLIENT SETNAME` and `HELLO SETNAME` take `name`, which can't have
+/// the spaces `CLIENT LIST` separates its fields with.
+fn valid_client_name(name: &[u8]) -> bool {
+    name.iter().all(u8::is_ascii_graphic)
+}
+
+/// Removes the keys of database `index` whose deadline is at or before
+/// `now`, dropping their access stamps, invalidating their watches and
+/// raising `expired` events. Returns how many keys expired.
+fn expire_keys(
+    hmap: &mut HashMap<Bytes, StoredValue>,
+    ttl_map: &mut TtlMap,
+    access_map: &mut AccessMap,
+    versions: &mut Versions,
+    pubsub: &mut PubSub,
+    index: usize,
+    now: Instant,
+) -> u64 {
+    let mut expired = 0;
+    for key in hmap.remove_expired(ttl_map, now) {
+        access_map.remove(&key);
+        versions.touch(&key);
+        pubsub.notify(KeyspaceEvents::EXPIRED, "expired", index, &key);
+        expired += 1;
+    }
+    expired
+}
+
+/// The deadline `ttl` from now for an `EXPIRE`-like `command`, which fails
+/// for one too far out to represent.
+fn expiry_deadline(ttl: Duration, command: &str) -> anyhow::Result<Instant> {
+    Instant::now()
+        .checked_add(ttl)
+        .with_context(|| format!("invalid expire time in '{command}' command"))
+}
+
+/// Runs `BLPOP`/`BRPOP`: pops from the first of `keys` holding an element,
+/// or blocks the client on all of them for `timeout`, with no reply until
+/// then. Returns the pop to log, if any.
+fn pop_or_block<S: Read + Write>(
+    client: &mut Client<S>,
+    hmap: &mut HashMap<Bytes, StoredValue>,
+    blocking: &mut Blocking,
+    pubsub: &mut PubSub,
+    keys: &[&[u8]],
+    end: End,
+    timeout: Duration,
+) -> anyhow::Result<Option<OwnedCommand>> {
+    match blocking::pop_first(hmap, keys, end) {
+        Err(e) => client.ops.wrong_type(e.to_string())?,
+        Ok(Some((key, value))) => {
+            client.ops.write_array([key, value.as_ref()].iter(), 2)?;
+            pubsub.notify(KeyspaceEvents::LIST, end.event(), client.db_index, key);
+            let key = Bytes::copy_from_slice(key);
+            return Ok(Some(match end {
+                End::Front => OwnedCommand::Lpop(key, None),
+                End::Back => OwnedCommand::Rpop(key, None),
+            }));
+        }
+        Ok(None) => {
+            let deadline = (!timeout.is_zero()).then(|| Instant::now() + timeout);
+            blocking.block(client.token, client.db_index, keys, end, deadline);
+        }
+    }
+    Ok(None)
+}
+
+fn record_latency(
+    latency_histograms: &mut HashMap<CompactString, Histogram>,
+    token: Token,
+    current_command: CompactString,
+    cmd_instant: Instant,
+) -> anyhow::Result<()> {
+    let latency = cmd_instant.elapsed().as_micros() as u64;
+    latency_histograms
+        .entry(current_command.clone())
+        .or_insert(Histogram::new(2, 30)?)
+        .increment(latency)
+        .with_context(|| format!("can't store latency {latency} for {current_command}"))?;
+    trace!("[{token:?}] command is executed, latency: {latency}usecs");
+    Ok(())
 }
@@ -0,0 +1,200 @@
+use crate::cmd::{Command, ExpiryOption};
+use anyhow::bail;
+use std::fmt;
+use std::str::FromStr;
+
+/// Event classes enabled by `notify-keyspace-events`, one flag character
+/// each.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct KeyspaceEvents(u16);
+
+impl KeyspaceEvents {
+    /// Publish on `__keyspace@<db>__:<key>`.
+    pub const KEYSPACE: Self = Self(1);
+    /// Publish on `__keyevent@<db>__:<event>`.
+    pub const KEYEVENT: Self = Self(1 << 1);
+    pub const GENERIC: Self = Self(1 << 2);
+    pub const STRING: Self = Self(1 << 3);
+    pub const LIST: Self = Self(1 << 4);
+    pub const SET: Self = Self(1 << 5);
+    pub const HASH: Self = Self(1 << 6);
+    pub const ZSET: Self = Self(1 << 7);
+    pub const EXPIRED: Self = Self(1 << 8);
+    pub const EVICTED: Self = Self(1 << 9);
+    /// Every class, `A` in the config string.
+    const ALL: Self = Self(0b11_1111_1100);
+
+    const CLASSES: [(char, Self); 8] = [
+        ('g', Self::GENERIC),
+        ('$', Self::STRING),
+        ('l', Self::LIST),
+        ('s', Self::SET),
+        ('h', Self::HASH),
+        ('z', Self::ZSET),
+        ('x', Self::EXPIRED),
+        ('e', Self::EVICTED),
+    ];
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether events of `class` are published on any channel.
+    pub fn is_enabled(self, class: Self) -> bool {
+        self.contains(class) && (self.contains(Self::KEYSPACE) || self.contains(Self::KEYEVENT))
+    }
+}
+
+impl FromStr for KeyspaceEvents {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut events = Self::default();
+        for c in s.chars() {
+            let flag = match c {
+                'K' => Self::KEYSPACE,
+                'E' => Self::KEYEVENT,
+                'A' => Self::ALL,
+                c => match Self::CLASSES.iter().find(|(name, _)| *name == c) {
+                    None => bail!("unknown keyspace event class '{c}'"),
+                    Some((_, class)) => *class,
+                },
+            };
+            events.0 |= flag.0;
+        }
+        Ok(events)
+    }
+}
+
+/// Writes the flags back in canonical order, the way `CONFIG GET` shows them.
+impl fmt::Display for KeyspaceEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.contains(Self::ALL) {
+            f.write_str("A")?;
+        } else {
+            for (name, class) in Self::CLASSES {
+                if self.contains(class) {
+                    write!(f, "{name}")?;
+                }
+            }
+        }
+        if self.contains(Self::KEYSPACE) {
+            f.write_str("K")?;
+        }
+        if self.contains(Self::KEYEVENT) {
+            f.write_str("E")?;
+        }
+        Ok(())
+    }
+}
+
+/// Events a command raises, with their class and the key they concern.
+/// Whether they fire depends on the key existing before or after the
+/// command.
+pub fn command_events<'a>(cmd: &Command<'a>) -> Vec<(KeyspaceEvents, &'static str, &'a [u8])> {
+    use KeyspaceEvents as Class;
+    match cmd {
+        Command::Del(keys) | Command::Unlink(keys) => keys
+            .iter()
+            .map(|key| (Class::GENERIC, "del", *key))
+            .collect(),
+        Command::GetDel(key) => vec![(Class::GENERIC, "del", *key)],
+        Command::GetEx(key, ExpiryOption::In(_) | ExpiryOption::At(_)) => {
+            vec![(Class::GENERIC, "expire", *key)]
+        }
+        Command::GetEx(key, ExpiryOption::Persist) => vec![(Class::GENERIC, "persist", *key)],
+        Command::Expire(key, _) | Command::Pexpire(key, _) | Command::PexpireAt(key, _) => {
+            vec![(Class::GENERIC, "expire", *key)]
+        }
+        // a copy into another database is notified where it runs
+        Command::Copy(_, dst, None, _) => vec![(Class::GENERIC, "copy_to", *dst)],
+        Command::Move(key, _) => vec![(Class::GENERIC, "move_from", *key)],
+        Command::Set(key, _, Some(_)) => vec![
+            (Class::STRING, "set", *key),
+            (Class::GENERIC, "expire", *key),
+        ],
+        Command::Set(key, ..)
+        | Command::SetNx(key, _)
+        | Command::Setnx(key, _)
+        | Command::SetXx(key, _)
+        | Command::SetAndGet(key, _)
+        | Command::SetKeepTtl(key, _) => vec![(Class::STRING, "set", *key)],
+        Command::SetRange(key, ..) => vec![(Class::STRING, "setrange", *key)],
+        Command::Incr(key)
+        | Command::IncrBy(key, _)
+        | Command::Decr(key)
+        | Command::DecrBy(key, _) => vec![(Class::STRING, "incrby", *key)],
+        Command::IncrByFloat(key, _) => vec![(Class::STRING, "incrbyfloat", *key)],
+        Command::Lpush(key, _) | Command::LpushX(key, _) => vec![(Class::LIST, "lpush", *key)],
+        Command::Rpush(key, _) | Command::RpushX(key, _) => vec![(Class::LIST, "rpush", *key)],
+        Command::Lpop(key, _) => vec![(Class::LIST, "lpop", *key)],
+        Command::Rpop(key, _) => vec![(Class::LIST, "rpop", *key)],
+        Command::Lset(key, ..) => vec![(Class::LIST, "lset", *key)],
+        Command::Lrem(key, ..) => vec![(Class::LIST, "lrem", *key)],
+        Command::Linsert(key, ..) => vec![(Class::LIST, "linsert", *key)],
+        Command::Hset(key, _) | Command::HMset(key, _) => vec![(Class::HASH, "hset", *key)],
+        Command::HincrBy(key, ..) => vec![(Class::HASH, "hincrby", *key)],
+        Command::HincrByFloat(key, ..) => vec![(Class::HASH, "hincrbyfloat", *key)],
+        Command::Sadd(key, _) => vec![(Class::SET, "sadd", *key)],
+        Command::Srem(key, _) => vec![(Class::SET, "srem", *key)],
+        Command::Spop(key, _) => vec![(Class::SET, "spop", *key)],
+        Command::Smove(src, dst, _) => vec![(Class::SET, "srem", *src), (Class::SET, "sadd", *dst)],
+        Command::Sinterstore(dst, _) => vec![(Class::SET, "sinterstore", *dst)],
+        Command::Sunionstore(dst, _) => vec![(Class::SET, "sunionstore", *dst)],
+        Command::Sdiffstore(dst, _) => vec![(Class::SET, "sdiffstore", *dst)],
+        Command::Zadd(key, _) => vec![(Class::ZSET, "zadd", *key)],
+        Command::Zrem(key, _) => vec![(Class::ZSET, "zrem", *key)],
+        Command::Zincrby(key, ..) => vec![(Class::ZSET, "zincr", *key)],
+        Command::Zremrangebyrank(key, ..) => vec![(Class::ZSET, "zremrangebyrank", *key)],
+        Command::Zremrangebyscore(key, ..) => vec![(Class::ZSET, "zremrangebyscore", *key)],
+        Command::Zunionstore(dst, ..) => vec![(Class::ZSET, "zunionstore", *dst)],
+        Command::Zinterstore(dst, ..) => vec![(Class::ZSET, "zinterstore", *dst)],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        let events: KeyspaceEvents = "Kg$".parse().unwrap();
+        assert!(events.is_enabled(KeyspaceEvents::STRING));
+        assert!(!events.is_enabled(KeyspaceEvents::LIST));
+        assert_eq!(events.to_string(), "g$K");
+        assert_eq!("EKA".parse::<KeyspaceEvents>().unwrap().to_string(), "AKE");
+        assert_eq!(
+            "xelzhs$g".parse::<KeyspaceEvents>().unwrap().to_string(),
+            "A"
+        );
+        assert_eq!(
+            "".parse::<KeyspaceEvents>().unwrap(),
+            KeyspaceEvents::default()
+        );
+        assert!("Kq".parse::<KeyspaceEvents>().is_err());
+
+        // a class alone publishes nowhere
+        let events: KeyspaceEvents = "g".parse().unwrap();
+        assert!(!events.is_enabled(KeyspaceEvents::GENERIC));
+    }
+
+    #[test]
+    fn test_command_events() {
+        assert_eq!(
+            command_events(&Command::Del(vec![b"a", b"b"])),
+            [
+                (KeyspaceEvents::GENERIC, "del", b"a".as_slice()),
+                (KeyspaceEvents::GENERIC, "del", b"b".as_slice())
+            ]
+        );
+        assert_eq!(
+            command_events(&Command::Smove(b"a", b"b", b"m")),
+            [
+                (KeyspaceEvents::SET, "srem", b"a".as_slice()),
+                (KeyspaceEvents::SET, "sadd", b"b".as_slice())
+            ]
+        );
+        assert!(command_events(&Command::Get(b"a")).is_empty());
+    }
+}
@@ -1,33 +1,117 @@
+use crate::err::WrongType;
+use crate::hmap_ops::HMapOps;
 use crate::stored_value::StoredValue;
 use anyhow::bail;
 use bytes::Bytes;
 use std::collections::HashMap;
 
 pub trait HMapNumericalOps {
-    fn incr_by(&mut self, key: &[u8], incr_by: i64) -> anyhow::Result<Option<Bytes>>;
+    fn incr_by(&mut self, key: &[u8], incr_by: i64) -> anyhow::Result<i64>;
+    fn incr_by_float(&mut self, key: &[u8], incr_by: f64) -> anyhow::Result<Bytes>;
+}
+
+/// Formats a float the way `INCRBYFLOAT` replies: no exponent and no
+/// trailing zeros, so `3.0` becomes `3`. It's rounded to the 16 significant
+/// digits an `f64` holds, dropping the binary rounding error that makes
+/// `0.1 + 0.2` `0.30000000000000004`, like Redis' long double arithmetic.
+pub fn format_float(value: f64) -> String {
+    let rounded: f64 = format!("{value:.15e}").parse().unwrap_or(value);
+    format!("{rounded}")
+}
+
+/// Adds `incr_by` to `current`, rejecting NaN and infinite inputs or results.
+pub fn add_float(current: f64, incr_by: f64) -> anyhow::Result<f64> {
+    let new_value = current + incr_by;
+    if !new_value.is_finite() {
+        bail!("increment would produce NaN or Infinity");
+    }
+    Ok(new_value)
 }
 
 impl HMapNumericalOps for HashMap<Bytes, StoredValue> {
-    fn incr_by(&mut self, key: &[u8], incr_by: i64) -> anyhow::Result<Option<Bytes>> {
-        match self.get(key) {
-            None => Ok(None),
-            Some(StoredValue::Plain(bytes)) => {
-                let str = String::from_utf8_lossy(bytes);
-
-                match str::parse::<i64>(&str) {
-                    Ok(num) => {
-                        let new_value = (num + incr_by).to_string();
-                        let value_as_bytes = Bytes::copy_from_slice(new_value.as_bytes());
-                        self.insert(
-                            Bytes::copy_from_slice(key),
-                            StoredValue::Plain(value_as_bytes.clone()),
-                        );
-                        Ok(Some(value_as_bytes))
-                    }
-                    Err(_) => bail!("stored value isn't a 64 bit integer"),
+    fn incr_by(&mut self, key: &[u8], incr_by: i64) -> anyhow::Result<i64> {
+        let current = match self.get_live(key) {
+            // a missing key counts as 0
+            None => 0,
+            Some(StoredValue::Plain(bytes)) | Some(StoredValue::TtlPlain(bytes, _)) => {
+                match str::from_utf8(bytes)
+                    .ok()
+                    .and_then(|s| s.parse::<i64>().ok())
+                {
+                    Some(num) => num,
+                    None => bail!("value is not an integer or out of range"),
                 }
             }
-            _ => bail!("stored value isn't a 64 bit integer"),
+            _ => bail!(WrongType),
+        };
+
+        let Some(new_value) = current.checked_add(incr_by) else {
+            bail!("increment or decrement would overflow");
+        };
+        store_number(self, key, Bytes::from(new_value.to_string()));
+        Ok(new_value)
+    }
+
+    fn incr_by_float(&mut self, key: &[u8], incr_by: f64) -> anyhow::Result<Bytes> {
+        let current = match self.get_live(key) {
+            None => 0.0,
+            Some(StoredValue::Plain(bytes)) | Some(StoredValue::TtlPlain(bytes, _)) => {
+                match str::from_utf8(bytes)
+                    .ok()
+                    .and_then(|s| s.parse::<f64>().ok())
+                {
+                    Some(num) if num.is_finite() => num,
+                    _ => bail!("value is not a valid float"),
+                }
+            }
+            _ => bail!(WrongType),
+        };
+
+        let new_value = Bytes::from(format_float(add_float(current, incr_by)?));
+        store_number(self, key, new_value.clone());
+        Ok(new_value)
+    }
+}
+
+/// Replaces a string value in place, keeping its TTL, or creates it.
+fn store_number(hmap: &mut HashMap<Bytes, StoredValue>, key: &[u8], value: Bytes) {
+    match hmap.get_mut(key) {
+        Some(StoredValue::Plain(bytes)) | Some(StoredValue::TtlPlain(bytes, _)) => {
+            *bytes = value;
         }
+        _ => {
+            hmap.insert(Bytes::copy_from_slice(key), StoredValue::Plain(value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incr_by_overflow_is_an_error() {
+        let mut hmap: HashMap<Bytes, StoredValue> = HashMap::new();
+        assert_eq!(hmap.incr_by(b"k", i64::MAX).unwrap(), i64::MAX);
+        assert!(hmap.incr_by(b"k", i64::MAX).is_err());
+        assert_eq!(hmap.incr_by(b"k", 0).unwrap(), i64::MAX);
+        assert_eq!(hmap.incr_by(b"low", i64::MIN).unwrap(), i64::MIN);
+        assert!(hmap.incr_by(b"low", -1).is_err());
+
+        hmap.insert(
+            Bytes::from_static(b"l"),
+            StoredValue::List(Default::default()),
+        );
+        assert!(hmap.incr_by(b"l", 1).unwrap_err().is::<WrongType>());
+    }
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(format_float(0.1 + 0.2), "0.3");
+        assert_eq!(format_float(3.0), "3");
+        assert_eq!(format_float(-1.5), "-1.5");
+        assert_eq!(format_float(1.0 / 3.0), "0.3333333333333333");
+        assert_eq!(format_float(1e20), "100000000000000000000");
+        assert_eq!(format_float(5e-7), "0.0000005");
     }
 }
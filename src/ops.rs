@@ -1,116 +1,540 @@
+use crate::pubsub::Message;
 use compact_str::CompactString;
 use histogram::Histogram;
 use mio::net::TcpStream;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{Read, Write};
 
-pub struct Ops {
-    stream: TcpStream,
+/// Wire protocol a client negotiated with `HELLO`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
 }
 
-impl Ops {
-    pub fn new(stream: TcpStream) -> Self {
-        Self { stream }
+/// RESP writer over a client connection. Generic over the stream so replies
+/// can be captured in memory. Replies are buffered and only written by
+/// `flush`, so a client that doesn't read can't block the event loop.
+pub struct Ops<S = TcpStream> {
+    stream: S,
+    /// Replies the stream hasn't taken yet.
+    out: Vec<u8>,
+    /// Decides how the RESP3-only types are written.
+    pub protocol: Protocol,
+}
+
+impl<S: Read + Write> Ops<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            out: Vec::new(),
+            protocol: Protocol::default(),
+        }
     }
 
     pub fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         self.stream.read(buf)
     }
 
+    /// Writes as much of the buffered replies as the stream takes without
+    /// blocking, returning whether all of them went out.
+    pub fn flush(&mut self) -> std::io::Result<bool> {
+        let mut written = 0;
+        let result = loop {
+            if written == self.out.len() {
+                break Ok(true);
+            }
+            match self.stream.write(&self.out[written..]) {
+                Ok(0) => break Err(std::io::ErrorKind::WriteZero.into()),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break Ok(false),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        self.out.drain(..written);
+        result
+    }
+
+    pub fn stream_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
     pub fn write_bulk_string<A: AsRef<[u8]>>(&mut self, bytes: A) -> std::io::Result<()> {
-        self.stream
+        self.out
             .write_fmt(format_args!("${}\r\n", bytes.as_ref().len(),))?;
-        self.stream.write_all(bytes.as_ref())?;
-        self.stream.write_all("\r\n".as_bytes())?;
+        self.out.write_all(bytes.as_ref())?;
+        self.out.write_all("\r\n".as_bytes())?;
         Ok(())
     }
 
+    /// Writes only the `*<len>` header; the caller writes the elements.
+    pub fn write_array_header(&mut self, len: usize) -> std::io::Result<()> {
+        self.out.write_fmt(format_args!("*{}\r\n", len))
+    }
+
     pub fn write_array<A: AsRef<[u8]>>(
         &mut self,
         array: impl Iterator<Item = A>,
         len: usize,
     ) -> std::io::Result<()> {
-        self.stream.write_fmt(format_args!("*{}\r\n", len))?;
+        self.write_array_header(len)?;
         for elem in array {
             self.write_bulk_string(elem.as_ref())?;
         }
         Ok(())
     }
 
+    /// Like `write_array`, but `None` entries are written as RESP nulls.
+    pub fn write_array_opt<A: AsRef<[u8]>>(
+        &mut self,
+        array: impl Iterator<Item = Option<A>>,
+        len: usize,
+    ) -> std::io::Result<()> {
+        self.out.write_fmt(format_args!("*{}\r\n", len))?;
+        for elem in array {
+            match elem {
+                None => self.key_not_found()?,
+                Some(elem) => self.write_bulk_string(elem.as_ref())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes only the header of a map of `len` pairs: `%<len>` in RESP3 or,
+    /// for RESP2, an array of the flattened pairs.
+    pub fn write_map_header(&mut self, len: usize) -> std::io::Result<()> {
+        match self.protocol {
+            Protocol::Resp2 => self.write_array_header(len * 2),
+            Protocol::Resp3 => self.out.write_fmt(format_args!("%{}\r\n", len)),
+        }
+    }
+
+    /// Writes a map of `len` pairs from `items`, which alternate between
+    /// keys and values.
+    pub fn write_map<A: AsRef<[u8]>>(
+        &mut self,
+        items: impl Iterator<Item = A>,
+        len: usize,
+    ) -> std::io::Result<()> {
+        self.write_map_header(len)?;
+        for item in items {
+            self.write_bulk_string(item.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Replies to `HELLO` with the server's metadata.
+    pub fn write_hello(&mut self, client_id: usize) -> std::io::Result<()> {
+        self.write_map_header(7)?;
+        self.write_bulk_string("server")?;
+        self.write_bulk_string("redis")?;
+        self.write_bulk_string("version")?;
+        self.write_bulk_string("7.0.0")?;
+        self.write_bulk_string("proto")?;
+        self.write_integer(match self.protocol {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        })?;
+        self.write_bulk_string("id")?;
+        self.write_integer(client_id)?;
+        self.write_bulk_string("mode")?;
+        self.write_bulk_string("standalone")?;
+        self.write_bulk_string("role")?;
+        self.write_bulk_string("master")?;
+        self.write_bulk_string("modules")?;
+        self.write_array_header(0)
+    }
+
+    /// Writes only the header of an out-of-band push: `><len>` in RESP3 or
+    /// a plain array in RESP2.
+    pub fn write_push_header(&mut self, len: usize) -> std::io::Result<()> {
+        match self.protocol {
+            Protocol::Resp2 => self.write_array_header(len),
+            Protocol::Resp3 => self.out.write_fmt(format_args!(">{}\r\n", len)),
+        }
+    }
+
+    /// Confirms a (un)subscription with the client's running count. `channel`
+    /// is `None` when unsubscribing without any subscriptions.
+    pub fn write_subscription(
+        &mut self,
+        kind: &str,
+        channel: Option<&[u8]>,
+        count: usize,
+    ) -> std::io::Result<()> {
+        self.write_push_header(3)?;
+        self.write_bulk_string(kind)?;
+        match channel {
+            None => self.write_null()?,
+            Some(channel) => self.write_bulk_string(channel)?,
+        }
+        self.write_integer(count)
+    }
+
+    /// Writes a published message, in the `pmessage` form with the matched
+    /// pattern for pattern subscriptions.
+    pub fn write_message(&mut self, message: &Message) -> std::io::Result<()> {
+        match &message.pattern {
+            None => {
+                self.write_push_header(3)?;
+                self.write_bulk_string("message")?;
+            }
+            Some(pattern) => {
+                self.write_push_header(4)?;
+                self.write_bulk_string("pmessage")?;
+                self.write_bulk_string(pattern)?;
+            }
+        }
+        self.write_bulk_string(&message.channel)?;
+        self.write_bulk_string(&message.payload)
+    }
+
     pub fn write_integer(&mut self, n: impl std::fmt::Display) -> std::io::Result<()> {
-        self.stream.write_fmt(format_args!(":{}\r\n", n))
+        self.out.write_fmt(format_args!(":{}\r\n", n))
+    }
+
+    /// Writes a double, `,<value>` in RESP3 or a bulk string in RESP2. It
+    /// takes the value as formatted, so integer scores are written exactly
+    /// even past the integers an `f64` holds.
+    pub fn write_double(&mut self, value: impl std::fmt::Display) -> std::io::Result<()> {
+        match self.protocol {
+            Protocol::Resp2 => self.write_bulk_string(value.to_string()),
+            Protocol::Resp3 => self.out.write_fmt(format_args!(",{}\r\n", value)),
+        }
+    }
+
+    /// Writes a null, `_` in RESP3 or a null bulk string in RESP2.
+    pub fn write_null(&mut self) -> std::io::Result<()> {
+        match self.protocol {
+            Protocol::Resp2 => self.out.write_all("$-1\r\n".as_bytes()),
+            Protocol::Resp3 => self.out.write_all("_\r\n".as_bytes()),
+        }
+    }
+
+    /// Writes a null array, `*-1` in RESP2 or `_` in RESP3.
+    pub fn write_null_array(&mut self) -> std::io::Result<()> {
+        match self.protocol {
+            Protocol::Resp2 => self.out.write_all("*-1\r\n".as_bytes()),
+            Protocol::Resp3 => self.out.write_all("_\r\n".as_bytes()),
+        }
+    }
+
+    pub fn write_integer_array(
+        &mut self,
+        items: impl Iterator<Item = i64>,
+        len: usize,
+    ) -> std::io::Result<()> {
+        self.write_array_header(len)?;
+        for n in items {
+            self.write_integer(n)?;
+        }
+        Ok(())
+    }
+
+    /// Writes an `*SCAN` reply: the next cursor and the page of elements.
+    pub fn write_scan<A: AsRef<[u8]>>(&mut self, cursor: u64, page: &[A]) -> std::io::Result<()> {
+        self.write_array_header(2)?;
+        self.write_bulk_string(cursor.to_string())?;
+        self.write_array(page.iter(), page.len())
+    }
+
+    /// Writes a RESP simple string. Payloads containing CR or LF can't be
+    /// represented as one and are written as a bulk string instead.
+    pub fn write_simple_string<A: AsRef<[u8]>>(&mut self, s: A) -> std::io::Result<()> {
+        let s = s.as_ref();
+        if s.iter().any(|&b| b == b'\r' || b == b'\n') {
+            return self.write_bulk_string(s);
+        }
+        self.out.write_all("+".as_bytes())?;
+        self.out.write_all(s)?;
+        self.out.write_all("\r\n".as_bytes())
     }
 
     pub fn ok(&mut self) -> std::io::Result<()> {
-        self.stream.write_all("+OK\r\n".as_bytes())
+        self.write_simple_string("OK")
     }
 
     pub fn pong(&mut self) -> std::io::Result<()> {
-        self.stream.write_all("+PONG\r\n".as_bytes())
+        self.write_simple_string("PONG")
+    }
+
+    pub fn queued(&mut self) -> std::io::Result<()> {
+        self.write_simple_string("QUEUED")
     }
 
     pub fn key_not_found(&mut self) -> std::io::Result<()> {
-        self.stream.write_all("$-1\r\n".as_bytes())
+        self.write_null()
     }
 
     pub fn wrong_type<A: AsRef<[u8]>>(&mut self, message: A) -> std::io::Result<()> {
-        self.stream.write_all("-WRONGTYPE ".as_bytes())?;
-        self.stream.write_all(message.as_ref())?;
-        self.stream.write_all("\r\n".as_bytes())
+        self.out.write_all("-WRONGTYPE ".as_bytes())?;
+        self.out.write_all(message.as_ref())?;
+        self.out.write_all("\r\n".as_bytes())
+    }
+
+    pub fn no_proto(&mut self) -> std::io::Result<()> {
+        self.out
+            .write_all("-NOPROTO unsupported protocol version\r\n".as_bytes())
+    }
+
+    pub fn no_auth(&mut self) -> std::io::Result<()> {
+        self.out
+            .write_all("-NOAUTH Authentication required.\r\n".as_bytes())
+    }
+
+    /// `NOAUTH` for a `HELLO` that doesn't authenticate the client itself.
+    pub fn hello_no_auth(&mut self) -> std::io::Result<()> {
+        self.out.write_all(
+            "-NOAUTH HELLO must be called with the client already authenticated, otherwise the \
+             HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and \
+             select the RESP protocol version at the same time\r\n"
+                .as_bytes(),
+        )
+    }
+
+    pub fn wrong_pass(&mut self) -> std::io::Result<()> {
+        self.out.write_all(
+            "-WRONGPASS invalid username-password pair or user is disabled.\r\n".as_bytes(),
+        )
+    }
+
+    pub fn read_only(&mut self) -> std::io::Result<()> {
+        self.out
+            .write_all("-READONLY You can't write against a read only replica.\r\n".as_bytes())
+    }
+
+    /// Answers `PSYNC` with a full resync: the replication id and offset the
+    /// write stream starts at, then the snapshot as a bulk string without the
+    /// trailing CRLF, as Redis sends it.
+    pub fn write_full_resync(
+        &mut self,
+        replid: &str,
+        offset: u64,
+        snapshot: &[u8],
+    ) -> std::io::Result<()> {
+        self.out.write_fmt(format_args!(
+            "+FULLRESYNC {replid} {offset}\r\n${}\r\n",
+            snapshot.len()
+        ))?;
+        self.out.write_all(snapshot)
+    }
+
+    /// Writes commands already encoded, the write stream fed to a replica.
+    pub fn write_raw(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.out.write_all(bytes)
+    }
+
+    pub fn exec_abort(&mut self) -> std::io::Result<()> {
+        self.out.write_all(
+            "-EXECABORT Transaction discarded because of previous errors.\r\n".as_bytes(),
+        )
     }
 
     pub(crate) fn generic_error<A: AsRef<[u8]>>(&mut self, message: A) -> std::io::Result<()> {
-        self.stream.write_all("-ERR ".as_bytes())?;
-        self.stream.write_all(message.as_ref())?;
-        self.stream.write_all("\r\n".as_bytes())
+        self.out.write_all("-ERR ".as_bytes())?;
+        self.out.write_all(message.as_ref())?;
+        self.out.write_all("\r\n".as_bytes())
     }
 
+    /// Replies to `LATENCY HISTOGRAM`: for each command named in `filter`,
+    /// or all of them when it's empty, its number of calls and the cumulative
+    /// count of calls at or under each power of two microseconds, as Redis
+    /// reports it. Buckets no call fell into are left out.
     pub fn write_latency_histogram(
         &mut self,
         histograms: &HashMap<CompactString, Histogram>,
         filter: &[&[u8]],
     ) -> std::io::Result<()> {
-        // LATENCY HISTOGRAM response in RESP2.
-        // Top-level array: alternating command_name, details.
-        // Each details array: "calls", <int>, "histogram_usec", <bucket array>.
-        // Each bucket array: alternating boundary, cumulative_count.
-        let mut buf = Vec::with_capacity(512);
-
-        let filtered: Vec<_> = if filter.is_empty() {
-            histograms.iter().collect()
-        } else {
-            histograms
-                .iter()
-                .filter(|(name, _)| {
-                    filter
+        let mut selected: Vec<_> = histograms
+            .iter()
+            .filter(|(name, _)| {
+                filter.is_empty()
+                    || filter
                         .iter()
                         .any(|f| name.as_bytes().eq_ignore_ascii_case(f))
-                })
-                .collect()
-        };
+            })
+            .collect();
+        selected.sort_unstable_by_key(|(name, _)| *name);
 
-        buf.extend_from_slice(format!("*{}\r\n", filtered.len() * 2).as_bytes());
-
-        for (name, histogram) in &filtered {
-            let calls = histogram.iter().count();
-            let buckets = histogram.iter().count();
-            buf.extend_from_slice(format!("${}\r\n{}\r\n", name.len(), name).as_bytes());
-            buf.extend_from_slice(b"*4\r\n");
-            buf.extend_from_slice(b"$5\r\ncalls\r\n");
-            buf.extend_from_slice(format!(":{}\r\n", calls).as_bytes());
-            buf.extend_from_slice(b"$14\r\nhistogram_usec\r\n");
-            buf.extend_from_slice(format!("*{}\r\n", buckets * 2).as_bytes());
-            for bucket in *histogram {
-                buf.extend_from_slice(
-                    format!(":{}\r\n:{}\r\n", bucket.end(), bucket.count()).as_bytes(),
-                );
+        self.write_map_header(selected.len())?;
+        for (name, histogram) in selected {
+            let mut buckets = BTreeMap::new();
+            for bucket in histogram {
+                if bucket.count() > 0 {
+                    let usec = bucket.end().max(1).next_power_of_two();
+                    *buckets.entry(usec).or_insert(0) += bucket.count();
+                }
+            }
+            self.write_bulk_string(name)?;
+            self.write_map_header(2)?;
+            self.write_bulk_string("calls")?;
+            self.write_integer(buckets.values().sum::<u64>())?;
+            self.write_bulk_string("histogram_usec")?;
+            self.write_map_header(buckets.len())?;
+            let mut calls = 0;
+            for (usec, count) in buckets {
+                calls += count;
+                self.write_integer(usec)?;
+                self.write_integer(calls)?;
             }
         }
-
-        self.stream.write_all(&buf)
+        Ok(())
     }
 
-    pub fn unwrap_stream(self) -> TcpStream {
+    pub fn unwrap_stream(self) -> S {
         self.stream
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn written(f: impl FnOnce(&mut Ops<Cursor<Vec<u8>>>) -> std::io::Result<()>) -> Vec<u8> {
+        let mut ops = Ops::new(Cursor::new(Vec::new()));
+        f(&mut ops).unwrap();
+        assert!(ops.flush().unwrap());
+        ops.unwrap_stream().into_inner()
+    }
+
+    /// Takes at most `capacity` bytes, then would block.
+    struct Slow {
+        written: Vec<u8>,
+        capacity: usize,
+    }
+
+    impl Read for Slow {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl Write for Slow {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.capacity - self.written.len());
+            if n == 0 {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            self.written.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_flush_keeps_what_would_block() {
+        let mut ops = Ops::new(Slow {
+            written: Vec::new(),
+            capacity: 3,
+        });
+        ops.ok().unwrap();
+        ops.pong().unwrap();
+        assert!(!ops.flush().unwrap());
+        assert_eq!(ops.stream.written, b"+OK");
+
+        ops.stream.capacity = 100;
+        assert!(ops.flush().unwrap());
+        assert_eq!(ops.stream.written, b"+OK\r\n+PONG\r\n");
+        assert!(ops.out.is_empty());
+    }
+
+    #[test]
+    fn test_write_integer_array() {
+        assert_eq!(
+            written(|ops| ops.write_integer_array([1, 0, -1].into_iter(), 3)),
+            b"*3\r\n:1\r\n:0\r\n:-1\r\n"
+        );
+        assert_eq!(
+            written(|ops| ops.write_integer_array(std::iter::empty(), 0)),
+            b"*0\r\n"
+        );
+    }
+
+    #[test]
+    fn test_resp3_writers_fall_back_in_resp2() {
+        assert_eq!(written(|ops| ops.write_double(1.5)), b"$3\r\n1.5\r\n");
+        assert_eq!(written(|ops| ops.write_null()), b"$-1\r\n");
+        assert_eq!(written(|ops| ops.write_null_array()), b"*-1\r\n");
+
+        let resp3 = |f: fn(&mut Ops<Cursor<Vec<u8>>>) -> std::io::Result<()>| {
+            written(|ops| {
+                ops.protocol = Protocol::Resp3;
+                f(ops)
+            })
+        };
+        assert_eq!(resp3(|ops| ops.write_double(-2.0)), b",-2\r\n");
+        assert_eq!(resp3(|ops| ops.write_double(f64::INFINITY)), b",inf\r\n");
+        assert_eq!(
+            resp3(|ops| ops.write_double(9007199254740993_i64)),
+            b",9007199254740993\r\n"
+        );
+        assert_eq!(resp3(|ops| ops.write_null()), b"_\r\n");
+    }
+
+    #[test]
+    fn test_write_subscription_per_protocol() {
+        assert_eq!(
+            written(|ops| ops.write_subscription("subscribe", Some(b"ch"), 1)),
+            b"*3\r\n$9\r\nsubscribe\r\n$2\r\nch\r\n:1\r\n"
+        );
+        assert_eq!(
+            written(|ops| {
+                ops.protocol = Protocol::Resp3;
+                ops.write_subscription("unsubscribe", None, 0)
+            }),
+            b">3\r\n$11\r\nunsubscribe\r\n_\r\n:0\r\n"
+        );
+    }
+
+    #[test]
+    fn test_write_latency_histogram() {
+        let mut histogram = Histogram::new(2, 30).unwrap();
+        for latency in [1, 3, 3, 100] {
+            histogram.increment(latency).unwrap();
+        }
+        let mut histograms = HashMap::new();
+        histograms.insert(CompactString::new("get"), histogram);
+        histograms.insert(CompactString::new("set"), Histogram::new(2, 30).unwrap());
+
+        assert_eq!(
+            written(|ops| ops.write_latency_histogram(&histograms, &[b"GET"])),
+            b"*2\r\n$3\r\nget\r\n\
+              *4\r\n$5\r\ncalls\r\n:4\r\n$14\r\nhistogram_usec\r\n\
+              *6\r\n:1\r\n:1\r\n:4\r\n:3\r\n:128\r\n:4\r\n"
+        );
+        assert_eq!(
+            written(|ops| {
+                ops.protocol = Protocol::Resp3;
+                ops.write_latency_histogram(&histograms, &[b"set"])
+            }),
+            b"%1\r\n$3\r\nset\r\n%2\r\n$5\r\ncalls\r\n:0\r\n$14\r\nhistogram_usec\r\n%0\r\n"
+        );
+        let all = written(|ops| ops.write_latency_histogram(&histograms, &[]));
+        assert!(all.starts_with(b"*4\r\n$3\r\nget\r\n"));
+        assert_eq!(
+            written(|ops| ops.write_latency_histogram(&histograms, &[b"del"])),
+            b"*0\r\n"
+        );
+    }
+
+    #[test]
+    fn test_write_map_per_protocol() {
+        assert_eq!(
+            written(|ops| ops.write_map(["f", "v"].into_iter(), 1)),
+            b"*2\r\n$1\r\nf\r\n$1\r\nv\r\n"
+        );
+        assert_eq!(
+            written(|ops| {
+                ops.protocol = Protocol::Resp3;
+                ops.write_map(["f", "v"].into_iter(), 1)
+            }),
+            b"%1\r\n$1\r\nf\r\n$1\r\nv\r\n"
+        );
+    }
+}
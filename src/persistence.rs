@@ -0,0 +1,397 @@
+use crate::hmap_ops::TtlMap;
+use crate::ranked_set::RankedSet;
+use crate::stored_value::StoredValue;
+use anyhow::{Context, bail};
+use bytes::{Buf, BufMut, Bytes};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Snapshot files start with the magic bytes and a format version.
+const MAGIC: &[u8] = b"REDDIS";
+const VERSION: u8 = 2;
+
+/// Type tags, one per `StoredValue` variant.
+const PLAIN: u8 = 0;
+const TTL_PLAIN: u8 = 1;
+const LIST: u8 = 2;
+const DICT: u8 = 3;
+const SET: u8 = 4;
+const SORTED_SET: u8 = 5;
+
+/// Time since the Unix epoch, which deadlines are stored relative to, since
+/// an `Instant` means nothing to another process.
+pub fn unix_now() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// CRC-32 (IEEE) of `bytes`, to detect records torn by a partial write.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.put_u32_le(bytes.len() as u32);
+    buf.put_slice(bytes);
+}
+
+fn get_bytes(buf: &mut &[u8]) -> anyhow::Result<Bytes> {
+    let len = buf.try_get_u32_le()? as usize;
+    if buf.remaining() < len {
+        bail!(
+            "truncated value: {len} bytes expected, {} left",
+            buf.remaining()
+        );
+    }
+    Ok(buf.copy_to_bytes(len))
+}
+
+fn put_value(buf: &mut Vec<u8>, value: &StoredValue, now: Instant, unix_now: Duration) {
+    match value {
+        StoredValue::Plain(bytes) => {
+            buf.put_u8(PLAIN);
+            put_bytes(buf, bytes);
+        }
+        StoredValue::TtlPlain(bytes, deadline) => {
+            buf.put_u8(TTL_PLAIN);
+            put_bytes(buf, bytes);
+            let unix_deadline = unix_now + deadline.saturating_duration_since(now);
+            buf.put_u64_le(unix_deadline.as_millis() as u64);
+        }
+        StoredValue::List(items) => {
+            buf.put_u8(LIST);
+            buf.put_u32_le(items.len() as u32);
+            items.iter().for_each(|item| put_bytes(buf, item));
+        }
+        StoredValue::Dict(fields) => {
+            buf.put_u8(DICT);
+            buf.put_u32_le(fields.len() as u32);
+            for (field, value) in fields {
+                put_bytes(buf, field);
+                put_bytes(buf, value);
+            }
+        }
+        StoredValue::Set(members) => {
+            buf.put_u8(SET);
+            buf.put_u32_le(members.len() as u32);
+            members.iter().for_each(|member| put_bytes(buf, member));
+        }
+        StoredValue::SortedSet(_, scores) => {
+            // the ranked entries are rebuilt from the scores on load
+            buf.put_u8(SORTED_SET);
+            buf.put_u32_le(scores.len() as u32);
+            for (member, score) in scores {
+                put_bytes(buf, member);
+                buf.put_i64_le(*score);
+            }
+        }
+    }
+}
+
+/// How many bytes a snapshot takes to store `value`, type tag included.
+pub fn serialized_len(value: &StoredValue) -> usize {
+    let mut buf = Vec::new();
+    put_value(&mut buf, value, Instant::now(), unix_now());
+    buf.len()
+}
+
+/// Reads a value, returning its deadline separately for `TtlPlain`.
+fn get_value(
+    buf: &mut &[u8],
+    now: Instant,
+    unix_now: Duration,
+) -> anyhow::Result<(StoredValue, Option<Instant>)> {
+    let value = match buf.try_get_u8()? {
+        PLAIN => StoredValue::Plain(get_bytes(buf)?),
+        TTL_PLAIN => {
+            let bytes = get_bytes(buf)?;
+            let unix_deadline = Duration::from_millis(buf.try_get_u64_le()?);
+            let deadline = now + unix_deadline.saturating_sub(unix_now);
+            return Ok((StoredValue::TtlPlain(bytes, deadline), Some(deadline)));
+        }
+        LIST => {
+            let len = buf.try_get_u32_le()?;
+            let items = (0..len).map(|_| get_bytes(buf));
+            StoredValue::List(items.collect::<anyhow::Result<VecDeque<_>>>()?)
+        }
+        DICT => {
+            let len = buf.try_get_u32_le()?;
+            let mut fields = HashMap::new();
+            for _ in 0..len {
+                fields.insert(get_bytes(buf)?, get_bytes(buf)?);
+            }
+            StoredValue::Dict(fields)
+        }
+        SET => {
+            let len = buf.try_get_u32_le()?;
+            let members = (0..len).map(|_| get_bytes(buf));
+            StoredValue::Set(members.collect::<anyhow::Result<HashSet<_>>>()?)
+        }
+        SORTED_SET => {
+            let len = buf.try_get_u32_le()?;
+            let mut tree = RankedSet::default();
+            let mut scores = HashMap::new();
+            for _ in 0..len {
+                let member = get_bytes(buf)?;
+                let score = buf.try_get_i64_le()?;
+                tree.insert((score, member.clone()));
+                scores.insert(member, score);
+            }
+            StoredValue::SortedSet(tree, scores)
+        }
+        tag => bail!("unknown type tag {tag}"),
+    };
+    Ok((value, None))
+}
+
+/// Encodes every database: the header, then a record per key. A record is
+/// its length and CRC-32, then the database index, key, type tag and value.
+pub fn encode(dbs: &[HashMap<Bytes, StoredValue>]) -> Vec<u8> {
+    let (now, unix_now) = (Instant::now(), unix_now());
+    let mut buf = Vec::new();
+    buf.put_slice(MAGIC);
+    buf.put_u8(VERSION);
+    let mut record = Vec::new();
+    for (index, hmap) in dbs.iter().enumerate() {
+        for (key, value) in hmap {
+            record.clear();
+            record.put_u8(index as u8);
+            put_bytes(&mut record, key);
+            put_value(&mut record, value, now, unix_now);
+            buf.put_u32_le(record.len() as u32);
+            buf.put_u32_le(crc32(&record));
+            buf.put_slice(&record);
+        }
+    }
+    buf
+}
+
+/// Decodes a snapshot made by `encode` into `dbs`, registering deadlines in
+/// the matching `ttl_maps` and dropping keys that expired meanwhile. Records
+/// are checked one by one, so on a truncated or corrupted record the error
+/// is returned with the earlier records already loaded.
+pub fn decode(
+    mut buf: &[u8],
+    dbs: &mut [HashMap<Bytes, StoredValue>],
+    ttl_maps: &mut [TtlMap],
+) -> anyhow::Result<()> {
+    let (now, unix_now) = (Instant::now(), unix_now());
+    if !buf.starts_with(MAGIC) {
+        bail!("not a snapshot file");
+    }
+    buf.advance(MAGIC.len());
+    let version = buf.try_get_u8()?;
+    if version != VERSION {
+        bail!("unsupported snapshot version {version}");
+    }
+    while buf.has_remaining() {
+        let len = buf.try_get_u32_le()? as usize;
+        let crc = buf.try_get_u32_le()?;
+        if buf.remaining() < len {
+            bail!(
+                "truncated record: {len} bytes expected, {} left",
+                buf.remaining()
+            );
+        }
+        let (mut record, rest) = buf.split_at(len);
+        buf = rest;
+        if crc32(record) != crc {
+            bail!("corrupted record: checksum mismatch");
+        }
+        let index = record.try_get_u8()? as usize;
+        if index >= dbs.len() {
+            bail!("database index {index} out of range");
+        }
+        let key = get_bytes(&mut record)?;
+        let (value, deadline) = get_value(&mut record, now, unix_now)?;
+        match deadline {
+            Some(deadline) if deadline <= now => continue,
+            Some(deadline) => ttl_maps[index]
+                .entry(deadline)
+                .or_default()
+                .push(key.clone()),
+            None => {}
+        }
+        dbs[index].insert(key, value);
+    }
+    Ok(())
+}
+
+/// Writes a snapshot of `dbs` to `path`. It goes to a temporary file first,
+/// so a crash mid-write leaves the previous snapshot intact.
+pub fn save(path: &Path, dbs: &[HashMap<Bytes, StoredValue>]) -> anyhow::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, encode(dbs))
+        .with_context(|| format!("can't write {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("can't rename {} to {}", tmp_path.display(), path.display()))
+}
+
+/// Loads the snapshot at `path`, if there's one, into `dbs`.
+pub fn load(
+    path: &Path,
+    dbs: &mut [HashMap<Bytes, StoredValue>],
+    ttl_maps: &mut [TtlMap],
+) -> anyhow::Result<bool> {
+    let buf = match std::fs::read(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        result => result.with_context(|| format!("can't read {}", path.display()))?,
+    };
+    decode(&buf, dbs, ttl_maps).with_context(|| format!("can't load {}", path.display()))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(
+        dbs: &[HashMap<Bytes, StoredValue>],
+    ) -> (Vec<HashMap<Bytes, StoredValue>>, Vec<TtlMap>) {
+        let mut loaded = vec![HashMap::new(); dbs.len()];
+        let mut ttl_maps = vec![TtlMap::new(); dbs.len()];
+        decode(&encode(dbs), &mut loaded, &mut ttl_maps).unwrap();
+        (loaded, ttl_maps)
+    }
+
+    fn bytes(s: &'static str) -> Bytes {
+        Bytes::from_static(s.as_bytes())
+    }
+
+    #[test]
+    fn test_round_trip_every_variant() {
+        let mut dbs = vec![HashMap::new(), HashMap::new()];
+        dbs[0].insert(bytes("plain"), StoredValue::Plain(bytes("v")));
+        dbs[0].insert(
+            bytes("list"),
+            StoredValue::List(VecDeque::from([bytes("a"), bytes("b")])),
+        );
+        dbs[0].insert(
+            bytes("dict"),
+            StoredValue::Dict(HashMap::from([(bytes("f"), bytes("v"))])),
+        );
+        dbs[1].insert(
+            bytes("set"),
+            StoredValue::Set(HashSet::from([bytes("a"), bytes("b")])),
+        );
+        dbs[1].insert(bytes("empty"), StoredValue::Plain(Bytes::new()));
+
+        let (loaded, ttl_maps) = round_trip(&dbs);
+        assert_eq!(loaded, dbs);
+        assert!(ttl_maps.iter().all(TtlMap::is_empty));
+    }
+
+    #[test]
+    fn test_round_trip_sorted_set() {
+        let mut tree = RankedSet::default();
+        tree.insert((2, bytes("b")));
+        tree.insert((-1, bytes("a")));
+        let scores = HashMap::from([(bytes("b"), 2), (bytes("a"), -1)]);
+        let dbs = vec![HashMap::from([(
+            bytes("zset"),
+            StoredValue::SortedSet(tree, scores.clone()),
+        )])];
+
+        let (loaded, _) = round_trip(&dbs);
+        let Some(StoredValue::SortedSet(tree, loaded_scores)) = loaded[0].get(b"zset".as_slice())
+        else {
+            panic!("not a sorted set: {loaded:?}");
+        };
+        assert_eq!(loaded_scores, &scores);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.rank(&(2, bytes("b"))), 1);
+    }
+
+    #[test]
+    fn test_round_trip_ttl_plain() {
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let dbs = vec![HashMap::from([(
+            bytes("ttl"),
+            StoredValue::TtlPlain(bytes("v"), deadline),
+        )])];
+
+        let (loaded, ttl_maps) = round_trip(&dbs);
+        let Some(StoredValue::TtlPlain(value, loaded_deadline)) = loaded[0].get(b"ttl".as_slice())
+        else {
+            panic!("not a TTL value: {loaded:?}");
+        };
+        assert_eq!(value, "v");
+        // stored with millisecond precision
+        let drift = loaded_deadline
+            .saturating_duration_since(deadline)
+            .max(deadline.saturating_duration_since(*loaded_deadline));
+        assert!(drift < Duration::from_secs(1));
+        assert_eq!(ttl_maps[0].get(loaded_deadline), Some(&vec![bytes("ttl")]));
+    }
+
+    #[test]
+    fn test_decode_rejects_garbage() {
+        let mut dbs = vec![HashMap::new()];
+        let mut ttl_maps = vec![TtlMap::new()];
+        assert!(decode(b"nope", &mut dbs, &mut ttl_maps).is_err());
+        assert!(decode(b"REDDIS\x09", &mut dbs, &mut ttl_maps).is_err());
+        assert!(decode(b"REDDIS\x02\x05", &mut dbs, &mut ttl_maps).is_err());
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_decode_tolerates_corrupted_tail() {
+        let first = vec![HashMap::from([(
+            bytes("a"),
+            StoredValue::Plain(bytes("1")),
+        )])];
+        let second = vec![HashMap::from([(
+            bytes("b"),
+            StoredValue::Plain(bytes("2")),
+        )])];
+        let mut snapshot = encode(&first);
+        // a second record, as if appended by the same encoder
+        snapshot.extend_from_slice(&encode(&second)[MAGIC.len() + 1..]);
+
+        let mut flipped = snapshot.clone();
+        *flipped.last_mut().unwrap() ^= 0xff;
+        let truncated = &snapshot[..snapshot.len() - 1];
+        for corrupted in [flipped.as_slice(), truncated] {
+            let mut dbs = vec![HashMap::new()];
+            let mut ttl_maps = vec![TtlMap::new()];
+            assert!(decode(corrupted, &mut dbs, &mut ttl_maps).is_err());
+            assert_eq!(dbs, first);
+        }
+    }
+
+    #[test]
+    fn test_decode_drops_expired() {
+        let now = Instant::now();
+        let dbs = vec![HashMap::from([
+            (bytes("old"), StoredValue::TtlPlain(bytes("v"), now)),
+            (bytes("new"), StoredValue::Plain(bytes("v"))),
+        ])];
+
+        let (loaded, ttl_maps) = round_trip(&dbs);
+        assert_eq!(loaded[0].len(), 1);
+        assert!(loaded[0].contains_key(b"new".as_slice()));
+        assert!(ttl_maps[0].is_empty());
+    }
+
+    #[test]
+    fn test_serialized_len() {
+        // the type tag, then the length of the bytes and the bytes
+        assert_eq!(serialized_len(&StoredValue::Plain(bytes("v"))), 6);
+        let list = StoredValue::List(VecDeque::from([bytes("a"), bytes("bc")]));
+        assert_eq!(serialized_len(&list), 1 + 4 + 5 + 6);
+    }
+}
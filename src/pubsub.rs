@@ -0,0 +1,279 @@
+use crate::glob::glob_match;
+use crate::notify::KeyspaceEvents;
+use bytes::Bytes;
+use mio::Token;
+use std::collections::{HashMap, HashSet};
+
+/// A published message on its way to one subscriber.
+#[derive(Debug, PartialEq)]
+pub struct Message {
+    /// The subscribed pattern that matched, `None` for a channel subscription.
+    pub pattern: Option<Bytes>,
+    pub channel: Bytes,
+    pub payload: Bytes,
+}
+
+/// Subscribers by channel, or by pattern, and the reverse.
+#[derive(Debug, Default)]
+struct Subscriptions {
+    subscribers: HashMap<Bytes, HashSet<Token>>,
+    of_client: HashMap<Token, HashSet<Bytes>>,
+}
+
+impl Subscriptions {
+    fn count(&self, token: Token) -> usize {
+        self.of_client.get(&token).map_or(0, HashSet::len)
+    }
+
+    fn add(&mut self, token: Token, name: &[u8]) {
+        let name = Bytes::copy_from_slice(name);
+        self.subscribers
+            .entry(name.clone())
+            .or_default()
+            .insert(token);
+        self.of_client.entry(token).or_default().insert(name);
+    }
+
+    fn remove(&mut self, token: Token, name: &[u8]) {
+        if let Some(subscribers) = self.subscribers.get_mut(name) {
+            subscribers.remove(&token);
+            if subscribers.is_empty() {
+                self.subscribers.remove(name);
+            }
+        }
+        if let Some(names) = self.of_client.get_mut(&token) {
+            names.remove(name);
+            if names.is_empty() {
+                self.of_client.remove(&token);
+            }
+        }
+    }
+
+    fn of(&self, token: Token) -> Vec<Bytes> {
+        self.of_client
+            .get(&token)
+            .map_or_else(Vec::new, |names| names.iter().cloned().collect())
+    }
+}
+
+/// Channel and pattern subscriptions of all clients. `PUBLISH` can't write to
+/// the other clients' streams while the publisher is borrowed, so messages
+/// wait in an outbox until the event loop delivers them.
+#[derive(Debug, Default)]
+pub struct PubSub {
+    channels: Subscriptions,
+    patterns: Subscriptions,
+    outbox: Vec<(Token, Message)>,
+    /// Keyspace notifications to publish, from `notify-keyspace-events`.
+    pub keyspace_events: KeyspaceEvents,
+}
+
+impl PubSub {
+    /// Number of channels and patterns `token` is subscribed to.
+    pub fn subscription_count(&self, token: Token) -> usize {
+        self.channels.count(token) + self.patterns.count(token)
+    }
+
+    /// Subscribes `token` to `channel`, returning its subscription count.
+    pub fn subscribe(&mut self, token: Token, channel: &[u8]) -> usize {
+        self.channels.add(token, channel);
+        self.subscription_count(token)
+    }
+
+    /// Unsubscribes `token` from `channel`, returning its subscription count.
+    pub fn unsubscribe(&mut self, token: Token, channel: &[u8]) -> usize {
+        self.channels.remove(token, channel);
+        self.subscription_count(token)
+    }
+
+    /// Subscribes `token` to the channels matching the glob `pattern`,
+    /// returning its subscription count.
+    pub fn psubscribe(&mut self, token: Token, pattern: &[u8]) -> usize {
+        self.patterns.add(token, pattern);
+        self.subscription_count(token)
+    }
+
+    pub fn punsubscribe(&mut self, token: Token, pattern: &[u8]) -> usize {
+        self.patterns.remove(token, pattern);
+        self.subscription_count(token)
+    }
+
+    /// Channels `token` is subscribed to, in no particular order.
+    pub fn channels_of(&self, token: Token) -> Vec<Bytes> {
+        self.channels.of(token)
+    }
+
+    pub fn patterns_of(&self, token: Token) -> Vec<Bytes> {
+        self.patterns.of(token)
+    }
+
+    /// Drops every subscription of a disconnected client.
+    pub fn unsubscribe_all(&mut self, token: Token) {
+        for channel in self.channels.of(token) {
+            self.channels.remove(token, &channel);
+        }
+        for pattern in self.patterns.of(token) {
+            self.patterns.remove(token, &pattern);
+        }
+    }
+
+    /// Queues `payload` for the subscribers of `channel` and of the patterns
+    /// matching it, returning how many messages will be delivered. A client
+    /// subscribed several ways gets a message for each.
+    pub fn publish(&mut self, channel: &[u8], payload: &[u8]) -> usize {
+        let channel = Bytes::copy_from_slice(channel);
+        let payload = Bytes::copy_from_slice(payload);
+        let exact = self
+            .channels
+            .subscribers
+            .get(&channel)
+            .into_iter()
+            .flatten()
+            .map(|token| (*token, None));
+        let matching = self
+            .patterns
+            .subscribers
+            .iter()
+            .filter(|(pattern, _)| glob_match(pattern, &channel))
+            .flat_map(|(pattern, tokens)| {
+                tokens.iter().map(|token| (*token, Some(pattern.clone())))
+            });
+        let before = self.outbox.len();
+        for (token, pattern) in exact.chain(matching) {
+            let message = Message {
+                pattern,
+                channel: channel.clone(),
+                payload: payload.clone(),
+            };
+            self.outbox.push((token, message));
+        }
+        self.outbox.len() - before
+    }
+
+    /// Publishes a keyspace notification of `class` about `key`, if that
+    /// class is enabled.
+    pub fn notify(&mut self, class: KeyspaceEvents, event: &str, db_index: usize, key: &[u8]) {
+        if !self.keyspace_events.is_enabled(class) {
+            return;
+        }
+        if self.keyspace_events.contains(KeyspaceEvents::KEYSPACE) {
+            let mut channel = format!("__keyspace@{db_index}__:").into_bytes();
+            channel.extend_from_slice(key);
+            self.publish(&channel, event.as_bytes());
+        }
+        if self.keyspace_events.contains(KeyspaceEvents::KEYEVENT) {
+            let channel = format!("__keyevent@{db_index}__:{event}");
+            self.publish(channel.as_bytes(), key);
+        }
+    }
+
+    /// Hands out the messages published since the last call.
+    pub fn take_outbox(&mut self) -> Vec<(Token, Message)> {
+        std::mem::take(&mut self.outbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_to_subscribers() {
+        let mut pubsub = PubSub::default();
+        assert_eq!(pubsub.subscribe(Token(1), b"news"), 1);
+        assert_eq!(pubsub.subscribe(Token(1), b"news"), 1);
+        assert_eq!(pubsub.subscribe(Token(1), b"sport"), 2);
+        assert_eq!(pubsub.subscribe(Token(2), b"news"), 1);
+
+        assert_eq!(pubsub.publish(b"news", b"hi"), 2);
+        assert_eq!(pubsub.publish(b"weather", b"hi"), 0);
+        let mut outbox = pubsub.take_outbox();
+        outbox.sort_by_key(|(token, _)| *token);
+        let message = || Message {
+            pattern: None,
+            channel: Bytes::from_static(b"news"),
+            payload: Bytes::from_static(b"hi"),
+        };
+        assert_eq!(outbox, [(Token(1), message()), (Token(2), message())]);
+        assert!(pubsub.take_outbox().is_empty());
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let mut pubsub = PubSub::default();
+        pubsub.subscribe(Token(1), b"news");
+        pubsub.subscribe(Token(1), b"sport");
+        pubsub.subscribe(Token(2), b"news");
+        assert_eq!(pubsub.unsubscribe(Token(1), b"news"), 1);
+        assert_eq!(pubsub.unsubscribe(Token(1), b"nope"), 1);
+        assert_eq!(pubsub.publish(b"news", b"hi"), 1);
+
+        pubsub.unsubscribe_all(Token(1));
+        assert_eq!(pubsub.subscription_count(Token(1)), 0);
+        assert_eq!(pubsub.publish(b"sport", b"hi"), 0);
+        pubsub.unsubscribe_all(Token(2));
+        assert!(pubsub.channels.subscribers.is_empty());
+        assert!(pubsub.channels.of_client.is_empty());
+    }
+
+    #[test]
+    fn test_publish_to_patterns() {
+        let mut pubsub = PubSub::default();
+        assert_eq!(pubsub.subscribe(Token(1), b"news.tech"), 1);
+        assert_eq!(pubsub.psubscribe(Token(1), b"news.*"), 2);
+        assert_eq!(pubsub.psubscribe(Token(2), b"*"), 1);
+        assert_eq!(pubsub.psubscribe(Token(2), b"sport.*"), 2);
+
+        assert_eq!(pubsub.publish(b"news.tech", b"hi"), 3);
+        let mut outbox = pubsub.take_outbox();
+        outbox.sort_by(|(a, m), (b, n)| (a, &m.pattern).cmp(&(b, &n.pattern)));
+        let patterns: Vec<_> = outbox
+            .iter()
+            .map(|(token, message)| (token.0, message.pattern.as_deref()))
+            .collect();
+        assert_eq!(
+            patterns,
+            [
+                (1, None),
+                (1, Some(b"news.*".as_slice())),
+                (2, Some(b"*".as_slice()))
+            ]
+        );
+
+        assert_eq!(pubsub.punsubscribe(Token(2), b"*"), 1);
+        assert_eq!(pubsub.publish(b"weather", b"hi"), 0);
+        pubsub.unsubscribe_all(Token(2));
+        assert!(pubsub.patterns_of(Token(2)).is_empty());
+    }
+
+    #[test]
+    fn test_notify() {
+        let mut pubsub = PubSub::default();
+        pubsub.psubscribe(Token(1), b"__key*");
+        pubsub.notify(KeyspaceEvents::GENERIC, "del", 0, b"a");
+        assert!(pubsub.take_outbox().is_empty());
+
+        pubsub.keyspace_events = "KEg".parse().unwrap();
+        pubsub.notify(KeyspaceEvents::STRING, "set", 0, b"a");
+        assert!(pubsub.take_outbox().is_empty());
+        pubsub.notify(KeyspaceEvents::GENERIC, "del", 3, b"a");
+        let messages: Vec<_> = pubsub
+            .take_outbox()
+            .into_iter()
+            .map(|(_, message)| (message.channel, message.payload))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (
+                    Bytes::from_static(b"__keyspace@3__:a"),
+                    Bytes::from_static(b"del")
+                ),
+                (
+                    Bytes::from_static(b"__keyevent@3__:del"),
+                    Bytes::from_static(b"a")
+                )
+            ]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashSet;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::SystemTime;
+
+/// A xorshift64* generator backing the random commands (SPOP, SRANDMEMBER,
+/// HRANDFIELD, ...). Fast and small, but not cryptographically secure.
+#[derive(Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn with_seed(seed: u64) -> Self {
+        // xorshift never leaves the all-zero state
+        Self {
+            state: if seed == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                seed
+            },
+        }
+    }
+
+    /// Seeds from the OS randomness behind `RandomState`'s hash keys.
+    pub fn from_entropy() -> Self {
+        Self::with_seed(RandomState::new().hash_one(SystemTime::now()))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A uniformly distributed index below `len`, which must be non-zero.
+    pub fn pick_index(&mut self, len: usize) -> usize {
+        ((self.next_u64() as u128 * len as u128) >> 64) as usize
+    }
+
+    /// `k` distinct indices below `len` in ascending order, using Floyd's
+    /// algorithm so only `k` slots are ever allocated.
+    pub fn sample_distinct(&mut self, len: usize, k: usize) -> Vec<usize> {
+        let k = k.min(len);
+        let mut picked = HashSet::with_capacity(k);
+        for j in len - k..len {
+            let t = self.pick_index(j + 1);
+            if !picked.insert(t) {
+                picked.insert(j);
+            }
+        }
+        let mut picked: Vec<usize> = picked.into_iter().collect();
+        picked.sort_unstable();
+        picked
+    }
+
+    /// Picks random `items` the way `SRANDMEMBER` and `HRANDFIELD` do: up to
+    /// `count` distinct items, or exactly `|count|` items with possible
+    /// repeats when `count` is negative.
+    pub fn sample<T: Clone>(
+        &mut self,
+        items: impl ExactSizeIterator<Item = T>,
+        count: i64,
+    ) -> Vec<T> {
+        let len = items.len();
+        if len == 0 {
+            return vec![];
+        }
+        let wanted = count.unsigned_abs() as usize;
+        if count < 0 {
+            let items: Vec<T> = items.collect();
+            return (0..wanted)
+                .map(|_| items[self.pick_index(len)].clone())
+                .collect();
+        }
+        if wanted >= len {
+            return items.collect();
+        }
+        let mut picked = self.sample_distinct(len, wanted).into_iter().peekable();
+        items
+            .enumerate()
+            .filter_map(|(index, item)| picked.next_if_eq(&index).map(|_| item))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_sequences_repeat() {
+        let mut a = Rng::with_seed(7);
+        let mut b = Rng::with_seed(7);
+        assert!((0..100).all(|_| a.next_u64() == b.next_u64()));
+        assert_ne!(Rng::with_seed(0).next_u64(), 0);
+    }
+
+    #[test]
+    fn test_pick_index_in_range() {
+        let mut rng = Rng::with_seed(1);
+        let mut seen = [false; 10];
+        for _ in 0..1_000 {
+            seen[rng.pick_index(10)] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn test_sample_distinct() {
+        let mut rng = Rng::with_seed(3);
+        for k in [0, 1, 5, 10, 20] {
+            let picked = rng.sample_distinct(10, k);
+            assert_eq!(picked.len(), k.min(10));
+            assert!(picked.windows(2).all(|w| w[0] < w[1]));
+            assert!(picked.iter().all(|&i| i < 10));
+        }
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut rng = Rng::with_seed(5);
+        let items = [1, 2, 3, 4, 5];
+        let picked = rng.sample(items.iter(), 3);
+        assert_eq!(picked.len(), 3);
+        assert!(picked.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(rng.sample(items.iter(), 9).len(), 5);
+        assert_eq!(rng.sample(items.iter(), -9).len(), 9);
+        assert!(rng.sample(std::iter::empty::<u8>(), -3).is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+use bytes::Bytes;
+use std::ops::{Bound, Range};
+
+/// Entries of a sorted set, ordered by score and then by member.
+pub type Entry = (i64, Bytes);
+
+/// Blocks are split once they outgrow this many entries.
+const MAX_BLOCK_LEN: usize = 512;
+
+/// An ordered set of `(score, member)` entries that answers rank queries in
+/// O(log n), which a `BTreeSet` can only do by counting.
+///
+/// Entries live in sorted blocks of at most `MAX_BLOCK_LEN`, alongside the
+/// number of entries preceding each block. A rank is a binary search over the
+/// blocks plus one inside a block. Updates shift at most one block and
+/// refresh the prefix counts after it, O(`MAX_BLOCK_LEN` + n / `MAX_BLOCK_LEN`).
+#[derive(Debug, Default, Clone)]
+pub struct RankedSet {
+    blocks: Vec<Vec<Entry>>,
+    /// `offsets[i]` is the number of entries in `blocks[..i]`.
+    offsets: Vec<usize>,
+    len: usize,
+}
+
+impl RankedSet {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Index of the block `entry` belongs to, or `blocks.len()` when it's
+    /// greater than every entry.
+    fn block_of(&self, entry: &Entry) -> usize {
+        self.blocks
+            .partition_point(|block| block.last().is_some_and(|last| last < entry))
+    }
+
+    /// Number of entries strictly less than `entry`.
+    pub fn rank(&self, entry: &Entry) -> usize {
+        let block = self.block_of(entry);
+        match self.blocks.get(block) {
+            None => self.len,
+            Some(entries) => self.offsets[block] + entries.partition_point(|e| e < entry),
+        }
+    }
+
+    pub fn contains(&self, entry: &Entry) -> bool {
+        self.blocks
+            .get(self.block_of(entry))
+            .is_some_and(|entries| entries.binary_search(entry).is_ok())
+    }
+
+    pub fn insert(&mut self, entry: Entry) -> bool {
+        if self.blocks.is_empty() {
+            self.blocks.push(vec![entry]);
+            self.offsets.push(0);
+            self.len = 1;
+            return true;
+        }
+        // past the end goes into the last block
+        let block = self.block_of(&entry).min(self.blocks.len() - 1);
+        let entries = &mut self.blocks[block];
+        let Err(pos) = entries.binary_search(&entry) else {
+            return false;
+        };
+        entries.insert(pos, entry);
+        if entries.len() > MAX_BLOCK_LEN {
+            let tail = entries.split_off(entries.len() / 2);
+            self.blocks.insert(block + 1, tail);
+        }
+        self.len += 1;
+        self.reindex(block);
+        true
+    }
+
+    pub fn remove(&mut self, entry: &Entry) -> bool {
+        let block = self.block_of(entry);
+        let Some(entries) = self.blocks.get_mut(block) else {
+            return false;
+        };
+        let Ok(pos) = entries.binary_search(entry) else {
+            return false;
+        };
+        entries.remove(pos);
+        if entries.is_empty() {
+            self.blocks.remove(block);
+        }
+        self.len -= 1;
+        self.reindex(block);
+        true
+    }
+
+    /// Recomputes the prefix counts of `blocks[from..]`.
+    fn reindex(&mut self, from: usize) {
+        self.offsets.truncate(from);
+        let mut offset = match from {
+            0 => 0,
+            _ => self.offsets[from - 1] + self.blocks[from - 1].len(),
+        };
+        for block in &self.blocks[from..] {
+            self.offsets.push(offset);
+            offset += block.len();
+        }
+    }
+
+    /// Block and in-block position of the entry at `index`.
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let block = self.offsets.partition_point(|&offset| offset <= index) - 1;
+        (block, index - self.offsets[block])
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Entry> {
+        self.slice(0..self.len)
+    }
+
+    /// Entries with ranks in `range`, which must lie within `0..len()`.
+    pub fn slice(&self, range: Range<usize>) -> impl DoubleEndedIterator<Item = &Entry> {
+        let (blocks, first_pos, last_pos) = if range.is_empty() {
+            (&self.blocks[..0], 0, 0)
+        } else {
+            let (first, first_pos) = self.locate(range.start);
+            let (last, last_pos) = self.locate(range.end - 1);
+            (&self.blocks[first..=last], first_pos, last_pos)
+        };
+        let last = blocks.len().saturating_sub(1);
+        blocks.iter().enumerate().flat_map(move |(i, block)| {
+            let start = if i == 0 { first_pos } else { 0 };
+            let end = if i == last { last_pos + 1 } else { block.len() };
+            block[start..end].iter()
+        })
+    }
+
+    /// Entries within the given bounds, in order.
+    pub fn range(
+        &self,
+        (start, end): (Bound<Entry>, Bound<Entry>),
+    ) -> impl DoubleEndedIterator<Item = &Entry> {
+        let first = match &start {
+            Bound::Unbounded => 0,
+            Bound::Included(entry) => self.rank(entry),
+            Bound::Excluded(entry) => self.rank(entry) + self.contains(entry) as usize,
+        };
+        let last = match &end {
+            Bound::Unbounded => self.len,
+            Bound::Included(entry) => self.rank(entry) + self.contains(entry) as usize,
+            Bound::Excluded(entry) => self.rank(entry),
+        };
+        self.slice(first..last.max(first))
+    }
+}
+
+/// Equal sets can be split into blocks differently, so compare the entries.
+impl PartialEq for RankedSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn entry(score: i64) -> Entry {
+        (score, Bytes::from(format!("m{score}")))
+    }
+
+    #[test]
+    fn test_matches_btree_set() {
+        let mut model = BTreeSet::new();
+        let mut set = RankedSet::default();
+        // a small LCG keeps the sequence reproducible without a rand crate
+        let mut seed: u64 = 42;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) as i64 % 5_000
+        };
+        for _ in 0..20_000 {
+            let e = entry(next());
+            if next() % 3 == 0 {
+                assert_eq!(set.remove(&e), model.remove(&e));
+            } else {
+                assert_eq!(set.insert(e.clone()), model.insert(e));
+            }
+        }
+        assert_eq!(set.len(), model.len());
+        assert!(set.iter().eq(model.iter()));
+        assert!(set.iter().rev().eq(model.iter().rev()));
+        for score in (-10..5_010).step_by(7) {
+            let e = entry(score);
+            assert_eq!(set.rank(&e), model.range(..&e).count());
+            assert_eq!(set.contains(&e), model.contains(&e));
+        }
+        let (from, to) = (model.len() / 3, model.len() / 2);
+        assert!(
+            set.slice(from..to)
+                .eq(model.iter().skip(from).take(to - from))
+        );
+        assert!(
+            set.slice(from..to)
+                .rev()
+                .eq(model.iter().skip(from).take(to - from).rev())
+        );
+        assert!(set.slice(from..from).next().is_none());
+        let bounds = (Bound::Excluded(entry(100)), Bound::Included(entry(2_000)));
+        assert!(set.range(bounds.clone()).eq(model.range(bounds)));
+    }
+
+    #[test]
+    fn test_eq_ignores_block_layout() {
+        let mut grown = RankedSet::default();
+        for score in 0..2_000 {
+            grown.insert(entry(score));
+        }
+        let mut shrunk = grown.clone();
+        for score in 2_000..4_000 {
+            shrunk.insert(entry(score));
+        }
+        for score in 2_000..4_000 {
+            shrunk.remove(&entry(score));
+        }
+        assert_eq!(grown, shrunk);
+        shrunk.remove(&entry(0));
+        assert_ne!(grown, shrunk);
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut set = RankedSet::default();
+        assert_eq!(set.rank(&entry(1)), 0);
+        assert!(set.iter().next().is_none());
+        assert!(!set.remove(&entry(1)));
+        assert!(set.insert(entry(1)));
+        assert!(set.remove(&entry(1)));
+        assert!(set.is_empty());
+        assert!(set.iter().next().is_none());
+    }
+}
@@ -0,0 +1,385 @@
+use crate::aof;
+use crate::cmd::owned::OwnedCommand;
+use crate::random::Rng;
+use anyhow::{Context, bail};
+use mio::net::TcpStream;
+use mio::{Interest, Registry, Token};
+use std::io::{self, Read, Write};
+use std::net::ToSocketAddrs;
+use std::time::{Duration, Instant};
+
+/// What a replica sends once connected: no replication id or offset to
+/// resume from, so the master answers with a full resync.
+const PSYNC: &[u8] = b"*3\r\n$5\r\nPSYNC\r\n$1\r\n?\r\n$2\r\n-1\r\n";
+
+/// How long a replica waits before connecting again to a master it lost.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Both sides of replication. As a master, the server feeds its writes to a
+/// single replica, which `PSYNC` attaches after sending it a snapshot. As a
+/// replica, it follows the master `REPLICAOF` names, over a link the event
+/// loop owns. Every sync is a full resync: there's no backlog to resume from.
+#[derive(Debug)]
+pub struct Replication {
+    /// Identifies the write stream this server produces.
+    replid: String,
+    /// Bytes of write stream produced so far.
+    offset: u64,
+    replica: Option<Token>,
+    /// Write stream the replica is yet to be sent.
+    outbox: Vec<u8>,
+    /// Database the replica runs the stream's commands in.
+    db_index: usize,
+    /// Host and port of the master, `None` while this server is a master.
+    master: Option<(String, u16)>,
+    /// Whether the master's snapshot is loaded and its stream applied.
+    pub link_up: bool,
+    /// When the event loop should connect to the master, again or anew.
+    connect_at: Option<Instant>,
+}
+
+impl Replication {
+    pub fn new(rng: &mut Rng) -> Self {
+        Self {
+            replid: format!(
+                "{:016x}{:016x}{:08x}",
+                rng.next_u64(),
+                rng.next_u64(),
+                rng.next_u64() as u32
+            ),
+            offset: 0,
+            replica: None,
+            outbox: Vec::new(),
+            db_index: 0,
+            master: None,
+            link_up: false,
+            connect_at: None,
+        }
+    }
+
+    pub fn is_replica(&self) -> bool {
+        self.master.is_some()
+    }
+
+    pub fn has_replica(&self) -> bool {
+        self.replica.is_some()
+    }
+
+    pub fn replid(&self) -> &str {
+        &self.replid
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Starts feeding the write stream to `token`, which was just sent a
+    /// snapshot, returning the replica it replaces.
+    pub fn attach(&mut self, token: Token) -> Option<Token> {
+        self.outbox.clear();
+        // the snapshot loads like the replica's stream starts, in database 0
+        self.db_index = 0;
+        self.replica
+            .replace(token)
+            .filter(|&replaced| replaced != token)
+    }
+
+    /// Stops feeding `token`, if it's the replica.
+    pub fn detach(&mut self, token: Token) {
+        if self.replica == Some(token) {
+            self.replica = None;
+            self.outbox.clear();
+        }
+    }
+
+    /// Adds a write run in database `db_index` to the replica's stream,
+    /// encoded as the AOF logs it so it applies the same way.
+    pub fn feed(&mut self, db_index: usize, cmd: &OwnedCommand) {
+        if self.replica.is_none() {
+            return;
+        }
+        let len = self.outbox.len();
+        aof::encode(&mut self.outbox, &mut self.db_index, db_index, cmd);
+        self.offset += (self.outbox.len() - len) as u64;
+    }
+
+    /// Hands out the write stream fed since the last call, with the replica
+    /// it's for.
+    pub fn take_outbox(&mut self) -> Option<(Token, Vec<u8>)> {
+        match self.replica {
+            Some(replica) if !self.outbox.is_empty() => {
+                Some((replica, std::mem::take(&mut self.outbox)))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn master(&self) -> Option<(&str, u16)> {
+        self.master
+            .as_ref()
+            .map(|(host, port)| (host.as_str(), *port))
+    }
+
+    /// Follows `master`, or with `None` stops following one. Returns whether
+    /// that changed anything; the event loop drops the old link and opens the
+    /// new one on its next turn.
+    pub fn follow(&mut self, master: Option<(String, u16)>) -> bool {
+        if master == self.master {
+            return false;
+        }
+        self.link_up = false;
+        self.connect_at = Some(Instant::now());
+        self.master = master;
+        true
+    }
+
+    /// Schedules connecting again after the link to the master was lost.
+    pub fn link_lost(&mut self, now: Instant) {
+        self.link_up = false;
+        if self.master.is_some() {
+            self.connect_at = Some(now + RETRY_DELAY);
+        }
+    }
+
+    /// When the event loop has to wake up to relink to the master.
+    pub fn connect_at(&self) -> Option<Instant> {
+        self.connect_at
+    }
+
+    /// Whether the link to the master is due to be replaced at `now`,
+    /// clearing the schedule if it is.
+    pub fn take_connect(&mut self, now: Instant) -> bool {
+        let due = self.connect_at.is_some_and(|at| at <= now);
+        if due {
+            self.connect_at = None;
+        }
+        due
+    }
+
+    /// The fields of the `INFO` replication section.
+    pub fn info(&self) -> String {
+        let role = match &self.master {
+            None => "role:master\r\n".to_string(),
+            Some((host, port)) => format!(
+                "role:slave\r\n\
+                 master_host:{host}\r\n\
+                 master_port:{port}\r\n\
+                 master_link_status:{}\r\n",
+                if self.link_up { "up" } else { "down" }
+            ),
+        };
+        format!(
+            "{role}\
+             connected_slaves:{}\r\n\
+             master_replid:{}\r\n\
+             master_repl_offset:{}\r\n",
+            self.replica.is_some() as u8,
+            self.replid,
+            self.offset,
+        )
+    }
+}
+
+/// Where the link to the master is in syncing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LinkState {
+    /// The connection isn't established yet, `PSYNC` is still to be sent.
+    Connecting,
+    /// `PSYNC` is sent, the `+FULLRESYNC` line is awaited.
+    AwaitingResync,
+    /// The snapshot is arriving.
+    AwaitingSnapshot,
+    /// The snapshot is loaded, what follows is the write stream.
+    Streaming,
+}
+
+/// A replica's connection to its master.
+#[derive(Debug)]
+pub struct MasterLink {
+    stream: TcpStream,
+    state: LinkState,
+    /// What the master sent that's yet to be handled.
+    pub read_buf: Vec<u8>,
+    /// Database the streamed commands run in.
+    pub db_index: usize,
+}
+
+impl MasterLink {
+    /// Starts connecting to `host:port`, registering the connection under
+    /// `token`. The handshake goes on once it's writable.
+    pub fn connect(
+        host: &str,
+        port: u16,
+        registry: &Registry,
+        token: Token,
+    ) -> anyhow::Result<Self> {
+        let addr = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .with_context(|| format!("{host} has no address"))?;
+        let mut stream = TcpStream::connect(addr)?;
+        registry.register(&mut stream, token, Interest::READABLE | Interest::WRITABLE)?;
+        Ok(Self {
+            stream,
+            state: LinkState::Connecting,
+            read_buf: Vec::new(),
+            db_index: 0,
+        })
+    }
+
+    pub fn stream_mut(&mut self) -> &mut TcpStream {
+        &mut self.stream
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.state == LinkState::Streaming
+    }
+
+    /// Sends `PSYNC` once the connection is established, then only waits
+    /// for what the master sends back.
+    pub fn handshake(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+        if self.state != LinkState::Connecting {
+            return Ok(());
+        }
+        if let Some(e) = self.stream.take_error()? {
+            return Err(e);
+        }
+        match self.stream.peer_addr() {
+            Err(e) if e.kind() == io::ErrorKind::NotConnected => return Ok(()),
+            result => result?,
+        };
+        self.stream.write_all(PSYNC)?;
+        self.state = LinkState::AwaitingResync;
+        registry.reregister(&mut self.stream, token, Interest::READABLE)
+    }
+
+    /// Reads what the master sent, returning `false` once it closed the
+    /// connection.
+    pub fn receive(&mut self) -> io::Result<bool> {
+        let mut buf = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.read_buf.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(true),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the snapshot once it fully arrived, leaving the write stream
+    /// that follows it in `read_buf`.
+    pub fn take_snapshot(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        take_snapshot(&mut self.state, &mut self.read_buf)
+    }
+}
+
+/// Advances the sync through what's in `buf`: the `+FULLRESYNC` line, then
+/// the snapshot, sent as a bulk string without the trailing CRLF.
+fn take_snapshot(state: &mut LinkState, buf: &mut Vec<u8>) -> anyhow::Result<Option<Vec<u8>>> {
+    if *state == LinkState::AwaitingResync {
+        let Some(end) = buf.windows(2).position(|w| w == b"\r\n") else {
+            return Ok(None);
+        };
+        if !buf.starts_with(b"+FULLRESYNC ") {
+            bail!(
+                "master refused to sync: {}",
+                String::from_utf8_lossy(&buf[..end])
+            );
+        }
+        buf.drain(..end + 2);
+        *state = LinkState::AwaitingSnapshot;
+    }
+    if *state != LinkState::AwaitingSnapshot {
+        return Ok(None);
+    }
+    let Some(end) = buf.windows(2).position(|w| w == b"\r\n") else {
+        return Ok(None);
+    };
+    let len = buf[..end]
+        .strip_prefix(b"$")
+        .and_then(|len| str::from_utf8(len).ok()?.parse::<usize>().ok())
+        .context("malformed snapshot header")?;
+    let start = end + 2;
+    if buf.len() - start < len {
+        return Ok(None);
+    }
+    let snapshot = buf[start..start + len].to_vec();
+    buf.drain(..start + len);
+    *state = LinkState::Streaming;
+    Ok(Some(snapshot))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_feed() {
+        let mut replication = Replication::new(&mut Rng::with_seed(1));
+        let set = OwnedCommand::Set(Bytes::from_static(b"k"), Bytes::from_static(b"v"), None);
+        // nothing is kept without a replica to send it to
+        replication.feed(0, &set);
+        assert_eq!(replication.take_outbox(), None);
+        assert_eq!(replication.offset(), 0);
+
+        assert_eq!(replication.attach(Token(1)), None);
+        replication.feed(2, &set);
+        let stream = b"*2\r\n$6\r\nSELECT\r\n$1\r\n2\r\n*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n";
+        assert_eq!(replication.take_outbox(), Some((Token(1), stream.to_vec())));
+        assert_eq!(replication.offset(), stream.len() as u64);
+        assert_eq!(replication.take_outbox(), None);
+
+        assert_eq!(replication.attach(Token(2)), Some(Token(1)));
+        replication.detach(Token(1));
+        assert!(replication.has_replica());
+        replication.detach(Token(2));
+        assert!(!replication.has_replica());
+    }
+
+    #[test]
+    fn test_follow() {
+        let mut replication = Replication::new(&mut Rng::with_seed(1));
+        assert!(replication.info().starts_with("role:master\r\n"));
+        let now = Instant::now();
+        assert!(replication.follow(Some(("localhost".to_string(), 6380))));
+        assert!(!replication.follow(Some(("localhost".to_string(), 6380))));
+        assert!(replication.is_replica());
+        assert!(replication.take_connect(now + Duration::from_millis(1)));
+        assert!(!replication.take_connect(now + Duration::from_millis(1)));
+        assert!(replication.info().contains("master_link_status:down\r\n"));
+
+        replication.link_lost(now);
+        assert!(!replication.take_connect(now));
+        assert!(replication.take_connect(now + RETRY_DELAY));
+
+        // the old link still has to be dropped
+        assert!(replication.follow(None));
+        assert!(!replication.is_replica());
+        assert!(replication.take_connect(Instant::now()));
+        replication.link_lost(Instant::now());
+        assert_eq!(replication.connect_at(), None);
+    }
+
+    #[test]
+    fn test_take_snapshot() {
+        let mut state = LinkState::AwaitingResync;
+        let mut buf = b"+FULLRESYNC abc 0\r\n$5\r\nsna".to_vec();
+        assert_eq!(take_snapshot(&mut state, &mut buf).unwrap(), None);
+        assert_eq!(state, LinkState::AwaitingSnapshot);
+        buf.extend_from_slice(b"ps*1\r\n$4\r\nPING\r\n");
+        assert_eq!(
+            take_snapshot(&mut state, &mut buf).unwrap(),
+            Some(b"snaps".to_vec())
+        );
+        assert_eq!(state, LinkState::Streaming);
+        assert_eq!(buf, b"*1\r\n$4\r\nPING\r\n");
+        assert_eq!(take_snapshot(&mut state, &mut buf).unwrap(), None);
+
+        let mut state = LinkState::AwaitingResync;
+        let mut buf = b"-ERR unknown command 'PSYNC'\r\n".to_vec();
+        assert!(take_snapshot(&mut state, &mut buf).is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use crate::glob::glob_match;
+use bytes::Bytes;
+
+/// Elements returned per call when the client doesn't ask for a `COUNT`.
+pub const DEFAULT_COUNT: usize = 10;
+
+/// One page of an `*SCAN` over `items`, which must come in the same order on
+/// every call. The cursor is simply the index of the next item to visit, and
+/// `0` once everything was visited. `MATCH` filters each visited item, so a
+/// page can come back shorter than `count` or even empty.
+///
+/// Returns the next cursor and the matching items, flattened as key followed
+/// by the optional value (e.g. field and value for `HSCAN`).
+pub fn scan_page(
+    items: impl Iterator<Item = (Bytes, Option<Bytes>)>,
+    cursor: u64,
+    pattern: Option<&[u8]>,
+    count: Option<usize>,
+) -> (u64, Vec<Bytes>) {
+    let count = count.unwrap_or(DEFAULT_COUNT);
+    let mut visited = 0;
+    let mut page = Vec::new();
+    let mut items = items.skip(cursor as usize).peekable();
+    while visited < count
+        && let Some((key, value)) = items.next()
+    {
+        visited += 1;
+        if pattern.is_some_and(|pattern| !glob_match(pattern, &key)) {
+            continue;
+        }
+        page.push(key);
+        page.extend(value);
+    }
+    let next_cursor = match items.peek() {
+        None => 0,
+        Some(_) => cursor + visited as u64,
+    };
+    (next_cursor, page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(n: usize) -> impl Iterator<Item = (Bytes, Option<Bytes>)> {
+        (0..n).map(|i| (Bytes::from(format!("k{i}")), None))
+    }
+
+    #[test]
+    fn test_scan_visits_everything_once() {
+        let mut cursor = 0;
+        let mut seen = Vec::new();
+        loop {
+            let (next, page) = scan_page(items(25), cursor, None, Some(10));
+            seen.extend(page);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        assert_eq!(seen, items(25).map(|(k, _)| k).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_scan_match_and_values() {
+        let pairs = vec![
+            (Bytes::from("a1"), Some(Bytes::from("x"))),
+            (Bytes::from("b1"), Some(Bytes::from("y"))),
+        ];
+        let (next, page) = scan_page(pairs.into_iter(), 0, Some(b"a*"), None);
+        assert_eq!(next, 0);
+        assert_eq!(page, vec![Bytes::from("a1"), Bytes::from("x")]);
+        assert_eq!(scan_page(items(3), 7, None, None), (0, vec![]));
+    }
+}
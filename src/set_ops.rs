@@ -1,14 +1,50 @@
+use crate::list_ops::Popped;
+use crate::random::Rng;
+use crate::scan::scan_page;
 use crate::stored_value::StoredValue;
 use anyhow::bail;
 use bytes::Bytes;
 use std::collections::{HashMap, HashSet};
 
+/// Replaces `dst` with a set of `members`, deleting it if the result is
+/// empty. Returns the cardinality of the stored set.
+fn store_set(hmap: &mut HashMap<Bytes, StoredValue>, dst: &[u8], members: Vec<Bytes>) -> usize {
+    if members.is_empty() {
+        hmap.remove(dst);
+        return 0;
+    }
+    let set: HashSet<Bytes> = members.into_iter().collect();
+    let len = set.len();
+    hmap.insert(Bytes::copy_from_slice(dst), StoredValue::Set(set));
+    len
+}
+
 pub trait HMapSetOps {
     fn set_add(&mut self, key: &[u8], members: Vec<&[u8]>) -> anyhow::Result<usize>;
+    fn set_rem(&mut self, key: &[u8], members: Vec<&[u8]>) -> anyhow::Result<usize>;
+    fn set_pop(
+        &mut self,
+        key: &[u8],
+        count: Option<usize>,
+        rng: &mut Rng,
+    ) -> anyhow::Result<Popped>;
+    fn set_move(&mut self, src: &[u8], dst: &[u8], member: &[u8]) -> anyhow::Result<bool>;
+    fn set_rand(&self, key: &[u8], count: i64, rng: &mut Rng) -> anyhow::Result<Vec<Bytes>>;
     fn set_is_member(&self, key: &[u8], member: &[u8]) -> anyhow::Result<bool>;
+    fn set_scan(
+        &self,
+        key: &[u8],
+        cursor: u64,
+        pattern: Option<&[u8]>,
+        count: Option<usize>,
+    ) -> anyhow::Result<(u64, Vec<Bytes>)>;
+    fn set_mis_member(&self, key: &[u8], members: Vec<&[u8]>) -> anyhow::Result<Vec<bool>>;
     fn set_inter(&self, keys: &[&[u8]]) -> anyhow::Result<(Vec<&Bytes>, usize)>;
     fn set_union(&self, keys: &[&[u8]]) -> anyhow::Result<(Vec<&Bytes>, usize)>;
     fn set_diff(&self, keys: &[&[u8]]) -> anyhow::Result<(Vec<&Bytes>, usize)>;
+    fn set_inter_store(&mut self, dst: &[u8], keys: &[&[u8]]) -> anyhow::Result<usize>;
+    fn set_union_store(&mut self, dst: &[u8], keys: &[&[u8]]) -> anyhow::Result<usize>;
+    fn set_diff_store(&mut self, dst: &[u8], keys: &[&[u8]]) -> anyhow::Result<usize>;
     fn set_card(&self, key: &[u8]) -> anyhow::Result<Option<usize>>;
     fn set_members(&self, key: &[u8]) -> anyhow::Result<Option<(Vec<&Bytes>, usize)>>;
 }
@@ -32,6 +68,90 @@ impl HMapSetOps for HashMap<Bytes, StoredValue> {
         }
     }
 
+    fn set_rem(&mut self, key: &[u8], members: Vec<&[u8]>) -> anyhow::Result<usize> {
+        let set = match self.get_mut(key) {
+            None => return Ok(0),
+            Some(StoredValue::Set(set)) => set,
+            _ => bail!("stored value isn't a set"),
+        };
+        let removed = members.into_iter().filter(|m| set.remove(*m)).count();
+        if set.is_empty() {
+            self.remove(key);
+        }
+        Ok(removed)
+    }
+
+    fn set_move(&mut self, src: &[u8], dst: &[u8], member: &[u8]) -> anyhow::Result<bool> {
+        // both keys are type-checked before anything is modified, so a wrong
+        // destination never loses the member
+        if let Some(stored_value) = self.get(dst)
+            && !matches!(stored_value, StoredValue::Set(_))
+        {
+            bail!("stored value isn't a set");
+        }
+        let set = match self.get_mut(src) {
+            None => return Ok(false),
+            Some(StoredValue::Set(set)) => set,
+            _ => bail!("stored value isn't a set"),
+        };
+        if src == dst {
+            return Ok(set.contains(member));
+        }
+        let Some(member) = set.take(member) else {
+            return Ok(false);
+        };
+        if set.is_empty() {
+            self.remove(src);
+        }
+        match self
+            .entry(Bytes::copy_from_slice(dst))
+            .or_insert(StoredValue::Set(Default::default()))
+        {
+            StoredValue::Set(set) => {
+                set.insert(member);
+            }
+            _ => unreachable!("destination type checked above"),
+        }
+        Ok(true)
+    }
+
+    fn set_pop(
+        &mut self,
+        key: &[u8],
+        count: Option<usize>,
+        rng: &mut Rng,
+    ) -> anyhow::Result<Popped> {
+        let set = match self.get_mut(key) {
+            None if count.is_some() => return Ok(Popped::Multiple(vec![])),
+            None => return Ok(Popped::None),
+            Some(StoredValue::Set(set)) => set,
+            _ => bail!("stored value isn't a set"),
+        };
+        let mut popped: Vec<Bytes> = if count.is_some_and(|count| count >= set.len()) {
+            set.drain().collect()
+        } else {
+            rng.sample(set.iter().cloned(), count.unwrap_or(1) as i64)
+        };
+        for member in &popped {
+            set.remove(member);
+        }
+        if set.is_empty() {
+            self.remove(key);
+        }
+        Ok(match count {
+            Some(_) => Popped::Multiple(popped),
+            None => popped.pop().map_or(Popped::None, Popped::Single),
+        })
+    }
+
+    fn set_rand(&self, key: &[u8], count: i64, rng: &mut Rng) -> anyhow::Result<Vec<Bytes>> {
+        match self.get(key) {
+            None => Ok(vec![]),
+            Some(StoredValue::Set(set)) => Ok(rng.sample(set.iter().cloned(), count)),
+            _ => bail!("stored value isn't a set"),
+        }
+    }
+
     fn set_is_member(&self, key: &[u8], member: &[u8]) -> anyhow::Result<bool> {
         match self.get(key) {
             None => Ok(false),
@@ -40,6 +160,37 @@ impl HMapSetOps for HashMap<Bytes, StoredValue> {
         }
     }
 
+    fn set_scan(
+        &self,
+        key: &[u8],
+        cursor: u64,
+        pattern: Option<&[u8]>,
+        count: Option<usize>,
+    ) -> anyhow::Result<(u64, Vec<Bytes>)> {
+        match self.get(key) {
+            None => Ok((0, vec![])),
+            Some(StoredValue::Set(set)) => {
+                // sorted so the cursor means the same thing across calls
+                let mut members: Vec<&Bytes> = set.iter().collect();
+                members.sort_unstable();
+                let items = members.into_iter().map(|member| (member.clone(), None));
+                Ok(scan_page(items, cursor, pattern, count))
+            }
+            _ => bail!("stored value isn't a set"),
+        }
+    }
+
+    fn set_mis_member(&self, key: &[u8], members: Vec<&[u8]>) -> anyhow::Result<Vec<bool>> {
+        match self.get(key) {
+            None => Ok(vec![false; members.len()]),
+            Some(StoredValue::Set(set)) => Ok(members
+                .into_iter()
+                .map(|member| set.contains(member))
+                .collect()),
+            _ => bail!("stored value isn't a set"),
+        }
+    }
+
     fn set_inter(&self, keys: &[&[u8]]) -> anyhow::Result<(Vec<&Bytes>, usize)> {
         let mut iter = keys.iter();
         let first_key = match iter.next() {
@@ -111,6 +262,24 @@ impl HMapSetOps for HashMap<Bytes, StoredValue> {
         Ok((result.into_iter().collect(), len))
     }
 
+    fn set_inter_store(&mut self, dst: &[u8], keys: &[&[u8]]) -> anyhow::Result<usize> {
+        let (members, _) = self.set_inter(keys)?;
+        let members = members.into_iter().cloned().collect();
+        Ok(store_set(self, dst, members))
+    }
+
+    fn set_union_store(&mut self, dst: &[u8], keys: &[&[u8]]) -> anyhow::Result<usize> {
+        let (members, _) = self.set_union(keys)?;
+        let members = members.into_iter().cloned().collect();
+        Ok(store_set(self, dst, members))
+    }
+
+    fn set_diff_store(&mut self, dst: &[u8], keys: &[&[u8]]) -> anyhow::Result<usize> {
+        let (members, _) = self.set_diff(keys)?;
+        let members = members.into_iter().cloned().collect();
+        Ok(store_set(self, dst, members))
+    }
+
     fn set_card(&self, key: &[u8]) -> anyhow::Result<Option<usize>> {
         match self.get(key) {
             None => Ok(None),
@@ -0,0 +1,79 @@
+use mio::event::Source;
+use mio::unix::pipe::{self, Receiver, Sender};
+use mio::{Interest, Registry, Token};
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// Write end of the self-pipe. A signal handler can only reach statics.
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_signal(_: libc::c_int) {
+    let byte = 1u8;
+    // SAFETY: write(2) is async-signal-safe, and a failed write means the
+    // pipe is full, so a wakeup is pending anyway
+    unsafe { libc::write(PIPE.load(Ordering::Relaxed), (&raw const byte).cast(), 1) };
+}
+
+/// SIGINT and SIGTERM as events of the poll: the handlers write to a pipe
+/// whose read end is registered like any socket, so the event loop wakes up
+/// and can shut down in order.
+pub struct Signals {
+    receiver: Receiver,
+    // kept open for the handlers
+    _sender: Sender,
+}
+
+impl Signals {
+    pub fn install() -> io::Result<Self> {
+        let (sender, receiver) = pipe::new()?;
+        PIPE.store(sender.as_raw_fd(), Ordering::Relaxed);
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            // SAFETY: the handler only writes to the pipe
+            if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Self {
+            receiver,
+            _sender: sender,
+        })
+    }
+
+    /// Drains the pipe, returning whether any signal arrived.
+    pub fn received(&mut self) -> bool {
+        let mut buf = [0u8; 16];
+        let mut received = false;
+        while let Ok(n) = self.receiver.read(&mut buf)
+            && n > 0
+        {
+            received = true;
+        }
+        received
+    }
+}
+
+impl Source for Signals {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.receiver.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.receiver.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.receiver.deregister(registry)
+    }
+}
@@ -1,10 +1,16 @@
+use crate::cmd::{Aggregate, LexBound, ScoreBound};
+use crate::err::WrongType;
+use crate::ranked_set::RankedSet;
+use crate::scan::scan_page;
 use crate::stored_value::StoredValue;
 use anyhow::bail;
 use bytes::Bytes;
 use std::collections::HashMap;
+use std::ops::Bound;
 
 pub trait HMapSortedSetOps {
     fn zset_add(&mut self, key: &[u8], members: &[(i64, &[u8])]) -> anyhow::Result<usize>;
+    fn zset_rem(&mut self, key: &[u8], members: Vec<&[u8]>) -> anyhow::Result<usize>;
     fn zset_range(
         &self,
         key: &[u8],
@@ -22,15 +28,55 @@ pub trait HMapSortedSetOps {
     fn zset_rank(&self, key: &[u8], member: &[u8]) -> anyhow::Result<Option<usize>>;
     fn zset_revrank(&self, key: &[u8], member: &[u8]) -> anyhow::Result<Option<usize>>;
     fn zset_score(&self, key: &[u8], member: &[u8]) -> anyhow::Result<Option<i64>>;
+    fn zset_mscore(&self, key: &[u8], members: Vec<&[u8]>) -> anyhow::Result<Vec<Option<i64>>>;
     fn zset_range_by_score(
         &self,
         key: &[u8],
-        min: i64,
-        max: i64,
+        min: ScoreBound,
+        max: ScoreBound,
         withscores: bool,
     ) -> anyhow::Result<Option<(Vec<Bytes>, usize)>>;
+    fn zset_range_by_lex(
+        &self,
+        key: &[u8],
+        min: LexBound<&[u8]>,
+        max: LexBound<&[u8]>,
+    ) -> anyhow::Result<Option<(Vec<Bytes>, usize)>>;
+    fn zset_rem_range_by_rank(
+        &mut self,
+        key: &[u8],
+        start: isize,
+        stop: isize,
+    ) -> anyhow::Result<usize>;
+    fn zset_rem_range_by_score(
+        &mut self,
+        key: &[u8],
+        min: ScoreBound,
+        max: ScoreBound,
+    ) -> anyhow::Result<usize>;
+    fn zset_union_store(
+        &mut self,
+        dst: &[u8],
+        keys: &[&[u8]],
+        weights: Option<&[f64]>,
+        aggregate: Aggregate,
+    ) -> anyhow::Result<usize>;
+    fn zset_inter_store(
+        &mut self,
+        dst: &[u8],
+        keys: &[&[u8]],
+        weights: Option<&[f64]>,
+        aggregate: Aggregate,
+    ) -> anyhow::Result<usize>;
     fn zset_incr_by(&mut self, key: &[u8], incr: i64, member: &[u8]) -> anyhow::Result<i64>;
     fn zcard(&self, key: &[u8]) -> anyhow::Result<Option<usize>>;
+    fn zset_scan(
+        &self,
+        key: &[u8],
+        cursor: u64,
+        pattern: Option<&[u8]>,
+        count: Option<usize>,
+    ) -> anyhow::Result<(u64, Vec<Bytes>)>;
 }
 
 fn normalize_range(len: usize, start: isize, stop: isize) -> Option<(usize, usize)> {
@@ -53,6 +99,150 @@ fn normalize_range(len: usize, start: isize, stop: isize) -> Option<(usize, usiz
     Some((real_start, real_stop))
 }
 
+type ScoreRange = (Bound<(i64, Bytes)>, Bound<(i64, Bytes)>);
+
+/// Translates a `min`/`max` score range into bounds on the `(score, member)`
+/// tree, so `RankedSet::range` enforces them directly. Returns `None` when no
+/// integer score can fall inside the range.
+fn score_range(min: ScoreBound, max: ScoreBound) -> Option<ScoreRange> {
+    // the lowest and highest integer scores admitted by the bounds
+    let lowest = match min {
+        ScoreBound::Inclusive(min) => min.ceil(),
+        ScoreBound::Exclusive(min) => min.floor() + 1.0,
+        ScoreBound::NegInf => f64::NEG_INFINITY,
+        ScoreBound::PosInf => return None,
+    };
+    let highest = match max {
+        ScoreBound::Inclusive(max) => max.floor(),
+        ScoreBound::Exclusive(max) => max.ceil() - 1.0,
+        ScoreBound::PosInf => f64::INFINITY,
+        ScoreBound::NegInf => return None,
+    };
+    // i64::MAX rounds up to 2^63 as a float, so `>=` means "doesn't fit"
+    if lowest > highest || lowest >= i64::MAX as f64 || highest < i64::MIN as f64 {
+        return None;
+    }
+    // an empty member sorts before any other, so `(score, "")` is the first
+    // possible entry with that score
+    let start = if lowest <= i64::MIN as f64 {
+        Bound::Unbounded
+    } else {
+        Bound::Included((lowest as i64, Bytes::new()))
+    };
+    let end = if highest >= i64::MAX as f64 {
+        Bound::Unbounded
+    } else {
+        Bound::Excluded((highest as i64 + 1, Bytes::new()))
+    };
+    Some((start, end))
+}
+
+fn above_lex_min(min: LexBound<&[u8]>, member: &[u8]) -> bool {
+    match min {
+        LexBound::Inclusive(min) => member >= min,
+        LexBound::Exclusive(min) => member > min,
+        LexBound::Min => true,
+        LexBound::Max => false,
+    }
+}
+
+fn below_lex_max(max: LexBound<&[u8]>, member: &[u8]) -> bool {
+    match max {
+        LexBound::Inclusive(max) => member <= max,
+        LexBound::Exclusive(max) => member < max,
+        LexBound::Max => true,
+        LexBound::Min => false,
+    }
+}
+
+/// Removes the entries picked by `select` from the sorted set under `key`,
+/// deleting the key once it's empty. Returns the number of removed members.
+fn remove_entries(
+    hmap: &mut HashMap<Bytes, StoredValue>,
+    key: &[u8],
+    select: impl FnOnce(&RankedSet) -> Vec<(i64, Bytes)>,
+) -> anyhow::Result<usize> {
+    let (tree, scores) = match hmap.get_mut(key) {
+        None => return Ok(0),
+        Some(StoredValue::SortedSet(tree, scores)) => (tree, scores),
+        _ => bail!("stored value isn't a sorted set"),
+    };
+    let selected = select(tree);
+    for entry in &selected {
+        scores.remove(&entry.1);
+        tree.remove(entry);
+    }
+    if tree.is_empty() {
+        hmap.remove(key);
+    }
+    Ok(selected.len())
+}
+
+fn aggregate_scores(aggregate: Aggregate, a: f64, b: f64) -> f64 {
+    match aggregate {
+        Aggregate::Sum => a + b,
+        Aggregate::Min => a.min(b),
+        Aggregate::Max => a.max(b),
+    }
+}
+
+/// Weighted scores of every input of a `Z*STORE`, in key order. Plain sets
+/// count as a score of 1 per member and missing keys as empty inputs.
+fn weighted_inputs(
+    hmap: &HashMap<Bytes, StoredValue>,
+    keys: &[&[u8]],
+    weights: Option<&[f64]>,
+) -> anyhow::Result<Vec<HashMap<Bytes, f64>>> {
+    let mut inputs = Vec::with_capacity(keys.len());
+    for (idx, key) in keys.iter().enumerate() {
+        let weight = weights.map_or(1.0, |w| w[idx]);
+        let input = match hmap.get(*key) {
+            None => HashMap::new(),
+            Some(StoredValue::SortedSet(_, scores)) => scores
+                .iter()
+                .map(|(member, score)| (member.clone(), *score as f64 * weight))
+                .collect(),
+            Some(StoredValue::Set(set)) => {
+                set.iter().map(|member| (member.clone(), weight)).collect()
+            }
+            _ => bail!(WrongType),
+        };
+        inputs.push(input);
+    }
+    Ok(inputs)
+}
+
+/// Replaces `dst` with a sorted set built from `result`, deleting it if the
+/// result is empty. Returns the cardinality of the stored set.
+fn store_zset(
+    hmap: &mut HashMap<Bytes, StoredValue>,
+    dst: &[u8],
+    result: HashMap<Bytes, f64>,
+) -> anyhow::Result<usize> {
+    // scores are integers until the sorted set grows float scores, so refuse
+    // rather than silently truncate what the weights produced
+    if result.values().any(|s| s.fract() != 0.0 || !s.is_finite()) {
+        bail!("resulting score is not an integer");
+    }
+    if result.is_empty() {
+        hmap.remove(dst);
+        return Ok(0);
+    }
+    let mut tree = RankedSet::default();
+    let mut scores = HashMap::with_capacity(result.len());
+    for (member, score) in result {
+        let score = score as i64;
+        tree.insert((score, member.clone()));
+        scores.insert(member, score);
+    }
+    let len = scores.len();
+    hmap.insert(
+        Bytes::copy_from_slice(dst),
+        StoredValue::SortedSet(tree, scores),
+    );
+    Ok(len)
+}
+
 fn collect_with_scores(
     iter: impl Iterator<Item = (i64, Bytes)>,
     withscores: bool,
@@ -95,6 +285,25 @@ impl HMapSortedSetOps for HashMap<Bytes, StoredValue> {
         }
     }
 
+    fn zset_rem(&mut self, key: &[u8], members: Vec<&[u8]>) -> anyhow::Result<usize> {
+        let (tree, scores) = match self.get_mut(key) {
+            None => return Ok(0),
+            Some(StoredValue::SortedSet(tree, scores)) => (tree, scores),
+            _ => bail!("stored value isn't a sorted set"),
+        };
+        let mut removed = 0;
+        for member in members {
+            if let Some((member, score)) = scores.remove_entry(member) {
+                tree.remove(&(score, member));
+                removed += 1;
+            }
+        }
+        if scores.is_empty() {
+            self.remove(key);
+        }
+        Ok(removed)
+    }
+
     fn zset_range(
         &self,
         key: &[u8],
@@ -109,9 +318,7 @@ impl HMapSortedSetOps for HashMap<Bytes, StoredValue> {
                     return Ok(Some((vec![], 0)));
                 };
                 let iter = tree
-                    .iter()
-                    .skip(real_start)
-                    .take(real_stop - real_start + 1)
+                    .slice(real_start..real_stop + 1)
                     .map(|(s, m)| (*s, m.clone()));
                 Ok(Some(collect_with_scores(iter, withscores)))
             }
@@ -132,11 +339,10 @@ impl HMapSortedSetOps for HashMap<Bytes, StoredValue> {
                 let Some((real_start, real_stop)) = normalize_range(tree.len(), start, stop) else {
                     return Ok(Some((vec![], 0)));
                 };
+                let len = tree.len();
                 let iter = tree
-                    .iter()
+                    .slice(len - 1 - real_stop..len - real_start)
                     .rev()
-                    .skip(real_start)
-                    .take(real_stop - real_start + 1)
                     .map(|(s, m)| (*s, m.clone()));
                 Ok(Some(collect_with_scores(iter, withscores)))
             }
@@ -151,7 +357,7 @@ impl HMapSortedSetOps for HashMap<Bytes, StoredValue> {
                 None => Ok(None),
                 Some(score) => {
                     let target = (*score, Bytes::copy_from_slice(member));
-                    Ok(Some(tree.range(..&target).count()))
+                    Ok(Some(tree.rank(&target)))
                 }
             },
             _ => bail!("stored value isn't a sorted set"),
@@ -165,7 +371,7 @@ impl HMapSortedSetOps for HashMap<Bytes, StoredValue> {
                 None => Ok(None),
                 Some(score) => {
                     let target = (*score, Bytes::copy_from_slice(member));
-                    let rank = tree.range(..&target).count();
+                    let rank = tree.rank(&target);
                     Ok(Some(tree.len() - 1 - rank))
                 }
             },
@@ -181,27 +387,127 @@ impl HMapSortedSetOps for HashMap<Bytes, StoredValue> {
         }
     }
 
+    fn zset_mscore(&self, key: &[u8], members: Vec<&[u8]>) -> anyhow::Result<Vec<Option<i64>>> {
+        match self.get(key) {
+            None => Ok(vec![None; members.len()]),
+            Some(StoredValue::SortedSet(_, scores)) => Ok(members
+                .into_iter()
+                .map(|member| scores.get(member).copied())
+                .collect()),
+            _ => bail!("stored value isn't a sorted set"),
+        }
+    }
+
     fn zset_range_by_score(
         &self,
         key: &[u8],
-        min: i64,
-        max: i64,
+        min: ScoreBound,
+        max: ScoreBound,
         withscores: bool,
+    ) -> anyhow::Result<Option<(Vec<Bytes>, usize)>> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(StoredValue::SortedSet(tree, _)) => {
+                let Some(range) = score_range(min, max) else {
+                    return Ok(Some((vec![], 0)));
+                };
+                let iter = tree.range(range).map(|(s, m)| (*s, m.clone()));
+                Ok(Some(collect_with_scores(iter, withscores)))
+            }
+            _ => bail!("stored value isn't a sorted set"),
+        }
+    }
+
+    /// Meant for sorted sets whose members all share one score. With mixed
+    /// scores the members are still filtered one by one, but returned in
+    /// score order rather than lexicographic order.
+    fn zset_range_by_lex(
+        &self,
+        key: &[u8],
+        min: LexBound<&[u8]>,
+        max: LexBound<&[u8]>,
     ) -> anyhow::Result<Option<(Vec<Bytes>, usize)>> {
         match self.get(key) {
             None => Ok(None),
             Some(StoredValue::SortedSet(tree, _)) => {
                 let iter = tree
                     .iter()
-                    .skip_while(|(s, _)| *s < min)
-                    .take_while(|(s, _)| *s <= max)
+                    .filter(|(_, m)| above_lex_min(min, m) && below_lex_max(max, m))
                     .map(|(s, m)| (*s, m.clone()));
-                Ok(Some(collect_with_scores(iter, withscores)))
+                Ok(Some(collect_with_scores(iter, false)))
             }
             _ => bail!("stored value isn't a sorted set"),
         }
     }
 
+    fn zset_rem_range_by_rank(
+        &mut self,
+        key: &[u8],
+        start: isize,
+        stop: isize,
+    ) -> anyhow::Result<usize> {
+        remove_entries(self, key, |tree| {
+            match normalize_range(tree.len(), start, stop) {
+                None => vec![],
+                Some((real_start, real_stop)) => {
+                    tree.slice(real_start..real_stop + 1).cloned().collect()
+                }
+            }
+        })
+    }
+
+    fn zset_rem_range_by_score(
+        &mut self,
+        key: &[u8],
+        min: ScoreBound,
+        max: ScoreBound,
+    ) -> anyhow::Result<usize> {
+        remove_entries(self, key, |tree| match score_range(min, max) {
+            None => vec![],
+            Some(range) => tree.range(range).cloned().collect(),
+        })
+    }
+
+    fn zset_union_store(
+        &mut self,
+        dst: &[u8],
+        keys: &[&[u8]],
+        weights: Option<&[f64]>,
+        aggregate: Aggregate,
+    ) -> anyhow::Result<usize> {
+        let mut result: HashMap<Bytes, f64> = HashMap::new();
+        for input in weighted_inputs(self, keys, weights)? {
+            for (member, score) in input {
+                result
+                    .entry(member)
+                    .and_modify(|acc| *acc = aggregate_scores(aggregate, *acc, score))
+                    .or_insert(score);
+            }
+        }
+        store_zset(self, dst, result)
+    }
+
+    fn zset_inter_store(
+        &mut self,
+        dst: &[u8],
+        keys: &[&[u8]],
+        weights: Option<&[f64]>,
+        aggregate: Aggregate,
+    ) -> anyhow::Result<usize> {
+        let mut inputs = weighted_inputs(self, keys, weights)?.into_iter();
+        let mut result = inputs.next().unwrap_or_default();
+        for input in inputs {
+            result.retain(|member, acc| match input.get(member) {
+                None => false,
+                Some(score) => {
+                    *acc = aggregate_scores(aggregate, *acc, *score);
+                    true
+                }
+            });
+        }
+        store_zset(self, dst, result)
+    }
+
     fn zset_incr_by(&mut self, key: &[u8], incr: i64, member: &[u8]) -> anyhow::Result<i64> {
         let stored_value =
             self.entry(Bytes::copy_from_slice(key))
@@ -232,4 +538,88 @@ impl HMapSortedSetOps for HashMap<Bytes, StoredValue> {
             _ => bail!("stored value isn't a sorted set"),
         }
     }
+
+    fn zset_scan(
+        &self,
+        key: &[u8],
+        cursor: u64,
+        pattern: Option<&[u8]>,
+        count: Option<usize>,
+    ) -> anyhow::Result<(u64, Vec<Bytes>)> {
+        match self.get(key) {
+            None => Ok((0, vec![])),
+            Some(StoredValue::SortedSet(tree, _)) => {
+                let items = tree
+                    .iter()
+                    .map(|(score, member)| (member.clone(), Some(Bytes::from(score.to_string()))));
+                Ok(scan_page(items, cursor, pattern, count))
+            }
+            _ => bail!("stored value isn't a sorted set"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zset_of(members: &[(i64, &[u8])]) -> HashMap<Bytes, StoredValue> {
+        let mut hmap = HashMap::new();
+        hmap.zset_add(b"z", members).unwrap();
+        hmap
+    }
+
+    #[test]
+    fn test_zset_range_by_score() {
+        let hmap = zset_of(&[(-600, b"d"), (750, b"b"), (870, b"c"), (900, b"a")]);
+        let range = |min, max| {
+            hmap.zset_range_by_score(b"z", min, max, false)
+                .unwrap()
+                .unwrap()
+                .0
+        };
+
+        // min > max is an empty range, not a panic or a partial scan
+        assert!(range(ScoreBound::Inclusive(900.0), ScoreBound::Inclusive(750.0)).is_empty());
+        assert!(range(ScoreBound::Exclusive(750.0), ScoreBound::Exclusive(751.0)).is_empty());
+        assert!(range(ScoreBound::PosInf, ScoreBound::NegInf).is_empty());
+        // bounds falling into gaps between the stored scores
+        assert_eq!(
+            range(ScoreBound::Inclusive(-100.5), ScoreBound::Inclusive(880.5)),
+            vec!["b", "c"]
+        );
+        assert_eq!(
+            range(ScoreBound::Exclusive(750.0), ScoreBound::Exclusive(900.0)),
+            vec!["c"]
+        );
+        assert_eq!(
+            range(ScoreBound::NegInf, ScoreBound::PosInf),
+            vec!["d", "b", "c", "a"]
+        );
+        assert_eq!(
+            range(ScoreBound::Inclusive(-1e30), ScoreBound::Inclusive(1e30)),
+            vec!["d", "b", "c", "a"]
+        );
+    }
+
+    #[test]
+    fn test_zset_range_by_lex() {
+        let hmap = zset_of(&[(0, b"a"), (0, b"b"), (0, b"c"), (0, b"d")]);
+        let range = |min, max| hmap.zset_range_by_lex(b"z", min, max).unwrap().unwrap().0;
+
+        assert_eq!(
+            range(LexBound::Min, LexBound::Max),
+            vec!["a", "b", "c", "d"]
+        );
+        assert_eq!(
+            range(LexBound::Exclusive(b"a"), LexBound::Exclusive(b"d")),
+            vec!["b", "c"]
+        );
+        assert_eq!(
+            range(LexBound::Inclusive(b"b"), LexBound::Inclusive(b"c")),
+            vec!["b", "c"]
+        );
+        assert!(range(LexBound::Exclusive(b"d"), LexBound::Max).is_empty());
+        assert!(range(LexBound::Max, LexBound::Min).is_empty());
+    }
 }
@@ -1,6 +1,62 @@
 use compact_str::CompactString;
 use histogram::Histogram;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// How often the command count is sampled for the ops/sec rate, and over
+/// how long, as Redis does.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+const SAMPLE_WINDOW: Duration = Duration::from_millis(1600);
+
+/// Server-wide counters `INFO` reports, cleared by `CONFIG RESETSTAT`.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub total_connections_received: u64,
+    pub total_commands_processed: u64,
+    /// Keys read-only commands found, or didn't.
+    pub keyspace_hits: u64,
+    pub keyspace_misses: u64,
+    pub expired_keys: u64,
+    /// The command count before the first command of each `SAMPLE_INTERVAL`,
+    /// over the last `SAMPLE_WINDOW`.
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl Stats {
+    pub fn command_processed(&mut self, now: Instant) {
+        if self
+            .samples
+            .back()
+            .is_none_or(|(at, _)| now.duration_since(*at) >= SAMPLE_INTERVAL)
+        {
+            self.samples.push_back((now, self.total_commands_processed));
+        }
+        self.total_commands_processed += 1;
+        while self
+            .samples
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > SAMPLE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Commands processed per second, over the last `SAMPLE_WINDOW`.
+    pub fn ops_per_sec(&self, now: Instant) -> u64 {
+        let Some((at, count)) = self
+            .samples
+            .iter()
+            .find(|(at, _)| now.duration_since(*at) <= SAMPLE_WINDOW)
+        else {
+            return 0;
+        };
+        let elapsed = now.duration_since(*at).as_secs_f64();
+        if elapsed == 0.0 {
+            return 0;
+        }
+        ((self.total_commands_processed - count) as f64 / elapsed) as u64
+    }
+}
 
 pub struct CommandStats {}
 
@@ -26,3 +82,40 @@ impl CommandStats {
         command_stats
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ops_per_sec() {
+        let mut stats = Stats::default();
+        let start = Instant::now();
+        assert_eq!(stats.ops_per_sec(start), 0);
+        // 10 commands every 100ms
+        for tick in 0..10 {
+            for _ in 0..10 {
+                stats.command_processed(start + SAMPLE_INTERVAL * tick);
+            }
+        }
+        assert_eq!(stats.total_commands_processed, 100);
+        assert_eq!(stats.ops_per_sec(start + SAMPLE_INTERVAL * 10), 100);
+        // the rate fades once the commands stop
+        assert_eq!(stats.ops_per_sec(start + SAMPLE_WINDOW * 2), 0);
+    }
+
+    #[test]
+    fn test_make() {
+        let mut latency_histograms = HashMap::new();
+        assert_eq!(
+            CommandStats::make(&latency_histograms),
+            "# Commandstats\r\n"
+        );
+
+        let mut histogram = Histogram::new(2, 30).unwrap();
+        histogram.increment(100).unwrap();
+        latency_histograms.insert(CompactString::new("get"), histogram);
+        let stats = CommandStats::make(&latency_histograms);
+        assert!(stats.starts_with("# Commandstats\r\ncmdstat_get:calls=1,"));
+    }
+}
@@ -1,13 +1,36 @@
+use crate::ranked_set::RankedSet;
 use bytes::Bytes;
-use std::collections::{BTreeSet, HashMap, HashSet, LinkedList};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::time::Instant;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum StoredValue {
     Plain(Bytes),
     TtlPlain(Bytes, Instant),
-    List(LinkedList<Bytes>),
+    List(VecDeque<Bytes>),
     Dict(HashMap<Bytes, Bytes>),
     Set(HashSet<Bytes>),
-    SortedSet(BTreeSet<(i64, Bytes)>, HashMap<Bytes, i64>),
+    SortedSet(RankedSet, HashMap<Bytes, i64>),
+}
+
+impl StoredValue {
+    /// The name Redis gives the encoding closest to how the value is stored.
+    /// Strings are told apart the way Redis does, though all are kept as is.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            StoredValue::Plain(bytes) | StoredValue::TtlPlain(bytes, _) => {
+                let is_int = std::str::from_utf8(bytes).is_ok_and(|s| s.parse::<i64>().is_ok());
+                if is_int {
+                    "int"
+                } else if bytes.len() <= 44 {
+                    "embstr"
+                } else {
+                    "raw"
+                }
+            }
+            StoredValue::List(_) => "quicklist",
+            StoredValue::Dict(_) | StoredValue::Set(_) => "hashtable",
+            StoredValue::SortedSet(..) => "skiplist",
+        }
+    }
 }
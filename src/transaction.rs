@@ -0,0 +1,184 @@
+use crate::cmd::owned::OwnedCommand;
+use bytes::Bytes;
+use std::collections::HashMap;
+
+/// Write versions of the keys clients `WATCH` in one database. Only watched
+/// keys are tracked, so writing any other key costs a failed lookup.
+#[derive(Debug, Default, Clone)]
+pub struct Versions {
+    /// Version of each watched key and how many clients watch it.
+    keys: HashMap<Bytes, (u64, usize)>,
+}
+
+impl Versions {
+    /// Starts watching `key`, returning its current version.
+    fn watch(&mut self, key: &[u8]) -> u64 {
+        let (version, watchers) = self.keys.entry(Bytes::copy_from_slice(key)).or_default();
+        *watchers += 1;
+        *version
+    }
+
+    fn unwatch(&mut self, key: &[u8]) {
+        if let Some((_, watchers)) = self.keys.get_mut(key) {
+            *watchers -= 1;
+            if *watchers == 0 {
+                self.keys.remove(key);
+            }
+        }
+    }
+
+    fn version(&self, key: &[u8]) -> u64 {
+        self.keys.get(key).map_or(0, |(version, _)| *version)
+    }
+
+    /// Records a write to `key`. A deleted or expired key counts as written.
+    pub fn touch(&mut self, key: &[u8]) {
+        if let Some((version, _)) = self.keys.get_mut(key) {
+            *version += 1;
+        }
+    }
+
+    /// Records a write to every key, for flushes.
+    pub fn touch_all(&mut self) {
+        for (version, _) in self.keys.values_mut() {
+            *version += 1;
+        }
+    }
+}
+
+/// What `EXEC` should do with a client's transaction.
+#[derive(Debug, PartialEq)]
+pub enum Exec {
+    /// There was no `MULTI`.
+    NotStarted,
+    /// A command failed to queue.
+    Failed,
+    /// A watched key was written since `WATCH`.
+    Aborted,
+    Run(Vec<OwnedCommand>),
+}
+
+/// A client's `MULTI` block and `WATCH`ed keys.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    /// Commands queued since `MULTI`, `None` outside a transaction.
+    queued: Option<Vec<OwnedCommand>>,
+    failed: bool,
+    /// Watched keys as database, key and version when watched.
+    watched: Vec<(usize, Bytes, u64)>,
+}
+
+impl Transaction {
+    pub fn is_open(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    pub fn begin(&mut self) {
+        self.queued = Some(Vec::new());
+        self.failed = false;
+    }
+
+    pub fn queue(&mut self, cmd: OwnedCommand) {
+        if let Some(queued) = &mut self.queued {
+            queued.push(cmd);
+        }
+    }
+
+    /// Marks an open transaction as failed, so `EXEC` discards it.
+    pub fn fail(&mut self) {
+        if self.is_open() {
+            self.failed = true;
+        }
+    }
+
+    pub fn watch(&mut self, versions: &mut [Versions], db_index: usize, key: &[u8]) {
+        let version = versions[db_index].watch(key);
+        self.watched
+            .push((db_index, Bytes::copy_from_slice(key), version));
+    }
+
+    pub fn unwatch(&mut self, versions: &mut [Versions]) {
+        for (db_index, key, _) in self.watched.drain(..) {
+            versions[db_index].unwatch(&key);
+        }
+    }
+
+    /// Drops the queued commands and the watched keys.
+    pub fn discard(&mut self, versions: &mut [Versions]) {
+        self.queued = None;
+        self.unwatch(versions);
+    }
+
+    /// Ends the transaction, handing out the queued commands unless it failed
+    /// or a watched key changed.
+    pub fn exec(&mut self, versions: &mut [Versions]) -> Exec {
+        let dirty = self
+            .watched
+            .iter()
+            .any(|(db_index, key, version)| versions[*db_index].version(key) != *version);
+        let queued = self.queued.take();
+        self.unwatch(versions);
+        match queued {
+            None => Exec::NotStarted,
+            Some(_) if self.failed => Exec::Failed,
+            Some(_) if dirty => Exec::Aborted,
+            Some(queued) => Exec::Run(queued),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exec_runs_queued() {
+        let mut versions = vec![Versions::default(); 2];
+        let mut transaction = Transaction::default();
+        assert_eq!(transaction.exec(&mut versions), Exec::NotStarted);
+
+        transaction.watch(&mut versions, 1, b"a");
+        transaction.begin();
+        transaction.queue(OwnedCommand::Ping);
+        // other databases and keys are unrelated
+        versions[0].touch(b"a");
+        versions[1].touch(b"b");
+        assert_eq!(
+            transaction.exec(&mut versions),
+            Exec::Run(vec![OwnedCommand::Ping])
+        );
+        assert!(!transaction.is_open());
+        assert!(versions[1].keys.is_empty());
+    }
+
+    #[test]
+    fn test_exec_aborts_on_watched_write() {
+        let mut versions = vec![Versions::default()];
+        let mut transaction = Transaction::default();
+        let mut other = Transaction::default();
+        transaction.watch(&mut versions, 0, b"a");
+        other.watch(&mut versions, 0, b"a");
+        transaction.begin();
+        versions[0].touch(b"a");
+        assert_eq!(transaction.exec(&mut versions), Exec::Aborted);
+
+        // still tracked for the other watcher
+        assert_eq!(versions[0].version(b"a"), 1);
+        other.begin();
+        assert_eq!(other.exec(&mut versions), Exec::Aborted);
+        assert!(versions[0].keys.is_empty());
+    }
+
+    #[test]
+    fn test_exec_discards_failed() {
+        let mut versions = vec![Versions::default()];
+        let mut transaction = Transaction::default();
+        transaction.fail();
+        transaction.begin();
+        transaction.fail();
+        assert_eq!(transaction.exec(&mut versions), Exec::Failed);
+
+        transaction.begin();
+        assert_eq!(transaction.exec(&mut versions), Exec::Run(vec![]));
+    }
+}